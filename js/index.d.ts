@@ -0,0 +1,2 @@
+export { convert } from "./pkg/sparql2rify.js";
+export type { ConvertError, Entity, RdfNode, Rule } from "./pkg/sparql2rify.js";
@@ -0,0 +1,7 @@
+// ES module entry point. The wasm-bindgen output in ./pkg needs to be initialized before use,
+// this does so once on import so callers can use `convert` straight away.
+import init, { convert } from "./pkg/sparql2rify.js";
+
+await init();
+
+export { convert };
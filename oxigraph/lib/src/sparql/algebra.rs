@@ -946,68 +946,66 @@ impl GraphPattern {
     }
 
     fn add_visible_variables<'a>(&'a self, vars: &mut BTreeSet<&'a Variable>) {
-        match self {
-            GraphPattern::BGP(p) => {
-                for pattern in p {
-                    match pattern {
-                        TripleOrPathPattern::Triple(tp) => {
-                            if let TermOrVariable::Variable(ref s) = tp.subject {
-                                vars.insert(s);
+        // uses an explicit stack, deeply nested machine generated patterns overflow a recursive walk
+        let mut stack = vec![self];
+        while let Some(pattern) = stack.pop() {
+            match pattern {
+                GraphPattern::BGP(p) => {
+                    for pattern in p {
+                        match pattern {
+                            TripleOrPathPattern::Triple(tp) => {
+                                if let TermOrVariable::Variable(ref s) = tp.subject {
+                                    vars.insert(s);
+                                }
+                                if let NamedNodeOrVariable::Variable(ref p) = tp.predicate {
+                                    vars.insert(p);
+                                }
+                                if let TermOrVariable::Variable(ref o) = tp.object {
+                                    vars.insert(o);
+                                }
                             }
-                            if let NamedNodeOrVariable::Variable(ref p) = tp.predicate {
-                                vars.insert(p);
-                            }
-                            if let TermOrVariable::Variable(ref o) = tp.object {
-                                vars.insert(o);
-                            }
-                        }
-                        TripleOrPathPattern::Path(ppp) => {
-                            if let TermOrVariable::Variable(ref s) = ppp.subject {
-                                vars.insert(s);
-                            }
-                            if let TermOrVariable::Variable(ref o) = ppp.object {
-                                vars.insert(o);
+                            TripleOrPathPattern::Path(ppp) => {
+                                if let TermOrVariable::Variable(ref s) = ppp.subject {
+                                    vars.insert(s);
+                                }
+                                if let TermOrVariable::Variable(ref o) = ppp.object {
+                                    vars.insert(o);
+                                }
                             }
                         }
                     }
                 }
-            }
-            GraphPattern::Join(a, b) => {
-                a.add_visible_variables(vars);
-                b.add_visible_variables(vars);
-            }
-            GraphPattern::LeftJoin(a, b, _) => {
-                a.add_visible_variables(vars);
-                b.add_visible_variables(vars);
-            }
-            GraphPattern::Filter(_, p) => p.add_visible_variables(vars),
-            GraphPattern::Union(a, b) => {
-                a.add_visible_variables(vars);
-                b.add_visible_variables(vars);
-            }
-            GraphPattern::Graph(g, p) => {
-                if let NamedNodeOrVariable::Variable(ref g) = g {
-                    vars.insert(g);
+                GraphPattern::Join(a, b)
+                | GraphPattern::LeftJoin(a, b, _)
+                | GraphPattern::Union(a, b) => {
+                    stack.push(b);
+                    stack.push(a);
                 }
-                p.add_visible_variables(vars);
-            }
-            GraphPattern::Extend(p, v, _) => {
-                vars.insert(v);
-                p.add_visible_variables(vars);
-            }
-            GraphPattern::Minus(a, _) => a.add_visible_variables(vars),
-            GraphPattern::Service(_, p, _) => p.add_visible_variables(vars),
-            GraphPattern::AggregateJoin(_, a) => {
-                for (_, v) in a {
+                GraphPattern::Filter(_, p) => stack.push(p),
+                GraphPattern::Graph(g, p) => {
+                    if let NamedNodeOrVariable::Variable(ref g) = g {
+                        vars.insert(g);
+                    }
+                    stack.push(p);
+                }
+                GraphPattern::Extend(p, v, _) => {
                     vars.insert(v);
+                    stack.push(p);
+                }
+                GraphPattern::Minus(a, _) => stack.push(a),
+                GraphPattern::Service(_, p, _) => stack.push(p),
+                GraphPattern::AggregateJoin(_, a) => {
+                    for (_, v) in a {
+                        vars.insert(v);
+                    }
                 }
+                GraphPattern::Data(b) => vars.extend(b.variables_iter()),
+                GraphPattern::OrderBy(l, _)
+                | GraphPattern::Distinct(l)
+                | GraphPattern::Reduced(l)
+                | GraphPattern::Slice(l, _, _) => stack.push(l),
+                GraphPattern::Project(_, pv) => vars.extend(pv.iter()),
             }
-            GraphPattern::Data(b) => vars.extend(b.variables_iter()),
-            GraphPattern::OrderBy(l, _) => l.add_visible_variables(vars),
-            GraphPattern::Project(_, pv) => vars.extend(pv.iter()),
-            GraphPattern::Distinct(l) => l.add_visible_variables(vars),
-            GraphPattern::Reduced(l) => l.add_visible_variables(vars),
-            GraphPattern::Slice(l, _, _) => l.add_visible_variables(vars),
         }
     }
 }
@@ -1078,7 +1078,7 @@ parser! {
         rule GroupGraphPatternSub() -> GraphPattern = a:TriplesBlock()? _ b:GroupGraphPatternSub_item()* {
             let mut p = a.map_or_else(Vec::default, |v| vec![PartialGraphPattern::Other(GraphPattern::BGP(v))]);
             for v in b {
-                p.extend_from_slice(&v)
+                p.extend(v)
             }
             let mut filter: Option<Expression> = None;
             let mut g = GraphPattern::default();
@@ -0,0 +1,50 @@
+#ifndef SPARQL2RIFY_H
+#define SPARQL2RIFY_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum Sparql2rifyStatus {
+  SPARQL2RIFY_STATUS_OK = 0,
+  /**
+   * the query was a null pointer or was not valid utf-8
+   */
+  SPARQL2RIFY_STATUS_INVALID_INPUT,
+  /**
+   * the query is not valid SPARQL
+   */
+  SPARQL2RIFY_STATUS_PARSE_ERROR,
+  /**
+   * the query is valid SPARQL but has no rify equivalent
+   */
+  SPARQL2RIFY_STATUS_INVALID_RULE,
+} Sparql2rifyStatus;
+
+/**
+ * Convert a nul terminated SPARQL CONSTRUCT query to a rify rule.
+ *
+ * On success `*out_json` is set to the rule as a nul terminated JSON string. Otherwise
+ * `*out_err` is set to a nul terminated description of the problem. Either string must be freed
+ * with `sparql2rify_free`. Lints are not reported.
+ *
+ * # Safety
+ *
+ * `sparql` must be null or point to a nul terminated string. `out_json` and `out_err` must be
+ * valid for writes.
+ */
+enum Sparql2rifyStatus sparql2rify_convert(const char *sparql, char **out_json, char **out_err);
+
+/**
+ * Free a string returned by this library. Passing null does nothing.
+ *
+ * # Safety
+ *
+ * `s` must be null or a string returned by this library that has not already been freed.
+ */
+void sparql2rify_free(char *s);
+
+#endif /* SPARQL2RIFY_H */
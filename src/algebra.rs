@@ -0,0 +1,192 @@
+//! Handling of SPARQL algebra without recursion. Machine generated queries can nest unions and
+//! joins tens of thousands deep, which overflows the stack of a recursive walker. Even dropping
+//! such a pattern normally recurses once per level.
+
+use oxigraph::sparql::algebra::{GraphPattern, GroupPattern, TripleOrPathPattern};
+use std::mem;
+use std::ops::Deref;
+use std::rc::Rc;
+
+/// the graph patterns directly nested within `pattern`
+pub fn children(pattern: &GraphPattern) -> Vec<&GraphPattern> {
+    match pattern {
+        GraphPattern::BGP(_) | GraphPattern::Data(_) => vec![],
+        GraphPattern::Join(a, b)
+        | GraphPattern::LeftJoin(a, b, _)
+        | GraphPattern::Union(a, b)
+        | GraphPattern::Minus(a, b) => vec![a, b],
+        GraphPattern::Filter(_, p)
+        | GraphPattern::Graph(_, p)
+        | GraphPattern::Extend(p, _, _)
+        | GraphPattern::Service(_, p, _)
+        | GraphPattern::AggregateJoin(GroupPattern(_, p), _)
+        | GraphPattern::OrderBy(p, _)
+        | GraphPattern::Project(p, _)
+        | GraphPattern::Distinct(p)
+        | GraphPattern::Reduced(p)
+        | GraphPattern::Slice(p, _, _) => vec![p],
+    }
+}
+
+fn children_mut(pattern: &mut GraphPattern) -> Vec<&mut GraphPattern> {
+    match pattern {
+        GraphPattern::BGP(_) | GraphPattern::Data(_) => vec![],
+        GraphPattern::Join(a, b)
+        | GraphPattern::LeftJoin(a, b, _)
+        | GraphPattern::Union(a, b)
+        | GraphPattern::Minus(a, b) => vec![a, b],
+        GraphPattern::Filter(_, p)
+        | GraphPattern::Graph(_, p)
+        | GraphPattern::Extend(p, _, _)
+        | GraphPattern::Service(_, p, _)
+        | GraphPattern::AggregateJoin(GroupPattern(_, p), _)
+        | GraphPattern::OrderBy(p, _)
+        | GraphPattern::Project(p, _)
+        | GraphPattern::Distinct(p)
+        | GraphPattern::Reduced(p)
+        | GraphPattern::Slice(p, _, _) => vec![p],
+    }
+}
+
+/// Visit `root` and every pattern nested in it, parents before children and left before right.
+pub fn walk(root: &GraphPattern) -> Walk<'_> {
+    Walk { stack: vec![root] }
+}
+
+pub struct Walk<'a> {
+    stack: Vec<&'a GraphPattern>,
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = &'a GraphPattern;
+
+    fn next(&mut self) -> Option<&'a GraphPattern> {
+        let next = self.stack.pop()?;
+        self.stack.extend(children(next).into_iter().rev());
+        Some(next)
+    }
+}
+
+/// The triples of a pattern made only of basic graph patterns and joins of them, as a group
+/// written with extra braces, `{ { ?a ?b ?c } { ?c ?d ?e } }`, parses to, in the order they were
+/// written. None for any other pattern.
+pub fn flatten(pattern: &GraphPattern) -> Option<Vec<&TripleOrPathPattern>> {
+    let mut ret = Vec::new();
+    for pattern in walk(pattern) {
+        match pattern {
+            GraphPattern::Join(..) => {}
+            GraphPattern::BGP(bgp) => ret.extend(bgp),
+            _ => return None,
+        }
+    }
+    Some(ret)
+}
+
+/// Drop a graph pattern one level at a time. Each pattern has its children swapped out for
+/// empty leaves before it is dropped, so no drop ever recurses.
+pub fn dismantle(pattern: GraphPattern) {
+    let mut stack = vec![pattern];
+    while let Some(mut next) = stack.pop() {
+        for child in children_mut(&mut next) {
+            stack.push(mem::replace(child, GraphPattern::BGP(Vec::new())));
+        }
+    }
+}
+
+/// A shared graph pattern that is dismantled rather than recursively dropped, if this is the last
+/// reference to it.
+pub struct Dismantling(Rc<GraphPattern>);
+
+impl Dismantling {
+    pub fn new(pattern: Rc<GraphPattern>) -> Self {
+        Self(pattern)
+    }
+}
+
+impl Deref for Dismantling {
+    type Target = GraphPattern;
+
+    fn deref(&self) -> &GraphPattern {
+        &self.0
+    }
+}
+
+impl Drop for Dismantling {
+    fn drop(&mut self) {
+        let pattern = mem::replace(&mut self.0, Rc::new(GraphPattern::BGP(Vec::new())));
+        if let Ok(pattern) = Rc::try_unwrap(pattern) {
+            dismantle(pattern);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// far deeper than a recursive walk or drop survives on a test thread's stack
+    const DEPTH: usize = 1_000_000;
+
+    fn deep_union() -> GraphPattern {
+        (0..DEPTH).fold(GraphPattern::BGP(Vec::new()), |acc, _| {
+            GraphPattern::Union(Box::new(acc), Box::new(GraphPattern::BGP(Vec::new())))
+        })
+    }
+
+    #[test]
+    fn walk_deep() {
+        let pattern = deep_union();
+        assert_eq!(walk(&pattern).count(), 2 * DEPTH + 1);
+        dismantle(pattern);
+    }
+
+    #[test]
+    fn drop_deep() {
+        drop(Dismantling::new(Rc::new(deep_union())));
+    }
+
+    #[test]
+    fn flatten_joins() {
+        let query = "CONSTRUCT {} WHERE { { ?a ?b ?c { ?c ?d ?e } } ?e ?f ?g . { } }";
+        let query: oxigraph::sparql::algebra::Query = query.parse().unwrap();
+        let pattern = match &query.0 {
+            oxigraph::sparql::algebra::QueryVariants::Construct { algebra, .. } => match &**algebra
+            {
+                GraphPattern::Project(pattern, _) => pattern,
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        let triples: Vec<String> = flatten(pattern)
+            .unwrap()
+            .iter()
+            .map(|triple| triple.to_string())
+            .collect();
+        assert_eq!(triples, ["?a ?b ?c .", "?c ?d ?e .", "?e ?f ?g ."]);
+        let leaf = || Box::new(GraphPattern::BGP(Vec::new()));
+        assert!(flatten(&GraphPattern::Join(
+            leaf(),
+            Box::new(GraphPattern::Union(leaf(), leaf()))
+        ))
+        .is_none());
+    }
+
+    #[test]
+    fn walk_order() {
+        let leaf = || Box::new(GraphPattern::BGP(Vec::new()));
+        let pattern = GraphPattern::Join(
+            Box::new(GraphPattern::Distinct(leaf())),
+            Box::new(GraphPattern::Union(leaf(), leaf())),
+        );
+        let names: Vec<_> = walk(&pattern)
+            .map(|p| match p {
+                GraphPattern::Join(..) => "join",
+                GraphPattern::Distinct(..) => "distinct",
+                GraphPattern::Union(..) => "union",
+                GraphPattern::BGP(..) => "bgp",
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(names, ["join", "distinct", "bgp", "union", "bgp", "bgp"]);
+    }
+}
@@ -0,0 +1,89 @@
+//! Several queries in one input. Queries are separated by a `;` outside any braces, as
+//! operations are in SPARQL Update, or by a blank line between the end of one query and the
+//! start of the next. Blank lines anywhere else, e.g. between the prefixes and the body of a
+//! query, don't separate anything.
+
+use crate::n3::{skip_space, skip_token};
+
+/// keywords a query can start with, in any case
+const STARTS: &[&str] = &["PREFIX", "BASE", "CONSTRUCT", "SELECT", "ASK", "DESCRIBE"];
+
+/// The queries in `text`, in order, with empty ones left out. Text that doesn't lex, such as
+/// an unterminated string, ends up in the last query for the parser to report.
+pub fn split(text: &str) -> Vec<&str> {
+    let mut ret = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    // whether the current query has a closed group, so it can end at a blank line
+    let mut closed = false;
+    let mut i = 0;
+    while i < text.len() {
+        let end = match text.as_bytes()[i] {
+            b'{' => {
+                depth += 1;
+                false
+            }
+            b'}' => {
+                depth -= 1;
+                closed |= depth == 0;
+                false
+            }
+            b';' => depth == 0,
+            b'\n' => depth == 0 && closed && blank_line_then_query(&text[i + 1..]),
+            _ => false,
+        };
+        if end {
+            ret.push(&text[start..i]);
+            start = i + 1;
+            closed = false;
+        }
+        i = skip_token(text, i).unwrap_or(text.len());
+    }
+    ret.push(&text[start..]);
+    ret.retain(|query| !skip_space(query).is_empty());
+    ret
+}
+
+/// whether `text` starts with a blank line and the next thing in it is the start of a query
+fn blank_line_then_query(text: &str) -> bool {
+    let blank = match text.find('\n') {
+        Some(end) => text[..end].trim().is_empty(),
+        None => false,
+    };
+    let next = skip_space(text);
+    blank
+        && STARTS.iter().any(|keyword| {
+            next.get(..keyword.len())
+                .is_some_and(|word| word.eq_ignore_ascii_case(keyword))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn separators() {
+        let text = "PREFIX ex: <http://example.com/a;b#>
+
+CONSTRUCT { ?a ex:p ?b ; ex:q \"};\" . }
+
+WHERE { ?a ex:r ?b . } ;
+construct { ?a ex:s ?b . } where { ?a ex:t ?b . }
+
+# the last one
+
+CONSTRUCT { ?a ex:u ?b . } WHERE { ?a ex:v ?b . FILTER (?b < 3) }
+;
+";
+        let queries = split(text);
+        assert_eq!(queries.len(), 3);
+        assert!(queries[0].starts_with("PREFIX") && queries[0].ends_with("?b . } "));
+        assert_eq!(
+            queries[1].trim(),
+            "construct { ?a ex:s ?b . } where { ?a ex:t ?b . }"
+        );
+        assert!(queries[2].trim().starts_with("# the last one"));
+        assert_eq!(split(" ; \n"), Vec::<&str>::new());
+    }
+}
@@ -0,0 +1,780 @@
+//! Rewriting literals, and optionally IRIs, to canonical forms. rify matches bound nodes by
+//! string equality, so a rule mentioning `"05"^^xsd:integer` never matches data from a store that wrote
+//! the same number as `"5"`.
+
+use crate::types::{RdfNode, RuleParts, RDF_LANG_STRING, XSD_STRING};
+use oxilangtag::LanguageTag;
+use rify::Entity;
+use unicode_normalization::UnicodeNormalization;
+
+pub(crate) const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// xsd:integer and the types derived from it, which all share its lexical space
+pub(crate) const INTEGER_TYPES: &[&str] = &[
+    "integer",
+    "nonPositiveInteger",
+    "negativeInteger",
+    "long",
+    "int",
+    "short",
+    "byte",
+    "nonNegativeInteger",
+    "unsignedLong",
+    "unsignedInt",
+    "unsignedShort",
+    "unsignedByte",
+    "positiveInteger",
+];
+
+/// Which kinds of literal to canonicalize.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Canonicalize {
+    /// xsd:integer and its subtypes, xsd:decimal, xsd:float and xsd:double
+    pub numbers: bool,
+    /// xsd:dateTime and xsd:dateTimeStamp
+    pub date_times: bool,
+    /// also shift date times with a timezone to UTC, only applies along with `date_times`
+    pub utc: bool,
+    /// Use BCP 47 case conventions for language tags, `en-US` rather than the `en-us` the parser
+    /// produces.
+    pub language_tags: bool,
+    /// Unicode NFC for IRIs and the values of literals, so visually identical strings from
+    /// different sources are equal.
+    pub nfc: bool,
+    /// Lowercase IRI schemes and hosts, uppercase percent-encodings and remove dot segments.
+    pub iris: bool,
+}
+
+impl Canonicalize {
+    /// Rewrite every literal in `rule`, returning how many terms changed. Literals that are not
+    /// valid for their datatype are left alone.
+    pub fn rule(&self, rule: &mut RuleParts) -> usize {
+        let mut changed = 0;
+        for ent in rule.if_all.iter_mut().chain(&mut rule.then).flatten() {
+            let mut rewritten = false;
+            if self.nfc {
+                match ent {
+                    Entity::Bound(RdfNode::Iri(iri)) => rewritten |= nfc(iri),
+                    Entity::Bound(RdfNode::Literal {
+                        value, datatype, ..
+                    }) => {
+                        rewritten |= nfc(value);
+                        if let Some(datatype) = datatype {
+                            rewritten |= nfc(datatype);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            if self.iris {
+                match ent {
+                    Entity::Bound(RdfNode::Iri(iri)) => {
+                        rewritten |= replace(iri, |iri| Some(normalize_iri(iri)))
+                    }
+                    Entity::Bound(RdfNode::Literal {
+                        datatype: Some(datatype),
+                        ..
+                    }) => rewritten |= replace(datatype, |iri| Some(normalize_iri(iri))),
+                    _ => {}
+                }
+            }
+            if let Entity::Bound(RdfNode::Literal {
+                value,
+                datatype,
+                language,
+            }) = ent
+            {
+                if let Some(datatype) = datatype {
+                    rewritten |= replace(value, |value| self.literal(value, datatype));
+                }
+                if let Some(tag) = language.as_mut().filter(|_| self.language_tags) {
+                    rewritten |= replace(tag, |tag| {
+                        let normalized = LanguageTag::parse_and_normalize(tag).ok()?;
+                        Some(normalized.into_inner())
+                    });
+                }
+            }
+            changed += usize::from(rewritten);
+        }
+        changed
+    }
+
+    /// the canonical form of a literal, if it is of a kind selected by `self` and valid
+    pub(crate) fn literal(&self, value: &str, datatype: &str) -> Option<String> {
+        let local = datatype.strip_prefix(XSD)?;
+        match local {
+            "dateTime" | "dateTimeStamp" if self.date_times => date_time(value, self.utc),
+            _ if !self.numbers => None,
+            "decimal" => decimal(value),
+            "float" => float(value, |v| v.parse::<f32>().map(|f| format!("{:e}", f))),
+            "double" => float(value, |v| v.parse::<f64>().map(|f| format!("{:e}", f))),
+            _ if INTEGER_TYPES.contains(&local) => integer(value),
+            _ => None,
+        }
+    }
+}
+
+/// Whether literals whose datatype follows from their form carry it explicitly. Data from some
+/// sources writes `"a"` rather than `"a"^^xsd:string`, rules only match it if they do the same.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DatatypePolicy {
+    /// leave out xsd:string on simple literals
+    pub omit_string: bool,
+    /// leave out rdf:langString on language tagged literals
+    pub omit_lang_string: bool,
+}
+
+impl DatatypePolicy {
+    /// Add or leave out the datatypes of literals in `rule`, returning how many changed.
+    pub fn rule(&self, rule: &mut RuleParts) -> usize {
+        let mut changed = 0;
+        for ent in rule.if_all.iter_mut().chain(&mut rule.then).flatten() {
+            if let Entity::Bound(node) = ent {
+                changed += self.node(node) as usize;
+            }
+        }
+        changed
+    }
+
+    /// Add or leave out the datatype of `node` if it is a literal, returning whether it changed.
+    pub fn node(&self, node: &mut RdfNode) -> bool {
+        if let RdfNode::Literal {
+            datatype, language, ..
+        } = node
+        {
+            let (implicit, omit) = match language {
+                None => (XSD_STRING, self.omit_string),
+                Some(_) => (RDF_LANG_STRING, self.omit_lang_string),
+            };
+            match datatype {
+                Some(explicit) if omit && explicit == implicit => *datatype = None,
+                None if !omit => *datatype = Some(implicit.to_string()),
+                _ => return false,
+            }
+            return true;
+        }
+        false
+    }
+}
+
+fn nfc(s: &mut String) -> bool {
+    // most strings are ascii, which is always in NFC
+    replace(s, |s| {
+        if s.is_ascii() {
+            None
+        } else {
+            Some(s.nfc().collect())
+        }
+    })
+}
+
+/// Set `s` to what `rewrite` gives for it if anything, returning whether that changed it. Only
+/// the rewritten string is allocated, so a long literal that is left as it is isn't copied.
+fn replace(s: &mut String, rewrite: impl FnOnce(&str) -> Option<String>) -> bool {
+    match rewrite(s) {
+        Some(rewritten) if rewritten != *s => {
+            *s = rewritten;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The syntax based normalizations from RFC 3986 section 6.2.2 that never change which resource
+/// an IRI identifies, e.g. `HTTP://Example.COM/a/./b/../c%2f` becomes `http://example.com/a/c%2F`.
+fn normalize_iri(iri: &str) -> String {
+    let colon = match iri.find(':') {
+        Some(colon) => colon,
+        None => return percent_upper(iri),
+    };
+    let (scheme, rest) = (&iri[..colon], &iri[colon + 1..]);
+    let mut ret = scheme.to_ascii_lowercase();
+    ret.push(':');
+
+    let (hier, suffix) = rest.split_at(rest.find(['?', '#']).unwrap_or(rest.len()));
+    let path = match hier.strip_prefix("//") {
+        Some(authority_path) => {
+            let (authority, path) =
+                authority_path.split_at(authority_path.find('/').unwrap_or(authority_path.len()));
+            ret.push_str("//");
+            ret.push_str(&authority_lower_host(authority));
+            path
+        }
+        None => hier,
+    };
+    if path.starts_with('/') {
+        ret.push_str(&remove_dot_segments(path));
+    } else {
+        ret.push_str(path);
+    }
+    ret.push_str(suffix);
+    percent_upper(&ret)
+}
+
+/// lowercase the host in `userinfo@host:port`
+fn authority_lower_host(authority: &str) -> String {
+    let host_start = authority.rfind('@').map(|at| at + 1).unwrap_or(0);
+    let (userinfo, host_port) = authority.split_at(host_start);
+    // the port is after the last colon, unless that colon is inside an IPv6 literal
+    let port_start = match host_port.rfind(':') {
+        Some(colon) if !host_port[colon..].contains(']') => colon,
+        _ => host_port.len(),
+    };
+    let (host, port) = host_port.split_at(port_start);
+    format!("{}{}{}", userinfo, host.to_lowercase(), port)
+}
+
+fn percent_upper(iri: &str) -> String {
+    let mut ret = String::with_capacity(iri.len());
+    let mut rest = iri;
+    while let Some(pct) = rest.find('%') {
+        ret.push_str(&rest[..pct]);
+        let escape = rest[pct..]
+            .get(..3)
+            .filter(|e| e.bytes().skip(1).all(|b| b.is_ascii_hexdigit()));
+        match escape {
+            Some(escape) => {
+                ret.push_str(&escape.to_ascii_uppercase());
+                rest = &rest[pct + 3..];
+            }
+            None => {
+                ret.push('%');
+                rest = &rest[pct + 1..];
+            }
+        }
+    }
+    ret.push_str(rest);
+    ret
+}
+
+/// RFC 3986 section 5.2.4
+fn remove_dot_segments(path: &str) -> String {
+    let mut input = path.to_string();
+    let mut output = String::with_capacity(path.len());
+    while !input.is_empty() {
+        if input.starts_with("../") {
+            input.drain(..3);
+        } else if input.starts_with("./") || input.starts_with("/./") {
+            input.drain(..2);
+        } else if input == "/." {
+            input = "/".to_string();
+        } else if input.starts_with("/../") || input == "/.." {
+            input.replace_range(..3, "");
+            if input.is_empty() {
+                input.push('/');
+            }
+            output.truncate(output.rfind('/').unwrap_or(0));
+        } else if input == "." || input == ".." {
+            input.clear();
+        } else {
+            let end = input[1..].find('/').map(|i| i + 1).unwrap_or(input.len());
+            output.extend(input.drain(..end));
+        }
+    }
+    output
+}
+
+/// split off an optional sign, returning whether it was negative
+fn sign(value: &str) -> (bool, &str) {
+    match value.as_bytes().first() {
+        Some(b'-') => (true, &value[1..]),
+        Some(b'+') => (false, &value[1..]),
+        _ => (false, value),
+    }
+}
+
+fn is_digits(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// `-0012` becomes `-12`, `+0` becomes `0`
+pub(crate) fn integer(value: &str) -> Option<String> {
+    let (negative, digits) = sign(value.trim());
+    if digits.is_empty() || !is_digits(digits) {
+        return None;
+    }
+    let digits = digits.trim_start_matches('0');
+    Some(match (negative, digits) {
+        (_, "") => "0".to_string(),
+        (true, _) => format!("-{}", digits),
+        (false, _) => digits.to_string(),
+    })
+}
+
+/// XSD 1.1 canonical decimals, `+05.10` becomes `5.1` and `5.0` becomes `5`
+pub(crate) fn decimal(value: &str) -> Option<String> {
+    let (negative, unsigned) = sign(value.trim());
+    let (int, frac) = match unsigned.find('.') {
+        Some(dot) => (&unsigned[..dot], &unsigned[dot + 1..]),
+        None => (unsigned, ""),
+    };
+    if (int.is_empty() && frac.is_empty()) || !is_digits(int) || !is_digits(frac) {
+        return None;
+    }
+    let int = match int.trim_start_matches('0') {
+        "" => "0",
+        int => int,
+    };
+    let frac = frac.trim_end_matches('0');
+    let negative = negative && (int, frac) != ("0", "");
+    Some(format!(
+        "{}{}{}{}",
+        if negative { "-" } else { "" },
+        int,
+        if frac.is_empty() { "" } else { "." },
+        frac
+    ))
+}
+
+/// XSD 1.1 canonical floats and doubles, the shortest mantissa that round trips with exactly one
+/// digit before the point, e.g. `1.25E2`. `to_exp` formats a parsed value with `{:e}`.
+fn float<E>(value: &str, to_exp: impl Fn(&str) -> Result<String, E>) -> Option<String> {
+    let value = value.trim();
+    match value {
+        "INF" | "+INF" => return Some("INF".to_string()),
+        "-INF" => return Some("-INF".to_string()),
+        "NaN" => return Some("NaN".to_string()),
+        _ => {}
+    }
+    // rust accepts spellings xsd does not, like "inf"
+    let lexical = value.bytes().any(|b| b.is_ascii_digit())
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b));
+    if !lexical {
+        return None;
+    }
+    let exp = to_exp(value).ok()?;
+    let (mantissa, exponent) = exp.split_at(exp.find('e')?);
+    let point = if mantissa.contains('.') { "" } else { ".0" };
+    Some(format!("{}{}E{}", mantissa, point, &exponent[1..]))
+}
+
+/// the canonical form of an xsd:dateTime, optionally shifted to UTC if it has a timezone
+pub(crate) fn date_time(value: &str, utc: bool) -> Option<String> {
+    let mut date_time = DateTime::parse(value)?;
+    if utc {
+        date_time.shift_to_utc()?;
+    }
+    Some(date_time.to_string())
+}
+
+/// An xsd:dateTime. Minutes are counted from midnight on 0000-03-01 to make date arithmetic
+/// simple, see `days_from_civil`. XSD puts no limit on the year, dates too far from it to count
+/// in an `i64` are left as they are.
+#[derive(Debug, PartialEq)]
+struct DateTime {
+    minutes: i64,
+    second: u8,
+    /// fractional seconds without trailing zeros
+    fraction: String,
+    /// offset from UTC in minutes
+    timezone: Option<i64>,
+}
+
+impl DateTime {
+    /// Parse the lexical form, also accepting single digit months, days, hours, minutes and
+    /// seconds, which some tools emit.
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (date, time) = value.split_at(value.find('T')?);
+        let time = &time[1..];
+
+        if date.starts_with('+') {
+            return None;
+        }
+        let (negative, date) = sign(date);
+        let mut date = date.split('-');
+        let (year, month, day) = (date.next()?, date.next()?, date.next()?);
+        if date.next().is_some() || year.len() < 4 {
+            return None;
+        }
+        let year: i64 = field(year, usize::MAX)?.parse().ok()?;
+        let year = if negative { -year } else { year };
+        let month: u8 = field(month, 2)?.parse().ok()?;
+        let day: u8 = field(day, 2)?.parse().ok()?;
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+
+        let tz_start = time.find(['Z', '+', '-']);
+        let (time, timezone) = match tz_start {
+            Some(i) => (&time[..i], Some(timezone(&time[i..])?)),
+            None => (time, None),
+        };
+        let mut time = time.split(':');
+        let (hour, minute, second) = (time.next()?, time.next()?, time.next()?);
+        if time.next().is_some() {
+            return None;
+        }
+        let (second, fraction) = match second.find('.') {
+            Some(dot) => (&second[..dot], &second[dot + 1..]),
+            None => (second, ""),
+        };
+        if !is_digits(fraction) {
+            return None;
+        }
+        let fraction = fraction.trim_end_matches('0').to_string();
+        let hour: i64 = field(hour, 2)?.parse().ok()?;
+        let minute: i64 = field(minute, 2)?.parse().ok()?;
+        let second: u8 = field(second, 2)?.parse().ok()?;
+        let midnight = hour == 24 && minute == 0 && second == 0 && fraction.is_empty();
+        if hour > 23 && !midnight || minute > 59 || second > 59 {
+            return None;
+        }
+
+        let minutes = days_from_civil(year, month, day)?
+            .checked_mul(24 * 60)?
+            .checked_add(hour * 60 + minute)?;
+        Some(Self {
+            minutes,
+            second,
+            fraction,
+            timezone,
+        })
+    }
+
+    /// `None` if the time in UTC can't be counted
+    fn shift_to_utc(&mut self) -> Option<()> {
+        if let Some(offset) = self.timezone {
+            self.minutes = self.minutes.checked_sub(offset)?;
+            self.timezone = Some(0);
+        }
+        Some(())
+    }
+}
+
+/// `s` if it is between 1 and `max` digits long
+fn field(s: &str, max: usize) -> Option<&str> {
+    if s.is_empty() || s.len() > max || !is_digits(s) {
+        return None;
+    }
+    Some(s)
+}
+
+/// parse `Z`, `+hh:mm` or `-hh:mm` to an offset in minutes
+fn timezone(tz: &str) -> Option<i64> {
+    if tz == "Z" {
+        return Some(0);
+    }
+    let (negative, tz) = sign(tz);
+    let (hours, minutes) = tz.split_at(tz.find(':')?);
+    let hours: i64 = field(hours, 2)?.parse().ok()?;
+    let minutes: i64 = field(&minutes[1..], 2)?.parse().ok()?;
+    let offset = hours * 60 + minutes;
+    if minutes > 59 || offset > 14 * 60 {
+        return None;
+    }
+    Some(if negative { -offset } else { offset })
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 0000-03-01 in the proleptic Gregorian calendar, which like XSD 1.1 has a year
+/// zero. Starting the year in March puts the leap day at the end of it. This is Howard
+/// Hinnant's `days_from_civil`, `None` if the days don't fit in an `i64`.
+fn days_from_civil(year: i64, month: u8, day: u8) -> Option<i64> {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era.checked_mul(146_097)?.checked_add(day_of_era)
+}
+
+/// the inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// the canonical form, `-0001-02-03T04:05:06.7Z`
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (year, month, day) = civil_from_days(self.minutes.div_euclid(24 * 60));
+        let minute_of_day = self.minutes.rem_euclid(24 * 60);
+        if year < 0 {
+            write!(f, "-")?;
+        }
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year.abs(),
+            month,
+            day,
+            minute_of_day / 60,
+            minute_of_day % 60,
+            self.second
+        )?;
+        if !self.fraction.is_empty() {
+            write!(f, ".{}", self.fraction)?;
+        }
+        match self.timezone {
+            None => Ok(()),
+            Some(0) => write!(f, "Z"),
+            Some(offset) => write!(
+                f,
+                "{}{:02}:{:02}",
+                if offset < 0 { "-" } else { "+" },
+                offset.abs() / 60,
+                offset.abs() % 60
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn canonical(value: &str, datatype: &str) -> Option<String> {
+        let numbers = Canonicalize {
+            numbers: true,
+            ..Canonicalize::default()
+        };
+        numbers.literal(value, &format!("{}{}", XSD, datatype))
+    }
+
+    #[test]
+    fn numbers() {
+        let cases = [
+            ("+05", "integer", Some("5")),
+            ("-0012", "long", Some("-12")),
+            ("-0", "integer", Some("0")),
+            ("5.0", "integer", None),
+            ("+05.10", "decimal", Some("5.1")),
+            ("5.0", "decimal", Some("5")),
+            (".5", "decimal", Some("0.5")),
+            ("-0.0", "decimal", Some("0")),
+            ("5.", "decimal", Some("5")),
+            (".", "decimal", None),
+            ("125", "double", Some("1.25E2")),
+            ("1e0", "double", Some("1.0E0")),
+            ("-0", "double", Some("-0.0E0")),
+            ("0.1", "float", Some("1.0E-1")),
+            ("+INF", "float", Some("INF")),
+            ("inf", "double", None),
+            ("5", "string", None),
+        ];
+        for (value, datatype, expected) in &cases {
+            assert_eq!(
+                canonical(value, datatype).as_deref(),
+                *expected,
+                "{}^^xsd:{}",
+                value,
+                datatype
+            );
+        }
+    }
+
+    #[test]
+    fn off_by_default() {
+        let integer = format!("{}integer", XSD);
+        assert_eq!(Canonicalize::default().literal("+05", &integer), None);
+    }
+
+    #[test]
+    fn date_times() {
+        let local = Canonicalize {
+            date_times: true,
+            ..Canonicalize::default()
+        };
+        let utc = Canonicalize { utc: true, ..local };
+        let date_time = format!("{}dateTime", XSD);
+        let cases = [
+            ("2020-01-02T03:04:05", Some("2020-01-02T03:04:05"), None),
+            ("2020-1-2T3:4:5.500", Some("2020-01-02T03:04:05.5"), None),
+            (
+                "2020-01-02T03:04:05.0+00:00",
+                Some("2020-01-02T03:04:05Z"),
+                None,
+            ),
+            ("2020-12-31T24:00:00", Some("2021-01-01T00:00:00"), None),
+            (
+                "2020-03-01T01:30:00+02:00",
+                Some("2020-03-01T01:30:00+02:00"),
+                Some("2020-02-29T23:30:00Z"),
+            ),
+            (
+                "2019-12-31T23:00:00-01:30",
+                Some("2019-12-31T23:00:00-01:30"),
+                Some("2020-01-01T00:30:00Z"),
+            ),
+            ("-0001-01-01T00:00:00", Some("-0001-01-01T00:00:00"), None),
+            ("2019-02-29T00:00:00", None, None),
+            ("2020-01-02", None, None),
+            ("2020-01-02T03:04:60", None, None),
+            ("2020-01-02T03:04:05+15:00", None, None),
+            // valid, but too far off to count the minutes of
+            ("20000000000000-01-01T00:00:00", None, None),
+            ("-100000000000000000-01-01T00:00:00", None, None),
+        ];
+        for (value, canonical, in_utc) in &cases {
+            assert_eq!(local.literal(value, &date_time).as_deref(), *canonical);
+            let in_utc = in_utc.or(*canonical);
+            assert_eq!(utc.literal(value, &date_time).as_deref(), in_utc);
+        }
+        assert_eq!(
+            Canonicalize::default().literal("2020-1-2T3:4:5", &date_time),
+            None
+        );
+    }
+
+    #[test]
+    fn language_tags() {
+        let literal = |language: &str| {
+            Entity::Bound(RdfNode::Literal {
+                value: "colour".to_string(),
+                datatype: Some(RDF_LANG_STRING.to_string()),
+                language: Some(language.to_string()),
+            })
+        };
+        let mut rule = RuleParts {
+            if_all: vec![[
+                literal("en-gb"),
+                literal("zh-hant-tw"),
+                literal("not a tag"),
+            ]],
+            then: vec![],
+        };
+        let tags = Canonicalize {
+            language_tags: true,
+            ..Canonicalize::default()
+        };
+        tags.rule(&mut rule);
+        assert_eq!(
+            rule.if_all,
+            vec![[
+                literal("en-GB"),
+                literal("zh-Hant-TW"),
+                literal("not a tag")
+            ]]
+        );
+    }
+
+    #[test]
+    fn nfc() {
+        let decomposed = "http://example.com/cafe\u{301}";
+        let composed = "http://example.com/caf\u{e9}";
+        let mut rule = RuleParts {
+            if_all: vec![[
+                Entity::Unbound("cafe\u{301}".to_string()),
+                Entity::Bound(RdfNode::Iri(decomposed.to_string())),
+                Entity::Bound(RdfNode::Literal {
+                    value: "cafe\u{301}".to_string(),
+                    datatype: Some(decomposed.to_string()),
+                    language: None,
+                }),
+            ]],
+            then: vec![],
+        };
+        let nfc = Canonicalize {
+            nfc: true,
+            ..Canonicalize::default()
+        };
+        nfc.rule(&mut rule);
+        assert_eq!(
+            rule.if_all,
+            vec![[
+                Entity::Unbound("cafe\u{301}".to_string()),
+                Entity::Bound(RdfNode::Iri(composed.to_string())),
+                Entity::Bound(RdfNode::Literal {
+                    value: "caf\u{e9}".to_string(),
+                    datatype: Some(composed.to_string()),
+                    language: None,
+                }),
+            ]]
+        );
+    }
+
+    #[test]
+    fn iris() {
+        let cases = [
+            (
+                "HTTP://Example.COM/a/./b/../c%2f?Q=%7e#F",
+                "http://example.com/a/c%2F?Q=%7E#F",
+            ),
+            ("http://User@EXAMPLE.com:80/", "http://User@example.com:80/"),
+            ("http://[::A]:8080/x/..", "http://[::a]:8080/"),
+            ("http://example.com/../../a/..", "http://example.com/"),
+            ("http://example.com/a/b/./../", "http://example.com/a/"),
+            ("URN:ISBN:0-486-27557-4", "urn:ISBN:0-486-27557-4"),
+            ("urn:x:a/../b", "urn:x:a/../b"),
+            ("http://example.com/100%", "http://example.com/100%"),
+        ];
+        for (iri, normal) in &cases {
+            assert_eq!(normalize_iri(iri), *normal, "{}", iri);
+        }
+    }
+
+    #[test]
+    fn datatype_policy() {
+        let literal = |datatype: Option<&str>, language: Option<&str>| {
+            Entity::Bound(RdfNode::Literal {
+                value: "a".to_string(),
+                datatype: datatype.map(str::to_string),
+                language: language.map(str::to_string),
+            })
+        };
+        let integer = format!("{}integer", XSD);
+        let explicit = RuleParts {
+            if_all: vec![[
+                literal(Some(XSD_STRING), None),
+                literal(Some(RDF_LANG_STRING), Some("en")),
+                literal(Some(&integer), None),
+            ]],
+            then: vec![],
+        };
+        let mut rule = explicit.clone();
+
+        let omit = DatatypePolicy {
+            omit_string: true,
+            omit_lang_string: true,
+        };
+        omit.rule(&mut rule);
+        assert_eq!(
+            rule.if_all,
+            vec![[
+                literal(None, None),
+                literal(None, Some("en")),
+                literal(Some(&integer), None),
+            ]]
+        );
+        assert_eq!(
+            serde_json::to_value(&rule.if_all[0][1]).unwrap(),
+            serde_json::json!({"Bound": {"Literal": {"value": "a", "language": "en"}}})
+        );
+
+        DatatypePolicy::default().rule(&mut rule);
+        assert_eq!(rule, explicit);
+    }
+
+    #[test]
+    fn calendar_round_trip() {
+        for days in -800_000..800_000 {
+            let (year, month, day) = civil_from_days(days);
+            assert!(day >= 1 && day <= days_in_month(year, month));
+            assert_eq!(days_from_civil(year, month, day), Some(days));
+        }
+    }
+}
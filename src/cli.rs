@@ -0,0 +1,708 @@
+use rify::Entity;
+use sparql2rify::canon::{Canonicalize, DatatypePolicy};
+use sparql2rify::dock::DefaultGraph;
+use sparql2rify::endpoint::Seen;
+use sparql2rify::lint::{Level, Levels, LevelsConfig};
+use sparql2rify::output::Format;
+use sparql2rify::partition::By;
+use sparql2rify::schema::Lift;
+use sparql2rify::tags::{self, Selection};
+use sparql2rify::types::RdfNode;
+use sparql2rify::validity;
+use sparql2rify::vocab::Policy;
+use sparql2rify::Graph;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Options controlling a conversion.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub command: Command,
+    pub levels: Levels,
+    pub canonicalize: Canonicalize,
+    pub datatypes: DatatypePolicy,
+    /// if given, literals with other datatypes trigger the unlisted_datatype lint
+    pub allowed_datatypes: Option<BTreeSet<String>>,
+    /// namespaces rules may and may not use
+    pub namespaces: Policy,
+    /// if given, conditions using other predicates and classes trigger the unseen_term lint
+    pub endpoint: Option<Seen>,
+    /// resolve relative IRIs in queries against this
+    pub base: Option<String>,
+    /// accept FROM and FROM NAMED, ignoring them or, with quads, scoping claims to their graphs
+    pub allow_from: bool,
+    /// rename variables, writing their original names here
+    pub rename_vars: Option<PathBuf>,
+    pub format: Format,
+    /// keep the conditions of rules in the order they were written
+    pub preserve_order: bool,
+    /// merge rules with the same conditions when converting files into a ruleset
+    pub merge_rules: bool,
+    /// keep conditions that are implied by the other conditions of a rule
+    pub keep_redundant: bool,
+    /// convert a query with OPTIONAL to a rule with the optional triples and one without
+    pub split_optional: bool,
+    /// convert to rules over quads rather than triples
+    pub quads: bool,
+    /// convert FILTER NOT EXISTS and MINUS to negative conditions, writing the extended rule format
+    pub extended: bool,
+    /// how the default graph is named in rules over quads
+    pub default_graph: DefaultGraph,
+    /// the graph the conclusions of rules over quads are in, None for the default graph
+    pub conclusion_graph: Graph,
+    /// add warnings and rewrites to the rules written
+    pub embed_diagnostics: bool,
+    /// add where each variable appears to the rules written
+    pub emit_var_map: bool,
+    /// add whether each rule matches and derives rdf:type claims to the rules written
+    pub emit_type_usage: bool,
+    /// add the JSON-LD fields documenting each rule to the rules written
+    pub emit_linked_data: bool,
+    /// write the rdf:type conclusions of rules here rather than to the output
+    pub split_types: Option<PathBuf>,
+    /// tag every rule written with these, as well as the tags of its query
+    pub tags: Vec<String>,
+    /// the rules to load from a ruleset read from stdin
+    pub select: Selection,
+    /// write here instead of stdout
+    pub output: Option<PathBuf>,
+    /// write the rewrites applied to each rule here
+    pub audit: Option<PathBuf>,
+    /// report how long each stage of converting each query took
+    pub timings: bool,
+    /// fail on rules with literals longer than this many bytes
+    pub max_literal_length: Option<usize>,
+    /// abbreviate literals longer than this many bytes in reports
+    pub truncate_literals: Option<usize>,
+    /// keep rules that are also in this ruleset at the same index
+    pub previous: Option<PathBuf>,
+    /// report how the rules written differ from this ruleset
+    pub against: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub enum Command {
+    /// convert a single query read from stdin
+    #[default]
+    Convert,
+    /// convert each of a list of files, in order, into one ruleset
+    ConvertFiles { inputs: Vec<PathBuf> },
+    /// convert several queries read from stdin into one ruleset
+    Batch { jobs: usize, verify_serial: bool },
+    /// check that a query read from stdin converts, without writing it anywhere
+    Check,
+    /// convert a stream of newline delimited JSON records read from stdin
+    Ndjson,
+    /// report the size of the rule converted from a query read from stdin
+    Weigh,
+    /// print a hash of the rule converted from a query read from stdin that doesn't depend on
+    /// variable names or claim order
+    Fingerprint,
+    /// translate rules read from stdin out of Dock's claim deduction format
+    FromDock,
+    /// generate rules from a credential schema read from stdin
+    FromSchema { context: PathBuf, lift: Lift },
+    /// generate rules from a CSV table of predicate mappings read from stdin
+    FromCsv { prefixes: BTreeMap<String, String> },
+    /// hash a ruleset read from stdin for publishing
+    Manifest { signer: String, version: String },
+    /// write Turtle that fires a rule read from stdin, or near misses that don't
+    Sample { negative: bool },
+    /// bind variables of a rule read from stdin to the terms in a JSON file
+    Specialize { bindings: PathBuf },
+    /// apply a ruleset read from stdin to an oxigraph store, inserting what it derives
+    Apply {
+        store: PathBuf,
+        graph: Option<String>,
+        jobs: usize,
+        memory_budget: Option<usize>,
+        round_graphs: Option<String>,
+    },
+    /// report claims a ruleset read from stdin implies for the data in a file but are missing
+    CheckData { data: PathBuf },
+    /// report how close each rule of a ruleset read from stdin comes to deriving a triple
+    WhyNot { data: PathBuf, expect: String },
+    /// report what a rule would derive from the data in a file, without changing anything
+    Impact { rule: PathBuf, data: PathBuf },
+    /// list the IRIs a ruleset read from stdin uses, by namespace
+    Vocab,
+    /// replace IRIs across a ruleset read from stdin
+    Refactor { map: BTreeMap<String, String> },
+    /// encode a ruleset read from stdin as a rulepack
+    Pack,
+    /// decode a rulepack read from stdin
+    Unpack,
+    /// split a ruleset read from stdin into a file for each predicate or graph it writes to
+    Partition { by: By },
+    /// drop the rules of a ruleset read from stdin that expired before a date
+    Prune { at: String },
+    /// chain the first rule into the second
+    Compose { first: PathBuf, second: PathBuf },
+    /// replace IRIs in a rule read from stdin with the variables named in a JSON file
+    Generalize { variables: PathBuf },
+    /// check a ruleset read from stdin against a manifest
+    VerifyManifest { manifest: PathBuf },
+    /// keep a combined ruleset up to date with a set of query files and directories
+    Watch {
+        inputs: Vec<PathBuf>,
+        interval: Duration,
+    },
+}
+
+pub enum Parsed {
+    Run(Box<Args>),
+    Help,
+    /// print the long form explanation of an error code or lint
+    Explain(String),
+}
+
+/// parse command line arguments, not including the program name
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Parsed, String> {
+    let mut ret = Args::default();
+    let mut positional = Vec::new();
+    let mut interval = None;
+    let mut context = None;
+    let mut bindings = None;
+    let mut variables = None;
+    let mut map = BTreeMap::new();
+    let mut prefixes = BTreeMap::new();
+    let mut negative = false;
+    let mut verify_serial = false;
+    let mut store = None;
+    let mut data = None;
+    let mut rule = None;
+    let mut expect = None;
+    let mut jobs = None;
+    let mut memory_budget = None;
+    let mut round_graphs = None;
+    let mut graph = None;
+    let mut lift = Lift::default();
+    let mut vocab = None;
+    let mut by = None;
+    let mut at = None;
+    let mut signer = None;
+    let mut version = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" | "-h" => return Ok(Parsed::Help),
+            "--explain" => return Ok(Parsed::Explain(value(&arg, &mut args)?)),
+            "--allow" | "-A" => set_level(&mut ret, value(&arg, &mut args)?, Level::Allow)?,
+            "--warn" | "-W" => set_level(&mut ret, value(&arg, &mut args)?, Level::Warn)?,
+            "--deny" | "-D" => set_level(&mut ret, value(&arg, &mut args)?, Level::Deny)?,
+            "--lint-config" => {
+                let path = value(&arg, &mut args)?;
+                let file = File::open(&path).map_err(|e| format!("{}: {}", path, e))?;
+                let config: LevelsConfig =
+                    serde_json::from_reader(file).map_err(|e| format!("{}: {}", path, e))?;
+                ret.levels.apply(config).map_err(|e| e.to_string())?;
+            }
+            "--canonicalize" => match value(&arg, &mut args)?.as_str() {
+                "numbers" => ret.canonicalize.numbers = true,
+                "language-tags" => ret.canonicalize.language_tags = true,
+                "nfc" => ret.canonicalize.nfc = true,
+                "iris" => ret.canonicalize.iris = true,
+                "date-times" => ret.canonicalize.date_times = true,
+                "date-times-utc" => {
+                    ret.canonicalize.date_times = true;
+                    ret.canonicalize.utc = true;
+                }
+                other => return Err(invalid(other)),
+            },
+            "--datatypes" => {
+                let path = value(&arg, &mut args)?;
+                let file = File::open(&path).map_err(|e| format!("{}: {}", path, e))?;
+                let allowed: Vec<String> =
+                    serde_json::from_reader(file).map_err(|e| format!("{}: {}", path, e))?;
+                ret.allowed_datatypes
+                    .get_or_insert_with(BTreeSet::new)
+                    .extend(allowed);
+            }
+            "--namespaces" => {
+                let path = value(&arg, &mut args)?;
+                let file = File::open(&path).map_err(|e| format!("{}: {}", path, e))?;
+                ret.namespaces =
+                    serde_json::from_reader(file).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            "--endpoint" => ret.endpoint = Some(endpoint(&value(&arg, &mut args)?)?),
+            "--base" => ret.base = Some(value(&arg, &mut args)?),
+            "--allow-from" => ret.allow_from = true,
+            "--omit-datatype" => match value(&arg, &mut args)?.as_str() {
+                "string" => ret.datatypes.omit_string = true,
+                "lang-string" => ret.datatypes.omit_lang_string = true,
+                other => return Err(invalid(other)),
+            },
+            "--rename-vars" => ret.rename_vars = Some(value(&arg, &mut args)?.into()),
+            "--preserve-order" => ret.preserve_order = true,
+            "--keep-redundant" => ret.keep_redundant = true,
+            "--split-optional" => ret.split_optional = true,
+            "--merge-rules" => ret.merge_rules = true,
+            "--quads" => ret.quads = true,
+            "--extended" => ret.extended = true,
+            "--default-graph" => ret.default_graph = value(&arg, &mut args)?.parse()?,
+            "--conclusion-graph" => {
+                ret.conclusion_graph = match value(&arg, &mut args)?.parse()? {
+                    DefaultGraph::Sentinel => None,
+                    DefaultGraph::Iri(iri) => Some(Entity::Bound(RdfNode::Iri(iri))),
+                    DefaultGraph::Variable(name) => Some(Entity::Unbound(name)),
+                }
+            }
+            "--embed-diagnostics" => ret.embed_diagnostics = true,
+            "--emit-var-map" => ret.emit_var_map = true,
+            "--emit-type-usage" => ret.emit_type_usage = true,
+            "--emit-linked-data" => ret.emit_linked_data = true,
+            "--timings" => ret.timings = true,
+            "--split-types" => ret.split_types = Some(value(&arg, &mut args)?.into()),
+            "--tag" => {
+                let list = value(&arg, &mut args)?;
+                if tags::split(&list).next().is_none() {
+                    return Err(invalid(&list));
+                }
+                tags::add(&mut ret.tags, tags::split(&list));
+            }
+            "--include-tags" => {
+                let list = value(&arg, &mut args)?;
+                ret.select
+                    .include
+                    .extend(tags::split(&list).map(str::to_string));
+            }
+            "--exclude-tags" => {
+                let list = value(&arg, &mut args)?;
+                ret.select
+                    .exclude
+                    .extend(tags::split(&list).map(str::to_string));
+            }
+            "--format" => ret.format = value(&arg, &mut args)?.parse()?,
+            "--output" | "-o" => ret.output = Some(value(&arg, &mut args)?.into()),
+            "--audit" => ret.audit = Some(value(&arg, &mut args)?.into()),
+            "--max-literal-length" => {
+                let n = value(&arg, &mut args)?;
+                ret.max_literal_length = Some(n.parse().map_err(|_| invalid(&n))?);
+            }
+            "--truncate-literals" => {
+                let n = value(&arg, &mut args)?;
+                ret.truncate_literals = Some(n.parse().map_err(|_| invalid(&n))?);
+            }
+            "--previous" => ret.previous = Some(value(&arg, &mut args)?.into()),
+            "--against" => ret.against = Some(value(&arg, &mut args)?.into()),
+            "--interval" => {
+                let ms = value(&arg, &mut args)?;
+                let ms = ms.parse().map_err(|_| invalid(&ms))?;
+                interval = Some(Duration::from_millis(ms));
+            }
+            "--signer" => signer = Some(value(&arg, &mut args)?),
+            "--ruleset-version" => version = Some(value(&arg, &mut args)?),
+            "--bindings" => bindings = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--negative" => negative = true,
+            "--verify-serial" => verify_serial = true,
+            "--jobs" | "-j" => {
+                let n = value(&arg, &mut args)?;
+                jobs = Some(
+                    n.parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| invalid(&n))?,
+                );
+            }
+            "--memory-budget" => {
+                let n = value(&arg, &mut args)?;
+                memory_budget = Some(n.parse().map_err(|_| invalid(&n))?);
+            }
+            "--data" => data = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--rule" => rule = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--expect" => expect = Some(value(&arg, &mut args)?),
+            "--store" => store = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--graph" => graph = Some(value(&arg, &mut args)?),
+            "--round-graphs" => round_graphs = Some(value(&arg, &mut args)?),
+            "--variables" => variables = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--by" => by = Some(value(&arg, &mut args)?.parse()?),
+            "--at" => {
+                let date = value(&arg, &mut args)?;
+                if !validity::is_date(&date) {
+                    return Err(invalid(&date));
+                }
+                at = Some(date);
+            }
+            "--map" => {
+                let pair = value(&arg, &mut args)?;
+                let (old, new) = pair.split_once('=').ok_or_else(|| invalid(&pair))?;
+                map.insert(old.to_string(), new.to_string());
+            }
+            "--prefix" => {
+                let pair = value(&arg, &mut args)?;
+                let (prefix, namespace) = pair.split_once('=').ok_or_else(|| invalid(&pair))?;
+                prefixes.insert(prefix.to_string(), namespace.to_string());
+            }
+            "--context" => context = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--vocab" => vocab = Some(value(&arg, &mut args)?),
+            "--type" => lift.subject_type = Some(value(&arg, &mut args)?),
+            "--issuer" => lift.issuer = Some(value(&arg, &mut args)?),
+            _ if arg.starts_with('-') => return Err(invalid(&arg)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    ret.command = match positional.next().as_deref() {
+        None => Command::Convert,
+        Some("convert") => {
+            let inputs: Vec<PathBuf> = positional.by_ref().map(PathBuf::from).collect();
+            if inputs.is_empty() {
+                Command::Convert
+            } else {
+                Command::ConvertFiles { inputs }
+            }
+        }
+        Some("batch") => Command::Batch {
+            jobs: jobs.take().unwrap_or(1),
+            verify_serial: std::mem::take(&mut verify_serial),
+        },
+        Some("check") => Command::Check,
+        Some("weigh") => Command::Weigh,
+        Some("fingerprint") => Command::Fingerprint,
+        Some("ndjson") => Command::Ndjson,
+        Some("from-dock") => Command::FromDock,
+        Some("from-csv") => Command::FromCsv {
+            prefixes: std::mem::take(&mut prefixes),
+        },
+        Some("from-schema") => match (context.take(), vocab.take()) {
+            (Some(context), Some(vocab)) => {
+                lift.vocab = vocab;
+                let lift = std::mem::take(&mut lift);
+                Command::FromSchema { context, lift }
+            }
+            _ => return Err("from-schema expects --context and --vocab, try --help.".to_string()),
+        },
+        Some("manifest") => match (signer.take(), version.take()) {
+            (Some(signer), Some(version)) => Command::Manifest { signer, version },
+            _ => {
+                return Err(
+                    "manifest expects --signer and --ruleset-version, try --help.".to_string(),
+                )
+            }
+        },
+        Some("sample") => Command::Sample {
+            negative: std::mem::take(&mut negative),
+        },
+        Some("specialize") => match bindings.take() {
+            Some(bindings) => Command::Specialize { bindings },
+            None => return Err("specialize expects --bindings, try --help.".to_string()),
+        },
+        Some("generalize") => match variables.take() {
+            Some(variables) => Command::Generalize { variables },
+            None => return Err("generalize expects --variables, try --help.".to_string()),
+        },
+        Some("apply") if graph.is_some() && round_graphs.is_some() => {
+            return Err("--graph and --round-graphs can't be combined, try --help.".to_string())
+        }
+        Some("apply") => match store.take() {
+            Some(store) => Command::Apply {
+                store,
+                graph: graph.take(),
+                jobs: jobs.take().unwrap_or(1),
+                memory_budget: memory_budget.take(),
+                round_graphs: round_graphs.take(),
+            },
+            None => return Err("apply expects --store, try --help.".to_string()),
+        },
+        Some("vocab") => Command::Vocab,
+        Some("refactor") if !map.is_empty() => Command::Refactor {
+            map: std::mem::take(&mut map),
+        },
+        Some("refactor") => return Err("refactor expects --map, try --help.".to_string()),
+        Some("pack") => Command::Pack,
+        Some("unpack") => Command::Unpack,
+        Some("partition") => {
+            let by = by.take().unwrap_or_default();
+            if ret.output.is_none() {
+                return Err("partition expects --output, try --help.".to_string());
+            }
+            if by == By::Graph && !matches!(ret.format, Format::Rify | Format::Dock) {
+                return Err(
+                    "partition --by graph reads and writes rules in the dock format, try --help."
+                        .to_string(),
+                );
+            }
+            Command::Partition { by }
+        }
+        Some("prune") => match at.take() {
+            Some(at) => Command::Prune { at },
+            None => return Err("prune expects --at, try --help.".to_string()),
+        },
+        Some("check-data") => match data.take() {
+            Some(data) => Command::CheckData { data },
+            None => return Err("check-data expects --data, try --help.".to_string()),
+        },
+        Some("why-not") => match (data.take(), expect.take()) {
+            (Some(data), Some(expect)) => Command::WhyNot { data, expect },
+            _ => return Err("why-not expects --data and --expect, try --help.".to_string()),
+        },
+        Some("impact") => match (rule.take(), data.take()) {
+            (Some(rule), Some(data)) => Command::Impact { rule, data },
+            _ => return Err("impact expects --rule and --data, try --help.".to_string()),
+        },
+        Some("compose") => match (positional.next(), positional.next()) {
+            (Some(first), Some(second)) => Command::Compose {
+                first: first.into(),
+                second: second.into(),
+            },
+            _ => return Err("compose expects two rules, try --help.".to_string()),
+        },
+        Some("verify-manifest") => match positional.next() {
+            Some(manifest) => Command::VerifyManifest {
+                manifest: manifest.into(),
+            },
+            None => return Err("verify-manifest expects a manifest, try --help.".to_string()),
+        },
+        Some("watch") => {
+            let inputs: Vec<PathBuf> = positional.by_ref().map(PathBuf::from).collect();
+            if inputs.is_empty() || ret.output.is_none() {
+                return Err("watch expects input paths and --output, try --help.".to_string());
+            }
+            Command::Watch {
+                inputs,
+                interval: interval.take().unwrap_or(Duration::from_millis(500)),
+            }
+        }
+        Some(other) => return Err(invalid(other)),
+    };
+    if ret.rename_vars.is_some() && ret.command != Command::Convert {
+        return Err("--rename-vars only applies to a single conversion, try --help.".to_string());
+    }
+    if ret.merge_rules && !matches!(ret.command, Command::ConvertFiles { .. }) {
+        return Err("--merge-rules only applies to convert <FILE>..., try --help.".to_string());
+    }
+    if ret.previous.is_some() && !matches!(ret.command, Command::ConvertFiles { .. }) {
+        return Err("--previous only applies to convert <FILE>..., try --help.".to_string());
+    }
+    if ret.against.is_some() && !matches!(ret.command, Command::ConvertFiles { .. }) {
+        return Err("--against only applies to convert <FILE>..., try --help.".to_string());
+    }
+    let converts = matches!(
+        ret.command,
+        Command::Convert
+            | Command::ConvertFiles { .. }
+            | Command::Batch { .. }
+            | Command::Ndjson
+            | Command::Watch { .. }
+    );
+    if (ret.embed_diagnostics || ret.emit_var_map || ret.emit_type_usage || ret.emit_linked_data)
+        && !converts
+    {
+        return Err(
+            "--embed-diagnostics, --emit-var-map, --emit-type-usage and --emit-linked-data only \
+             apply to conversions, try --help."
+                .to_string(),
+        );
+    }
+    // a rule's fingerprint, its @id, only covers the triples of if_all and then
+    if ret.emit_linked_data && (ret.quads || ret.extended) {
+        return Err(
+            "--emit-linked-data can't be combined with --quads or --extended, try --help."
+                .to_string(),
+        );
+    }
+    if !ret.tags.is_empty() && !converts {
+        return Err("--tag only applies to conversions, try --help.".to_string());
+    }
+    if ret.max_literal_length.is_some() && !converts && ret.command != Command::Check {
+        return Err(
+            "--max-literal-length only applies to conversions and check, try --help.".to_string(),
+        );
+    }
+    if ret.truncate_literals.is_some()
+        && !matches!(
+            ret.command,
+            Command::CheckData { .. } | Command::WhyNot { .. }
+        )
+    {
+        return Err(
+            "--truncate-literals only applies to check-data and why-not, try --help.".to_string(),
+        );
+    }
+    let loads = matches!(
+        ret.command,
+        Command::FromDock
+            | Command::Manifest { .. }
+            | Command::VerifyManifest { .. }
+            | Command::Apply { .. }
+            | Command::CheckData { .. }
+            | Command::WhyNot { .. }
+            | Command::Vocab
+            | Command::Refactor { .. }
+            | Command::Pack
+            | Command::Partition { .. }
+            | Command::Prune { .. }
+    );
+    if !ret.select.is_empty() && !loads {
+        return Err(
+            "--include-tags and --exclude-tags only apply to subcommands reading a ruleset, \
+             try --help."
+                .to_string(),
+        );
+    }
+    if ret.split_types.is_some() {
+        if !matches!(
+            ret.command,
+            Command::ConvertFiles { .. } | Command::Batch { .. }
+        ) {
+            return Err(
+                "--split-types only applies to convert <FILE>... and batch, try --help."
+                    .to_string(),
+            );
+        }
+        if ret.emit_var_map {
+            return Err(
+                "--split-types can't be combined with --emit-var-map, try --help.".to_string(),
+            );
+        }
+    }
+    if ret.timings {
+        if !matches!(
+            ret.command,
+            Command::Convert | Command::ConvertFiles { .. } | Command::Batch { .. }
+        ) {
+            return Err("--timings only applies to convert and batch, try --help.".to_string());
+        }
+        if ret.extended {
+            return Err("--timings can't be combined with --extended, try --help.".to_string());
+        }
+    }
+    if ret.audit.is_some()
+        && !matches!(ret.command, Command::Convert | Command::ConvertFiles { .. })
+    {
+        return Err("--audit only applies to convert, try --help.".to_string());
+    }
+    if !ret.quads && ret.default_graph != DefaultGraph::default() {
+        return Err("--default-graph only applies with --quads, try --help.".to_string());
+    }
+    if !ret.quads && ret.conclusion_graph.is_some() {
+        return Err("--conclusion-graph only applies with --quads, try --help.".to_string());
+    }
+    if ret.quads {
+        if ret.command != Command::Convert {
+            return Err("--quads only applies to a single conversion, try --help.".to_string());
+        }
+        if ret.rename_vars.is_some() || ret.emit_var_map || ret.split_optional {
+            return Err(
+                "--quads can't be combined with --rename-vars, --emit-var-map or \
+                 --split-optional, try --help."
+                    .to_string(),
+            );
+        }
+        if !matches!(ret.format, Format::Rify | Format::Dock) {
+            return Err(
+                "--quads rules are always written in the dock format, try --help.".to_string(),
+            );
+        }
+    }
+    if ret.extended {
+        if ret.command != Command::Convert {
+            return Err("--extended only applies to a single conversion, try --help.".to_string());
+        }
+        if ret.quads || ret.rename_vars.is_some() || ret.emit_var_map || ret.split_optional {
+            return Err(
+                "--extended can't be combined with --quads, --rename-vars, --emit-var-map or \
+                 --split-optional, try --help."
+                    .to_string(),
+            );
+        }
+        if ret.format != Format::Rify {
+            return Err(
+                "--extended rules are written in rify's format with an if_none section, the \
+                 other formats only have room for plain rify rules, try --help."
+                    .to_string(),
+            );
+        }
+    }
+    if let Some(extra) = positional.next() {
+        return Err(invalid(&extra));
+    }
+    if interval.is_some() {
+        return Err("--interval only applies to watch, try --help.".to_string());
+    }
+    if bindings.is_some() {
+        return Err("--bindings only applies to specialize, try --help.".to_string());
+    }
+    if data.is_some() {
+        return Err(
+            "--data only applies to check-data, why-not and impact, try --help.".to_string(),
+        );
+    }
+    if rule.is_some() {
+        return Err("--rule only applies to impact, try --help.".to_string());
+    }
+    if expect.is_some() {
+        return Err("--expect only applies to why-not, try --help.".to_string());
+    }
+    if store.is_some() || graph.is_some() || memory_budget.is_some() || round_graphs.is_some() {
+        return Err(
+            "--store, --graph, --memory-budget and --round-graphs only apply to apply, try --help."
+                .to_string(),
+        );
+    }
+    if jobs.is_some() {
+        return Err("--jobs only applies to apply and batch, try --help.".to_string());
+    }
+    if verify_serial {
+        return Err("--verify-serial only applies to batch, try --help.".to_string());
+    }
+    if negative {
+        return Err("--negative only applies to sample, try --help.".to_string());
+    }
+    if !map.is_empty() {
+        return Err("--map only applies to refactor, try --help.".to_string());
+    }
+    if !prefixes.is_empty() {
+        return Err("--prefix only applies to from-csv, try --help.".to_string());
+    }
+    if by.is_some() {
+        return Err("--by only applies to partition, try --help.".to_string());
+    }
+    if at.is_some() {
+        return Err("--at only applies to prune, try --help.".to_string());
+    }
+    if variables.is_some() {
+        return Err("--variables only applies to generalize, try --help.".to_string());
+    }
+    if signer.is_some() || version.is_some() {
+        return Err(
+            "--signer and --ruleset-version only apply to manifest, try --help.".to_string(),
+        );
+    }
+    if context.is_some() || vocab.is_some() || lift != Lift::default() {
+        return Err(
+            "--context, --vocab, --type and --issuer only apply to from-schema, try --help."
+                .to_string(),
+        );
+    }
+    Ok(Parsed::Run(Box::new(ret)))
+}
+
+fn invalid(arg: &str) -> String {
+    format!("Invalid argument \"{}\", try --help.", arg)
+}
+
+fn value(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("{} expects a value, try --help.", flag))
+}
+
+fn set_level(args: &mut Args, name: String, level: Level) -> Result<(), String> {
+    args.levels.set(&name, level).map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "endpoint")]
+fn endpoint(url: &str) -> Result<Seen, String> {
+    Seen::fetch(url).map_err(|e| format!("{}: {}", url, e))
+}
+
+#[cfg(not(feature = "endpoint"))]
+fn endpoint(_url: &str) -> Result<Seen, String> {
+    Err(
+        "sparql2rify was built without --endpoint support, rebuild it with --features endpoint."
+            .to_string(),
+    )
+}
@@ -0,0 +1,240 @@
+//! Chaining rules. When the claims one rule derives are what another rule matches, the two can
+//! be replaced by a single rule going straight from the first one's conditions to the second
+//! one's conclusions, so an environment that can only afford one pass of reasoning still gets
+//! the multi step result.
+
+use crate::fingerprint;
+use crate::types::{RdfNode, RuleParts};
+use crate::util::as_unbound;
+use crate::vars::Fresh;
+use rify::{Claim, Entity};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The rules that derive what `second` derives when it matches claims derived by `first`. Each
+/// way of matching some of `second`'s conditions to `first`'s conclusions gives a rule with the
+/// rest of `second`'s conditions and, for each matched condition, a copy of `first`'s, under the
+/// most general assignment of variables that makes the matched claims equal. Copies and
+/// `second` have their variables renamed where they clash with `first`'s. Rules that only
+/// differ in the names of their variables or the order of their claims are listed once.
+pub fn compose(first: &RuleParts, second: &RuleParts) -> Vec<RuleParts> {
+    let mut taken = Fresh::avoiding(first.if_all.iter().chain(&first.then));
+    let second = rename_apart(second, &mut taken);
+    let mut ret = Vec::new();
+    let mut seen = BTreeSet::new();
+    let mut matched = vec![None; second.if_all.len()];
+    choose(first, &second, &mut matched, &mut |matched| {
+        let mut taken = taken.clone();
+        let mut unifier = Unifier::default();
+        let mut if_all = Vec::new();
+        let mut copies = 0;
+        for (condition, conclusion) in second.if_all.iter().zip(matched) {
+            match conclusion {
+                Some(j) => {
+                    // every matched condition is derived by a firing of its own
+                    let copy = if copies == 0 {
+                        first.clone()
+                    } else {
+                        rename_apart(first, &mut taken)
+                    };
+                    copies += 1;
+                    if !unifier.claims(&copy.then[*j], condition) {
+                        return;
+                    }
+                    if_all.extend(copy.if_all);
+                }
+                None => if_all.push(condition.clone()),
+            }
+        }
+        let if_all = if_all.iter().map(|claim| unifier.apply(claim)).collect();
+        let then = second
+            .then
+            .iter()
+            .map(|claim| unifier.apply(claim))
+            .collect();
+        let rule = RuleParts { if_all, then };
+        if seen.insert(fingerprint::fingerprint(&rule)) {
+            ret.push(rule);
+        }
+    });
+    ret
+}
+
+/// call `f` with every assignment of conditions to the conclusions they are matched to that
+/// matches at least one
+fn choose(
+    first: &RuleParts,
+    second: &RuleParts,
+    matched: &mut Vec<Option<usize>>,
+    f: &mut impl FnMut(&[Option<usize>]),
+) {
+    fn go(
+        i: usize,
+        conclusions: usize,
+        matched: &mut Vec<Option<usize>>,
+        f: &mut impl FnMut(&[Option<usize>]),
+    ) {
+        if i == matched.len() {
+            if matched.iter().any(Option::is_some) {
+                f(matched);
+            }
+            return;
+        }
+        for choice in std::iter::once(None).chain((0..conclusions).map(Some)) {
+            matched[i] = choice;
+            go(i + 1, conclusions, matched, f);
+        }
+        matched[i] = None;
+    }
+    debug_assert_eq!(matched.len(), second.if_all.len());
+    go(0, first.then.len(), matched, f)
+}
+
+fn names(rule: &RuleParts) -> BTreeSet<String> {
+    rule.if_all
+        .iter()
+        .chain(&rule.then)
+        .flatten()
+        .filter_map(as_unbound)
+        .map(str::to_string)
+        .collect()
+}
+
+/// `rule` with each variable in `taken` renamed to one that isn't, its variables then taken
+fn rename_apart(rule: &RuleParts, taken: &mut Fresh) -> RuleParts {
+    let ours = names(rule);
+    let clashing: Vec<String> = ours
+        .iter()
+        .filter(|name| taken.is_taken(name))
+        .cloned()
+        .collect();
+    for name in &ours {
+        taken.reserve(name);
+    }
+    let mut renamed = BTreeMap::new();
+    for name in clashing {
+        let fresh = taken.name(&name);
+        renamed.insert(name, fresh);
+    }
+    let rename = |claim: &Claim<Entity<String, RdfNode>>| {
+        let rename = |ent: &Entity<String, RdfNode>| match as_unbound(ent) {
+            Some(name) => Entity::Unbound(renamed.get(name).map_or(name, String::as_str).into()),
+            None => ent.clone(),
+        };
+        let [s, p, o] = claim;
+        [rename(s), rename(p), rename(o)]
+    };
+    RuleParts {
+        if_all: rule.if_all.iter().map(rename).collect(),
+        then: rule.then.iter().map(rename).collect(),
+    }
+}
+
+/// An assignment of terms to variables, built up one equation at a time.
+#[derive(Default)]
+struct Unifier {
+    bound: BTreeMap<String, Entity<String, RdfNode>>,
+}
+
+impl Unifier {
+    /// what `ent` stands for under the assignment so far
+    fn resolve(&self, ent: &Entity<String, RdfNode>) -> Entity<String, RdfNode> {
+        let mut ent = ent.clone();
+        while let Some(next) = as_unbound(&ent).and_then(|name| self.bound.get(name)) {
+            ent = next.clone();
+        }
+        ent
+    }
+
+    /// Extend the assignment so `conclusion` and `condition` are the same claim, returning
+    /// false if they can't be. Variables of the condition are bound first so the conclusion's
+    /// names are kept.
+    fn claims(
+        &mut self,
+        conclusion: &Claim<Entity<String, RdfNode>>,
+        condition: &Claim<Entity<String, RdfNode>>,
+    ) -> bool {
+        conclusion
+            .iter()
+            .zip(condition)
+            .all(|(conclusion, condition)| {
+                let conclusion = self.resolve(conclusion);
+                let condition = self.resolve(condition);
+                if conclusion == condition {
+                    return true;
+                }
+                match (as_unbound(&conclusion), as_unbound(&condition)) {
+                    (_, Some(name)) => self.bound.insert(name.to_string(), conclusion.clone()),
+                    (Some(name), None) => self.bound.insert(name.to_string(), condition.clone()),
+                    (None, None) => return false,
+                };
+                true
+            })
+    }
+
+    fn apply(&self, claim: &Claim<Entity<String, RdfNode>>) -> Claim<Entity<String, RdfNode>> {
+        let [s, p, o] = claim;
+        [self.resolve(s), self.resolve(p), self.resolve(o)]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chain_two_rules() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri =
+            |suffix: &str| Entity::Bound(RdfNode::Iri(format!("http://example.com/{}", suffix)));
+        let child = RuleParts {
+            if_all: vec![[unbd("a"), iri("parent"), unbd("b")]],
+            then: vec![[unbd("b"), iri("child"), unbd("a")]],
+        };
+        let adult_child = RuleParts {
+            if_all: vec![
+                [unbd("b"), iri("child"), unbd("a")],
+                [unbd("b"), iri("age"), iri("Adult")],
+            ],
+            then: vec![[unbd("a"), iri("adultChild"), unbd("b")]],
+        };
+        let composed = compose(&child, &adult_child);
+        assert_eq!(
+            composed,
+            vec![RuleParts {
+                if_all: vec![
+                    [unbd("a"), iri("parent"), unbd("b")],
+                    [unbd("b"), iri("age"), iri("Adult")],
+                ],
+                then: vec![[unbd("a"), iri("adultChild"), unbd("b")]],
+            }]
+        );
+
+        // both conditions of grandparent can be derived by the same rule
+        let grandparent = RuleParts {
+            if_all: vec![
+                [unbd("x"), iri("child"), unbd("y")],
+                [unbd("y"), iri("child"), unbd("z")],
+            ],
+            then: vec![[unbd("z"), iri("grandparent"), unbd("x")]],
+        };
+        let composed = compose(&child, &grandparent);
+        assert_eq!(composed.len(), 3);
+        let both = RuleParts {
+            if_all: vec![
+                [unbd("a"), iri("parent"), unbd("b")],
+                [unbd("b"), iri("parent"), unbd("c")],
+            ],
+            then: vec![[unbd("a"), iri("grandparent"), unbd("c")]],
+        };
+        assert!(composed
+            .iter()
+            .any(|rule| fingerprint::fingerprint(rule) == fingerprint::fingerprint(&both)));
+
+        // nothing child derives is an age
+        let ages = RuleParts {
+            if_all: vec![[unbd("b"), iri("age"), unbd("age")]],
+            then: vec![[unbd("b"), iri("hasAge"), iri("true")]],
+        };
+        assert_eq!(compose(&child, &ages), vec![]);
+    }
+}
@@ -1,24 +1,527 @@
+use crate::algebra;
+use crate::fold::{self, Functions};
 use crate::types;
-use crate::RdfNode;
+use crate::types::{RdfNode, RDF_LANG_STRING, XSD_STRING};
+use crate::ConvertOptions;
 use oxigraph::model::{Literal, LiteralContent, Term};
 use oxigraph::sparql::algebra::{
-    NamedNodeOrVariable, TermOrVariable, TripleOrPathPattern, TriplePattern,
+    Aggregation, Expression, Function, GraphPattern, NamedNodeOrVariable, TermOrVariable,
+    TripleOrPathPattern, TriplePattern,
 };
+use oxigraph::sparql::Variable;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Look through the whole WHERE clause for anything that can't be converted. This runs before
+/// any conversion work so huge machine generated queries that are going to be rejected anyway,
+/// e.g. because of a VALUES clause with thousands of rows, are rejected quickly. GRAPH blocks
+/// are only allowed when converting to a rule over quads, UNION and VALUES only when expanding
+/// a query into several rules, and OPTIONAL without a FILTER only when splitting it too. FILTER
+/// and BIND are only allowed if they might be folded, see `fold`, or in the case of a FILTER
+/// EXISTS merged, see `exists`, and FILTER NOT EXISTS and MINUS only when converting to a rule
+/// with negative conditions. One calling a function such as RAND() gets an error of its own,
+/// since no rule could ever express it. Everything else gets the error for what it is, see
+/// `unsupported`, so the query can be fixed or the flag it needs passed.
+pub fn scan(
+    where_clause: &GraphPattern,
+    options: &ConvertOptions,
+) -> Result<(), types::InvalidRule> {
+    let ConvertOptions {
+        quads,
+        expand,
+        optional,
+        negation,
+        ref functions,
+    } = *options;
+    // the solution modifiers of the query itself are around or just inside its projection
+    let projected = match where_clause {
+        GraphPattern::Project(projected, _) => Some(&**projected),
+        _ => None,
+    };
+    for pattern in algebra::walk(where_clause) {
+        match pattern {
+            GraphPattern::Project(..) if std::ptr::eq(pattern, where_clause) => {}
+            GraphPattern::Slice(_, _, limit) if std::ptr::eq(pattern, where_clause) => {
+                let modifier = if limit.is_some() { "LIMIT" } else { "OFFSET" };
+                return Err(types::InvalidRule::UnsupportedModifier { modifier });
+            }
+            GraphPattern::OrderBy(..) if projected.is_some_and(|p| std::ptr::eq(pattern, p)) => {
+                let modifier = "ORDER BY";
+                return Err(types::InvalidRule::UnsupportedModifier { modifier });
+            }
+            GraphPattern::Join(..) => {}
+            GraphPattern::Graph(..) if quads => {}
+            GraphPattern::Union(..) | GraphPattern::Data(..) if expand => {}
+            GraphPattern::LeftJoin(_, _, None) if expand && optional => {}
+            GraphPattern::Minus(_, pattern) if negation => {
+                exists(pattern)?;
+            }
+            GraphPattern::BGP(bgp) => {
+                if bgp
+                    .iter()
+                    .any(|tp| matches!(tp, TripleOrPathPattern::Path(_)))
+                {
+                    return Err(types::InvalidRule::IllegalPathPattern);
+                }
+            }
+            // HAVING, which is about the aggregates
+            GraphPattern::Filter(_, p) if matches!(**p, GraphPattern::AggregateJoin(..)) => {}
+            GraphPattern::Filter(expression, _) => {
+                if let Some(err) = nondeterministic(expression) {
+                    return Err(err);
+                }
+                for condition in fold::conditions(expression) {
+                    match condition {
+                        Expression::Exists(pattern) => {
+                            exists(pattern)?;
+                        }
+                        _ => match not_exists(condition) {
+                            Some(_) if !negation => {
+                                return Err(types::InvalidRule::NegationNeedsExtended)
+                            }
+                            Some(pattern) => {
+                                exists(pattern)?;
+                            }
+                            None if fold::supported(condition, functions) => {}
+                            None => return Err(unsupported_filter(condition)),
+                        },
+                    }
+                }
+            }
+            GraphPattern::Extend(_, variable, expression) => {
+                if let Some(err) = nondeterministic(expression) {
+                    return Err(err);
+                }
+                if !fold::supported(expression, functions) {
+                    return Err(unfoldable(variable));
+                }
+            }
+            _ => return Err(unsupported(pattern)),
+        }
+    }
+    Ok(())
+}
+
+/// The error for a pattern no rule can hold, or only with a flag. Basic graph patterns, joins,
+/// FILTER and BIND are checked on their own.
+pub fn unsupported(pattern: &GraphPattern) -> types::InvalidRule {
+    match pattern {
+        GraphPattern::Union(..) => types::InvalidRule::UnsupportedUnion,
+        GraphPattern::Data(..) => types::InvalidRule::UnsupportedValues,
+        GraphPattern::LeftJoin(..) => types::InvalidRule::UnsupportedOptional,
+        GraphPattern::Graph(..) => types::InvalidRule::UnsupportedGraph,
+        GraphPattern::Minus(..) => types::InvalidRule::NegationNeedsExtended,
+        GraphPattern::Service(..) => types::InvalidRule::UnsupportedService,
+        GraphPattern::AggregateJoin(_, aggregates) => {
+            let name = match aggregates.first() {
+                Some((aggregate, _)) => aggregate_name(aggregate),
+                None => "GROUP BY",
+            };
+            let name = name.to_string();
+            types::InvalidRule::UnsupportedAggregate { name }
+        }
+        GraphPattern::Project(..)
+        | GraphPattern::Distinct(..)
+        | GraphPattern::Reduced(..)
+        | GraphPattern::Slice(..)
+        | GraphPattern::OrderBy(..) => types::InvalidRule::UnsupportedSubquery,
+        GraphPattern::BGP(..)
+        | GraphPattern::Join(..)
+        | GraphPattern::Filter(..)
+        | GraphPattern::Extend(..) => unreachable!("{} is checked on its own", pattern),
+    }
+}
+
+fn aggregate_name(aggregate: &Aggregation) -> &'static str {
+    match aggregate {
+        Aggregation::Count(..) => "COUNT",
+        Aggregation::Sum(..) => "SUM",
+        Aggregation::Min(..) => "MIN",
+        Aggregation::Max(..) => "MAX",
+        Aggregation::Avg(..) => "AVG",
+        Aggregation::Sample(..) => "SAMPLE",
+        Aggregation::GroupConcat(..) => "GROUP_CONCAT",
+    }
+}
+
+/// the error for a FILTER `condition` that can't be folded
+fn unsupported_filter(condition: &Expression) -> types::InvalidRule {
+    let expr = condition.to_string();
+    types::InvalidRule::UnsupportedFilter { expr }
+}
+
+/// The triples of `FILTER EXISTS { .. }`. Under rule semantics they are only more conditions, so
+/// they are merged into the pattern the FILTER is in, with the variables it shares. Only a basic
+/// graph pattern can be merged, or one split into groups by extra braces, its variables not in
+/// the pattern then only standing for some term, as blank nodes would, see `Exists`.
+fn exists(pattern: &GraphPattern) -> Result<Vec<&TriplePattern>, types::InvalidRule> {
+    match algebra::flatten(pattern) {
+        Some(triples) => as_triples(triples),
+        None => Err(types::InvalidRule::UnsupportedExistsPattern),
+    }
+}
+
+/// the pattern of `FILTER NOT EXISTS { .. }`, which must not match for a rule with negative
+/// conditions to fire
+fn not_exists(condition: &Expression) -> Option<&GraphPattern> {
+    match condition {
+        Expression::UnaryNot(inner) => match &**inner {
+            Expression::Exists(pattern) => Some(pattern),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// the error for a BIND of `variable` that can't be folded
+fn unfoldable(variable: &Variable) -> types::InvalidRule {
+    let name = variable.as_str().to_string();
+    types::InvalidRule::UnfoldableBind { name }
+}
+
+/// the first call in `expression` to a function that isn't deterministic, if any
+fn nondeterministic(expression: &Expression) -> Option<types::InvalidRule> {
+    let mut stack = vec![expression];
+    while let Some(expression) = stack.pop() {
+        match expression {
+            Expression::FunctionCall(function, arguments) => {
+                if let Function::Rand | Function::Now | Function::UUID | Function::StrUUID =
+                    function
+                {
+                    let function = function.to_string();
+                    return Some(types::InvalidRule::NondeterministicFunction { function });
+                }
+                stack.extend(arguments.iter().rev());
+            }
+            Expression::Or(a, b)
+            | Expression::And(a, b)
+            | Expression::Equal(a, b)
+            | Expression::NotEqual(a, b)
+            | Expression::Greater(a, b)
+            | Expression::GreaterOrEq(a, b)
+            | Expression::Lower(a, b)
+            | Expression::LowerOrEq(a, b)
+            | Expression::Add(a, b)
+            | Expression::Sub(a, b)
+            | Expression::Mul(a, b)
+            | Expression::Div(a, b) => {
+                stack.push(b);
+                stack.push(a);
+            }
+            Expression::In(a, list) | Expression::NotIn(a, list) => {
+                stack.extend(list.iter().rev());
+                stack.push(a);
+            }
+            Expression::UnaryPlus(a) | Expression::UnaryMinus(a) | Expression::UnaryNot(a) => {
+                stack.push(a)
+            }
+            Expression::NamedNode(_)
+            | Expression::Literal(_)
+            | Expression::Variable(_)
+            | Expression::Exists(_)
+            | Expression::Bound(_) => {}
+        }
+    }
+    None
+}
 
 /// try to represent a basic graph pattern as triples only. If the pattern contains path items
 /// return Err
-pub fn as_triples(bgp: &[TripleOrPathPattern]) -> Result<Vec<TriplePattern>, types::InvalidRule> {
-    bgp.iter()
+pub fn as_triples<'a>(
+    bgp: impl IntoIterator<Item = &'a TripleOrPathPattern>,
+) -> Result<Vec<&'a TriplePattern>, types::InvalidRule> {
+    bgp.into_iter()
         .map(|trpl| match trpl {
-            TripleOrPathPattern::Triple(tp @ TriplePattern { .. }) => Ok(tp.clone()),
+            TripleOrPathPattern::Triple(tp @ TriplePattern { .. }) => Ok(tp),
             TripleOrPathPattern::Path(_) => Err(types::InvalidRule::IllegalPathPattern),
         })
         .collect()
 }
 
+/// A triple of a WHERE clause and the graph it has to be matched in, None for the default graph.
+pub type Quad<'a> = (&'a TriplePattern, Option<&'a NamedNodeOrVariable>);
+
+/// One way of matching a WHERE clause, the triples that must all match, the terms VALUES
+/// blocks and BINDs fix variables to, the triples of FILTER EXISTS that must match too and the
+/// patterns of FILTER NOT EXISTS and MINUS that must not match.
+#[derive(Clone, Debug, Default)]
+pub struct Branch<'a> {
+    pub quads: Vec<Quad<'a>>,
+    pub values: BTreeMap<&'a str, RdfNode>,
+    pub exists: Vec<Exists<'a>>,
+    pub absent: Vec<Absent<'a>>,
+}
+
+/// The triples of a FILTER EXISTS, and their variables that the pattern it was applied to didn't
+/// bind, which stand for any term within it and must be renamed apart from the rest of the rule
+/// before the triples join its conditions.
+#[derive(Clone, Debug)]
+pub struct Exists<'a> {
+    pub quads: Vec<Quad<'a>>,
+    pub local: BTreeSet<&'a str>,
+}
+
+/// A pattern that must not match, and its variables that the pattern it was applied to didn't
+/// bind, which stand for any term within it.
+#[derive(Clone, Debug)]
+pub struct Absent<'a> {
+    pub triples: Vec<&'a TriplePattern>,
+    pub local: BTreeSet<&'a str>,
+}
+
+impl<'a> Branch<'a> {
+    /// the variables its triples bind
+    fn matched(&self) -> BTreeSet<&'a str> {
+        let mut ret = variables(self.quads.iter().map(|(triple, _)| *triple));
+        for graph in self.quads.iter().filter_map(|(_, graph)| *graph) {
+            if let NamedNodeOrVariable::Variable(v) = graph {
+                ret.insert(v.as_str());
+            }
+        }
+        ret
+    }
+
+    /// the variables its triples bind or its VALUES and BINDs fix
+    fn bound(&self) -> BTreeSet<&'a str> {
+        let mut ret = self.matched();
+        ret.extend(self.values.keys());
+        ret
+    }
+
+    /// Add a pattern that must not match, the variables it shares being those bound so far.
+    fn exclude(&mut self, triples: Vec<&'a TriplePattern>) {
+        let local = self.local(&triples);
+        self.absent.push(Absent { triples, local });
+    }
+
+    /// Add the triples of a FILTER EXISTS, the variables they share being those bound so far.
+    fn require(&mut self, quads: Vec<Quad<'a>>) {
+        let triples: Vec<_> = quads.iter().map(|(triple, _)| *triple).collect();
+        let local = self.local(&triples);
+        self.exists.push(Exists { quads, local });
+    }
+
+    /// the variables of `triples` it doesn't bind
+    fn local(&self, triples: &[&'a TriplePattern]) -> BTreeSet<&'a str> {
+        let bound = self.bound();
+        variables(triples.iter().copied())
+            .into_iter()
+            .filter(|v| !bound.contains(v))
+            .collect()
+    }
+
+    /// the branch matching both `self` and `other`, None if their VALUES disagree
+    fn join(&self, other: &Self) -> Option<Self> {
+        let mut values = self.values.clone();
+        for (name, term) in &other.values {
+            if values.entry(name).or_insert_with(|| term.clone()) != term {
+                return None;
+            }
+        }
+        let quads = self.quads.iter().chain(&other.quads).cloned().collect();
+        let exists = self.exists.iter().chain(&other.exists).cloned().collect();
+        let absent = self.absent.iter().chain(&other.absent).cloned().collect();
+        Some(Self {
+            quads,
+            values,
+            exists,
+            absent,
+        })
+    }
+}
+
+/// the variables of `triples`
+fn variables<'a>(triples: impl IntoIterator<Item = &'a TriplePattern>) -> BTreeSet<&'a str> {
+    let mut ret = BTreeSet::new();
+    for triple in triples {
+        for term in [&triple.subject, &triple.object] {
+            if let TermOrVariable::Variable(v) = term {
+                ret.insert(v.as_str());
+            }
+        }
+        if let NamedNodeOrVariable::Variable(v) = &triple.predicate {
+            ret.insert(v.as_str());
+        }
+    }
+    ret
+}
+
+/// The branches of a WHERE clause made of basic graph patterns, GRAPH blocks, VALUES blocks,
+/// joins, unions and OPTIONALs of these. Each row of a VALUES block is a branch, and a join has a
+/// branch for each way of picking a branch of both sides whose VALUES agree, as when multiplying
+/// out brackets. An OPTIONAL has the branches of the join with its triples followed by those of
+/// the pattern without them. A BIND fixes its variable in each branch of the pattern it extends
+/// to the value of its expression there, and a FILTER drops the branches it is false for, both
+/// failing if the value can't be folded. The triples of a FILTER EXISTS are added to what each
+/// branch it keeps must match, and the pattern of a FILTER NOT EXISTS to what it must not match,
+/// as is that of a MINUS to each branch it shares a variable with. The variables of such a
+/// pattern that the branch doesn't bind are local to it. Those of a FILTER NOT EXISTS or MINUS
+/// may not be bound by the rest of the branch either. Branches, and the triples in each, are in
+/// the order they were written.
+pub fn branches<'a>(
+    where_clause: &'a GraphPattern,
+    functions: &Functions,
+) -> Result<Vec<Branch<'a>>, types::InvalidRule> {
+    enum Step<'a> {
+        Visit(&'a GraphPattern, Option<&'a NamedNodeOrVariable>),
+        Join,
+        Union,
+        Optional,
+        Minus(&'a GraphPattern),
+        Filter(&'a Expression, Option<&'a NamedNodeOrVariable>),
+        Extend(&'a Variable, &'a Expression),
+    }
+    // the branches of each pattern visited whose parent isn't done yet
+    let mut done: Vec<Vec<Branch>> = Vec::new();
+    let mut stack = vec![Step::Visit(where_clause, None)];
+    while let Some(step) = stack.pop() {
+        match step {
+            Step::Visit(GraphPattern::BGP(bgp), graph) => {
+                let quads = as_triples(bgp)?.into_iter().map(|tp| (tp, graph)).collect();
+                done.push(vec![Branch {
+                    quads,
+                    ..Branch::default()
+                }]);
+            }
+            Step::Visit(GraphPattern::Data(bindings), _) => {
+                let rows = bindings.values_iter().map(|row| Branch {
+                    quads: vec![],
+                    exists: vec![],
+                    absent: vec![],
+                    // UNDEF leaves a variable free
+                    values: bindings
+                        .variables()
+                        .iter()
+                        .zip(row)
+                        .filter_map(|(var, term)| Some((var.as_str(), term.as_ref()?.into())))
+                        .collect(),
+                });
+                done.push(rows.collect());
+            }
+            Step::Visit(GraphPattern::Join(a, b), graph) => {
+                stack.extend([Step::Join, Step::Visit(b, graph), Step::Visit(a, graph)]);
+            }
+            Step::Visit(GraphPattern::Union(a, b), graph) => {
+                stack.extend([Step::Union, Step::Visit(b, graph), Step::Visit(a, graph)]);
+            }
+            Step::Visit(GraphPattern::LeftJoin(a, b, None), graph) => {
+                stack.extend([Step::Optional, Step::Visit(b, graph), Step::Visit(a, graph)]);
+            }
+            Step::Visit(GraphPattern::Minus(a, b), graph) => {
+                stack.extend([Step::Minus(b), Step::Visit(a, graph)]);
+            }
+            Step::Visit(GraphPattern::Graph(name, p), _) => stack.push(Step::Visit(p, Some(name))),
+            Step::Visit(GraphPattern::Filter(expression, p), graph) => {
+                stack.extend([Step::Filter(expression, graph), Step::Visit(p, graph)]);
+            }
+            Step::Visit(GraphPattern::Extend(p, variable, expression), graph) => {
+                stack.extend([Step::Extend(variable, expression), Step::Visit(p, graph)]);
+            }
+            Step::Visit(pattern, _) => return Err(unsupported(pattern)),
+            Step::Join => {
+                let b = done.pop().expect("both sides are done");
+                let a = done.pop().expect("both sides are done");
+                done.push(
+                    a.iter()
+                        .flat_map(|a| b.iter().filter_map(move |b| a.join(b)))
+                        .collect(),
+                );
+            }
+            Step::Optional => {
+                let b = done.pop().expect("both sides are done");
+                let a = done.pop().expect("both sides are done");
+                let with: Vec<Branch> = a
+                    .iter()
+                    .flat_map(|a| b.iter().filter_map(move |b| a.join(b)))
+                    .collect();
+                done.push(with.into_iter().chain(a).collect());
+            }
+            Step::Minus(pattern) => {
+                let triples = exists(pattern)?;
+                let shared = variables(triples.iter().copied());
+                let mut removes = false;
+                for branch in done.last_mut().expect("the pattern is done") {
+                    // a MINUS only removes matches it shares a variable with
+                    if !shared.is_disjoint(&branch.bound()) {
+                        branch.exclude(triples.clone());
+                        removes = true;
+                    }
+                }
+                if !removes {
+                    return Err(types::InvalidRule::MinusSharesNothing);
+                }
+            }
+            Step::Filter(expression, graph) => {
+                let (exists_in, conditions): (Vec<_>, Vec<_>) = fold::conditions(expression)
+                    .into_iter()
+                    .partition(|condition| matches!(condition, Expression::Exists(_)));
+                let mut required = Vec::new();
+                for condition in exists_in {
+                    if let Expression::Exists(pattern) = condition {
+                        let quads = exists(pattern)?.into_iter().map(|tp| (tp, graph));
+                        required.push(quads.collect::<Vec<_>>());
+                    }
+                }
+                let (absent, conditions): (Vec<_>, Vec<_>) = conditions
+                    .into_iter()
+                    .partition(|condition| not_exists(condition).is_some());
+                let absent = absent
+                    .into_iter()
+                    .filter_map(not_exists)
+                    .map(exists)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut kept = Vec::new();
+                for mut branch in done.pop().expect("the pattern is done") {
+                    // the rest of the FILTER can't see what EXISTS binds
+                    let matched = branch.matched();
+                    match fold::filter(&conditions, &mut branch.values, &matched, functions) {
+                        Ok(true) => {
+                            for triples in &absent {
+                                branch.exclude(triples.clone());
+                            }
+                            for quads in &required {
+                                branch.require(quads.clone());
+                            }
+                            kept.push(branch);
+                        }
+                        Ok(false) => {}
+                        Err(condition) => return Err(unsupported_filter(condition)),
+                    }
+                }
+                done.push(kept);
+            }
+            Step::Extend(variable, expression) => {
+                for branch in done.last_mut().expect("the pattern is done") {
+                    let value = fold::evaluate(expression, &branch.values, functions)
+                        .ok_or_else(|| unfoldable(variable))?;
+                    branch.values.insert(variable.as_str(), value);
+                }
+            }
+            Step::Union => {
+                let b = done.pop().expect("both sides are done");
+                let mut a = done.pop().expect("both sides are done");
+                a.extend(b);
+                done.push(a);
+            }
+        }
+    }
+    let branches = done.pop().expect("the whole clause is done");
+    for branch in &branches {
+        let bound = branch.bound();
+        for absent in &branch.absent {
+            if let Some(name) = absent.local.iter().find(|v| bound.contains(*v)) {
+                let name = name.to_string();
+                return Err(types::InvalidRule::NegationScope { name });
+            }
+        }
+    }
+    Ok(branches)
+}
+
 /// convert an oxigraph basic graph pattern to a graph usable in as a rify `if_all` or `then` clause
-pub fn to_rify_pattern(bgp: &[TriplePattern]) -> Vec<rify::Claim<rify::Entity<String, RdfNode>>> {
-    bgp.iter().map(to_rify_triple).collect()
+pub fn to_rify_pattern<'a>(
+    bgp: impl IntoIterator<Item = &'a TriplePattern>,
+) -> Vec<rify::Claim<rify::Entity<String, RdfNode>>> {
+    bgp.into_iter().map(to_rify_triple).collect()
 }
 
 fn to_rify_triple(trpl: &TriplePattern) -> rify::Claim<rify::Entity<String, RdfNode>> {
@@ -36,44 +539,46 @@ fn to_rify_triple(trpl: &TriplePattern) -> rify::Claim<rify::Entity<String, RdfN
 
 fn tov_to_rify_entity(patt: &TermOrVariable) -> rify::Entity<String, types::RdfNode> {
     match patt {
-        TermOrVariable::Term(t) => rify::Entity::Bound(t.clone().into()),
-        TermOrVariable::Variable(v) => rify::Entity::Unbound(v.name.clone()),
+        TermOrVariable::Term(t) => rify::Entity::Bound(t.into()),
+        TermOrVariable::Variable(v) => rify::Entity::Unbound(v.as_str().to_string()),
     }
 }
 
-fn nnov_to_rify_entity(patt: &NamedNodeOrVariable) -> rify::Entity<String, types::RdfNode> {
+pub fn nnov_to_rify_entity(patt: &NamedNodeOrVariable) -> rify::Entity<String, types::RdfNode> {
     match patt {
         NamedNodeOrVariable::NamedNode(nn) => {
-            rify::Entity::Bound(types::RdfNode::Iri(nn.iri.clone()))
+            rify::Entity::Bound(types::RdfNode::Iri(nn.as_str().to_string()))
         }
-        NamedNodeOrVariable::Variable(v) => rify::Entity::Unbound(v.name.clone()),
+        NamedNodeOrVariable::Variable(v) => rify::Entity::Unbound(v.as_str().to_string()),
     }
 }
 
-impl From<Term> for RdfNode {
-    fn from(t: Term) -> Self {
+/// Terms are only ever borrowed from the parsed query, so this is the only place strings are
+/// copied during conversion.
+impl From<&Term> for RdfNode {
+    fn from(t: &Term) -> Self {
         match t {
-            Term::NamedNode(iri) => Self::Iri(iri.iri),
+            Term::NamedNode(iri) => Self::Iri(iri.as_str().to_string()),
             Term::BlankNode(bn) => Self::Blank(bn.as_str().to_string()),
             Term::Literal(Literal {
                 0: LiteralContent::String(value),
             }) => Self::Literal {
-                value,
-                datatype: "http://www.w3.org/2001/XMLSchema#string".to_string(),
+                value: value.clone(),
+                datatype: Some(XSD_STRING.to_string()),
                 language: None,
             },
             Term::Literal(Literal {
                 0: LiteralContent::LanguageTaggedString { value, language },
             }) => Self::Literal {
-                value,
-                datatype: "http://www.w3.org/1999/02/22-rdf-syntax-ns#langString".to_string(),
-                language: Some(language),
+                value: value.clone(),
+                datatype: Some(RDF_LANG_STRING.to_string()),
+                language: Some(language.clone()),
             },
             Term::Literal(Literal {
                 0: LiteralContent::TypedLiteral { value, datatype },
             }) => Self::Literal {
-                value,
-                datatype: datatype.iri,
+                value: value.clone(),
+                datatype: Some(datatype.as_str().to_string()),
                 language: None,
             },
         }
@@ -0,0 +1,124 @@
+//! What changed between two builds of a ruleset. As with `order::stable_order`, rules with the
+//! same fingerprint are the same rule wherever they are, so renaming variables or reordering
+//! claims is no change. Rules written by `convert <FILE>...` name the file they came from, and a
+//! rule that is gone and one that is new from the same file are taken to be one rule that
+//! changed.
+
+use crate::fingerprint::fingerprint;
+use crate::types::RuleParts;
+use std::collections::{BTreeMap, VecDeque};
+
+/// A rule of a ruleset with the file it was converted from, if it says.
+#[derive(Debug, serde::Deserialize)]
+pub struct Sourced {
+    #[serde(flatten)]
+    pub rule: RuleParts,
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+/// How one rule changed, by index into the previous ruleset and the new one.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    Added { index: usize },
+    Removed { previous: usize },
+    Changed { previous: usize, index: usize },
+}
+
+/// The changes from `previous` to `rules`, each rule given with its source. Changes are in the
+/// order of `rules`, followed by the rules that were removed in the order of `previous`. A rule
+/// that changed is paired with the first rule removed from the same source.
+pub fn diff(
+    previous: &[(&RuleParts, Option<&str>)],
+    rules: &[(&RuleParts, Option<&str>)],
+) -> Vec<Change> {
+    let mut gone: BTreeMap<String, VecDeque<usize>> = BTreeMap::new();
+    for (i, (rule, _)) in previous.iter().enumerate() {
+        gone.entry(fingerprint(rule)).or_default().push_back(i);
+    }
+    let added: Vec<usize> = rules
+        .iter()
+        .enumerate()
+        .filter(|(_, (rule, _))| {
+            gone.get_mut(&fingerprint(rule))
+                .and_then(VecDeque::pop_front)
+                .is_none()
+        })
+        .map(|(i, _)| i)
+        .collect();
+    let mut removed: Vec<usize> = gone.into_values().flatten().collect();
+    removed.sort_unstable();
+    let mut ret = Vec::new();
+    for index in added {
+        let source = rules[index].1;
+        let same = removed
+            .iter()
+            .position(|&i| source.is_some() && previous[i].1 == source);
+        ret.push(match same {
+            Some(at) => Change::Changed {
+                previous: removed.remove(at),
+                index,
+            },
+            None => Change::Added { index },
+        });
+    }
+    ret.extend(
+        removed
+            .into_iter()
+            .map(|previous| Change::Removed { previous }),
+    );
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rify::Entity;
+
+    #[test]
+    fn changes() {
+        let rule = |a: &str, from: &str, to: &str| {
+            let unbd = |name: &str| Entity::Unbound(name.to_string());
+            let iri = |iri: &str| Entity::Bound(crate::RdfNode::Iri(iri.to_string()));
+            RuleParts {
+                if_all: vec![[unbd(a), iri(from), unbd("b")]],
+                then: vec![[unbd("b"), iri(to), unbd(a)]],
+            }
+        };
+        let previous = [
+            rule("a", "p", "q"),
+            rule("a", "q", "r"),
+            rule("a", "r", "s"),
+        ];
+        let previous = [
+            (&previous[0], Some("a.rq")),
+            (&previous[1], Some("b.rq")),
+            (&previous[2], Some("c.rq")),
+        ];
+        // a.rq only renamed a variable, b.rq was edited, c.rq deleted and d.rq added
+        let rules = [
+            rule("x", "p", "q"),
+            rule("a", "q", "z"),
+            rule("a", "x", "y"),
+        ];
+        let rules = [
+            (&rules[0], Some("a.rq")),
+            (&rules[1], Some("b.rq")),
+            (&rules[2], Some("d.rq")),
+        ];
+        assert_eq!(
+            diff(&previous, &rules),
+            [
+                Change::Changed {
+                    previous: 1,
+                    index: 1
+                },
+                Change::Added { index: 2 },
+                Change::Removed { previous: 2 },
+            ]
+        );
+        // without sources an edit is a removal and an addition
+        let unsourced: Vec<_> = rules.iter().map(|(rule, _)| (*rule, None)).collect();
+        assert_eq!(diff(&previous, &unsourced).len(), 4);
+    }
+}
@@ -0,0 +1,387 @@
+//! The rule format used by Dock's javascript claim deduction library. Claims there are quads
+//! rather than triples, the fourth element naming the graph the claim is in, and the default
+//! graph is a term of its own, `{"DefaultGraph": true}`. Rules over triples only ever match the
+//! default graph, `DockRule::from_quads` writes rules over other graphs.
+
+use crate::types::{RdfNode, RuleParts};
+use crate::util::as_unbound;
+use crate::Graph;
+use displaydoc::Display;
+use oxilangtag::LanguageTag;
+use rify::{Claim, Entity};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::str::FromStr;
+
+pub type DockClaim = [Entity<String, DockNode>; 4];
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct DockRule {
+    pub if_all: Vec<DockClaim>,
+    pub then: Vec<DockClaim>,
+}
+
+/// What `sparql2rify from-dock` reads, a single rule or a list of them.
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+pub enum DockRules {
+    One(DockRule),
+    Many(Vec<DockRule>),
+}
+
+/// An `RdfNode`, or the name of the default graph.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub enum DockNode {
+    Blank(String),
+    Iri(String),
+    Literal {
+        value: String,
+        datatype: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+    },
+    DefaultGraph(bool),
+}
+
+/// How the default graph is named in rules over quads, stores don't agree on it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DefaultGraph {
+    /// `{"DefaultGraph": true}`
+    #[default]
+    Sentinel,
+    /// a named graph the store uses as its default
+    Iri(String),
+    /// a variable, so claims outside GRAPH blocks match any graph, but all the same one
+    Variable(String),
+}
+
+impl FromStr for DefaultGraph {
+    type Err = String;
+
+    /// `default` for the sentinel, `?name` for a variable, or an absolute IRI
+    fn from_str(s: &str) -> Result<Self, String> {
+        if s == "default" {
+            Ok(Self::Sentinel)
+        } else if let Some(name) = s.strip_prefix('?').or_else(|| s.strip_prefix('$')) {
+            Ok(Self::Variable(name.to_string()))
+        } else if oxiri::Iri::parse(s).is_ok() {
+            Ok(Self::Iri(s.to_string()))
+        } else {
+            Err(format!("Invalid default graph \"{}\", try --help.", s))
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Display)]
+pub enum DockError {
+    /// Only claims in the default graph can be converted, found a claim in another graph.
+    NotDefaultGraph,
+    /// The default graph can only be used as the graph of a claim.
+    MisplacedDefaultGraph,
+    /// "{tag}" is not a valid BCP 47 language tag.
+    InvalidLanguageTag { tag: String },
+    /// The default graph variable ?{name} is already used by the rule.
+    DefaultGraphVariableInUse { name: String },
+    /// The default graph variable ?{name} would be unbound, no condition is in the default graph.
+    DefaultGraphVariableUnbound { name: String },
+    /// The conclusion graph variable ?{name} is not bound by any condition or GRAPH block.
+    ConclusionGraphUnbound { name: String },
+}
+
+impl Error for DockError {}
+
+impl From<&RuleParts> for DockRule {
+    fn from(rule: &RuleParts) -> Self {
+        let claims = |claims: &[Claim<Entity<String, RdfNode>>]| -> Vec<DockClaim> {
+            claims
+                .iter()
+                .map(|[s, p, o]| [to_dock(s), to_dock(p), to_dock(o), default_graph()])
+                .collect()
+        };
+        DockRule {
+            if_all: claims(&rule.if_all),
+            then: claims(&rule.then),
+        }
+    }
+}
+
+impl DockRule {
+    /// A rule over quads, `graphs` being the graph of each claim in `rule.if_all` as returned
+    /// by `convert_with` with `quads` set. Claims in the default graph are put in `default`.
+    pub fn from_quads(
+        rule: &RuleParts,
+        graphs: &[Graph],
+        default: &DefaultGraph,
+    ) -> Result<Self, DockError> {
+        Self::from_quads_into(rule, graphs, default, &None)
+    }
+
+    /// Like `from_quads`, with the conclusions in the graph `conclusions` rather than the
+    /// default graph. SPARQL has no way to write GRAPH in a CONSTRUCT template, so rules scoped
+    /// to a named graph, e.g. one bound by `GRAPH ?g` in the WHERE clause, that should derive
+    /// claims in it say so here.
+    pub fn from_quads_into(
+        rule: &RuleParts,
+        graphs: &[Graph],
+        default: &DefaultGraph,
+        conclusions: &Graph,
+    ) -> Result<Self, DockError> {
+        if let Some(Entity::Unbound(name)) = conclusions {
+            let mut bound = rule.if_all.iter().flatten().chain(graphs.iter().flatten());
+            if !bound.any(|ent| as_unbound(ent) == Some(name)) {
+                let name = name.clone();
+                return Err(DockError::ConclusionGraphUnbound { name });
+            }
+        }
+        let default = match default {
+            DefaultGraph::Sentinel => default_graph(),
+            DefaultGraph::Iri(iri) => Entity::Bound(DockNode::Iri(iri.clone())),
+            DefaultGraph::Variable(name) => {
+                let entities = rule.if_all.iter().chain(&rule.then).flatten();
+                if entities
+                    .chain(graphs.iter().flatten())
+                    .chain(conclusions)
+                    .any(|ent| as_unbound(ent) == Some(name))
+                {
+                    let name = name.clone();
+                    return Err(DockError::DefaultGraphVariableInUse { name });
+                }
+                let in_default = !rule.then.is_empty() && conclusions.is_none();
+                if in_default && graphs.iter().all(Option::is_some) {
+                    let name = name.clone();
+                    return Err(DockError::DefaultGraphVariableUnbound { name });
+                }
+                Entity::Unbound(name.clone())
+            }
+        };
+        let mut ret = DockRule::from(rule);
+        for claim in ret.if_all.iter_mut().chain(&mut ret.then) {
+            claim[3] = default.clone();
+        }
+        for (claim, graph) in ret.if_all.iter_mut().zip(graphs) {
+            if let Some(graph) = graph {
+                claim[3] = to_dock(graph);
+            }
+        }
+        if let Some(graph) = conclusions {
+            for claim in &mut ret.then {
+                claim[3] = to_dock(graph);
+            }
+        }
+        Ok(ret)
+    }
+}
+
+impl TryFrom<DockRule> for RuleParts {
+    type Error = DockError;
+
+    fn try_from(rule: DockRule) -> Result<Self, DockError> {
+        let claims = |claims: Vec<DockClaim>| -> Result<Vec<_>, DockError> {
+            claims
+                .into_iter()
+                .map(|[s, p, o, g]| {
+                    if g != default_graph() {
+                        return Err(DockError::NotDefaultGraph);
+                    }
+                    Ok([from_dock(s)?, from_dock(p)?, from_dock(o)?])
+                })
+                .collect()
+        };
+        Ok(RuleParts {
+            if_all: claims(rule.if_all)?,
+            then: claims(rule.then)?,
+        })
+    }
+}
+
+/// validate a tag and lowercase it, like the SPARQL parser does
+fn language_tag(tag: String) -> Result<String, DockError> {
+    match LanguageTag::parse(tag.to_ascii_lowercase()) {
+        Ok(tag) => Ok(tag.into_inner()),
+        Err(_) => Err(DockError::InvalidLanguageTag { tag }),
+    }
+}
+
+fn default_graph() -> Entity<String, DockNode> {
+    Entity::Bound(DockNode::DefaultGraph(true))
+}
+
+fn to_dock(ent: &Entity<String, RdfNode>) -> Entity<String, DockNode> {
+    match ent {
+        Entity::Unbound(name) => Entity::Unbound(name.clone()),
+        Entity::Bound(RdfNode::Blank(name)) => Entity::Bound(DockNode::Blank(name.clone())),
+        Entity::Bound(RdfNode::Iri(iri)) => Entity::Bound(DockNode::Iri(iri.clone())),
+        Entity::Bound(
+            node @ RdfNode::Literal {
+                value, language, ..
+            },
+        ) => Entity::Bound(DockNode::Literal {
+            value: value.clone(),
+            datatype: node.datatype().expect("literals have one").to_string(),
+            language: language.clone(),
+        }),
+    }
+}
+
+fn from_dock(ent: Entity<String, DockNode>) -> Result<Entity<String, RdfNode>, DockError> {
+    let node = match ent {
+        Entity::Unbound(name) => return Ok(Entity::Unbound(name)),
+        Entity::Bound(node) => node,
+    };
+    Ok(Entity::Bound(match node {
+        DockNode::Blank(name) => RdfNode::Blank(name),
+        DockNode::Iri(iri) => RdfNode::Iri(iri),
+        DockNode::Literal {
+            value,
+            datatype,
+            language,
+        } => RdfNode::Literal {
+            value,
+            datatype: Some(datatype),
+            language: language.map(language_tag).transpose()?,
+        },
+        DockNode::DefaultGraph(_) => return Err(DockError::MisplacedDefaultGraph),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::RDF_LANG_STRING;
+
+    #[test]
+    fn round_trip() {
+        let rule = RuleParts {
+            if_all: vec![[
+                Entity::Unbound("a".to_string()),
+                Entity::Bound(RdfNode::Iri("http://example.com/p".to_string())),
+                Entity::Bound(RdfNode::Literal {
+                    value: "hi".to_string(),
+                    datatype: Some(RDF_LANG_STRING.to_string()),
+                    language: Some("en".to_string()),
+                }),
+            ]],
+            then: vec![[
+                Entity::Unbound("a".to_string()),
+                Entity::Bound(RdfNode::Iri("http://example.com/p".to_string())),
+                Entity::Unbound("a".to_string()),
+            ]],
+        };
+        let dock = DockRule::from(&rule);
+        assert_eq!(
+            serde_json::to_value(&dock).unwrap()["then"],
+            serde_json::json!([[
+                {"Unbound": "a"},
+                {"Bound": {"Iri": "http://example.com/p"}},
+                {"Unbound": "a"},
+                {"Bound": {"DefaultGraph": true}},
+            ]])
+        );
+        assert_eq!(RuleParts::try_from(dock.clone()).unwrap(), rule);
+
+        let mut named = dock.clone();
+        named.then[0][3] = Entity::Unbound("g".to_string());
+        assert_eq!(
+            RuleParts::try_from(named).unwrap_err(),
+            DockError::NotDefaultGraph
+        );
+
+        let mut misplaced = dock.clone();
+        misplaced.then[0][2] = default_graph();
+        assert_eq!(
+            RuleParts::try_from(misplaced).unwrap_err(),
+            DockError::MisplacedDefaultGraph
+        );
+
+        let mut tagged = dock;
+        let literal = |language: &str| {
+            Entity::Bound(DockNode::Literal {
+                value: "hi".to_string(),
+                datatype: RDF_LANG_STRING.to_string(),
+                language: Some(language.to_string()),
+            })
+        };
+        tagged.if_all[0][2] = literal("EN");
+        assert_eq!(RuleParts::try_from(tagged.clone()).unwrap(), rule);
+        tagged.if_all[0][2] = literal("en_GB");
+        assert_eq!(
+            RuleParts::try_from(tagged).unwrap_err(),
+            DockError::InvalidLanguageTag {
+                tag: "en_GB".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn default_graphs() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| Entity::Bound(RdfNode::Iri(iri.to_string()));
+        let rule = RuleParts {
+            if_all: vec![
+                [unbd("a"), iri("http://example.com/p"), unbd("b")],
+                [unbd("b"), iri("http://example.com/p"), unbd("c")],
+            ],
+            then: vec![[unbd("a"), iri("http://example.com/p"), unbd("c")]],
+        };
+        let graphs = [None, Some(iri("http://example.com/g"))];
+        let graph_of = |default: &str| {
+            let default = default.parse().unwrap();
+            let dock = DockRule::from_quads(&rule, &graphs, &default).unwrap();
+            assert_eq!(
+                dock.if_all[1][3],
+                Entity::Bound(DockNode::Iri("http://example.com/g".to_string()))
+            );
+            assert_eq!(dock.if_all[0][3], dock.then[0][3]);
+            dock.then[0][3].clone()
+        };
+        assert_eq!(graph_of("default"), default_graph());
+        assert_eq!(
+            graph_of("http://example.com/default"),
+            Entity::Bound(DockNode::Iri("http://example.com/default".to_string()))
+        );
+        assert_eq!(graph_of("?graph"), Entity::Unbound("graph".to_string()));
+        assert!("not an iri".parse::<DefaultGraph>().is_err());
+
+        let variable = DefaultGraph::Variable("a".to_string());
+        assert_eq!(
+            DockRule::from_quads(&rule, &graphs, &variable).unwrap_err(),
+            DockError::DefaultGraphVariableInUse {
+                name: "a".to_string()
+            }
+        );
+        let variable = DefaultGraph::Variable("graph".to_string());
+        let named = [graphs[1].clone(), graphs[1].clone()];
+        assert_eq!(
+            DockRule::from_quads(&rule, &named, &variable).unwrap_err(),
+            DockError::DefaultGraphVariableUnbound {
+                name: "graph".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn conclusion_graphs() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| Entity::Bound(RdfNode::Iri(iri.to_string()));
+        let rule = RuleParts {
+            if_all: vec![[unbd("a"), iri("http://example.com/parent"), unbd("b")]],
+            then: vec![[unbd("b"), iri("http://example.com/child"), unbd("a")]],
+        };
+        let graphs = [Some(unbd("g"))];
+        let default = DefaultGraph::Variable("default".to_string());
+        let dock = DockRule::from_quads_into(&rule, &graphs, &default, &graphs[0]).unwrap();
+        assert_eq!(dock.then[0][3], Entity::Unbound("g".to_string()));
+        let named = Some(iri("http://example.com/inferred"));
+        let dock = DockRule::from_quads_into(&rule, &graphs, &default, &named).unwrap();
+        assert_eq!(
+            dock.then[0][3],
+            Entity::Bound(DockNode::Iri("http://example.com/inferred".to_string()))
+        );
+        assert_eq!(
+            DockRule::from_quads_into(&rule, &[None], &default, &Some(unbd("g"))).unwrap_err(),
+            DockError::ConclusionGraphUnbound {
+                name: "g".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! The vocabulary of the data behind a SPARQL endpoint, to check rules against before they are
+//! deployed there. A condition using a predicate or class the data never uses can't match, which
+//! usually means an IRI is misspelled. Fetching needs the `endpoint` feature, which enables
+//! oxigraph's HTTP client.
+
+use std::collections::BTreeSet;
+
+/// The predicates and classes used in the data behind an endpoint.
+#[derive(Debug, Default, PartialEq)]
+pub struct Seen {
+    pub predicates: BTreeSet<String>,
+    /// objects of rdf:type claims
+    pub classes: BTreeSet<String>,
+}
+
+#[cfg(feature = "endpoint")]
+impl Seen {
+    /// Ask `endpoint` for every predicate and class in its data. The queries are sent through a
+    /// SERVICE clause and have to look at all of the data, so a large endpoint may take a while.
+    pub fn fetch(endpoint: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        use crate::schema::RDF_TYPE;
+        use oxigraph::model::{NamedNode, Term};
+        use oxigraph::sparql::QueryResults;
+        use oxigraph::MemoryStore;
+
+        let endpoint = NamedNode::new(endpoint)?;
+        let store = MemoryStore::new();
+        let iris = |pattern: String| -> Result<BTreeSet<String>, Box<dyn std::error::Error>> {
+            // the subquery is sent as is, so only distinct IRIs come back
+            let query = format!(
+                "SELECT ?x WHERE {{ SERVICE {} {{ SELECT DISTINCT ?x WHERE {{ {} }} }} }}",
+                endpoint, pattern
+            );
+            let mut ret = BTreeSet::new();
+            if let QueryResults::Solutions(solutions) = store.query(query.as_str())? {
+                for solution in solutions {
+                    if let Some(Term::NamedNode(iri)) = solution?.get("x") {
+                        ret.insert(iri.as_str().to_string());
+                    }
+                }
+            }
+            Ok(ret)
+        };
+        Ok(Self {
+            predicates: iris("?s ?x ?o".to_string())?,
+            classes: iris(format!("?s <{}> ?x", RDF_TYPE))?,
+        })
+    }
+}
@@ -0,0 +1,828 @@
+//! Long form explanations of conversion errors and lints, printed by `sparql2rify --explain`.
+//! The one line messages say what went wrong, these say why and how to rewrite the query.
+
+use crate::types::InvalidRule;
+
+impl InvalidRule {
+    /// the stable code used to look up an explanation of this error
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::MustBeConstruct => "E001",
+            Self::IllegalFrom => "E002",
+            Self::IllegalBaseIri => "E003",
+            Self::IllegalPathPattern => "E005",
+            Self::UnboundImplied { .. } => "E006",
+            Self::NameCollision { .. } => "E007",
+            Self::BlankNodeImplied { .. } => "E008",
+            Self::InvalidIri { .. } => "E009",
+            Self::NondeterministicFunction { .. } => "E010",
+            Self::NamespaceNotAllowed { .. } => "E011",
+            Self::NamespaceDenied { .. } => "E012",
+            Self::CollectionImplied => "E013",
+            Self::NeverMatches => "E014",
+            Self::UnfoldableBind { .. } => "E015",
+            Self::NegationNeedsExtended => "E016",
+            Self::NotStratified => "E017",
+            Self::MinusSharesNothing => "E018",
+            Self::NegationScope { .. } => "E019",
+            Self::LiteralTooLong { .. } => "E020",
+            Self::UnsupportedUnion => "E021",
+            Self::UnsupportedValues => "E022",
+            Self::UnsupportedOptional => "E023",
+            Self::UnsupportedGraph => "E024",
+            Self::UnsupportedFilter { .. } => "E025",
+            Self::UnsupportedExistsPattern => "E026",
+            Self::UnsupportedSubquery => "E027",
+            Self::UnsupportedService => "E028",
+            Self::UnsupportedAggregate { .. } => "E029",
+            Self::UnsupportedModifier { .. } => "E030",
+        }
+    }
+}
+
+/// The explanation of an error code or lint name, codes are case insensitive.
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(code))
+        .map(|(_, text)| *text)
+}
+
+/// Every code that has an explanation.
+pub fn codes() -> impl Iterator<Item = &'static str> {
+    EXPLANATIONS.iter().map(|(code, _)| *code)
+}
+
+const EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "E001",
+        r#"Only CONSTRUCT queries can be converted.
+
+A rule derives new claims from the claims it matches. A CONSTRUCT query has both
+halves: the WHERE clause is what the rule matches and the template is what it
+derives. SELECT, ASK and DESCRIBE queries have no template, so there is nothing
+for the rule to derive.
+
+Erroneous example:
+
+    SELECT ?a WHERE { ?a <http://example.com/parent> ?b . }
+
+Write the claims the rule should derive as a CONSTRUCT template:
+
+    CONSTRUCT { ?b <http://example.com/child> ?a . }
+    WHERE { ?a <http://example.com/parent> ?b . }
+"#,
+    ),
+    (
+        "E002",
+        r#"FROM and FROM NAMED are not allowed.
+
+A rule is applied to whatever claims the reasoner is given, it can't choose
+which dataset it reads from. A FROM clause would be silently ignored, so it is
+rejected instead.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/q> ?b . }
+    FROM <http://example.com/data>
+    WHERE { ?a <http://example.com/p> ?b . }
+
+Remove the FROM clause and pass the data to the reasoner instead. To match
+claims in a particular graph, use a GRAPH block with --quads.
+
+Queries copied from elsewhere can be converted as they are with --allow-from,
+which ignores the FROM clauses. With --quads it keeps their meaning where a
+rule can: claims outside GRAPH blocks are matched in the single FROM graph,
+and GRAPH variables are bound to the single FROM NAMED graph.
+"#,
+    ),
+    (
+        "E003",
+        r#"The query has relative IRIs but no base to resolve them against.
+
+Rules only hold absolute IRIs. Relative IRIs in a query are resolved against
+the BASE it declares, or else the one passed with --base, before conversion.
+With neither there is nothing to resolve them against.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <q> ?b . } WHERE { ?a <p> ?b . }
+
+Declare a BASE:
+
+    BASE <http://example.com/>
+    CONSTRUCT { ?a <q> ?b . } WHERE { ?a <p> ?b . }
+
+pass one on the command line with --base http://example.com/, or write
+absolute IRIs, e.g. with a prefix:
+
+    PREFIX ex: <http://example.com/>
+    CONSTRUCT { ?a ex:q ?b . } WHERE { ?a ex:p ?b . }
+"#,
+    ),
+    (
+        "E005",
+        r#"Property paths other than fixed sequences are not allowed.
+
+Each condition of a rule is a single claim. A path such as ex:knows+ matches a
+chain of claims of any length, and ex:a|ex:b matches one of several claims,
+which no single rule can express. Sequences such as ex:a/ex:b are fine, they
+are split into one claim for each step.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/ancestor> ?b . }
+    WHERE { ?a <http://example.com/parent>+ ?b . }
+
+Write repetition as a recursive pair of rules:
+
+    CONSTRUCT { ?a <http://example.com/ancestor> ?b . }
+    WHERE { ?a <http://example.com/parent> ?b . }
+
+    CONSTRUCT { ?a <http://example.com/ancestor> ?c . }
+    WHERE { ?a <http://example.com/parent> ?b .
+            ?b <http://example.com/ancestor> ?c . }
+
+and alternatives as one rule for each alternative.
+"#,
+    ),
+    (
+        "E006",
+        r#"A variable in the CONSTRUCT template does not appear in the WHERE clause.
+
+A rule can only derive claims about things it matched. A variable that only
+appears in the template has no value, so the claim it is in can't be derived.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/q> ?c . }
+    WHERE { ?a <http://example.com/p> ?b . }
+
+This is usually a typo. Use a variable the WHERE clause binds:
+
+    CONSTRUCT { ?a <http://example.com/q> ?b . }
+    WHERE { ?a <http://example.com/p> ?b . }
+"#,
+    ),
+    (
+        "E007",
+        r#"A blank node has the same name as a variable.
+
+Blank nodes in the WHERE clause match anything, so they are converted to
+variables of the same name. A blank node _:x and a variable ?x would become the
+same variable and match the same thing, which is almost never what was meant.
+
+Erroneous example:
+
+    CONSTRUCT { ?x <http://example.com/q> ?y . }
+    WHERE { ?x <http://example.com/p> _:x . _:x <http://example.com/p> ?y . }
+
+Rename the blank node:
+
+    CONSTRUCT { ?x <http://example.com/q> ?y . }
+    WHERE { ?x <http://example.com/p> _:mid . _:mid <http://example.com/p> ?y . }
+"#,
+    ),
+    (
+        "E008",
+        r#"The CONSTRUCT template contains a blank node.
+
+SPARQL mints a fresh blank node each time the template is instantiated, but a
+rule derives the same claims every time it matches. A blank node in the
+template would name one node shared by every match, which merges things that
+should be distinct.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/address> [] . }
+    WHERE { ?a <http://example.com/street> ?street . }
+
+Derive claims about something the rule matched instead, or give the node an
+IRI:
+
+    CONSTRUCT { ?a <http://example.com/street> ?street . }
+    WHERE { ?a <http://example.com/address> ?address .
+            ?address <http://example.com/street> ?street . }
+"#,
+    ),
+    (
+        "E009",
+        r#"An IRI in the rule is not a valid absolute IRI.
+
+Rules are matched against claims by comparing IRIs exactly, so every IRI in a
+rule, including the datatypes of literals, must be absolute and well formed.
+The SPARQL parser already rejects bad IRIs in queries, this error comes from
+rules given in JSON.
+
+Erroneous example:
+
+    {"if_all": [[{"Unbound": "a"}, {"Bound": {"Iri": "example.com/p"}}, {"Unbound": "b"}]],
+     "then": []}
+
+Write the full IRI, including its scheme:
+
+    {"if_all": [[{"Unbound": "a"}, {"Bound": {"Iri": "http://example.com/p"}}, {"Unbound": "b"}]],
+     "then": []}
+"#,
+    ),
+    (
+        "E010",
+        r#"A FILTER or BIND calls a function that isn't deterministic.
+
+A reasoner applies a rule over and over until nothing new is derived, and two
+reasoners given the same data must derive the same claims. RAND(), NOW(),
+UUID() and STRUUID() give a different result each time they are called, so a
+rule using them would derive something different on every run and never
+settle.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/checkedAt> ?now . }
+    WHERE { ?a <http://example.com/status> ?status . BIND (NOW() AS ?now) }
+
+Put the value in the data the rule is applied to, e.g. a claim about when the
+data was collected, and match it:
+
+    CONSTRUCT { ?a <http://example.com/checkedAt> ?now . }
+    WHERE { ?a <http://example.com/status> ?status .
+            <http://example.com/dataset> <http://example.com/collectedAt> ?now . }
+"#,
+    ),
+    (
+        "E011",
+        r#"The rule uses an IRI outside the namespaces allowed by --namespaces.
+
+When the policy file lists namespaces under "allow", every IRI a rule uses as
+a subject, predicate or object must start with one of them, so a ruleset can
+only touch approved vocabularies. Datatypes of literals are not checked, use
+--datatypes to limit those.
+
+Erroneous example, with {"allow": ["https://schema.org/"]}:
+
+    CONSTRUCT { ?a <http://example.com/knows> ?b . }
+    WHERE { ?a <https://schema.org/knows> ?b . }
+
+Use a term from an allowed vocabulary, or add its namespace to the policy:
+
+    CONSTRUCT { ?b <https://schema.org/knows> ?a . }
+    WHERE { ?a <https://schema.org/knows> ?b . }
+"#,
+    ),
+    (
+        "E012",
+        r#"The rule uses an IRI in a namespace denied by --namespaces.
+
+IRIs in a namespace listed under "deny" may not appear in a rule at all. Those
+in a namespace listed under "deny_implied" may be matched but not appear in
+what the rule derives, which keeps rules from redefining terms other rules and
+reasoners rely on, e.g. those of rdf: and owl:.
+
+Erroneous example, with {"deny_implied": ["http://www.w3.org/2002/07/owl#"]}:
+
+    CONSTRUCT { ?a <http://www.w3.org/2002/07/owl#sameAs> ?b . }
+    WHERE { ?a <http://example.com/alias> ?b . }
+
+Derive a claim in a vocabulary of your own instead:
+
+    CONSTRUCT { ?a <http://example.com/sameAs> ?b . }
+    WHERE { ?a <http://example.com/alias> ?b . }
+"#,
+    ),
+    (
+        "E013",
+        r#"The CONSTRUCT template contains a collection.
+
+A collection, ( ?a ?b ), stands for a chain of blank nodes linked by rdf:first
+and rdf:rest. Like any other blank node in a template (see E008), each would
+name one node shared by every match, so the lists derived for different matches
+would be merged into one with several firsts and rests.
+
+Erroneous example:
+
+    CONSTRUCT { ?team <http://example.com/members> ( ?a ?b ) . }
+    WHERE { ?team <http://example.com/lead> ?a ; <http://example.com/deputy> ?b . }
+
+Derive the members directly, or match a list that is already in the data and
+derive claims about its nodes:
+
+    CONSTRUCT { ?team <http://example.com/member> ?a , ?b . }
+    WHERE { ?team <http://example.com/lead> ?a ; <http://example.com/deputy> ?b . }
+"#,
+    ),
+    (
+        "E014",
+        r#"The WHERE clause never matches.
+
+A FILTER whose value is known while converting is compiled away, see E025.
+When it is always false the rule could never fire, which is almost always a
+mistake, e.g. a comparison with a misspelled constant.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/q> ?b . }
+    WHERE { ?a <http://example.com/p> ?b .
+            BIND ("prod" AS ?env) FILTER (?env = "production") }
+
+Fix the comparison, or remove the rule.
+"#,
+    ),
+    (
+        "E015",
+        r#"A BIND can't be folded into the rule.
+
+A rule can't compute new terms. A BIND whose value is known while converting,
+e.g. BIND (<http://example.com/x> AS ?v) or one using only variables VALUES
+fix, is compiled away by putting its value in place of the variable
+everywhere. One computing a value from the claims the rule matches can't be.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/label> ?label . }
+    WHERE { ?a <http://example.com/name> ?name . BIND (UCASE(?name) AS ?label) }
+
+Have the data, or another rule, provide the value as a claim to match.
+"#,
+    ),
+    (
+        "E016",
+        r#"FILTER NOT EXISTS and MINUS need the extended rule format.
+
+A rify rule fires when all of its conditions hold, it has no way to say a claim
+must be missing. The extended format, written by --extended, adds an "if_none"
+section: a list of patterns, each the triples of a FILTER NOT EXISTS { .. } or
+MINUS { .. }, none of which may match for the rule to fire. Only reasoners with stratified
+negation can apply such rules, rify and Dock's claim deduction can't.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/trusted> true . }
+    WHERE { ?a <http://example.com/member> <http://example.com/org> .
+            FILTER NOT EXISTS { ?a <http://example.com/revoked> ?when } }
+
+Convert it with --extended for a reasoner that supports it, or have a claim say
+what the negation did, e.g. ?a <http://example.com/status> "active".
+"#,
+    ),
+    (
+        "E017",
+        r#"The rule derives claims its own negative conditions forbid.
+
+A rule in the extended format only fires when none of its negative conditions,
+its FILTER NOT EXISTS and MINUS blocks, match. If one of the claims it derives could match
+such a condition, what it derives could undo the reason it fired, and no order
+of applying rules, or strata, makes that consistent.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/reviewed> true . }
+    WHERE { ?a a <http://example.com/Report> .
+            FILTER NOT EXISTS { ?a <http://example.com/reviewed> true } }
+
+Derive a different claim than the one that must be missing, e.g.
+<http://example.com/needsReview>.
+"#,
+    ),
+    (
+        "E018",
+        r#"MINUS shares no variable with the pattern it is applied to.
+
+MINUS { .. } removes the matches of the pattern before it that agree with a
+match of its own on the variables they share. Sharing none, they agree on
+nothing, so it never removes a match and the rule would fire as if it weren't
+there. Unlike FILTER NOT EXISTS, it doesn't check whether its pattern matches
+anywhere at all.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/trusted> true . }
+    WHERE { ?a a <http://example.com/Issuer> .
+            MINUS { ?issuer <http://example.com/revoked> true } }
+
+Use the variable the pattern is about, here ?a instead of ?issuer, or FILTER
+NOT EXISTS if the rule must not fire once any match exists.
+"#,
+    ),
+    (
+        "E019",
+        r#"A variable of a negative condition is bound outside of it too.
+
+The variables of a FILTER NOT EXISTS or MINUS that the pattern it is applied to
+doesn't bind are local to it: they stand for any term, as ?when does in "?a has
+no revocation, whenever it was". A rule has a single scope for its variables
+though, so the same name can't also be bound elsewhere in the WHERE clause, as
+when the negation is in a group joined with triples binding it.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/trusted> true . }
+    WHERE { { ?a a <http://example.com/Issuer>
+              MINUS { ?a <http://example.com/revoked> ?when } }
+            ?a <http://example.com/registered> ?when . }
+
+Rename the variable in one of the places, or move the negation to the group
+that binds it if it should refer to the same term.
+"#,
+    ),
+    (
+        "E020",
+        r#"A literal is longer than --max-literal-length allows.
+
+Queries sometimes embed whole documents as literals, e.g. a base64 encoded
+credential, which can run to megabytes. A rule holding one is copied into
+every ruleset and every reasoner that loads it, so deployments that don't
+expect them set a limit on the length of literals, in bytes of UTF-8.
+
+Erroneous example, with --max-literal-length 16:
+
+    CONSTRUCT { ?a <http://example.com/trusted> true . }
+    WHERE { ?a <http://example.com/proof> "eyJhbGciOiJFUzI1NiJ9.eyJzdWIiOiJhIn0" . }
+
+Match a hash of the payload, or an identifier for it, rather than the payload
+itself, or raise the limit if such rules are intended.
+"#,
+    ),
+    (
+        "E021",
+        r#"UNION can't be converted to a single rule.
+
+A rule matches a fixed set of claims that must all be present, and a UNION
+matches one of several. Converting to rules, as the command line tool does
+unless given --quads or --extended, expands a UNION into a rule for each of
+its branches, sharing the conclusions the branch binds the variables of.
+
+Erroneous example, with --quads:
+
+    CONSTRUCT { ?a <http://example.com/related> ?b . }
+    WHERE { { ?a <http://example.com/parent> ?b }
+            UNION { ?a <http://example.com/sibling> ?b } }
+
+Convert without --quads, or write a query for each branch.
+"#,
+    ),
+    (
+        "E022",
+        r#"VALUES can't be converted to a single rule.
+
+VALUES matches one of several rows of terms. Converting to rules, as the
+command line tool does unless given --quads or --extended, expands it into a
+rule for each row, with the terms of the row in place of its variables.
+
+Erroneous example, with --quads:
+
+    CONSTRUCT { ?a <http://example.com/trusted> true . }
+    WHERE { ?a <http://example.com/issuer> ?issuer .
+            VALUES ?issuer { <http://example.com/a> <http://example.com/b> } }
+
+Convert without --quads, or write a query for each row.
+"#,
+    ),
+    (
+        "E023",
+        r#"OPTIONAL can't be converted to a single rule.
+
+A rule matches a fixed set of claims, and OPTIONAL matches its triples only if
+they are there. --split-optional converts a query with OPTIONAL into a rule
+with the optional triples and one without them, as a UNION of the two would
+be expanded, leaving out of the second the conclusions using variables only
+the optional triples bind. An OPTIONAL with a FILTER in it can't be split, the
+FILTER decides which of the two rules applies.
+
+Erroneous example, without --split-optional:
+
+    CONSTRUCT { ?a <http://example.com/contact> ?b . }
+    WHERE { ?a <http://example.com/email> ?b .
+            OPTIONAL { ?a <http://example.com/name> ?n } }
+
+Pass --split-optional, or drop the OPTIONAL if its triples aren't used in the
+template, as here.
+"#,
+    ),
+    (
+        "E024",
+        r#"GRAPH can only be converted to a rule over quads.
+
+rify rules match triples, which aren't in any graph. --quads converts to rules
+over quads instead, each condition in the graph of the GRAPH block it is in.
+
+Erroneous example, without --quads:
+
+    CONSTRUCT { ?a <http://example.com/trusted> true . }
+    WHERE { GRAPH <http://example.com/registry> { ?a a <http://example.com/Issuer> } }
+
+Pass --quads, or leave the GRAPH block out to match the triples in any graph.
+"#,
+    ),
+    (
+        "E025",
+        r#"A FILTER condition depends on the claims the rule matches.
+
+A rule has no conditions other than claims, so a FILTER is only converted if
+it can be compiled away: if its value is known while converting. It may use
+constants, variables VALUES or a BIND fix, =, !=, &&, ||, !, IN, sameTerm()
+and IF(). A FILTER (?x = <iri>), or = "string" or sameTerm(?x, ..), puts the
+constant in place of ?x. The triples of a FILTER EXISTS { .. } are added to
+the conditions, and FILTER NOT EXISTS is E016.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/adult> true . }
+    WHERE { ?a <http://example.com/age> ?age . FILTER (?age >= 18) }
+
+A FILTER usually becomes a claim that some other rule or the data provides:
+
+    CONSTRUCT { ?a <http://example.com/adult> true . }
+    WHERE { ?a <http://example.com/ageGroup> <http://example.com/Adult> . }
+"#,
+    ),
+    (
+        "E026",
+        r#"EXISTS, NOT EXISTS or MINUS holds more than triples.
+
+The triples of an EXISTS are added to the conditions of the rule, and those of
+a NOT EXISTS or MINUS become a negative condition with --extended. Either way
+they can only be triples, possibly grouped by extra braces, not OPTIONAL,
+FILTER, UNION or any other pattern.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/trusted> true . }
+    WHERE { ?a a <http://example.com/Issuer> .
+            FILTER EXISTS { ?a <http://example.com/audit> ?result
+                            FILTER (?result = "pass") } }
+
+Put the constant in place of the variable, here ?result.
+"#,
+    ),
+    (
+        "E027",
+        r#"Subqueries can't be converted.
+
+A rule has a single WHERE clause, with no way to project, order or limit the
+matches of part of it.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/trusted> true . }
+    WHERE { { SELECT ?a WHERE { ?a a <http://example.com/Issuer> } } }
+
+Write the triples of the subquery in the WHERE clause itself.
+"#,
+    ),
+    (
+        "E028",
+        r#"SERVICE can't be converted.
+
+A reasoner applies rules to the claims it holds, it never queries other
+endpoints, so a rule can't match claims elsewhere.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/trusted> true . }
+    WHERE { SERVICE <http://example.com/sparql> { ?a a <http://example.com/Issuer> } }
+
+Load the claims into the reasoner, and match them in the WHERE clause itself.
+"#,
+    ),
+    (
+        "E029",
+        r#"Aggregates and GROUP BY can't be converted.
+
+A rule fires for each match of its conditions on its own, so it can't count,
+sum or otherwise combine several matches, or group them.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/author> true . }
+    WHERE { ?a <http://example.com/wrote> ?b . }
+    GROUP BY ?a
+
+Leave GROUP BY out, a rule derives each claim once however many matches imply
+it. Compute any aggregate before reasoning, and match the claim holding it.
+"#,
+    ),
+    (
+        "E030",
+        r#"ORDER BY, LIMIT and OFFSET can't be converted.
+
+A rule derives every claim it can, each once, so the matches it derives them
+from have no order and can't be cut short.
+
+Erroneous example:
+
+    CONSTRUCT { ?a <http://example.com/trusted> true . }
+    WHERE { ?a a <http://example.com/Issuer> . }
+    LIMIT 10
+
+Leave the modifiers out.
+"#,
+    ),
+    (
+        "identity_rule",
+        r#"Every claim the rule derives is one it already matched (a warning).
+
+Such a rule never derives anything new. It usually comes from copying the
+WHERE clause into the template and forgetting to change it.
+
+Example:
+
+    CONSTRUCT { ?a <http://example.com/knows> ?b . }
+    WHERE { ?a <http://example.com/knows> ?b . }
+
+Derive the claim the rule was meant to, here the symmetric one:
+
+    CONSTRUCT { ?b <http://example.com/knows> ?a . }
+    WHERE { ?a <http://example.com/knows> ?b . }
+
+Use -A identity_rule to silence the warning.
+"#,
+    ),
+    (
+        "predicate_only_variable",
+        r#"A variable is only ever used as a predicate (a warning).
+
+A variable predicate that isn't constrained anywhere else matches every
+predicate in the data, so the rule applies far more widely than intended.
+
+Example:
+
+    CONSTRUCT { ?a <http://example.com/mentions> ?b . }
+    WHERE { ?a ?p ?b . }
+
+Name the predicate, or constrain the variable with another claim:
+
+    CONSTRUCT { ?a <http://example.com/mentions> ?b . }
+    WHERE { ?a ?p ?b .
+            ?p <http://www.w3.org/2000/01/rdf-schema#subPropertyOf>
+               <http://example.com/reference> . }
+
+Use -A predicate_only_variable to silence the warning.
+"#,
+    ),
+    (
+        "unlisted_datatype",
+        r#"A literal has a datatype that is not in the --datatypes list (a warning).
+
+Literals only match literals of exactly the same datatype, so a misspelled or
+unexpected datatype makes a rule silently match nothing.
+
+Example, with --datatypes listing xsd:integer:
+
+    CONSTRUCT { ?a <http://example.com/adult> true . }
+    WHERE { ?a <http://example.com/age> "18"^^<http://www.w3.org/2001/XMLSchema#int> . }
+
+Use a permitted datatype, or add the datatype to the list if it is intended.
+Plain and language tagged literals are always permitted. Use
+-D unlisted_datatype to make the warning an error.
+"#,
+    ),
+    (
+        "blank_node_constant",
+        r#"A rule holds a blank node rather than a variable (a warning).
+
+A blank node in a WHERE clause matches anything, so when a query is converted
+its blank nodes become variables and the rule means exactly what the query
+did. Rules given as JSON, in rify's or Dock's format, can still hold blank
+nodes, and a reasoner compares them by label like IRIs. Blank node labels only
+mean something within the document they appear in, so such a condition matches
+unrelated nodes from other documents that happen to share the label, and a
+conclusion holding one makes every derived claim name the same node.
+
+Example:
+
+    {"if_all": [[{"Unbound": "a"}, {"Bound": {"Iri": "http://example.com/p"}},
+                 {"Bound": {"Blank": "b"}}]],
+     "then": [[{"Unbound": "a"}, {"Bound": {"Iri": "http://example.com/q"}},
+               {"Bound": {"Iri": "http://example.com/o"}}]]}
+
+Use a variable to match anything, or an IRI to name a particular node:
+
+    {"if_all": [[{"Unbound": "a"}, {"Bound": {"Iri": "http://example.com/p"}},
+                 {"Unbound": "b"}]],
+     "then": [[{"Unbound": "a"}, {"Bound": {"Iri": "http://example.com/q"}},
+               {"Bound": {"Iri": "http://example.com/o"}}]]}
+
+Use -A blank_node_constant to silence the warning.
+"#,
+    ),
+    (
+        "unseen_term",
+        r#"A condition uses a predicate or class the --endpoint has no data for (a warning).
+
+A condition only matches claims that use exactly the same IRIs. If the data
+at the endpoint never uses a predicate, or never gives anything a class, a
+condition naming it can't match there, which usually means the IRI is
+misspelled.
+
+Example, against an endpoint holding schema.org data:
+
+    CONSTRUCT { ?a <http://schema.org/knows> ?b . }
+    WHERE { ?a <http://schema.org/spose> ?b . }
+
+Fix the IRI:
+
+    CONSTRUCT { ?a <http://schema.org/knows> ?b . }
+    WHERE { ?a <http://schema.org/spouse> ?b . }
+
+Only conditions are checked, a rule may derive claims the data doesn't have
+yet. Use -A unseen_term to silence the warning, e.g. for rules meant to run on
+data that will only be loaded later.
+"#,
+    ),
+    (
+        "malformed_language_tag",
+        r#"A literal has a language tag that is not well-formed BCP 47 (a warning).
+
+Language tagged literals only match literals with the same tag, compared as
+strings, and data from stores that validate tags never holds a malformed one.
+A tag with a subtag longer than eight letters, or with an underscore, probably
+comes from a mistake. --canonicalize language-tags fixes the case of well-formed
+tags only, malformed ones are left as they are.
+
+Example:
+
+    {"if_all": [[{"Unbound": "a"}, {"Bound": {"Iri": "http://example.com/label"}},
+                 {"Bound": {"Literal": {"value": "colour", "language": "en_GB"}}}]],
+     "then": [[{"Unbound": "a"}, {"Bound": {"Iri": "http://example.com/british"}},
+               {"Bound": {"Literal": {"value": "true"}}}]]}
+
+Use a well-formed tag:
+
+    {"if_all": [[{"Unbound": "a"}, {"Bound": {"Iri": "http://example.com/label"}},
+                 {"Bound": {"Literal": {"value": "colour", "language": "en-GB"}}}]],
+     "then": [[{"Unbound": "a"}, {"Bound": {"Iri": "http://example.com/british"}},
+               {"Bound": {"Literal": {"value": "true"}}}]]}
+
+Use -D malformed_language_tag to make the warning an error.
+"#,
+    ),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lint::LINTS;
+
+    #[test]
+    fn every_code_is_explained() {
+        let errors = [
+            InvalidRule::MustBeConstruct,
+            InvalidRule::IllegalFrom,
+            InvalidRule::IllegalBaseIri,
+            InvalidRule::IllegalPathPattern,
+            InvalidRule::UnboundImplied {
+                name: String::new(),
+            },
+            InvalidRule::NameCollision {
+                name: String::new(),
+            },
+            InvalidRule::BlankNodeImplied {
+                name: String::new(),
+            },
+            InvalidRule::InvalidIri { iri: String::new() },
+            InvalidRule::NondeterministicFunction {
+                function: String::new(),
+            },
+            InvalidRule::NamespaceNotAllowed { iri: String::new() },
+            InvalidRule::NamespaceDenied {
+                iri: String::new(),
+                namespace: String::new(),
+                usage: "use",
+            },
+            InvalidRule::CollectionImplied,
+            InvalidRule::NeverMatches,
+            InvalidRule::UnfoldableBind {
+                name: String::new(),
+            },
+            InvalidRule::NegationNeedsExtended,
+            InvalidRule::NotStratified,
+            InvalidRule::MinusSharesNothing,
+            InvalidRule::NegationScope {
+                name: String::new(),
+            },
+            InvalidRule::LiteralTooLong {
+                length: 0,
+                limit: 0,
+                literal: String::new(),
+            },
+            InvalidRule::UnsupportedUnion,
+            InvalidRule::UnsupportedValues,
+            InvalidRule::UnsupportedOptional,
+            InvalidRule::UnsupportedGraph,
+            InvalidRule::UnsupportedFilter {
+                expr: String::new(),
+            },
+            InvalidRule::UnsupportedExistsPattern,
+            InvalidRule::UnsupportedSubquery,
+            InvalidRule::UnsupportedService,
+            InvalidRule::UnsupportedAggregate {
+                name: String::new(),
+            },
+            InvalidRule::UnsupportedModifier { modifier: "LIMIT" },
+        ];
+        let mut codes: Vec<&str> = errors.iter().map(InvalidRule::code).collect();
+        codes.extend(LINTS);
+        assert_eq!(codes, super::codes().collect::<Vec<_>>());
+        assert_eq!(explain("e006"), explain("E006"));
+        assert_eq!(explain("E999"), None);
+    }
+}
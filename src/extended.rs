@@ -0,0 +1,93 @@
+//! Rules with negative conditions, for reasoners with stratified negation, e.g. to apply deny
+//! lists. The extended rule format is rify's with an `if_none` section, a list of patterns none
+//! of which may match for the rule to fire, each converted from a `FILTER NOT EXISTS { .. }` or a
+//! `MINUS { .. }`:
+//!
+//! ```text
+//! {"if_all": [..], "then": [..], "if_none": [[..], ..]}
+//! ```
+//!
+//! Variables of a pattern that aren't in `if_all` stand for any term, within the pattern only.
+//! rify, and the subcommands of the command line tool that read rify rules, can't apply these, so
+//! rules with negative conditions are only ever written in this format.
+
+use crate::output::Annotations;
+use crate::types::{InvalidRule, RdfNode, RuleParts};
+use rify::{Claim, Entity};
+use serde::{Deserialize, Serialize};
+
+/// Claims that match together.
+pub type Pattern = Vec<Claim<Entity<String, RdfNode>>>;
+
+/// A rule in the extended format.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtendedRule {
+    #[serde(flatten)]
+    pub rule: RuleParts,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub if_none: Vec<Pattern>,
+}
+
+impl ExtendedRule {
+    /// Check that the rule could be applied: it is a valid rify rule with negative conditions,
+    /// every IRI is absolute, and none of its conclusions could match a negative condition, as
+    /// then no stratification could order it.
+    pub fn check(&self) -> Result<(), InvalidRule> {
+        check(&self.rule, &self.if_none)
+    }
+
+    /// The negative conditions as the `if_all` of a rule deriving nothing, to check and rewrite
+    /// them as any other conditions.
+    pub fn negative(&self) -> RuleParts {
+        RuleParts {
+            if_all: self.if_none.concat(),
+            then: vec![],
+        }
+    }
+
+    /// Put the negative conditions back from a rewritten `negative()`.
+    pub fn set_negative(&mut self, negative: RuleParts) {
+        let mut claims = negative.if_all.into_iter();
+        for pattern in &mut self.if_none {
+            for claim in pattern {
+                *claim = claims
+                    .next()
+                    .expect("rewrites never change the number of claims");
+            }
+        }
+    }
+}
+
+/// `ExtendedRule::check` for a rule and negative conditions that aren't in one
+pub fn check(rule: &RuleParts, if_none: &[Pattern]) -> Result<(), InvalidRule> {
+    rule.check()?;
+    let negative = RuleParts {
+        if_all: if_none.concat(),
+        then: vec![],
+    };
+    negative.check_iris()?;
+    if rule.then.iter().any(|claim| {
+        negative
+            .if_all
+            .iter()
+            .any(|condition| unifies(claim, condition))
+    }) {
+        return Err(InvalidRule::NotStratified);
+    }
+    Ok(())
+}
+
+/// whether some claim could match both `a` and `b`, which don't share variables
+fn unifies(a: &Claim<Entity<String, RdfNode>>, b: &Claim<Entity<String, RdfNode>>) -> bool {
+    a.iter().zip(b).all(|pair| match pair {
+        (Entity::Bound(a), Entity::Bound(b)) => a == b,
+        _ => true,
+    })
+}
+
+/// whether a rule of a ruleset has negative conditions, which a rify rule can't have
+pub fn has_negation(rule: &Annotations) -> bool {
+    rule.get("if_none")
+        .and_then(|if_none| if_none.as_array())
+        .is_some_and(|if_none| !if_none.is_empty())
+}
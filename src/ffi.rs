@@ -0,0 +1,131 @@
+//! C bindings. The matching header is `sparql2rify.h`, regenerate it after changing this file
+//! with `cbindgen --config cbindgen.toml --output sparql2rify.h`.
+
+use crate::{parse, sparql2rify, Error};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    Ok = 0,
+    /// the query was a null pointer or was not valid utf-8
+    InvalidInput,
+    /// the query is not valid SPARQL
+    ParseError,
+    /// the query is valid SPARQL but has no rify equivalent
+    InvalidRule,
+}
+
+/// Convert a nul terminated SPARQL CONSTRUCT query to a rify rule.
+///
+/// On success `*out_json` is set to the rule as a nul terminated JSON string. Otherwise
+/// `*out_err` is set to a nul terminated description of the problem. Either string must be freed
+/// with `sparql2rify_free`. Lints are not reported.
+///
+/// # Safety
+///
+/// `sparql` must be null or point to a nul terminated string. `out_json` and `out_err` must be
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sparql2rify_convert(
+    sparql: *const c_char,
+    out_json: *mut *mut c_char,
+    out_err: *mut *mut c_char,
+) -> Status {
+    *out_json = ptr::null_mut();
+    *out_err = ptr::null_mut();
+    match convert(sparql) {
+        Ok(json) => {
+            *out_json = to_c_string(json);
+            Status::Ok
+        }
+        Err((status, message)) => {
+            *out_err = to_c_string(message);
+            status
+        }
+    }
+}
+
+/// Free a string returned by this library. Passing null does nothing.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by this library that has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn sparql2rify_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+unsafe fn convert(sparql: *const c_char) -> Result<String, (Status, String)> {
+    if sparql.is_null() {
+        return Err((Status::InvalidInput, "query is null".to_string()));
+    }
+    let sparql = CStr::from_ptr(sparql)
+        .to_str()
+        .map_err(|e| (Status::InvalidInput, e.to_string()))?;
+    let query = parse(sparql, None).map_err(error)?;
+    let (rule, _warnings) =
+        sparql2rify(query).map_err(|source| error(Error::InvalidRule { source }))?;
+    Ok(serde_json::to_string(&rule).expect("rules always serialize"))
+}
+
+fn error(err: Error) -> (Status, String) {
+    let status = match err {
+        Error::Parse { .. } => Status::ParseError,
+        Error::InvalidRule { .. } => Status::InvalidRule,
+    };
+    (status, err.to_string())
+}
+
+/// neither JSON nor error messages ever contain a nul byte
+fn to_c_string(s: String) -> *mut c_char {
+    CString::new(s).expect("no interior nul").into_raw()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn call(sparql: &CStr) -> (Status, Option<String>, Option<String>) {
+        let mut json = ptr::null_mut();
+        let mut err = ptr::null_mut();
+        unsafe {
+            let status = sparql2rify_convert(sparql.as_ptr(), &mut json, &mut err);
+            let take = |s: *mut c_char| {
+                let ret = (!s.is_null()).then(|| CStr::from_ptr(s).to_str().unwrap().to_string());
+                sparql2rify_free(s);
+                ret
+            };
+            (status, take(json), take(err))
+        }
+    }
+
+    #[test]
+    fn convert() {
+        let query = CString::new("CONSTRUCT { ?s ?p ?o . } WHERE { ?o ?p ?s . }").unwrap();
+        let (status, json, err) = call(&query);
+        assert_eq!(status, Status::Ok);
+        assert!(json.unwrap().starts_with("{\"if_all\":"));
+        assert_eq!(err, None);
+
+        let query = CString::new("SELECT * WHERE { ?o ?p ?s . }").unwrap();
+        let (status, json, err) = call(&query);
+        assert_eq!(status, Status::InvalidRule);
+        assert_eq!(json, None);
+        assert!(err.is_some());
+
+        let (status, _, _) = call(&CString::new("CONSTRUCT").unwrap());
+        assert_eq!(status, Status::ParseError);
+
+        // valid SPARQL, but a relative IRI with no base to resolve it against
+        let query = CString::new("CONSTRUCT { ?s <p> ?o . } WHERE { ?s <p> ?o . }").unwrap();
+        let (status, json, err) = call(&query);
+        assert_eq!(status, Status::InvalidRule);
+        assert_eq!(json, None);
+        assert!(err.unwrap().contains("base"));
+    }
+}
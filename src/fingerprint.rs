@@ -0,0 +1,266 @@
+//! Hashes of rules that don't depend on how their variables are named or in which order their
+//! claims are written, so rules that only differ in those ways can be deduplicated and content
+//! addressed as one.
+//!
+//! A rule is hashed in a canonical form. Variables are told apart by how they are used, refining
+//! that until it stops splitting them, and where that leaves a tie every way of breaking it is
+//! tried and the smallest result kept. The variables are then named `v0`, `v1`, ... in that
+//! order and the claims of each clause sorted, duplicates removed. Blank nodes, which only
+//! appear in rules read as JSON, are relabelled `_:b0`, `_:b1`, ... the same way.
+
+use crate::manifest;
+use crate::types::{RdfNode, RuleParts};
+use rify::{Claim, Entity};
+use std::collections::BTreeSet;
+
+/// `rule` in canonical form. Two rules have the same canonical form exactly when one can be
+/// turned into the other by renaming variables and blank nodes, and reordering or repeating
+/// claims.
+pub fn canonical_form(rule: &RuleParts) -> RuleParts {
+    let rule = RuleParts {
+        if_all: distinct(&rule.if_all),
+        then: distinct(&rule.then),
+    };
+    let names: Vec<Name> = rule
+        .if_all
+        .iter()
+        .chain(&rule.then)
+        .flatten()
+        .filter_map(name)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    // variables sort before blank nodes, refining keeps them apart
+    let colors = names
+        .iter()
+        .map(|name| usize::from(matches!(name, Name::Blank(_))))
+        .collect();
+    label(&rule, &names, colors).1
+}
+
+/// hex encoded SHA-256 of the canonical form of `rule` as rify JSON without whitespace
+pub fn fingerprint(rule: &RuleParts) -> String {
+    manifest::hash(&canonical_form(rule))
+}
+
+fn distinct(claims: &[Claim<Entity<String, RdfNode>>]) -> Vec<Claim<Entity<String, RdfNode>>> {
+    let mut ret: Vec<_> = Vec::new();
+    for claim in claims {
+        if !ret.contains(claim) {
+            ret.push(claim.clone());
+        }
+    }
+    ret
+}
+
+/// Something that can be renamed.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Name<'a> {
+    Var(&'a str),
+    Blank(&'a str),
+}
+
+fn name(ent: &Entity<String, RdfNode>) -> Option<Name<'_>> {
+    match ent {
+        Entity::Unbound(name) => Some(Name::Var(name)),
+        Entity::Bound(RdfNode::Blank(name)) => Some(Name::Blank(name)),
+        Entity::Bound(_) => None,
+    }
+}
+
+/// how a name appears in a claim, from its own point of view
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Term<'a> {
+    Bound(&'a RdfNode),
+    Var(usize),
+    This,
+}
+
+/// The canonical form of `rule` given a coloring of its names, along with its JSON for
+/// comparing candidates.
+fn label(rule: &RuleParts, names: &[Name], mut colors: Vec<usize>) -> (String, RuleParts) {
+    refine(rule, names, &mut colors);
+    let tied = (0..names.len()).find_map(|color| {
+        let class: Vec<usize> = (0..names.len()).filter(|&i| colors[i] == color).collect();
+        Some(class).filter(|class| class.len() > 1)
+    });
+    match tied {
+        None => rename(rule, names, &colors),
+        Some(class) => class
+            .into_iter()
+            .map(|chosen| {
+                let split = (0..names.len())
+                    .map(|i| {
+                        colors[i] * 2 + usize::from(i != chosen && colors[i] == colors[chosen])
+                    })
+                    .collect();
+                label(rule, names, split)
+            })
+            .min_by(|a, b| a.0.cmp(&b.0))
+            .expect("a tie has at least two members"),
+    }
+}
+
+/// Split colors by the claims each variable appears in, until that splits no further. Colors
+/// are left numbered from 0.
+fn refine(rule: &RuleParts, names: &[Name], colors: &mut Vec<usize>) {
+    loop {
+        let signatures: Vec<_> = names
+            .iter()
+            .enumerate()
+            .map(|(i, this)| {
+                let mut uses = Vec::new();
+                for (clause, claims) in [&rule.if_all, &rule.then].iter().enumerate() {
+                    for [s, p, o] in claims.iter() {
+                        if [s, p, o].iter().any(|ent| name(ent) == Some(*this)) {
+                            let term = |ent| term(ent, *this, names, colors);
+                            uses.push((clause, [term(s), term(p), term(o)]));
+                        }
+                    }
+                }
+                uses.sort();
+                (colors[i], uses)
+            })
+            .collect();
+        let distinct: Vec<_> = signatures
+            .iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let before = colors.iter().collect::<BTreeSet<_>>().len();
+        let refined: Vec<usize> = signatures
+            .iter()
+            .map(|sig| distinct.binary_search(&sig).expect("listed"))
+            .collect();
+        *colors = refined;
+        if distinct.len() == before {
+            return;
+        }
+    }
+}
+
+fn term<'a>(
+    ent: &'a Entity<String, RdfNode>,
+    this: Name,
+    names: &[Name],
+    colors: &[usize],
+) -> Term<'a> {
+    match (name(ent), ent) {
+        (Some(name), _) if name == this => Term::This,
+        (Some(name), _) => Term::Var(colors[names.binary_search(&name).expect("listed")]),
+        (None, Entity::Bound(node)) => Term::Bound(node),
+        (None, Entity::Unbound(_)) => unreachable!("variables are names"),
+    }
+}
+
+/// Name each variable `v{color}` and each blank node `_:b{n}` in order of color, colors being
+/// distinct, and sort the claims.
+fn rename(rule: &RuleParts, names: &[Name], colors: &[usize]) -> (String, RuleParts) {
+    let vars = names
+        .iter()
+        .filter(|name| matches!(name, Name::Var(_)))
+        .count();
+    let entity = |ent: &Entity<String, RdfNode>| match name(ent) {
+        Some(Name::Var(name)) => {
+            let color = colors[names.binary_search(&Name::Var(name)).expect("listed")];
+            Entity::Unbound(format!("v{}", color))
+        }
+        Some(Name::Blank(name)) => {
+            let color = colors[names.binary_search(&Name::Blank(name)).expect("listed")];
+            Entity::Bound(RdfNode::Blank(format!("b{}", color - vars)))
+        }
+        None => ent.clone(),
+    };
+    let claims = |claims: &[Claim<Entity<String, RdfNode>>]| {
+        let mut claims: Vec<(String, Claim<Entity<String, RdfNode>>)> = claims
+            .iter()
+            .map(|[s, p, o]| {
+                let claim = [entity(s), entity(p), entity(o)];
+                (
+                    serde_json::to_string(&claim).expect("claims always serialize"),
+                    claim,
+                )
+            })
+            .collect();
+        claims.sort_by(|a, b| a.0.cmp(&b.0));
+        claims.into_iter().map(|(_, claim)| claim).collect()
+    };
+    let rule = RuleParts {
+        if_all: claims(&rule.if_all),
+        then: claims(&rule.then),
+    };
+    let json = serde_json::to_string(&rule).expect("rules always serialize");
+    (json, rule)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn invariant_under_renaming_and_reordering() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri =
+            |suffix: &str| Entity::Bound(RdfNode::Iri(format!("http://example.com/{}", suffix)));
+        let rule = RuleParts {
+            if_all: vec![
+                [unbd("a"), iri("parent"), unbd("b")],
+                [unbd("b"), iri("parent"), unbd("c")],
+            ],
+            then: vec![[unbd("a"), iri("grandparent"), unbd("c")]],
+        };
+        let renamed = RuleParts {
+            if_all: vec![
+                [unbd("y"), iri("parent"), unbd("z")],
+                [unbd("x"), iri("parent"), unbd("y")],
+                [unbd("x"), iri("parent"), unbd("y")],
+            ],
+            then: vec![[unbd("x"), iri("grandparent"), unbd("z")]],
+        };
+        assert_eq!(canonical_form(&rule), canonical_form(&renamed));
+        assert_eq!(fingerprint(&rule), fingerprint(&renamed));
+
+        let reversed = RuleParts {
+            then: vec![[unbd("c"), iri("grandparent"), unbd("a")]],
+            ..rule.clone()
+        };
+        assert_ne!(fingerprint(&rule), fingerprint(&reversed));
+
+        let blank = |name: &str| Entity::Bound(RdfNode::Blank(name.to_string()));
+        let with_blank = |name: &str| RuleParts {
+            if_all: vec![[unbd("a"), iri("parent"), blank(name)]],
+            then: vec![[unbd("a"), iri("child"), blank(name)]],
+        };
+        assert_eq!(fingerprint(&with_blank("x")), fingerprint(&with_blank("y")));
+        assert_eq!(canonical_form(&with_blank("x")).then[0][2], blank("b0"));
+    }
+
+    #[test]
+    fn symmetric_rules() {
+        // every variable looks the same until one is picked out
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let knows = Entity::Bound(RdfNode::Iri("http://example.com/knows".to_string()));
+        let cycle = |names: [&str; 3]| RuleParts {
+            if_all: vec![
+                [unbd(names[0]), knows.clone(), unbd(names[1])],
+                [unbd(names[1]), knows.clone(), unbd(names[2])],
+                [unbd(names[2]), knows.clone(), unbd(names[0])],
+            ],
+            then: vec![],
+        };
+        let canonical = canonical_form(&cycle(["a", "b", "c"]));
+        assert_eq!(canonical, canonical_form(&cycle(["c", "a", "b"])));
+        assert_eq!(canonical, canonical_form(&cycle(["b", "c", "a"])));
+        assert_eq!(canonical, canonical_form(&canonical));
+
+        let triangle = RuleParts {
+            if_all: vec![
+                [unbd("a"), knows.clone(), unbd("b")],
+                [unbd("b"), knows.clone(), unbd("c")],
+                [unbd("a"), knows.clone(), unbd("c")],
+            ],
+            then: vec![],
+        };
+        assert_ne!(canonical, canonical_form(&triangle));
+    }
+}
@@ -0,0 +1,419 @@
+//! Constant folding of the expressions in FILTER and BIND. A rule can't evaluate expressions, but
+//! one whose value is already known while converting, because it only uses constants and
+//! variables that VALUES or an earlier BIND fix, can be compiled away: a BIND becomes the term it
+//! binds, and a FILTER keeps or drops the branch of the WHERE clause it is in. Few SPARQL
+//! operators are built in, embedders add the custom functions of their own vocabularies, e.g. for
+//! minting IRIs, with `Functions`.
+
+use crate::types::{RdfNode, RDF_LANG_STRING, XSD_STRING};
+use oxigraph::model::Term;
+use oxigraph::sparql::algebra::{Expression, Function as Builtin};
+use oxigraph::sparql::Variable;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+const XSD_BOOLEAN: &str = "http://www.w3.org/2001/XMLSchema#boolean";
+
+/// expressions nested deeper than this aren't folded, so evaluating them can't overflow the stack
+const DEPTH: usize = 256;
+
+/// A custom SPARQL function that can be called while converting a query.
+pub trait Function: Send + Sync {
+    /// The result of calling the function with `arguments`, or None if it has none for them or
+    /// it can only be known once the rule is applied. A call that can't be folded keeps the query
+    /// from being converted.
+    fn call(&self, arguments: &[RdfNode]) -> Option<RdfNode>;
+}
+
+impl<F> Function for F
+where
+    F: Fn(&[RdfNode]) -> Option<RdfNode> + Send + Sync,
+{
+    fn call(&self, arguments: &[RdfNode]) -> Option<RdfNode> {
+        self(arguments)
+    }
+}
+
+/// The custom functions conversion may call, by IRI.
+#[derive(Default)]
+pub struct Functions {
+    by_iri: BTreeMap<String, Box<dyn Function>>,
+}
+
+impl Functions {
+    /// Fold calls to the function named by `iri` with `function`, in place of any registered
+    /// for it before.
+    pub fn register(
+        &mut self,
+        iri: impl Into<String>,
+        function: impl Function + 'static,
+    ) -> &mut Self {
+        self.by_iri.insert(iri.into(), Box::new(function));
+        self
+    }
+
+    pub fn contains(&self, iri: &str) -> bool {
+        self.by_iri.contains_key(iri)
+    }
+}
+
+impl fmt::Debug for Functions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.by_iri.keys()).finish()
+    }
+}
+
+/// Whether `expression` only uses operators and functions that can be folded, without looking at
+/// its variables. Checked before conversion so queries that can't be folded are rejected early.
+pub(crate) fn supported(expression: &Expression, functions: &Functions) -> bool {
+    let mut stack = vec![expression];
+    while let Some(expression) = stack.pop() {
+        match expression {
+            Expression::NamedNode(_) | Expression::Literal(_) | Expression::Variable(_) => {}
+            Expression::Or(a, b)
+            | Expression::And(a, b)
+            | Expression::Equal(a, b)
+            | Expression::NotEqual(a, b) => {
+                stack.push(b);
+                stack.push(a);
+            }
+            Expression::In(a, list) | Expression::NotIn(a, list) => {
+                stack.extend(list);
+                stack.push(a);
+            }
+            Expression::UnaryNot(a) => stack.push(a),
+            Expression::FunctionCall(function, arguments) => {
+                match function {
+                    Builtin::SameTerm | Builtin::If => {}
+                    Builtin::Custom(iri) if functions.contains(iri.as_str()) => {}
+                    _ => return false,
+                }
+                stack.extend(arguments);
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// the `&&` conditions of a FILTER, in order
+pub(crate) fn conditions(expression: &Expression) -> Vec<&Expression> {
+    let mut ret = Vec::new();
+    let mut stack = vec![expression];
+    while let Some(expression) = stack.pop() {
+        match expression {
+            Expression::And(a, b) => {
+                stack.push(b);
+                stack.push(a);
+            }
+            _ => ret.push(expression),
+        }
+    }
+    ret
+}
+
+/// Fold the `conditions` of a FILTER in a branch of the WHERE clause where `bindings` fixes some
+/// variables and the triples bind those in `matched`. Each condition that only says a matched
+/// variable is some constant, `?x = <iri>`, `?x = "string"` or `sameTerm(?x, c)`, fixes the
+/// variable to that constant in `bindings`, as VALUES would. Returns whether the rest are true,
+/// or the first whose value can't be known while converting. Other literals are left alone,
+/// since `=` compares their values and "1"^^xsd:integer = "01"^^xsd:integer.
+pub(crate) fn filter<'a>(
+    conditions: &[&'a Expression],
+    bindings: &mut BTreeMap<&'a str, RdfNode>,
+    matched: &BTreeSet<&str>,
+    functions: &Functions,
+) -> Result<bool, &'a Expression> {
+    let mut fixed = bindings.clone();
+    let mut rest = Vec::new();
+    for &condition in conditions {
+        let fixes = equality(condition).and_then(|(variable, constant, any_term)| {
+            let name = variable.as_str();
+            let constant = evaluate(constant, bindings, functions)?;
+            let fixes = (any_term || exact(&constant))
+                && matched.contains(name)
+                && !bindings.contains_key(name);
+            Some((name, constant)).filter(|_| fixes)
+        });
+        match fixes {
+            Some((name, constant)) => {
+                if *fixed.entry(name).or_insert_with(|| constant.clone()) != constant {
+                    return Ok(false);
+                }
+            }
+            None => rest.push(condition),
+        }
+    }
+    for condition in rest {
+        let truth = evaluate(condition, &fixed, functions)
+            .as_ref()
+            .and_then(truth)
+            .ok_or(condition)?;
+        if !truth {
+            return Ok(false);
+        }
+    }
+    *bindings = fixed;
+    Ok(true)
+}
+
+/// The variable and the other side of `?x = ..` or `sameTerm(?x, ..)`, with whether it is
+/// sameTerm.
+fn equality(condition: &Expression) -> Option<(&Variable, &Expression, bool)> {
+    match condition {
+        Expression::Equal(a, b) => match (&**a, &**b) {
+            (Expression::Variable(v), c) | (c, Expression::Variable(v)) => Some((v, c, false)),
+            _ => None,
+        },
+        Expression::FunctionCall(Builtin::SameTerm, arguments) => match &arguments[..] {
+            [Expression::Variable(v), c] | [c, Expression::Variable(v)] => Some((v, c, true)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// whether `=` only holds for `term` itself
+fn exact(term: &RdfNode) -> bool {
+    match term {
+        RdfNode::Literal {
+            datatype: Some(datatype),
+            ..
+        } => datatype == XSD_STRING || datatype == RDF_LANG_STRING,
+        _ => true,
+    }
+}
+
+/// The value of `expression` with the variables in `bindings` bound to their terms, None if it
+/// can't be known while converting. Unlike SPARQL, an error anywhere makes the whole expression
+/// unknown, even where `||` or `&&` would not need it.
+pub(crate) fn evaluate(
+    expression: &Expression,
+    bindings: &BTreeMap<&str, RdfNode>,
+    functions: &Functions,
+) -> Option<RdfNode> {
+    Evaluator {
+        bindings,
+        functions,
+    }
+    .value(expression, 0)
+}
+
+/// The effective boolean value of a term, only known for xsd:boolean literals.
+pub(crate) fn truth(term: &RdfNode) -> Option<bool> {
+    match term {
+        RdfNode::Literal {
+            value,
+            datatype: Some(datatype),
+            ..
+        } if datatype == XSD_BOOLEAN => match value.as_str() {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+struct Evaluator<'a> {
+    bindings: &'a BTreeMap<&'a str, RdfNode>,
+    functions: &'a Functions,
+}
+
+impl<'a> Evaluator<'a> {
+    fn value(&self, expression: &Expression, depth: usize) -> Option<RdfNode> {
+        if depth > DEPTH {
+            return None;
+        }
+        let value = |expression| self.value(expression, depth + 1);
+        let truth = |expression| value(expression).as_ref().and_then(truth);
+        match expression {
+            Expression::NamedNode(iri) => Some(RdfNode::Iri(iri.as_str().to_string())),
+            Expression::Literal(literal) => Some((&Term::from(literal.clone())).into()),
+            Expression::Variable(variable) => self.bindings.get(variable.as_str()).cloned(),
+            Expression::Or(a, b) => Some(boolean(truth(a)? || truth(b)?)),
+            Expression::And(a, b) => Some(boolean(truth(a)? && truth(b)?)),
+            Expression::Equal(a, b) => Some(boolean(equal(&value(a)?, &value(b)?)?)),
+            Expression::NotEqual(a, b) => Some(boolean(!equal(&value(a)?, &value(b)?)?)),
+            Expression::In(a, list) | Expression::NotIn(a, list) => {
+                let a = value(a)?;
+                let mut found = false;
+                for b in list {
+                    found |= equal(&a, &value(b)?)?;
+                }
+                Some(boolean(found == matches!(expression, Expression::In(..))))
+            }
+            Expression::UnaryNot(a) => Some(boolean(!truth(a)?)),
+            Expression::FunctionCall(function, arguments) => match (function, &arguments[..]) {
+                (Builtin::SameTerm, [a, b]) => Some(boolean(value(a)? == value(b)?)),
+                (Builtin::If, [condition, then, otherwise]) => {
+                    value(if truth(condition)? { then } else { otherwise })
+                }
+                (Builtin::Custom(iri), arguments) => {
+                    let function = self.functions.by_iri.get(iri.as_str())?;
+                    let arguments: Vec<RdfNode> =
+                        arguments.iter().map(value).collect::<Option<_>>()?;
+                    function.call(&arguments)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+fn boolean(value: bool) -> RdfNode {
+    RdfNode::Literal {
+        value: value.to_string(),
+        datatype: Some(XSD_BOOLEAN.to_string()),
+        language: None,
+    }
+}
+
+/// `a = b` as in SPARQL, None where that depends on how the values of literals compare, which is
+/// only known for strings.
+fn equal(a: &RdfNode, b: &RdfNode) -> Option<bool> {
+    let string = |datatype: &Option<String>| {
+        matches!(
+            datatype.as_deref(),
+            None | Some(XSD_STRING) | Some(RDF_LANG_STRING)
+        )
+    };
+    match (a, b) {
+        (
+            RdfNode::Literal {
+                value,
+                datatype,
+                language,
+            },
+            RdfNode::Literal {
+                value: other_value,
+                datatype: other_datatype,
+                language: other_language,
+            },
+        ) => {
+            if string(datatype) && string(other_datatype) {
+                Some(value == other_value && language == other_language)
+            } else if a == b {
+                Some(true)
+            } else {
+                None
+            }
+        }
+        _ => Some(a == b),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{convert_with, sparql2rify, ConvertOptions, InvalidRule};
+    use rify::Entity;
+
+    #[test]
+    fn fold_filter_and_bind() {
+        let ex =
+            |suffix: &str| Entity::Bound(RdfNode::Iri(format!("http://example.com/{}", suffix)));
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let string = |value: &str| {
+            Entity::Bound(RdfNode::Literal {
+                value: value.to_string(),
+                datatype: Some(XSD_STRING.to_string()),
+                language: None,
+            })
+        };
+
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:q ?c . } WHERE { ?a ex:p ?b . BIND (ex:c AS ?c) }";
+        let (rule, _) = sparql2rify(query.parse().unwrap()).unwrap();
+        assert_eq!(rule.then, [[unbd("a"), ex("q"), ex("c")]]);
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:q ?c . } WHERE { BIND (\"x\" AS ?c) ?a ex:p ?c . }";
+        let (rule, _) = sparql2rify(query.parse().unwrap()).unwrap();
+        assert_eq!(rule.if_all, [[unbd("a"), ex("p"), string("x")]]);
+        assert_eq!(rule.then, [[unbd("a"), ex("q"), string("x")]]);
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:q ?t . }
+                     WHERE { ?a ex:p ?t ; ex:n ?n . FILTER (?t = ex:T && \"x\" = ?n) }";
+        let (rule, _) = sparql2rify(query.parse().unwrap()).unwrap();
+        assert_eq!(
+            rule.if_all,
+            [
+                [unbd("a"), ex("p"), ex("T")],
+                [unbd("a"), ex("n"), string("x")]
+            ]
+        );
+        assert_eq!(rule.then, [[unbd("a"), ex("q"), ex("T")]]);
+        let query = "CONSTRUCT { ?a ?b ?c . } WHERE { ?a ?b ?c . FILTER (sameTerm(?c, 1)) }";
+        assert!(sparql2rify(query.parse().unwrap()).is_ok());
+        for (filter, expr) in [
+            (
+                "?c = 1",
+                "(?c = \"1\"^^<http://www.w3.org/2001/XMLSchema#integer>)",
+            ),
+            ("?c = <http://example.com/c> && ?a != ?b", "(?a != ?b)"),
+        ] {
+            let query = format!(
+                "CONSTRUCT {{ ?a ?b ?c . }} WHERE {{ ?a ?b ?c . FILTER ({}) }}",
+                filter
+            );
+            assert_eq!(
+                sparql2rify(query.parse().unwrap()).unwrap_err(),
+                InvalidRule::UnsupportedFilter {
+                    expr: expr.to_string()
+                }
+            );
+        }
+        let query = "CONSTRUCT { ?a ?b ?d . } WHERE { ?a ?b ?c . BIND (?c AS ?d) }";
+        assert_eq!(
+            sparql2rify(query.parse().unwrap()).unwrap_err(),
+            InvalidRule::UnfoldableBind {
+                name: "d".to_string()
+            }
+        );
+        let query =
+            "CONSTRUCT { ?a ?b ?c . } WHERE { ?a ?b ?c . FILTER (\"a\" IN (\"b\", \"c\")) }";
+        assert_eq!(
+            sparql2rify(query.parse().unwrap()).unwrap_err(),
+            InvalidRule::NeverMatches
+        );
+
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:team ?team . }
+                     WHERE { ?a ex:dept ?name .
+                             VALUES ?name { \"sales\" \"legal\" \"ops\" }
+                             BIND (ex:mint(?name) AS ?team)
+                             FILTER (?name != \"ops\") }";
+        let expand = ConvertOptions {
+            expand: true,
+            ..ConvertOptions::default()
+        };
+        assert_eq!(
+            convert_with(query.parse().unwrap(), &expand).unwrap_err(),
+            InvalidRule::UnfoldableBind {
+                name: "team".to_string()
+            }
+        );
+        let mut functions = Functions::default();
+        functions.register(
+            "http://example.com/mint",
+            |arguments: &[RdfNode]| match arguments {
+                [RdfNode::Literal { value, .. }] => {
+                    Some(RdfNode::Iri(format!("http://example.com/{}", value)))
+                }
+                _ => None,
+            },
+        );
+        let options = ConvertOptions {
+            functions,
+            ..expand
+        };
+        let rules = convert_with(query.parse().unwrap(), &options).unwrap();
+        let rules: Vec<_> = rules.into_iter().map(|converted| converted.rule).collect();
+        assert_eq!(rules.len(), 2);
+        for (rule, name) in rules.iter().zip(["sales", "legal"]) {
+            assert_eq!(rule.if_all, [[unbd("a"), ex("dept"), string(name)]]);
+            assert_eq!(rule.then, [[unbd("a"), ex("team"), ex(name)]]);
+        }
+    }
+}
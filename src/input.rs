@@ -0,0 +1,84 @@
+//! Telling apart the kinds of input the command line tool accepts, so queries, N3 rules and
+//! rules that were already converted can all be passed to the same command.
+
+use crate::n3::skip_space;
+use serde::de::IgnoredAny;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// a SPARQL CONSTRUCT query
+    Sparql,
+    /// a rule in rify's JSON format
+    Rule,
+    /// an N3 rule, see `n3`
+    N3,
+}
+
+/// Guess the kind of `text` from how it starts. Anything that isn't recognisably JSON or N3 is
+/// assumed to be SPARQL so errors are reported by the SPARQL parser.
+pub fn detect(text: &str) -> Kind {
+    let mut rest = skip_space(text);
+    // an N3 formula never starts with a string, so this catches malformed JSON too
+    let json_object = rest.starts_with('{') && rest[1..].trim_start().starts_with('"');
+    if rest.starts_with('[') || json_object || serde_json::from_str::<IgnoredAny>(text).is_ok() {
+        return Kind::Rule;
+    }
+    if rest.starts_with('{') || rest.starts_with("@prefix") || rest.starts_with("@base") {
+        return Kind::N3;
+    }
+    // SPARQL style prefixes are allowed in N3 too, look at what follows them
+    while let Some(declaration) = strip_keyword(rest, "PREFIX") {
+        match declaration.find('>') {
+            Some(end) => rest = skip_space(&declaration[end + 1..]),
+            None => break,
+        }
+    }
+    if rest.starts_with('{') {
+        Kind::N3
+    } else {
+        Kind::Sparql
+    }
+}
+
+/// `text` after `keyword`, which is matched ignoring case
+fn strip_keyword<'a>(text: &'a str, keyword: &str) -> Option<&'a str> {
+    let start = text.get(..keyword.len())?;
+    if start.eq_ignore_ascii_case(keyword) {
+        Some(&text[keyword.len()..])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_kinds() {
+        let cases = [
+            ("CONSTRUCT { } WHERE { }", Kind::Sparql),
+            (
+                "PREFIX ex: <http://example.com/>\nCONSTRUCT {} WHERE {}",
+                Kind::Sparql,
+            ),
+            (
+                "# comment\nprefix ex: <http://example.com/> construct {} where {}",
+                Kind::Sparql,
+            ),
+            ("{\"if_all\": [], \"then\": []}", Kind::Rule),
+            ("{\n  \"if_all\": [", Kind::Rule),
+            ("  {}", Kind::Rule),
+            ("{} => {} .", Kind::N3),
+            ("{ ?a ?b ?c } => { } .", Kind::N3),
+            ("@prefix ex: <http://example.com/> .\n{} => {} .", Kind::N3),
+            (
+                "PREFIX ex: <http://example.com/>\n{ ?a ex:p ?b } => {} .",
+                Kind::N3,
+            ),
+        ];
+        for (text, kind) in &cases {
+            assert_eq!(detect(text), *kind, "{}", text);
+        }
+    }
+}
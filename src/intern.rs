@@ -0,0 +1,531 @@
+//! Rules with their strings shared. A large ruleset repeats the same handful of IRIs thousands of
+//! times, and read as `RuleParts` each occurrence is a `String` of its own. `read` deserializes a
+//! ruleset straight into rules whose strings come from an `Interner`, so each distinct string is
+//! held once however often it occurs, and the owned copies are never made.
+
+use crate::tags::Selection;
+use crate::types::{RdfNode, RuleParts, RDF_LANG_STRING, XSD_STRING};
+use rify::{Claim, Entity};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserializer;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
+
+/// Deduplicates strings so that large rulesets, where the same handful of IRIs are repeated
+/// thousands of times, only hold one allocation per distinct string.
+#[derive(Debug, Default)]
+pub struct Interner(HashSet<Arc<str>>);
+
+/// An `RdfNode` whose strings are shared through an `Interner`. Comparing two nodes from the same
+/// interner is still a string comparison, but cloning one is cheap.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Node {
+    Blank(Arc<str>),
+    Iri(Arc<str>),
+    Literal {
+        value: Arc<str>,
+        datatype: Option<Arc<str>>,
+        language: Option<Arc<str>>,
+    },
+}
+
+impl Node {
+    /// the datatype of a literal, including an implicit one, see `RdfNode::datatype`
+    pub fn datatype(&self) -> Option<&str> {
+        match self {
+            Node::Literal {
+                datatype: Some(datatype),
+                ..
+            } => Some(datatype),
+            Node::Literal { language: None, .. } => Some(XSD_STRING),
+            Node::Literal { .. } => Some(RDF_LANG_STRING),
+            _ => None,
+        }
+    }
+}
+
+pub type InternedEntity = Entity<Arc<str>, Node>;
+
+/// A rule in rify's format, with its strings shared.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rule {
+    pub if_all: Vec<Claim<InternedEntity>>,
+    pub then: Vec<Claim<InternedEntity>>,
+}
+
+impl Interner {
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(shared) = self.0.get(s) {
+            return shared.clone();
+        }
+        let shared: Arc<str> = Arc::from(s);
+        self.0.insert(shared.clone());
+        shared
+    }
+
+    pub fn node(&mut self, node: &RdfNode) -> Node {
+        match node {
+            RdfNode::Blank(name) => Node::Blank(self.intern(name)),
+            RdfNode::Iri(iri) => Node::Iri(self.intern(iri)),
+            RdfNode::Literal {
+                value,
+                datatype,
+                language,
+            } => Node::Literal {
+                value: self.intern(value),
+                datatype: datatype.as_deref().map(|d| self.intern(d)),
+                language: language.as_deref().map(|l| self.intern(l)),
+            },
+        }
+    }
+
+    pub fn entity(&mut self, ent: &Entity<String, RdfNode>) -> InternedEntity {
+        match ent {
+            Entity::Unbound(name) => Entity::Unbound(self.intern(name)),
+            Entity::Bound(node) => Entity::Bound(self.node(node)),
+        }
+    }
+
+    pub fn claim(&mut self, claim: &Claim<Entity<String, RdfNode>>) -> Claim<InternedEntity> {
+        let [s, p, o] = claim;
+        [self.entity(s), self.entity(p), self.entity(o)]
+    }
+
+    /// `rule` with its strings shared, for rules that are already in memory
+    pub fn rule(&mut self, rule: &RuleParts) -> Rule {
+        Rule {
+            if_all: rule.if_all.iter().map(|claim| self.claim(claim)).collect(),
+            then: rule.then.iter().map(|claim| self.claim(claim)).collect(),
+        }
+    }
+}
+
+/// Read a ruleset in rify's format, a JSON array of rules, interning each string as it is read.
+/// Only the rules `select` selects by their tags are kept, the other annotations are skipped.
+/// Returns the rules along with how many the ruleset has. A rule with negative conditions is an
+/// error, as it is for the subcommands that read rules as `RuleParts`.
+pub fn read(
+    reader: impl Read,
+    select: &Selection,
+    interner: &mut Interner,
+) -> Result<(Vec<Rule>, usize), serde_json::Error> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let ret = Ruleset { select, interner }.deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(ret)
+}
+
+struct Ruleset<'a> {
+    select: &'a Selection,
+    interner: &'a mut Interner,
+}
+
+impl<'de> DeserializeSeed<'de> for Ruleset<'_> {
+    type Value = (Vec<Rule>, usize);
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Ruleset<'_> {
+    type Value = (Vec<Rule>, usize);
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of rules")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut rules = Vec::new();
+        let mut count = 0;
+        while let Some(read) = seq.next_element_seed(RuleSeed(&mut *self.interner))? {
+            if read.negative {
+                return Err(de::Error::custom(format!(
+                    "rule {} has negative conditions (if_none), only a reasoner for the extended \
+                     format can apply it",
+                    count
+                )));
+            }
+            count += 1;
+            let tags: BTreeSet<&str> = read.tags.iter().map(String::as_str).collect();
+            if self.select.selects(&tags) {
+                rules.push(read.rule);
+            }
+        }
+        Ok((rules, count))
+    }
+}
+
+/// A rule as read, with what decides whether it is kept.
+struct ReadRule {
+    rule: Rule,
+    tags: Vec<String>,
+    negative: bool,
+}
+
+struct RuleSeed<'a>(&'a mut Interner);
+
+impl<'de> DeserializeSeed<'de> for RuleSeed<'_> {
+    type Value = ReadRule;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RuleSeed<'_> {
+    type Value = ReadRule;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a rule")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut if_all, mut then) = (None, None);
+        let mut tags = Vec::new();
+        let mut negative = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "if_all" => if_all = Some(map.next_value_seed(Claims(&mut *self.0))?),
+                "then" => then = Some(map.next_value_seed(Claims(&mut *self.0))?),
+                // as `tags::of` reads them, anything but an array of strings is no tags
+                "tags" => {
+                    if let serde_json::Value::Array(values) = map.next_value()? {
+                        let values = values.into_iter();
+                        tags.extend(values.filter_map(|tag| tag.as_str().map(str::to_string)));
+                    }
+                }
+                "if_none" => {
+                    let if_none = map.next_value::<Vec<IgnoredAny>>()?;
+                    negative = !if_none.is_empty();
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        let rule = Rule {
+            if_all: if_all.ok_or_else(|| de::Error::missing_field("if_all"))?,
+            then: then.ok_or_else(|| de::Error::missing_field("then"))?,
+        };
+        Ok(ReadRule {
+            rule,
+            tags,
+            negative,
+        })
+    }
+}
+
+struct Claims<'a>(&'a mut Interner);
+
+impl<'de> DeserializeSeed<'de> for Claims<'_> {
+    type Value = Vec<Claim<InternedEntity>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Claims<'_> {
+    type Value = Vec<Claim<InternedEntity>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of claims")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut ret = Vec::new();
+        while let Some(claim) = seq.next_element_seed(ClaimSeed(&mut *self.0))? {
+            ret.push(claim);
+        }
+        Ok(ret)
+    }
+}
+
+struct ClaimSeed<'a>(&'a mut Interner);
+
+impl<'de> DeserializeSeed<'de> for ClaimSeed<'_> {
+    type Value = Claim<InternedEntity>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ClaimSeed<'_> {
+    type Value = Claim<InternedEntity>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a claim of three entities")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut entity = |i| {
+            seq.next_element_seed(EntitySeed(&mut *self.0))?
+                .ok_or_else(|| de::Error::invalid_length(i, &self))
+        };
+        let (s, p, o) = (entity(0)?, entity(1)?, entity(2)?);
+        if seq.next_element::<IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(4, &self));
+        }
+        Ok([s, p, o])
+    }
+}
+
+/// `{"Unbound": name}` or `{"Bound": node}`, as rify serializes an `Entity`
+struct EntitySeed<'a>(&'a mut Interner);
+
+impl<'de> DeserializeSeed<'de> for EntitySeed<'_> {
+    type Value = InternedEntity;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntitySeed<'_> {
+    type Value = InternedEntity;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an Unbound or Bound entity")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let variant = map.next_key::<String>()?;
+        let ret = match variant.as_deref() {
+            Some("Unbound") => Entity::Unbound(map.next_value_seed(Str(&mut *self.0))?),
+            Some("Bound") => Entity::Bound(map.next_value_seed(NodeSeed(&mut *self.0))?),
+            Some(other) => return Err(de::Error::unknown_variant(other, &["Unbound", "Bound"])),
+            None => return Err(de::Error::invalid_length(0, &self)),
+        };
+        if map.next_key::<IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(2, &self));
+        }
+        Ok(ret)
+    }
+}
+
+/// an `RdfNode` as serde serializes it
+struct NodeSeed<'a>(&'a mut Interner);
+
+impl<'de> DeserializeSeed<'de> for NodeSeed<'_> {
+    type Value = Node;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for NodeSeed<'_> {
+    type Value = Node;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a Blank, Iri or Literal node")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let variant = map.next_key::<String>()?;
+        let ret = match variant.as_deref() {
+            Some("Blank") => Node::Blank(map.next_value_seed(Str(&mut *self.0))?),
+            Some("Iri") => Node::Iri(map.next_value_seed(Str(&mut *self.0))?),
+            Some("Literal") => map.next_value_seed(LiteralSeed(&mut *self.0))?,
+            Some(other) => {
+                return Err(de::Error::unknown_variant(
+                    other,
+                    &["Blank", "Iri", "Literal"],
+                ))
+            }
+            None => return Err(de::Error::invalid_length(0, &self)),
+        };
+        if map.next_key::<IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(2, &self));
+        }
+        Ok(ret)
+    }
+}
+
+struct LiteralSeed<'a>(&'a mut Interner);
+
+impl<'de> DeserializeSeed<'de> for LiteralSeed<'_> {
+    type Value = Node;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for LiteralSeed<'_> {
+    type Value = Node;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a literal")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut value, mut datatype, mut language) = (None, None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "value" => value = Some(map.next_value_seed(Str(&mut *self.0))?),
+                "datatype" => datatype = map.next_value_seed(OptionalStr(&mut *self.0))?,
+                "language" => language = map.next_value_seed(OptionalStr(&mut *self.0))?,
+                other => {
+                    let fields = &["value", "datatype", "language"];
+                    return Err(de::Error::unknown_field(other, fields));
+                }
+            }
+        }
+        Ok(Node::Literal {
+            value: value.ok_or_else(|| de::Error::missing_field("value"))?,
+            datatype,
+            language,
+        })
+    }
+}
+
+/// a string, interned without an owned copy of it being made first
+struct Str<'a>(&'a mut Interner);
+
+impl<'de> DeserializeSeed<'de> for Str<'_> {
+    type Value = Arc<str>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Str<'_> {
+    type Value = Arc<str>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+        Ok(self.0.intern(s))
+    }
+}
+
+/// a string or null
+struct OptionalStr<'a>(&'a mut Interner);
+
+impl<'de> DeserializeSeed<'de> for OptionalStr<'_> {
+    type Value = Option<Arc<str>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de> Visitor<'de> for OptionalStr<'_> {
+    type Value = Option<Arc<str>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a string or null")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        Str(self.0).deserialize(deserializer).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shares_repeated_strings() {
+        let iri = || Entity::Bound(RdfNode::Iri("http://example.com".to_string()));
+        let claims = [
+            [Entity::Unbound("a".to_string()), iri(), iri()],
+            [iri(), iri(), Entity::Unbound("a".to_string())],
+        ];
+
+        let mut interner = Interner::default();
+        let interned: Vec<_> = claims.iter().map(|c| interner.claim(c)).collect();
+        assert_eq!(interner.0.len(), 2);
+
+        let (first, second) = match (&interned[0][1], &interned[1][0]) {
+            (Entity::Bound(Node::Iri(a)), Entity::Bound(Node::Iri(b))) => (a, b),
+            _ => panic!(),
+        };
+        assert!(Arc::ptr_eq(first, second));
+    }
+
+    #[test]
+    fn reads_interned() {
+        let rule = |i: usize| {
+            serde_json::json!({
+                "if_all": [[
+                    {"Unbound": "a"},
+                    {"Bound": {"Iri": "http://example.com/memberOf"}},
+                    {"Bound": {"Literal": {"value": "x", "language": "en"}}},
+                ]],
+                "then": [[
+                    {"Unbound": "a"},
+                    {"Bound": {"Iri": "http://www.w3.org/1999/02/22-rdf-syntax-ns#type"}},
+                    {"Bound": {"Iri": "http://example.com/Member"}},
+                ]],
+                "tags": [(["even", "odd"][i % 2])],
+                "fingerprint": i.to_string(),
+            })
+        };
+        let ruleset = serde_json::Value::Array((0..1000).map(rule).collect()).to_string();
+        let owned: Vec<RuleParts> = serde_json::from_str(&ruleset).unwrap();
+
+        let mut interner = Interner::default();
+        let (rules, count) =
+            read(ruleset.as_bytes(), &Selection::default(), &mut interner).unwrap();
+        assert_eq!(count, 1000);
+        let mut expected = Interner::default();
+        let expected: Vec<Rule> = owned.iter().map(|rule| expected.rule(rule)).collect();
+        assert_eq!(rules, expected);
+
+        // read as `RuleParts` each rule holds 7 strings of its own, "a" twice, read interned the
+        // whole ruleset holds the 6 distinct ones once
+        let held: usize = interner.0.iter().map(|s| s.len()).sum();
+        let owned_bytes: usize = owned
+            .iter()
+            .flat_map(|rule| rule.if_all.iter().chain(&rule.then).flatten())
+            .map(|ent| match ent {
+                Entity::Unbound(name) => name.len(),
+                Entity::Bound(RdfNode::Iri(iri)) => iri.len(),
+                Entity::Bound(RdfNode::Literal {
+                    value, language, ..
+                }) => value.len() + language.as_ref().map_or(0, String::len),
+                Entity::Bound(RdfNode::Blank(name)) => name.len(),
+            })
+            .sum();
+        assert_eq!(interner.0.len(), 6);
+        assert_eq!(owned_bytes, (held + "a".len()) * 1000);
+        let iris = |rule: &Rule| match &rule.then[0][2] {
+            Entity::Bound(Node::Iri(iri)) => iri.clone(),
+            _ => panic!(),
+        };
+        assert!(Arc::ptr_eq(&iris(&rules[0]), &iris(&rules[999])));
+
+        let select = Selection {
+            include: std::iter::once("odd".to_string()).collect(),
+            ..Selection::default()
+        };
+        let (rules, count) = read(ruleset.as_bytes(), &select, &mut interner).unwrap();
+        assert_eq!((rules.len(), count), (500, 1000));
+
+        let negative = r#"[{"if_all": [], "then": [], "if_none": [[]]}]"#;
+        let err = read(negative.as_bytes(), &Selection::default(), &mut interner).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("rule 0 has negative conditions"));
+        let claim = r#"[{"if_all": [[{"Unbound": "a"}]], "then": []}]"#;
+        assert!(read(claim.as_bytes(), &Selection::default(), &mut interner).is_err());
+    }
+}
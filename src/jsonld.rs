@@ -0,0 +1,132 @@
+//! Rulesets as linked data, to publish alongside the ontologies they reason over. Each rule gets
+//! the fields of a JSON-LD node: its `@id` is its fingerprint, see `fingerprint`, it is
+//! `prov:wasDerivedFrom` the query it was converted from, identified by a hash of the query's
+//! text, and the query describes it in comments,
+//!
+//! ```text
+//! # @description Members of an organisation are trusted by it.
+//! ```
+//!
+//! which become its `dct:description`, several lines joined by spaces. Hashes are written as
+//! `urn:sha256:` URNs. The context only defines these fields, so JSON-LD processors read a rule
+//! as its documentation and skip the clauses of the rule itself.
+
+use crate::fingerprint::fingerprint;
+use crate::manifest;
+use crate::output::Annotations;
+use crate::types::RuleParts;
+use crate::util::comment_annotations;
+use serde_json::{json, Value};
+
+/// Dublin Core terms
+pub const DCT: &str = "http://purl.org/dc/terms/";
+/// the PROV ontology
+pub const PROV: &str = "http://www.w3.org/ns/prov#";
+
+/// What a query says about the rules converted from it, and which query it is.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Documentation {
+    pub description: Option<String>,
+    /// hex encoded SHA-256 of the text of the query
+    pub source: String,
+}
+
+impl Documentation {
+    pub fn parse(text: &str) -> Self {
+        let lines: Vec<&str> = comment_annotations(text)
+            .filter(|(_, name, line)| *name == "description" && !line.is_empty())
+            .map(|(_, _, line)| line)
+            .collect();
+        Self {
+            description: Some(lines.join(" ")).filter(|_| !lines.is_empty()),
+            source: manifest::digest(text.as_bytes()),
+        }
+    }
+}
+
+/// The JSON-LD fields of a rule converted from the queries `docs` document, more than one when
+/// it was merged from several. A field with several distinct values has them all, in order.
+pub fn annotations(rule: &RuleParts, docs: &[&Documentation]) -> Annotations {
+    let mut ret = Annotations::new();
+    ret.insert("@context".to_string(), json!({ "dct": DCT, "prov": PROV }));
+    ret.insert("@id".to_string(), urn(&fingerprint(rule)).into());
+    let descriptions = distinct(docs.iter().filter_map(|doc| doc.description.clone()));
+    if let Some(description) = one_or_many(descriptions.into_iter().map(Value::from).collect()) {
+        ret.insert("dct:description".to_string(), description);
+    }
+    let sources = distinct(docs.iter().map(|doc| urn(&doc.source)));
+    let sources = sources.into_iter().map(|id| json!({ "@id": id })).collect();
+    if let Some(sources) = one_or_many(sources) {
+        ret.insert("prov:wasDerivedFrom".to_string(), sources);
+    }
+    ret
+}
+
+fn urn(hash: &str) -> String {
+    format!("urn:sha256:{}", hash)
+}
+
+fn distinct(values: impl Iterator<Item = String>) -> Vec<String> {
+    let mut ret = Vec::new();
+    for value in values {
+        if !ret.contains(&value) {
+            ret.push(value);
+        }
+    }
+    ret
+}
+
+/// a single value as itself and several as an array, as JSON-LD writers usually do
+fn one_or_many(mut values: Vec<Value>) -> Option<Value> {
+    match values.len() {
+        0 => None,
+        1 => values.pop(),
+        _ => Some(values.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sparql2rify;
+
+    #[test]
+    fn documents_rules() {
+        let query = "# @description Members of an organisation\n\
+                     #  @description are trusted by it.\n\
+                     # @tags kyc\n\
+                     CONSTRUCT { ?a <http://example.com/trusts> ?b }\n\
+                     WHERE { ?a <http://example.com/member> ?b }";
+        let docs = Documentation::parse(query);
+        assert_eq!(
+            docs.description.as_deref(),
+            Some("Members of an organisation are trusted by it.")
+        );
+        assert_eq!(docs.source, manifest::digest(query.as_bytes()));
+        assert_eq!(Documentation::parse("# @description\n").description, None);
+
+        let (rule, _) = sparql2rify(query.parse().unwrap()).unwrap();
+        let id = format!("urn:sha256:{}", fingerprint(&rule));
+        let source = format!("urn:sha256:{}", docs.source);
+        assert_eq!(
+            Value::from(annotations(&rule, &[&docs, &docs])),
+            json!({
+                "@context": {
+                    "dct": "http://purl.org/dc/terms/",
+                    "prov": "http://www.w3.org/ns/prov#"
+                },
+                "@id": id,
+                "dct:description": "Members of an organisation are trusted by it.",
+                "prov:wasDerivedFrom": {"@id": source}
+            })
+        );
+
+        let other = Documentation::parse("CONSTRUCT {} WHERE {}");
+        let merged = annotations(&rule, &[&docs, &other]);
+        assert_eq!(
+            merged["prov:wasDerivedFrom"],
+            json!([{"@id": source}, {"@id": format!("urn:sha256:{}", other.source)}])
+        );
+        assert!(merged["dct:description"].is_string());
+    }
+}
@@ -0,0 +1,1265 @@
+//! Conversion of SPARQL CONSTRUCT queries to rify rules. The `sparql2rify` binary is a thin
+//! command line wrapper around this library.
+
+mod algebra;
+pub mod batch;
+pub mod canon;
+pub mod compose;
+mod convert;
+pub mod diff;
+pub mod dock;
+pub mod endpoint;
+pub mod explain;
+pub mod extended;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fingerprint;
+pub mod fold;
+pub mod input;
+pub mod intern;
+pub mod jsonld;
+pub mod lint;
+pub mod literals;
+pub mod manifest;
+pub mod mapping;
+pub mod merge;
+pub mod n3;
+#[cfg(feature = "node")]
+mod node;
+pub mod order;
+pub mod output;
+pub mod partition;
+pub mod provenance;
+pub mod reason;
+pub mod refactor;
+pub mod rulepack;
+pub mod sample;
+pub mod schema;
+pub mod simplify;
+pub mod specialize;
+#[cfg(feature = "store")]
+pub mod store;
+pub mod tags;
+pub mod typed;
+pub mod types;
+mod util;
+pub mod validity;
+pub mod vars;
+pub mod vocab;
+#[cfg(feature = "wasm")]
+mod wasm;
+pub mod weight;
+
+use crate::convert::{branches, nnov_to_rify_entity, scan, to_rify_pattern};
+use crate::extended::Pattern;
+use crate::fold::Functions;
+use crate::lint::Warning;
+pub use crate::types::{InvalidRule, RdfNode, RuleParts};
+use displaydoc::Display;
+use oxigraph::model::{GraphName, NamedOrBlankNode};
+use oxigraph::sparql::algebra::{GraphPattern, Query, QueryDataset, QueryVariants};
+use oxigraph::sparql::ParseError;
+use rify::{Entity, Rule};
+use std::collections::{BTreeMap, BTreeSet};
+
+const RDF_FIRST: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#first";
+
+/// The graph a claim of a rule over quads is in, None for the default graph.
+pub type Graph = Option<Entity<String, RdfNode>>;
+
+/// Why `convert` failed.
+#[derive(Debug, Display)]
+pub enum Error {
+    /// The query is not valid SPARQL: {source}
+    Parse { source: ParseError },
+    /// {source}
+    InvalidRule { source: InvalidRule },
+}
+
+impl std::error::Error for Error {}
+
+/// Parse a SPARQL CONSTRUCT query and convert it to a rify rule, for callers that need neither
+/// the lints nor a rule they can rewrite. `sparql2rify` gives both.
+pub fn convert(sparql: &str) -> Result<Rule<String, RdfNode>, Error> {
+    let query = parse(sparql, None)?;
+    let invalid = |source| Error::InvalidRule { source };
+    let (rule, _warnings) = sparql2rify(query).map_err(invalid)?;
+    rule.to_rule().map_err(invalid)
+}
+
+/// Parse a SPARQL query, resolving its relative IRIs against the BASE it declares, or `base` if
+/// it declares none, so conversion only ever sees absolute IRIs. A query with relative IRIs and
+/// no base to resolve them against is `IllegalBaseIri`.
+pub fn parse(sparql: &str, base: Option<&str>) -> Result<Query, Error> {
+    Query::parse(sparql, base).map_err(|source| {
+        // a query that parses once it has any base only failed for want of one
+        if base.is_none() && Query::parse(sparql, Some("http://example.invalid/")).is_ok() {
+            let source = InvalidRule::IllegalBaseIri;
+            Error::InvalidRule { source }
+        } else {
+            Error::Parse { source }
+        }
+    })
+}
+
+/// Convert a parsed query to a rule, along with any lints the rule triggers.
+pub fn sparql2rify(sparql: Query) -> Result<(RuleParts, Vec<Warning>), InvalidRule> {
+    let mut rules = convert_with(sparql, &ConvertOptions::default())?;
+    assert_eq!(rules.len(), 1, "only a UNION has several branches");
+    let ConvertedRule { rule, warnings, .. } = rules.pop().expect("just checked");
+    Ok((rule, warnings))
+}
+
+/// What `convert_with` converts besides the basic graph patterns, joins, FILTER EXISTS and the
+/// FILTERs and BINDs whose values are known while converting, see `fold`, that `sparql2rify`
+/// does. Everything is off by default.
+#[derive(Debug, Default)]
+pub struct ConvertOptions {
+    /// Rules over quads rather than triples. The WHERE clause may use GRAPH blocks, and each rule
+    /// has the graph of every claim in its `if_all`. Claims outside GRAPH blocks, and every claim
+    /// in `then`, are in the default graph.
+    pub quads: bool,
+    /// UNION and VALUES. Each way of picking one branch of every UNION and one row of every
+    /// VALUES block becomes a rule of its own, in the order they are written, with the terms of
+    /// the rows in place of their variables. The rules share the conclusions of the query,
+    /// except that, as in SPARQL, a conclusion using a variable only other branches bind is left
+    /// out, along with rules left with no conclusions. Rows of different VALUES blocks that give
+    /// a variable different terms match nothing, so they make no rule.
+    pub expand: bool,
+    /// With `expand`, split each OPTIONAL without a FILTER into a variant of the rules with its
+    /// triples, and one without them. As with UNION, the conclusions using variables only the
+    /// optional triples bind are left out of the variant without them.
+    pub optional: bool,
+    /// FILTER NOT EXISTS and MINUS, as negative conditions in the extended format, see
+    /// `extended`. They are checked like the rest, and a rule that could derive what they
+    /// forbid is `NotStratified`.
+    pub negation: bool,
+    /// Custom functions FILTER and BIND may call, which are folded like the builtin ones. A
+    /// branch a FILTER is false for makes no rule.
+    pub functions: Functions,
+}
+
+/// A rule converted by `convert_with`, and the lints it triggers.
+#[derive(Debug, PartialEq)]
+pub struct ConvertedRule {
+    pub rule: RuleParts,
+    /// the graph of each claim in `rule.if_all`, all the default graph unless converting to
+    /// quads
+    pub graphs: Vec<Graph>,
+    /// the patterns of its FILTER NOT EXISTS and MINUS blocks, none unless converting negation
+    pub if_none: Vec<Pattern>,
+    pub warnings: Vec<Warning>,
+}
+
+/// Convert a parsed query to rules as `options` allow, a query it allows nothing more of than
+/// `sparql2rify` does always becoming exactly one.
+pub fn convert_with(
+    sparql: Query,
+    options: &ConvertOptions,
+) -> Result<Vec<ConvertedRule>, InvalidRule> {
+    let rules = convert_query(sparql, options)?;
+    if options.negation {
+        for converted in &rules {
+            extended::check(&converted.rule, &converted.if_none)?;
+        }
+    }
+    Ok(rules)
+}
+
+/// The graphs the FROM and FROM NAMED clauses of a query name.
+#[derive(Debug, Default, PartialEq)]
+pub struct Dataset {
+    pub from: Vec<String>,
+    pub from_named: Vec<String>,
+}
+
+impl Dataset {
+    /// Put the claims of a rule over quads that are outside GRAPH blocks in the FROM graph, and
+    /// bind the variable of every GRAPH block to the FROM NAMED graph, as the query would have
+    /// read them. A rule can only say this for a single graph of each kind, a query naming more
+    /// is `IllegalFrom`.
+    pub fn apply(&self, rule: &mut RuleParts, graphs: &mut [Graph]) -> Result<(), InvalidRule> {
+        if self.from.len() > 1 || self.from_named.len() > 1 {
+            return Err(InvalidRule::IllegalFrom);
+        }
+        if let Some(from) = self.from.first() {
+            for graph in graphs.iter_mut().filter(|graph| graph.is_none()) {
+                *graph = Some(Entity::Bound(RdfNode::Iri(from.clone())));
+            }
+        }
+        if let Some(named) = self.from_named.first() {
+            let names: BTreeSet<String> = graphs
+                .iter()
+                .flatten()
+                .filter_map(util::as_unbound)
+                .map(str::to_string)
+                .collect();
+            let values: BTreeMap<&str, RdfNode> = names
+                .iter()
+                .map(|name| (name.as_str(), RdfNode::Iri(named.clone())))
+                .collect();
+            for ent in rule
+                .if_all
+                .iter_mut()
+                .chain(&mut rule.then)
+                .flatten()
+                .chain(graphs.iter_mut().flatten())
+            {
+                substitute(ent, &values);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Remove the FROM and FROM NAMED clauses of `sparql`, which conversion rejects as
+/// `IllegalFrom`, returning the graphs they named.
+pub fn take_dataset(sparql: &mut Query) -> Dataset {
+    let dataset = std::mem::take(sparql.dataset_mut());
+    Dataset {
+        from: dataset
+            .default
+            .into_iter()
+            .flatten()
+            .filter_map(|graph| match graph {
+                GraphName::NamedNode(iri) => Some(iri.into_string()),
+                _ => None,
+            })
+            .collect(),
+        from_named: dataset
+            .named
+            .into_iter()
+            .flatten()
+            .filter_map(|graph| match graph {
+                NamedOrBlankNode::NamedNode(iri) => Some(iri.into_string()),
+                _ => None,
+            })
+            .collect(),
+    }
+}
+
+/// the claims of `quads`, and the graph of each
+fn to_rify_quads(
+    quads: Vec<convert::Quad>,
+) -> (Vec<rify::Claim<Entity<String, RdfNode>>>, Vec<Graph>) {
+    let (bgp, graphs): (Vec<_>, Vec<_>) = quads.into_iter().unzip();
+    let graphs = graphs
+        .into_iter()
+        .map(|graph| graph.map(nnov_to_rify_entity))
+        .collect();
+    (to_rify_pattern(bgp), graphs)
+}
+
+/// Convert a query to rules, each with the patterns of its FILTER NOT EXISTS and MINUS blocks if
+/// `options` allow them.
+fn convert_query(
+    sparql: Query,
+    options: &ConvertOptions,
+) -> Result<Vec<ConvertedRule>, InvalidRule> {
+    // relative IRIs were resolved against the base while parsing
+    let (construct, dataset, algebra) = match sparql.0 {
+        QueryVariants::Construct {
+            construct,
+            dataset,
+            algebra,
+            ..
+        } => (construct, dataset, algebra::Dismantling::new(algebra)),
+        QueryVariants::Select { algebra, .. }
+        | QueryVariants::Describe { algebra, .. }
+        | QueryVariants::Ask { algebra, .. } => {
+            drop(algebra::Dismantling::new(algebra));
+            return Err(InvalidRule::MustBeConstruct);
+        }
+    };
+
+    if (QueryDataset {
+        default: Some(vec![GraphName::DefaultGraph]),
+        named: None,
+    } != dataset)
+    {
+        return Err(InvalidRule::IllegalFrom);
+    }
+
+    scan(&algebra, options)?;
+
+    let project = match &*algebra {
+        GraphPattern::Project(patt, _vars) => patt,
+        _ => unreachable!("scan only accepts a projection around the WHERE clause"),
+    };
+    let template = to_rify_pattern(construct.iter());
+    let branches: Vec<_> = branches(project, &options.functions)?
+        .into_iter()
+        .map(|branch| {
+            let (mut if_all, mut graphs) = to_rify_quads(branch.quads);
+            let absent: Vec<Pattern> = branch
+                .absent
+                .into_iter()
+                .map(|absent| to_rify_pattern(absent.triples))
+                .collect();
+            let exists: Vec<_> = branch
+                .exists
+                .into_iter()
+                .map(|exists| (to_rify_quads(exists.quads), exists.local))
+                .collect();
+            // what an EXISTS doesn't share is renamed so nothing outside it can refer to it
+            let required = exists.iter().flat_map(|((triples, _), _)| triples);
+            let mut fresh = vars::Fresh::avoiding(
+                if_all
+                    .iter()
+                    .chain(&template)
+                    .chain(absent.iter().flatten())
+                    .chain(required),
+            );
+            for name in graphs.iter().flatten().filter_map(util::as_unbound) {
+                fresh.reserve(name);
+            }
+            for name in branch.values.keys() {
+                fresh.reserve(name);
+            }
+            for ((mut triples, exists_graphs), local) in exists {
+                let renamed: BTreeMap<&str, String> = local
+                    .into_iter()
+                    .map(|name| (name, fresh.name(name)))
+                    .collect();
+                for ent in triples.iter_mut().flatten() {
+                    if let Entity::Unbound(name) = ent {
+                        if let Some(new) = renamed.get(name.as_str()) {
+                            *name = new.clone();
+                        }
+                    }
+                }
+                if_all.extend(triples);
+                graphs.extend(exists_graphs);
+            }
+            (if_all, graphs, branch.values, absent)
+        })
+        .collect();
+
+    // variables each branch binds, whether by matching or by VALUES
+    let bound: Vec<BTreeSet<&str>> = branches
+        .iter()
+        .map(|(if_all, graphs, values, _)| {
+            if_all
+                .iter()
+                .flatten()
+                .chain(graphs.iter().flatten())
+                .filter_map(util::as_unbound)
+                .chain(values.keys().copied())
+                .collect()
+        })
+        .collect();
+    let anywhere: BTreeSet<&str> = bound.iter().flatten().copied().collect();
+    let mut ret = Vec::new();
+    for ((if_all, graphs, values, absent), bound) in branches.iter().zip(&bound) {
+        // variables no branch binds are left in for the error to name them
+        let then: Vec<_> = template
+            .iter()
+            .filter(|claim| {
+                claim
+                    .iter()
+                    .filter_map(util::as_unbound)
+                    .all(|name| bound.contains(name) || !anywhere.contains(name))
+            })
+            .cloned()
+            .collect();
+        if then.is_empty() && !template.is_empty() {
+            continue;
+        }
+        let (mut if_all, mut graphs, mut then) = (if_all.clone(), graphs.clone(), then);
+        let mut absent = absent.clone();
+        for ent in if_all
+            .iter_mut()
+            .chain(&mut then)
+            .chain(absent.iter_mut().flatten())
+            .flatten()
+            .chain(graphs.iter_mut().flatten())
+        {
+            substitute(ent, values);
+        }
+        ret.push(convert_branch(if_all, graphs, then, absent)?);
+    }
+    if ret.is_empty() {
+        // no branch binds all the variables of any conclusion, the first says which it lacks
+        match branches.into_iter().next() {
+            Some((if_all, graphs, _, absent)) => {
+                ret.push(convert_branch(if_all, graphs, template, absent)?)
+            }
+            None if !options.expand => return Err(InvalidRule::NeverMatches),
+            None => {}
+        }
+    }
+    Ok(ret)
+}
+
+/// replace `ent` with the term VALUES fixed it to, if it is such a variable
+fn substitute(ent: &mut Entity<String, RdfNode>, values: &BTreeMap<&str, RdfNode>) {
+    let term = util::as_unbound(ent).and_then(|name| values.get(name));
+    if let Some(term) = term.cloned() {
+        *ent = Entity::Bound(term);
+    }
+}
+
+/// the rule matching one branch of the WHERE clause, with the patterns that must not match
+fn convert_branch(
+    mut if_all: Vec<rify::Claim<Entity<String, RdfNode>>>,
+    graphs: Vec<Graph>,
+    mut then: Vec<rify::Claim<Entity<String, RdfNode>>>,
+    if_none: Vec<Pattern>,
+) -> Result<ConvertedRule, InvalidRule> {
+    // blank nodes of negative conditions are renamed and unbound along with the rest
+    let (positive, lens): (usize, Vec<usize>) =
+        (if_all.len(), if_none.iter().map(Vec::len).collect());
+    if_all.extend(if_none.into_iter().flatten());
+
+    // named before they are reported so the error is the same every time
+    util::relabel_anonymous(&mut if_all, &mut then);
+
+    // blank nodes in `then` are a footgun so they are not allowed
+    let list_node = |[s, p, _]: &rify::Claim<Entity<String, RdfNode>>| {
+        util::as_blank(s).is_some()
+            && matches!(p, Entity::Bound(RdfNode::Iri(iri)) if iri == RDF_FIRST)
+    };
+    if then.iter().any(list_node) {
+        return Err(InvalidRule::CollectionImplied);
+    }
+    for ent in then.iter().flatten() {
+        if let Some(name) = util::as_blank(ent) {
+            return Err(InvalidRule::BlankNodeImplied {
+                name: name.to_string(),
+            });
+        }
+    }
+
+    // graphs are never blank, but their variables could collide with blank nodes
+    let graph_vars: BTreeSet<&str> = graphs
+        .iter()
+        .flatten()
+        .filter_map(util::as_unbound)
+        .collect();
+    for ent in if_all.iter().flatten() {
+        if let Some(name) = util::as_blank(ent).filter(|name| graph_vars.contains(name)) {
+            let name = name.to_string();
+            return Err(InvalidRule::NameCollision { name });
+        }
+    }
+    util::unbind_blanks(&mut if_all, &mut then)?;
+    let mut negative = if_all.split_off(positive);
+    let if_none = lens
+        .into_iter()
+        .map(|len| {
+            let rest = negative.split_off(len);
+            std::mem::replace(&mut negative, rest)
+        })
+        .collect();
+
+    let warnings = lint::check(&if_all, &then);
+
+    let rule = RuleParts { if_all, then };
+    if graph_vars.is_empty() {
+        rule.check()?;
+    } else {
+        // rify rules are over triples, so check variables are bound taking graphs into account
+        let bound: BTreeSet<&str> = rule
+            .if_all
+            .iter()
+            .flatten()
+            .filter_map(util::as_unbound)
+            .collect();
+        for ent in rule.then.iter().flatten() {
+            if let Some(name) = util::as_unbound(ent) {
+                if !bound.contains(name) && !graph_vars.contains(name) {
+                    let name = name.to_string();
+                    return Err(InvalidRule::UnboundImplied { name });
+                }
+            }
+        }
+    }
+    rule.check_iris()?;
+    Ok(ConvertedRule {
+        rule,
+        graphs,
+        if_none,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::extended::ExtendedRule;
+    use crate::types::RdfNode;
+    use crate::types::RdfNode::Iri;
+    use rify::Entity::{Bound, Unbound};
+
+    #[test]
+    fn simple_rule() {
+        let sparql = "CONSTRUCT { ?s ?p ?o . }  WHERE { ?s ?p ?o . }"
+            .parse()
+            .unwrap();
+        let (r, _) = sparql2rify(dbg!(sparql)).unwrap();
+        assert_eq!(
+            r.to_rule().unwrap(),
+            rify::Rule::create(
+                vec![[unbd("s"), unbd("p"), unbd("o")]],
+                vec![[unbd("s"), unbd("p"), unbd("o")]]
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn reified_claim() {
+        let sparql = "
+            PREFIX rdf: <http://www.w3.org/1999/02/22-rdf-syntax-ns#>
+            
+            CONSTRUCT {
+                ?s ?p ?o .
+            } WHERE {
+                ?a rdf:subject ?s ;
+                   rdf:predicate ?p ;
+                   rdf:object ?o .
+            }
+        "
+        .parse();
+        let (res, warnings) = sparql2rify(sparql.unwrap()).unwrap();
+        assert_eq!(warnings, vec![]);
+        assert_eq!(
+            res.to_rule().unwrap(),
+            rify::Rule::create(
+                vec![
+                    [unbd("a"), rdf("subject"), unbd("s")],
+                    [unbd("a"), rdf("predicate"), unbd("p")],
+                    [unbd("a"), rdf("object"), unbd("o")]
+                ],
+                vec![[unbd("s"), unbd("p"), unbd("o")]]
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn anonymous_blanknode() {
+        let sparql = "
+            PREFIX rdf: <http://www.w3.org/1999/02/22-rdf-syntax-ns#>
+            
+            CONSTRUCT { } WHERE {
+                [] rdf:subject [] .
+            }
+        "
+        .parse();
+        sparql2rify(sparql.unwrap()).unwrap();
+    }
+
+    #[test]
+    fn anonymous_blanknodes_are_stable() {
+        let query = "
+            CONSTRUCT { ?b0 <http://example.com/p> ?c . }
+            WHERE { ?b0 <http://example.com/p> [ <http://example.com/q> [] ; <http://example.com/r> ?c ] . }
+        ";
+        let (first, _) = sparql2rify(query.parse().unwrap()).unwrap();
+        let (second, _) = sparql2rify(query.parse().unwrap()).unwrap();
+        assert_eq!(first, second);
+        let p = |s: &str| Bound(Iri(format!("http://example.com/{}", s)));
+        assert_eq!(
+            first.if_all,
+            vec![
+                [unbd("b1"), p("q"), unbd("b2")],
+                [unbd("b1"), p("r"), unbd("c")],
+                [unbd("b0"), p("p"), unbd("b1")],
+            ]
+        );
+    }
+
+    #[test]
+    fn errs() {
+        use InvalidRule::*;
+        let cases: &[(_, &[_])] = &[
+            (MustBeConstruct, &["SELECT ?a ?b ?c WHERE { ?s ?p ?o . }"]),
+            (IllegalFrom, &[]),
+            (IllegalBaseIri, &[]),
+            (UnsupportedUnion, &["CONSTRUCT {} WHERE { {} UNION  {} . }"]),
+            (
+                UnsupportedValues,
+                &["CONSTRUCT {} WHERE { ?s ?p ?o } VALUES ?s { <http://example.com> }"],
+            ),
+            (
+                UnsupportedOptional,
+                &["CONSTRUCT {} WHERE { ?s ?p ?o OPTIONAL { ?o ?p ?s } }"],
+            ),
+            (
+                UnsupportedGraph,
+                &["CONSTRUCT {} WHERE { GRAPH <http://example.com> {} . }"],
+            ),
+            (
+                UnsupportedSubquery,
+                &[
+                    "CONSTRUCT {} WHERE { { SELECT ?s WHERE { ?s ?p ?o } } }",
+                    "CONSTRUCT {} WHERE { ?s ?p ?o { SELECT ?s WHERE { ?s ?p ?o } LIMIT 1 } }",
+                ],
+            ),
+            (
+                UnsupportedService,
+                &["CONSTRUCT {} WHERE { SERVICE <http://example.com> { ?s ?p ?o } }"],
+            ),
+            (
+                UnsupportedAggregate {
+                    name: "GROUP BY".to_string(),
+                },
+                &["CONSTRUCT {} WHERE { ?s ?p ?o } GROUP BY ?s"],
+            ),
+            (
+                UnsupportedModifier { modifier: "LIMIT" },
+                &["CONSTRUCT {} WHERE { ?s ?p ?o } ORDER BY ?s LIMIT 1"],
+            ),
+            (
+                UnsupportedModifier { modifier: "OFFSET" },
+                &["CONSTRUCT {} WHERE { ?s ?p ?o } OFFSET 1"],
+            ),
+            (
+                UnsupportedModifier {
+                    modifier: "ORDER BY",
+                },
+                &["CONSTRUCT {} WHERE { ?s ?p ?o } ORDER BY ?s"],
+            ),
+            (IllegalPathPattern, &[]),
+            (
+                UnboundImplied {
+                    name: "a".to_string(),
+                },
+                &["CONSTRUCT { ?a ?b ?c . } WHERE {}"],
+            ),
+            (
+                NameCollision {
+                    name: "a".to_string(),
+                },
+                &["CONSTRUCT {  } WHERE { _:a ?a <http://example.com> . }"],
+            ),
+        ];
+        for (err, queries) in cases {
+            for query in *queries {
+                assert_eq!(err, &sparql2rify(query.parse().unwrap()).unwrap_err());
+            }
+        }
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let query = format!(
+            "CONSTRUCT {{}} WHERE {{ {} {{}} }}",
+            "{} UNION ".repeat(100_000)
+        );
+        assert_eq!(
+            sparql2rify(query.parse().unwrap()).unwrap_err(),
+            InvalidRule::UnsupportedUnion
+        );
+    }
+
+    #[test]
+    fn sequence_path() {
+        // the parser chains the steps with blank nodes, which get names no variable has
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:gp ?b0 . } WHERE { ?a ex:p/^ex:q/ex:r ?b0 . }";
+        let (rule, _) = sparql2rify(query.parse().unwrap()).unwrap();
+        let ex = |suffix: &str| Bound(Iri(format!("http://example.com/{}", suffix)));
+        assert_eq!(
+            rule.if_all,
+            vec![
+                [unbd("a"), ex("p"), unbd("b1")],
+                [unbd("b2"), ex("q"), unbd("b1")],
+                [unbd("b2"), ex("r"), unbd("b0")],
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_fails_fast() {
+        let rows = "(<http://example.com>) ".repeat(100_000);
+        let query = format!(
+            "CONSTRUCT {{ ?s ?p ?o . }} WHERE {{ ?s ?p ?o . VALUES (?s) {{ {} }} }}",
+            rows
+        );
+        assert_eq!(
+            sparql2rify(query.parse().unwrap()).unwrap_err(),
+            InvalidRule::UnsupportedValues
+        );
+
+        let query = "CONSTRUCT {} WHERE { ?s <http://a.example>|<http://b.example> ?o . }";
+        assert_eq!(
+            sparql2rify(query.parse().unwrap()).unwrap_err(),
+            InvalidRule::IllegalPathPattern
+        );
+    }
+
+    #[test]
+    fn nondeterministic_function() {
+        let err = |query: &str| sparql2rify(query.parse().unwrap()).unwrap_err();
+        assert_eq!(
+            err("CONSTRUCT { ?a <http://example.com/at> ?now . }
+                 WHERE { ?a ?b ?c . BIND (NOW() AS ?now) }"),
+            InvalidRule::NondeterministicFunction {
+                function: "NOW".to_string()
+            }
+        );
+        assert_eq!(
+            err("CONSTRUCT { ?a ?b ?c . } WHERE { ?a ?b ?c . FILTER (STRLEN(STR(?c)) > RAND()) }"),
+            InvalidRule::NondeterministicFunction {
+                function: "RAND".to_string()
+            }
+        );
+        assert_eq!(
+            err("CONSTRUCT { ?a ?b ?c . } WHERE { ?a ?b ?c . FILTER (STRLEN(STR(?c)) > 3) }"),
+            InvalidRule::UnsupportedFilter {
+                expr: "(STRLEN(STR(?c)) > \"3\"^^<http://www.w3.org/2001/XMLSchema#integer>)"
+                    .to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn convert_text() {
+        let rule = convert("CONSTRUCT { ?s ?p ?o . } WHERE { ?s ?p ?o . }").unwrap();
+        assert_eq!(
+            rule,
+            rify::Rule::create(
+                vec![[unbd("s"), unbd("p"), unbd("o")]],
+                vec![[unbd("s"), unbd("p"), unbd("o")]]
+            )
+            .unwrap()
+        );
+        assert!(matches!(
+            convert("CONSTRUCT { ?s ?p ?o . }"),
+            Err(Error::Parse { .. })
+        ));
+        assert!(matches!(
+            convert("SELECT ?s WHERE { ?s ?p ?o . }"),
+            Err(Error::InvalidRule {
+                source: InvalidRule::MustBeConstruct
+            })
+        ));
+    }
+
+    #[test]
+    fn base_iris() {
+        let base = "http://example.com/rules/";
+        let query = "CONSTRUCT { ?s <p> <o> . } WHERE { ?s <p> ?o . }";
+        let (rule, _) = sparql2rify(parse(query, Some(base)).unwrap()).unwrap();
+        assert_eq!(
+            rule.to_rule().unwrap(),
+            rify::Rule::create(
+                vec![[
+                    unbd("s"),
+                    Bound(Iri("http://example.com/rules/p".to_string())),
+                    unbd("o")
+                ]],
+                vec![[
+                    unbd("s"),
+                    Bound(Iri("http://example.com/rules/p".to_string())),
+                    Bound(Iri("http://example.com/rules/o".to_string()))
+                ]]
+            )
+            .unwrap()
+        );
+
+        // a BASE in the query wins, and may itself be relative to the caller's
+        let query = "BASE <shared/> CONSTRUCT {} WHERE { ?s <p> ?o . }";
+        let (rule, _) = sparql2rify(parse(query, Some(base)).unwrap()).unwrap();
+        assert_eq!(
+            rule.if_all[0][1],
+            Bound(Iri("http://example.com/rules/shared/p".to_string()))
+        );
+        let (rule, _) = sparql2rify(
+            parse(
+                query
+                    .replace("shared/", "http://example.org/shared/")
+                    .as_str(),
+                None,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            rule.if_all[0][1],
+            Bound(Iri("http://example.org/shared/p".to_string()))
+        );
+
+        assert!(matches!(
+            parse(query, None),
+            Err(Error::InvalidRule {
+                source: InvalidRule::IllegalBaseIri
+            })
+        ));
+        assert!(matches!(
+            parse("CONSTRUCT {} WHERE { ?s <p> ?o }", None),
+            Err(Error::InvalidRule {
+                source: InvalidRule::IllegalBaseIri
+            })
+        ));
+        assert!(matches!(
+            parse("CONSTRUCT {} WHERE { ?s <p> }", None),
+            Err(Error::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_iri() {
+        let iri = |iri: &str| Bound(Iri(iri.to_string()));
+        let mut rule = RuleParts {
+            if_all: vec![[unbd("a"), iri("http://example.com"), unbd("b")]],
+            then: vec![],
+        };
+        rule.check_iris().unwrap();
+        rule.then.push([unbd("a"), iri("not an iri"), unbd("b")]);
+        assert_eq!(
+            rule.check_iris().unwrap_err(),
+            InvalidRule::InvalidIri {
+                iri: "not an iri".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn more_errs() {
+        let query = "CONSTRUCT { ?a ?b [] . } WHERE {}";
+        let err = sparql2rify(query.parse().unwrap()).unwrap_err();
+        match err {
+            InvalidRule::BlankNodeImplied { .. } => {}
+            _ => {
+                dbg!(err);
+                panic!();
+            }
+        }
+        let query = "CONSTRUCT { ?a ?b ( ?a ?b ) . } WHERE { ?a ?b ?c . }";
+        let err = sparql2rify(query.parse().unwrap()).unwrap_err();
+        assert_eq!(err, InvalidRule::CollectionImplied);
+    }
+
+    #[test]
+    fn identity_rule() {
+        let queries = [
+            "CONSTRUCT { ?s ?p ?o . } WHERE { ?s ?p ?o . }",
+            "CONSTRUCT { ?a <http://example.com> ?b . }
+             WHERE { ?a <http://example.com> ?b . ?b <http://example.com> ?a . }",
+        ];
+        for query in &queries {
+            let (_, warnings) = sparql2rify(query.parse().unwrap()).unwrap();
+            assert!(warnings.contains(&Warning::IdentityRule));
+        }
+
+        let query =
+            "CONSTRUCT { ?b <http://example.com> ?a . } WHERE { ?a <http://example.com> ?b . }";
+        let (_, warnings) = sparql2rify(query.parse().unwrap()).unwrap();
+        assert_eq!(warnings, vec![]);
+    }
+
+    #[test]
+    fn predicate_only_variable() {
+        let query = "CONSTRUCT { ?a <http://example.com> ?b . } WHERE { ?a ?p ?b . }";
+        let (_, warnings) = sparql2rify(query.parse().unwrap()).unwrap();
+        assert_eq!(
+            warnings,
+            vec![Warning::PredicateOnlyVariable {
+                name: "p".to_string()
+            }]
+        );
+
+        let query = "CONSTRUCT { ?a <http://example.com> ?b . } WHERE { ?a ?p ?b . ?p ?p ?p . }";
+        let (_, warnings) = sparql2rify(query.parse().unwrap()).unwrap();
+        assert_eq!(warnings, vec![]);
+    }
+
+    #[test]
+    fn quads() {
+        let query = "CONSTRUCT { ?s <http://example.com/in> ?g . }
+                     WHERE { ?s <http://example.com/a> ?o .
+                             GRAPH ?g { ?s <http://example.com/b> ?o . }
+                             GRAPH <http://example.com/g> { ?o <http://example.com/c> ?s . } }";
+        let ConvertedRule { rule, graphs, .. } = with(query, keep_graphs()).unwrap().remove(0);
+        assert_eq!(rule.if_all.len(), 3);
+        assert_eq!(
+            graphs,
+            vec![
+                None,
+                Some(unbd("g")),
+                Some(Bound(Iri("http://example.com/g".to_string())))
+            ]
+        );
+
+        assert_eq!(
+            sparql2rify(query.parse().unwrap()).unwrap_err(),
+            InvalidRule::UnsupportedGraph
+        );
+
+        let query = "CONSTRUCT { ?s <http://example.com/in> ?h . }
+                     WHERE { GRAPH ?g { ?s <http://example.com/b> ?o . } }";
+        assert_eq!(
+            with(query, keep_graphs()).unwrap_err(),
+            InvalidRule::UnboundImplied {
+                name: "h".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn from_clauses() {
+        let query = "CONSTRUCT { ?s <http://example.com/in> ?g . }
+                     FROM <http://example.com/default>
+                     FROM NAMED <http://example.com/named>
+                     WHERE { ?s <http://example.com/a> ?o .
+                             GRAPH ?g { ?s <http://example.com/b> ?o . } }";
+        assert_eq!(
+            with(query, keep_graphs()).unwrap_err(),
+            InvalidRule::IllegalFrom
+        );
+        let mut parsed: Query = query.parse().unwrap();
+        let dataset = take_dataset(&mut parsed);
+        assert_eq!(
+            dataset,
+            Dataset {
+                from: vec!["http://example.com/default".to_string()],
+                from_named: vec!["http://example.com/named".to_string()],
+            }
+        );
+        let converted = convert_with(parsed, &keep_graphs()).unwrap().remove(0);
+        let (mut rule, mut graphs) = (converted.rule, converted.graphs);
+        dataset.apply(&mut rule, &mut graphs).unwrap();
+        let iri = |iri: &str| Bound(Iri(iri.to_string()));
+        assert_eq!(
+            graphs,
+            [
+                Some(iri("http://example.com/default")),
+                Some(iri("http://example.com/named"))
+            ]
+        );
+        assert_eq!(rule.then[0][2], iri("http://example.com/named"));
+
+        let query = "CONSTRUCT { ?s <http://example.com/q> ?o . }
+                     FROM <http://example.com/a> FROM <http://example.com/b>
+                     WHERE { ?s <http://example.com/p> ?o . }";
+        let mut parsed: Query = query.parse().unwrap();
+        let dataset = take_dataset(&mut parsed);
+        let (mut rule, _) = sparql2rify(parsed).unwrap();
+        let mut graphs = vec![None];
+        assert_eq!(
+            dataset.apply(&mut rule, &mut graphs).unwrap_err(),
+            InvalidRule::IllegalFrom
+        );
+    }
+
+    #[test]
+    fn union_branches() {
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:related ?b . ?a ex:name ?n . }
+                     WHERE { { ?a ex:parent ?b . } UNION { ?b ex:child ?a . ?a ex:name ?n . }
+                             ?a ex:kind ex:Person . }";
+        let rules = with(query, expanding()).unwrap();
+        let ex = |suffix: &str| Bound(Iri(format!("http://example.com/{}", suffix)));
+        let rules: Vec<RuleParts> = rules.into_iter().map(|converted| converted.rule).collect();
+        assert_eq!(
+            rules,
+            vec![
+                RuleParts {
+                    if_all: vec![
+                        [unbd("a"), ex("parent"), unbd("b")],
+                        [unbd("a"), ex("kind"), ex("Person")],
+                    ],
+                    then: vec![[unbd("a"), ex("related"), unbd("b")]],
+                },
+                RuleParts {
+                    if_all: vec![
+                        [unbd("b"), ex("child"), unbd("a")],
+                        [unbd("a"), ex("name"), unbd("n")],
+                        [unbd("a"), ex("kind"), ex("Person")],
+                    ],
+                    then: vec![
+                        [unbd("a"), ex("related"), unbd("b")],
+                        [unbd("a"), ex("name"), unbd("n")],
+                    ],
+                },
+            ]
+        );
+
+        // each row of VALUES is a rule, rows that disagree make none
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:member ?org . }
+                     WHERE { ?a ex:worksFor ?org . VALUES ?org { ex:acme ex:globex } }
+                     VALUES (?org ?a) { (ex:acme UNDEF) (ex:initech ex:bob) }";
+        let rules = with(query, expanding()).unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(
+            rules[0].rule,
+            RuleParts {
+                if_all: vec![[unbd("a"), ex("worksFor"), ex("acme")]],
+                then: vec![[unbd("a"), ex("member"), ex("acme")]],
+            }
+        );
+
+        // a variable no branch binds is still an error
+        let query = "CONSTRUCT { ?a <http://example.com/p> ?x . }
+                     WHERE { { ?a <http://example.com/q> ?b . } UNION { ?b ?a ?c . } }";
+        assert_eq!(
+            with(query, expanding()).unwrap_err(),
+            InvalidRule::UnboundImplied {
+                name: "x".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn optional_variants() {
+        let query = "PREFIX foaf: <http://xmlns.com/foaf/0.1/>
+                     PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?p ex:name ?n . ?p ex:email ?e . }
+                     WHERE { ?p foaf:name ?n . OPTIONAL { ?p foaf:mbox ?e . } }";
+        let foaf = |suffix: &str| Bound(Iri(format!("http://xmlns.com/foaf/0.1/{}", suffix)));
+        let ex = |suffix: &str| Bound(Iri(format!("http://example.com/{}", suffix)));
+        let options = ConvertOptions {
+            optional: true,
+            ..expanding()
+        };
+        let rules: Vec<RuleParts> = with(query, options)
+            .unwrap()
+            .into_iter()
+            .map(|converted| converted.rule)
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                RuleParts {
+                    if_all: vec![
+                        [unbd("p"), foaf("name"), unbd("n")],
+                        [unbd("p"), foaf("mbox"), unbd("e")],
+                    ],
+                    then: vec![
+                        [unbd("p"), ex("name"), unbd("n")],
+                        [unbd("p"), ex("email"), unbd("e")],
+                    ],
+                },
+                RuleParts {
+                    if_all: vec![[unbd("p"), foaf("name"), unbd("n")]],
+                    then: vec![[unbd("p"), ex("name"), unbd("n")]],
+                },
+            ]
+        );
+        assert_eq!(
+            with(query, expanding()).unwrap_err(),
+            InvalidRule::UnsupportedOptional
+        );
+    }
+
+    #[test]
+    fn filter_exists() {
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a a ex:Grandchild . }
+                     WHERE { ?a ex:parent ?b .
+                             FILTER (EXISTS { ?b ex:parent ?c . ?c a ex:Person }
+                                     && ?b = ex:bob) }";
+        let ex = |suffix: &str| Bound(Iri(format!("http://example.com/{}", suffix)));
+        let (rule, _) = sparql2rify(query.parse().unwrap()).unwrap();
+        assert_eq!(
+            rule,
+            RuleParts {
+                if_all: vec![
+                    [unbd("a"), ex("parent"), ex("bob")],
+                    [ex("bob"), ex("parent"), unbd("c0")],
+                    [unbd("c0"), rdf("type"), ex("Person")],
+                ],
+                then: vec![[unbd("a"), rdf("type"), ex("Grandchild")]],
+            }
+        );
+        // as in SPARQL, what only EXISTS matches is unbound outside it
+        let unbound = query.replace("?a a ex:Grandchild", "?a ex:grandparent ?c");
+        assert_eq!(
+            sparql2rify(unbound.parse().unwrap()).unwrap_err(),
+            InvalidRule::UnboundImplied {
+                name: "c".to_string()
+            }
+        );
+        let joined = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:s ?x . }
+                     WHERE { { ?a ex:p ?b FILTER EXISTS { ?b ex:q ?x } } ?a ex:r ?x }";
+        assert_eq!(
+            sparql2rify(joined.parse().unwrap()).unwrap().0.if_all,
+            [
+                [unbd("a"), ex("p"), unbd("b")],
+                [unbd("a"), ex("r"), unbd("x")],
+                [unbd("b"), ex("q"), unbd("x0")],
+            ]
+        );
+        // extra braces only group the triples
+        let grouped = query.replace(
+            "{ ?b ex:parent ?c . ?c a ex:Person }",
+            "{ { ?b ex:parent ?c } { { ?c a ex:Person } } }",
+        );
+        assert_ne!(grouped, query);
+        assert_eq!(sparql2rify(grouped.parse().unwrap()).unwrap().0, rule);
+        // what EXISTS binds isn't known to the rest of the FILTER
+        let query = "CONSTRUCT { ?a ?b ?c . }
+                     WHERE { ?a ?b ?c . FILTER (EXISTS { ?c ?b ?d } && ?d = <urn:x:d>) }";
+        assert_eq!(
+            sparql2rify(query.parse().unwrap()).unwrap_err(),
+            InvalidRule::UnsupportedFilter {
+                expr: "(?d = <urn:x:d>)".to_string()
+            }
+        );
+        for (filter, err) in [
+            (
+                "EXISTS { ?c ?b ?a } || ?a = ?c",
+                InvalidRule::UnsupportedFilter {
+                    expr: "(EXISTS { BGP(?c ?b ?a .) } || (?a = ?c))".to_string(),
+                },
+            ),
+            (
+                "EXISTS { ?c ?b ?a OPTIONAL { ?a ?b ?c } }",
+                InvalidRule::UnsupportedExistsPattern,
+            ),
+        ] {
+            let query = format!(
+                "CONSTRUCT {{ ?a ?b ?c . }} WHERE {{ ?a ?b ?c . FILTER ({}) }}",
+                filter
+            );
+            assert_eq!(sparql2rify(query.parse().unwrap()).unwrap_err(), err);
+        }
+    }
+
+    #[test]
+    fn filter_not_exists() {
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:trusted true . }
+                     WHERE { ?a a ex:Issuer .
+                             FILTER (NOT EXISTS { ?a ex:listedBy [ a ex:DenyList ] }
+                                     && NOT EXISTS { ?a ex:revoked true }) }";
+        assert_eq!(
+            sparql2rify(query.parse().unwrap()).unwrap_err(),
+            InvalidRule::NegationNeedsExtended
+        );
+        let ex = |suffix: &str| Bound(Iri(format!("http://example.com/{}", suffix)));
+        let yes = Bound(RdfNode::Literal {
+            value: "true".to_string(),
+            datatype: Some("http://www.w3.org/2001/XMLSchema#boolean".to_string()),
+            language: None,
+        });
+        let rule = extended_rule(query).unwrap();
+        assert_eq!(
+            rule,
+            ExtendedRule {
+                rule: RuleParts {
+                    if_all: vec![[unbd("a"), rdf("type"), ex("Issuer")]],
+                    then: vec![[unbd("a"), ex("trusted"), yes.clone()]],
+                },
+                if_none: vec![
+                    vec![
+                        [unbd("b0"), rdf("type"), ex("DenyList")],
+                        [unbd("a"), ex("listedBy"), unbd("b0")],
+                    ],
+                    vec![[unbd("a"), ex("revoked"), yes]],
+                ],
+            }
+        );
+
+        // a rule deriving what it must not find can't be ordered before or after itself
+        let query = "CONSTRUCT { ?a <urn:x:p> ?a . }
+                     WHERE { ?a ?b ?c . FILTER NOT EXISTS { ?c <urn:x:p> ?d } }";
+        assert_eq!(
+            extended_rule(query).unwrap_err(),
+            InvalidRule::NotStratified
+        );
+    }
+
+    #[test]
+    fn minus() {
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:trusted ex:yes . }
+                     WHERE { ?a a ex:Issuer MINUS { ?a ex:revoked ?when } }";
+        assert_eq!(
+            sparql2rify(query.parse().unwrap()).unwrap_err(),
+            InvalidRule::NegationNeedsExtended
+        );
+        let ex = |suffix: &str| Bound(Iri(format!("http://example.com/{}", suffix)));
+        let rule = extended_rule(query).unwrap();
+        assert_eq!(
+            rule.if_none,
+            vec![vec![[unbd("a"), ex("revoked"), unbd("when")]]]
+        );
+        // a condition whose variable only the MINUS shares isn't implied by the others
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:ok ex:yes . }
+                     WHERE { ?a ex:p ?b . ?a ex:p ?c . MINUS { ?c ex:banned true } }";
+        let ExtendedRule { mut rule, if_none } = extended_rule(query).unwrap();
+        let kept = rule.if_all[1].clone();
+        let removed = simplify::simplify(&mut rule, &mut vec![None; 2], &if_none);
+        assert_eq!((removed, rule.if_all), (1, vec![kept]));
+
+        for (pattern, err) in [
+            (
+                "?a a ex:Issuer MINUS { ?b ex:revoked ?when }",
+                InvalidRule::MinusSharesNothing,
+            ),
+            (
+                "{ ?a a ex:Issuer MINUS { ?a ex:revoked ?when } } ?a ex:since ?when",
+                InvalidRule::NegationScope {
+                    name: "when".to_string(),
+                },
+            ),
+            (
+                "?a a ex:Issuer MINUS { ?a ex:revoked ?when FILTER (?when = 1) }",
+                InvalidRule::UnsupportedExistsPattern,
+            ),
+        ] {
+            let query = format!(
+                "PREFIX ex: <http://example.com/>
+                 CONSTRUCT {{ ?a ex:trusted ex:yes . }} WHERE {{ {} }}",
+                pattern
+            );
+            assert_eq!(extended_rule(&query).unwrap_err(), err, "{}", pattern);
+        }
+        // what an EXISTS doesn't share is its own, not that of a NOT EXISTS beside it
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:trusted ex:yes . }
+                     WHERE { ?a a ex:Issuer FILTER (EXISTS { ?a ex:since ?when }
+                                                    && NOT EXISTS { ?when ex:before ?a }) }";
+        let rule = extended_rule(query).unwrap();
+        assert_eq!(rule.rule.if_all[1], [unbd("a"), ex("since"), unbd("when0")]);
+        assert_eq!(
+            rule.if_none,
+            [vec![[unbd("when"), ex("before"), unbd("a")]]]
+        );
+    }
+
+    fn with(query: &str, options: ConvertOptions) -> Result<Vec<ConvertedRule>, InvalidRule> {
+        convert_with(query.parse().unwrap(), &options)
+    }
+
+    fn keep_graphs() -> ConvertOptions {
+        ConvertOptions {
+            quads: true,
+            ..ConvertOptions::default()
+        }
+    }
+
+    fn expanding() -> ConvertOptions {
+        ConvertOptions {
+            expand: true,
+            ..ConvertOptions::default()
+        }
+    }
+
+    fn extended_rule(query: &str) -> Result<ExtendedRule, InvalidRule> {
+        let options = ConvertOptions {
+            negation: true,
+            ..ConvertOptions::default()
+        };
+        let ConvertedRule { rule, if_none, .. } = with(query, options)?.remove(0);
+        Ok(ExtendedRule { rule, if_none })
+    }
+
+    fn rdf(suffix: &str) -> rify::Entity<String, RdfNode> {
+        Bound(Iri(format!(
+            "http://www.w3.org/1999/02/22-rdf-syntax-ns#{}",
+            suffix
+        )))
+    }
+
+    fn unbd(name: &str) -> rify::Entity<String, RdfNode> {
+        Unbound(name.to_string())
+    }
+}
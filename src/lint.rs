@@ -0,0 +1,478 @@
+use crate::endpoint::Seen;
+use crate::schema::RDF_TYPE;
+use crate::types::{RdfNode, RuleParts, RDF_LANG_STRING, XSD_STRING};
+use crate::util::{as_blank, as_unbound};
+use displaydoc::Display;
+use oxilangtag::LanguageTag;
+use rify::{Claim, Entity};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+/// Problems that don't prevent conversion but probably indicate a mistake in the query.
+#[derive(Debug, PartialEq, Display)]
+pub enum Warning {
+    #[doc = "Every claim in the CONSTRUCT clause already appears in the WHERE clause so this \
+             rule can never derive anything new."]
+    IdentityRule,
+    #[doc = "The variable \"{name}\" is only ever used as a predicate in the WHERE clause so \
+             the rule matches every predicate in the data. This usually means a constraint \
+             on \"{name}\" is missing."]
+    PredicateOnlyVariable { name: String },
+    /// The datatype <{datatype}> is not in the list of permitted datatypes.
+    UnlistedDatatype { datatype: String },
+    #[doc = "The blank node \"_:{name}\" is matched by its label. Unlike a blank node in a \
+             WHERE clause, which matches anything, it matches blank nodes from unrelated \
+             documents that happen to share the label, and every claim derived with it names \
+             the same node."]
+    BlankNodeConstant { name: String },
+    #[doc = "<{iri}> is never used as a {role} in the data at the endpoint, so conditions using \
+             it can't match there. It may be misspelled."]
+    UnseenTerm { iri: String, role: &'static str },
+    #[doc = "\"{tag}\" is not a well-formed BCP 47 language tag, so literals tagged with it \
+             probably don't match the data."]
+    MalformedLanguageTag { tag: String },
+}
+
+/// Names of every lint, as accepted by `--allow`, `--warn` and `--deny`.
+pub const LINTS: &[&str] = &[
+    "identity_rule",
+    "predicate_only_variable",
+    "unlisted_datatype",
+    "blank_node_constant",
+    "unseen_term",
+    "malformed_language_tag",
+];
+
+/// Lint group that applies to every lint, like `-D warnings` in rustc.
+pub const ALL: &str = "warnings";
+
+impl Warning {
+    /// the stable name used to refer to this warning on the command line
+    pub fn lint_name(&self) -> &'static str {
+        match self {
+            Self::IdentityRule => "identity_rule",
+            Self::PredicateOnlyVariable { .. } => "predicate_only_variable",
+            Self::UnlistedDatatype { .. } => "unlisted_datatype",
+            Self::BlankNodeConstant { .. } => "blank_node_constant",
+            Self::UnseenTerm { .. } => "unseen_term",
+            Self::MalformedLanguageTag { .. } => "malformed_language_tag",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// How each lint should be reported. Lints without an explicit level are warnings.
+#[derive(Debug, Default)]
+pub struct Levels(BTreeMap<String, Level>);
+
+/// Lint levels as read from a config file.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelsConfig {
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    warn: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+}
+
+impl Levels {
+    /// Set the level for a lint, or for all lints if `name` is "warnings". Later calls take
+    /// precedence over earlier ones.
+    pub fn set(&mut self, name: &str, level: Level) -> Result<(), UnknownLint> {
+        if name == ALL {
+            self.0.clear();
+        } else if !LINTS.contains(&name) {
+            let name = name.to_string();
+            return Err(UnknownLint { name });
+        }
+        self.0.insert(name.to_string(), level);
+        Ok(())
+    }
+
+    /// Apply levels from a config file. Unlike command line flags a config file has no
+    /// order, so the "warnings" group is applied first and can be refined by individual lints.
+    pub fn apply(&mut self, config: LevelsConfig) -> Result<(), UnknownLint> {
+        let LevelsConfig { allow, warn, deny } = config;
+        let levels = [
+            (allow, Level::Allow),
+            (warn, Level::Warn),
+            (deny, Level::Deny),
+        ];
+        for group in &[true, false] {
+            for (names, level) in &levels {
+                for name in names.iter().filter(|name| (*name == ALL) == *group) {
+                    self.set(name, *level)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn level(&self, warning: &Warning) -> Level {
+        self.0
+            .get(warning.lint_name())
+            .or_else(|| self.0.get(ALL))
+            .copied()
+            .unwrap_or(Level::Warn)
+    }
+}
+
+/// Unknown lint "{name}".
+#[derive(Debug, PartialEq, Display)]
+pub struct UnknownLint {
+    name: String,
+}
+
+impl Error for UnknownLint {}
+
+/// Conversion aborted due to {count} denied lint(s).
+#[derive(Debug, PartialEq, Display)]
+pub struct Denied {
+    count: usize,
+}
+
+impl Error for Denied {}
+
+/// A warning that was reported, or a rewrite that was applied to a rule, for embedding in the
+/// output document when stderr isn't captured.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    /// the lint name of a warning, or what asked for a rewrite
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticKind {
+    Warning,
+    Rewrite,
+}
+
+/// the warnings `report` prints as warnings, rather than silencing or failing on them
+pub fn diagnostics(warnings: &[Warning], levels: &Levels) -> Vec<Diagnostic> {
+    warnings
+        .iter()
+        .filter(|warning| levels.level(warning) == Level::Warn)
+        .map(|warning| Diagnostic {
+            kind: DiagnosticKind::Warning,
+            code: warning.lint_name().to_string(),
+            message: warning.to_string(),
+        })
+        .collect()
+}
+
+/// print warnings to stderr according to their level, failing if any of them are denied
+pub fn report(warnings: &[Warning], levels: &Levels) -> Result<(), Denied> {
+    let mut count = 0;
+    for warning in warnings {
+        let name = warning.lint_name();
+        match levels.level(warning) {
+            Level::Allow => {}
+            Level::Warn => eprintln!("warning[{}]: {}", name, warning),
+            Level::Deny => {
+                eprintln!("error[{}]: {}", name, warning);
+                count += 1;
+            }
+        }
+    }
+    if count == 0 {
+        Ok(())
+    } else {
+        Err(Denied { count })
+    }
+}
+
+/// inspect the body of a converted rule for legal but suspicious constructs
+pub fn check(
+    if_all: &[Claim<Entity<String, RdfNode>>],
+    then: &[Claim<Entity<String, RdfNode>>],
+) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    // an empty template is pointless too, but it's not a copy-paste artifact
+    if !then.is_empty() && then.iter().all(|claim| if_all.contains(claim)) {
+        warnings.push(Warning::IdentityRule);
+    }
+
+    let predicates: BTreeSet<&str> = if_all
+        .iter()
+        .filter_map(|[_, p, _]| as_unbound(p))
+        .collect();
+    let elsewhere: BTreeSet<&str> = if_all
+        .iter()
+        .flat_map(|[s, _, o]| vec![s, o])
+        .filter_map(as_unbound)
+        .collect();
+    for name in predicates.difference(&elsewhere) {
+        let name = name.to_string();
+        warnings.push(Warning::PredicateOnlyVariable { name });
+    }
+
+    // blank nodes in queries become variables, but rules read as JSON can still hold them
+    let blanks: BTreeSet<&str> = if_all
+        .iter()
+        .chain(then)
+        .flatten()
+        .filter_map(as_blank)
+        .collect();
+    for name in blanks {
+        let name = name.to_string();
+        warnings.push(Warning::BlankNodeConstant { name });
+    }
+
+    // --canonicalize language-tags can only fix the case of a tag that is well formed
+    let tags: BTreeSet<&str> = if_all
+        .iter()
+        .chain(then)
+        .flatten()
+        .filter_map(|ent| match ent {
+            Entity::Bound(RdfNode::Literal {
+                language: Some(tag),
+                ..
+            }) => Some(tag.as_str()),
+            _ => None,
+        })
+        .collect();
+    for tag in tags {
+        if LanguageTag::parse(tag).is_err() {
+            let tag = tag.to_string();
+            warnings.push(Warning::MalformedLanguageTag { tag });
+        }
+    }
+
+    warnings
+}
+
+/// Report each datatype used in `rule` that isn't in `allowed`, once. This is opt in since it
+/// needs a list of datatypes. Plain and language tagged literals are always permitted.
+pub fn check_datatypes(rule: &RuleParts, allowed: &BTreeSet<String>) -> Vec<Warning> {
+    let unlisted: BTreeSet<&str> = rule
+        .if_all
+        .iter()
+        .chain(&rule.then)
+        .flatten()
+        .filter_map(|ent| match ent {
+            Entity::Bound(node) => node.datatype(),
+            Entity::Unbound(_) => None,
+        })
+        .filter(|datatype| ![XSD_STRING, RDF_LANG_STRING].contains(datatype))
+        .filter(|datatype| !allowed.contains(*datatype))
+        .collect();
+    unlisted
+        .into_iter()
+        .map(|datatype| Warning::UnlistedDatatype {
+            datatype: datatype.to_string(),
+        })
+        .collect()
+}
+
+/// Warn about predicates and classes in the conditions of `rule` that aren't in `seen`. Terms in
+/// conclusions aren't checked, a rule may well derive claims the data doesn't have yet.
+pub fn check_seen(rule: &RuleParts, seen: &Seen) -> Vec<Warning> {
+    let iri = |ent: &Entity<String, RdfNode>| match ent {
+        Entity::Bound(RdfNode::Iri(iri)) => Some(iri.clone()),
+        _ => None,
+    };
+    let mut unseen = BTreeSet::new();
+    for [_, p, o] in &rule.if_all {
+        if let Some(p) = iri(p) {
+            if !seen.predicates.contains(&p) {
+                unseen.insert((p.clone(), "predicate"));
+            }
+            if let Some(o) = iri(o).filter(|o| p == RDF_TYPE && !seen.classes.contains(o)) {
+                unseen.insert((o, "class"));
+            }
+        }
+    }
+    unseen
+        .into_iter()
+        .map(|(iri, role)| Warning::UnseenTerm { iri, role })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        let identity = Warning::IdentityRule;
+        let predicate = Warning::PredicateOnlyVariable {
+            name: "p".to_string(),
+        };
+
+        let mut levels = Levels::default();
+        assert_eq!(levels.level(&identity), Level::Warn);
+
+        levels.set("identity_rule", Level::Deny).unwrap();
+        assert_eq!(levels.level(&identity), Level::Deny);
+        assert_eq!(levels.level(&predicate), Level::Warn);
+
+        // a group level resets earlier per-lint levels, later per-lint levels win
+        levels.set(ALL, Level::Allow).unwrap();
+        levels.set("predicate_only_variable", Level::Deny).unwrap();
+        assert_eq!(levels.level(&identity), Level::Allow);
+        assert_eq!(levels.level(&predicate), Level::Deny);
+
+        let mut levels = Levels::default();
+        let config = serde_json::from_str(
+            r#"{ "allow": ["predicate_only_variable"], "deny": ["warnings"] }"#,
+        )
+        .unwrap();
+        levels.apply(config).unwrap();
+        assert_eq!(levels.level(&identity), Level::Deny);
+        assert_eq!(levels.level(&predicate), Level::Allow);
+
+        assert_eq!(
+            levels.set("identity", Level::Allow),
+            Err(UnknownLint {
+                name: "identity".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn every_lint_is_listed() {
+        let warnings = [
+            Warning::IdentityRule,
+            Warning::PredicateOnlyVariable {
+                name: "p".to_string(),
+            },
+            Warning::UnlistedDatatype {
+                datatype: "http://example.com".to_string(),
+            },
+            Warning::BlankNodeConstant {
+                name: "b".to_string(),
+            },
+            Warning::UnseenTerm {
+                iri: "http://example.com".to_string(),
+                role: "predicate",
+            },
+            Warning::MalformedLanguageTag {
+                tag: "en_GB".to_string(),
+            },
+        ];
+        for warning in &warnings {
+            assert!(LINTS.contains(&warning.lint_name()));
+        }
+        assert_eq!(warnings.len(), LINTS.len());
+    }
+
+    #[test]
+    fn blank_node_constant() {
+        let blank = Entity::Bound(RdfNode::Blank("b".to_string()));
+        let s = Entity::Unbound("s".to_string());
+        let p = Entity::Bound(RdfNode::Iri("http://example.com".to_string()));
+        let if_all = [[s.clone(), p.clone(), blank.clone()]];
+        let then = [[blank, p, s]];
+        assert_eq!(
+            check(&if_all, &then),
+            [Warning::BlankNodeConstant {
+                name: "b".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn malformed_language_tag() {
+        let literal = |tag: &str| {
+            Entity::Bound(RdfNode::Literal {
+                value: "colour".to_string(),
+                datatype: Some(RDF_LANG_STRING.to_string()),
+                language: Some(tag.to_string()),
+            })
+        };
+        let s = Entity::Unbound("s".to_string());
+        let p = Entity::Bound(RdfNode::Iri("http://example.com".to_string()));
+        let if_all = [
+            [s.clone(), p.clone(), literal("en-gb")],
+            [s.clone(), p.clone(), literal("not a tag")],
+        ];
+        let q = Entity::Bound(RdfNode::Iri("http://example.com/q".to_string()));
+        let then = [[s, q, literal("not a tag")]];
+        assert_eq!(
+            check(&if_all, &then),
+            [Warning::MalformedLanguageTag {
+                tag: "not a tag".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn datatypes() {
+        let literal = |value: &str, datatype: Option<&str>| {
+            Entity::Bound(RdfNode::Literal {
+                value: value.to_string(),
+                datatype: datatype.map(str::to_string),
+                language: None,
+            })
+        };
+        let integer = "http://www.w3.org/2001/XMLSchema#integer";
+        let typo = "http://www.w3.org/2001/XMLSchema#integr";
+        let s = Entity::Unbound("s".to_string());
+        let p = Entity::Bound(RdfNode::Iri("http://example.com".to_string()));
+        let rule = RuleParts {
+            if_all: vec![
+                [s.clone(), p.clone(), literal("1", Some(integer))],
+                [s.clone(), p.clone(), literal("2", Some(typo))],
+                [s.clone(), p.clone(), literal("plain", None)],
+            ],
+            then: vec![[s, p, literal("3", Some(typo))]],
+        };
+        let allowed = [integer.to_string()].iter().cloned().collect();
+        assert_eq!(
+            check_datatypes(&rule, &allowed),
+            [Warning::UnlistedDatatype {
+                datatype: typo.to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn unseen_terms() {
+        let iri =
+            |suffix: &str| Entity::Bound(RdfNode::Iri(format!("http://example.com/{}", suffix)));
+        let s = Entity::Unbound("s".to_string());
+        let rule = RuleParts {
+            if_all: vec![
+                [
+                    s.clone(),
+                    Entity::Bound(RdfNode::Iri(RDF_TYPE.to_string())),
+                    iri("Persn"),
+                ],
+                [s.clone(), iri("name"), Entity::Unbound("n".to_string())],
+                [s.clone(), iri("nmae"), iri("name")],
+            ],
+            then: vec![[s, iri("new"), iri("Person")]],
+        };
+        let seen = Seen {
+            predicates: [RDF_TYPE, "http://example.com/name"]
+                .iter()
+                .map(|iri| iri.to_string())
+                .collect(),
+            classes: ["http://example.com/Person".to_string()]
+                .iter()
+                .cloned()
+                .collect(),
+        };
+        let unseen = |iri: &str, role| Warning::UnseenTerm {
+            iri: format!("http://example.com/{}", iri),
+            role,
+        };
+        assert_eq!(
+            check_seen(&rule, &seen),
+            [unseen("Persn", "class"), unseen("nmae", "predicate")]
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Long literals, e.g. base64 payloads embedded in credentials, which can run to megabytes.
+//! Conversion borrows them rather than copying them wherever it can, `check` rejects rules with
+//! literals longer than a limit, and reports abbreviate them to their start, length and hash,
+//! enough to recognise one and tell it apart from others:
+//!
+//! ```text
+//! "eyJhbGciOiJFUzI1NiJ9…[2097152 bytes, sha256:<hex>]"
+//! ```
+
+use crate::manifest;
+use crate::types::{InvalidRule, RdfNode, RuleParts};
+use rify::Entity;
+use serde_json::Value;
+use std::borrow::Cow;
+
+/// how much of a literal an error quotes
+const QUOTED: usize = 40;
+
+/// Fail with `LiteralTooLong` if a literal of `rule` is longer than `limit` bytes.
+pub fn check(rule: &RuleParts, limit: usize) -> Result<(), InvalidRule> {
+    let long = rule
+        .if_all
+        .iter()
+        .chain(&rule.then)
+        .flatten()
+        .find_map(|ent| match ent {
+            Entity::Bound(RdfNode::Literal { value, .. }) if value.len() > limit => Some(value),
+            _ => None,
+        });
+    match long {
+        Some(value) => Err(InvalidRule::LiteralTooLong {
+            length: value.len(),
+            limit,
+            literal: abbreviate(value, QUOTED).into_owned(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// `value` if it is at most `keep` bytes long, otherwise its first `keep` bytes, or less to end
+/// on a character, followed by its length and hash.
+pub fn abbreviate(value: &str, keep: usize) -> Cow<'_, str> {
+    if value.len() <= keep {
+        return Cow::Borrowed(value);
+    }
+    let end = (0..=keep)
+        .rev()
+        .find(|&i| value.is_char_boundary(i))
+        .unwrap_or(0);
+    Cow::Owned(format!(
+        "{}…[{} bytes, sha256:{}]",
+        &value[..end],
+        value.len(),
+        manifest::digest(value.as_bytes())
+    ))
+}
+
+/// Abbreviate the value of each literal in a report, wherever it is nested.
+pub fn abbreviate_json(report: &mut Value, keep: usize) {
+    match report {
+        Value::Object(fields) => {
+            if let Some(Value::String(value)) = fields
+                .get_mut("Literal")
+                .and_then(|literal| literal.get_mut("value"))
+            {
+                if let Cow::Owned(abbreviated) = abbreviate(value, keep) {
+                    *value = abbreviated;
+                }
+            }
+            fields.values_mut().for_each(|v| abbreviate_json(v, keep));
+        }
+        Value::Array(values) => values.iter_mut().for_each(|v| abbreviate_json(v, keep)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn long_literals() {
+        let payload = "é".repeat(3000);
+        let literal = |value: &str| {
+            Entity::Bound(RdfNode::Literal {
+                value: value.to_string(),
+                datatype: None,
+                language: None,
+            })
+        };
+        let iri = Entity::Bound(RdfNode::Iri("http://example.com/payload".to_string()));
+        let rule = RuleParts {
+            if_all: vec![[Entity::Unbound("a".to_string()), iri, literal(&payload)]],
+            then: vec![],
+        };
+        assert_eq!(check(&rule, 6000), Ok(()));
+        let hash = manifest::digest(payload.as_bytes());
+        let quoted = format!("{}…[6000 bytes, sha256:{}]", "é".repeat(20), hash);
+        assert_eq!(
+            check(&rule, 5999),
+            Err(InvalidRule::LiteralTooLong {
+                length: 6000,
+                limit: 5999,
+                literal: quoted
+            })
+        );
+
+        // cut on a character
+        assert!(abbreviate(&payload, 3).starts_with("é…["));
+        assert!(matches!(abbreviate("short", 5), Cow::Borrowed("short")));
+
+        let mut report = json!([{
+            "claim": [{"Iri": "http://example.com/a"}, {"Literal": {"value": payload}}],
+            "bindings": {"b": {"Literal": {"value": "kurz", "language": "de"}}}
+        }]);
+        abbreviate_json(&mut report, 4);
+        assert_eq!(
+            report,
+            json!([{
+                "claim": [
+                    {"Iri": "http://example.com/a"},
+                    {"Literal": {"value": format!("éé…[6000 bytes, sha256:{}]", hash)}}
+                ],
+                "bindings": {"b": {"Literal": {"value": "kurz", "language": "de"}}}
+            }])
+        );
+    }
+}
@@ -1,223 +1,2037 @@
-mod convert;
-mod types;
-mod util;
-
-use crate::convert::{as_triples, to_rify_pattern};
-use crate::types::{InvalidRule, RdfNode};
-use oxigraph::model::GraphName;
-use oxigraph::sparql::algebra::{GraphPattern, Query, QueryDataset, QueryVariants};
-use rify::Rule;
-use std::borrow::Borrow;
+mod cli;
+mod timings;
+mod watch;
+
+use oxigraph::io::{GraphFormat, GraphParser};
+use oxigraph::model::{Term, Triple};
+use oxigraph::sparql::algebra::Query;
+use rify::Claim;
+use serde::de::DeserializeOwned;
+use serde_json::from_value;
+use sparql2rify::dock::DockRule;
+use sparql2rify::dock::DockRules;
+use sparql2rify::extended::{self, ExtendedRule};
+use sparql2rify::input::{self, Kind};
+use sparql2rify::jsonld::{self, Documentation};
+use sparql2rify::lint::{Diagnostic, DiagnosticKind, Warning};
+use sparql2rify::manifest::Manifest;
+use sparql2rify::provenance::{self, Operation, Parent, Provenance};
+use sparql2rify::tags;
+use sparql2rify::types::{InvalidRule, RdfNode, RuleParts};
+use sparql2rify::validity::Validity;
+use sparql2rify::{
+    batch, compose, convert_with, diff, explain, fingerprint, intern, lint, literals, mapping,
+    merge, n3, order, output, partition, reason, refactor, rulepack, sample, schema, simplify,
+    specialize, take_dataset, vars, vocab, weight, ConvertOptions, ConvertedRule, Dataset, Graph,
+};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::error::Error;
-use std::io::{stdin, stdout, Read};
+use std::fs::File;
+use std::io::{stdin, stdout, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
 use std::process::exit;
+use std::thread::sleep;
+use std::time::Duration;
+use timings::{timed, Timings};
 
 fn main() {
-    handle_args();
-
-    let res = || -> Result<(), Box<dyn Error>> {
-        let mut stin = String::new();
-        stdin().read_to_string(&mut stin)?;
-        let q = Query::parse(&stin, None)?;
-        let rules = sparql2rify(q)?;
-        serde_json::to_writer_pretty(stdout(), &rules)?;
-        println!();
-        Ok(())
-    }();
+    let args = handle_args();
+
+    let res = match &args.command {
+        cli::Command::Convert => convert_stdin(&args),
+        cli::Command::ConvertFiles { inputs } => convert_files(&args, inputs),
+        cli::Command::Batch {
+            jobs,
+            verify_serial,
+        } => batch_stdin(&args, *jobs, *verify_serial),
+        cli::Command::Check => check_stdin(&args),
+        cli::Command::Ndjson => ndjson_stdin(&args),
+        cli::Command::Weigh => weigh_stdin(&args),
+        cli::Command::Fingerprint => fingerprint_stdin(&args),
+        cli::Command::FromDock => from_dock_stdin(&args),
+        cli::Command::FromSchema { context, lift } => from_schema_stdin(&args, context, lift),
+        cli::Command::FromCsv { prefixes } => from_csv_stdin(&args, prefixes),
+        cli::Command::Manifest { signer, version } => manifest_stdin(&args, signer, version),
+        cli::Command::Sample { negative } => sample_stdin(&args, *negative),
+        cli::Command::Specialize { bindings } => specialize_stdin(&args, bindings),
+        cli::Command::Apply {
+            store,
+            graph,
+            jobs,
+            memory_budget,
+            round_graphs,
+        } => apply_stdin(
+            &args,
+            store,
+            graph.as_deref(),
+            *jobs,
+            *memory_budget,
+            round_graphs,
+        ),
+        cli::Command::CheckData { data } => check_data_stdin(&args, data),
+        cli::Command::WhyNot { data, expect } => why_not_stdin(&args, data, expect),
+        cli::Command::Impact { rule, data } => impact_file(&args, rule, data),
+        cli::Command::Vocab => vocab_stdin(&args),
+        cli::Command::Refactor { map } => refactor_stdin(&args, map),
+        cli::Command::Pack => pack_stdin(&args),
+        cli::Command::Unpack => unpack_stdin(&args),
+        cli::Command::Partition { by } => partition_stdin(&args, *by),
+        cli::Command::Prune { at } => prune_stdin(&args, at),
+        cli::Command::Compose { first, second } => compose_files(&args, first, second),
+        cli::Command::Generalize { variables } => generalize_stdin(&args, variables),
+        cli::Command::VerifyManifest { manifest } => verify_manifest_stdin(&args, manifest),
+        cli::Command::Watch { inputs, interval } => watch_files(&args, inputs, *interval),
+    };
 
     if let Err(e) = res {
-        eprintln!("{}", e);
+        match e.downcast_ref::<InvalidRule>() {
+            Some(invalid) => {
+                eprintln!("error[{}]: {}", invalid.code(), invalid);
+                eprintln!(
+                    "For more information, try `sparql2rify --explain {}`.",
+                    invalid.code()
+                );
+            }
+            None => eprintln!("{}", e),
+        }
         exit(1);
     }
 }
 
-fn handle_args() {
-    match std::env::args().nth(1).as_deref() {
-        None => {}
-        Some("--help") | Some("-h") => {
-            eprintln!("sparql2rify - Convert a SPARQL CONSTRUCT clause to a rify rule.");
-            eprintln!("USE: cat input.sparql | sparql2rify > output.json");
-            exit(0);
+fn convert_stdin(args: &cli::Args) -> Result<(), Box<dyn Error>> {
+    let mut stin = String::new();
+    stdin().read_to_string(&mut stin)?;
+    if args.extended {
+        return convert_extended(args, &stin);
+    }
+    let mut timings = Timings::default();
+    let mut converted = convert_text_timed(&stin, args, &mut timings)?;
+    let declared = Declared::parse(&stin)?;
+    if args.timings {
+        time_serialize(args, &mut timings, || {
+            let converted = converted.iter();
+            converted
+                .map(|(rule, _, diagnostics)| {
+                    (rule, annotations(args, rule, diagnostics, &declared))
+                })
+                .collect()
+        });
+        timings::report(1, None, &timings);
+    }
+    if converted.len() != 1 {
+        return write_expanded(args, converted, &declared);
+    }
+    let (mut rule, graphs, mut diagnostics) = converted.pop().expect("just checked");
+    if let Some(path) = &args.rename_vars {
+        let original = vars::rename(&mut rule);
+        let mut table = File::create(path)?;
+        serde_json::to_writer_pretty(&mut table, &vars::mapping_table(&original))?;
+        writeln!(table)?;
+        diagnostics.push(Diagnostic {
+            kind: DiagnosticKind::Rewrite,
+            code: "rename-vars".to_string(),
+            message: format!(
+                "Renamed {} variable(s), their original names are in {}.",
+                original.len(),
+                path.display()
+            ),
+        });
+    }
+    let mut out = open_output(args)?;
+    let annotations = annotations(args, &rule, &diagnostics, &declared);
+    let audit = [output::Audit::new(vec![], &rule, &diagnostics)];
+    if args.quads {
+        let rule =
+            DockRule::from_quads_into(&rule, &graphs, &args.default_graph, &args.conclusion_graph)?;
+        let rule = output::annotated_quads(&rule, annotations);
+        serde_json::to_writer_pretty(&mut out, &rule)?;
+    } else if annotations.is_empty() {
+        output::write(&mut out, &rule, args.format)?;
+    } else {
+        let rule = output::annotated(&rule, args.format, annotations);
+        serde_json::to_writer_pretty(&mut out, &rule)?;
+    }
+    writeln!(out)?;
+    write_audit(args, &audit)
+}
+
+/// `convert_stdin` with `--extended`, converting FILTER NOT EXISTS and MINUS to negative
+/// conditions
+fn convert_extended(args: &cli::Args, text: &str) -> Result<(), Box<dyn Error>> {
+    let declared = Declared::parse(text)?;
+    let (mut rule, warnings) = match input::detect(text) {
+        Kind::Sparql => extended_sparql(text, args)?,
+        Kind::N3 => extended_sparql(&n3::to_sparql(text)?, args)?,
+        Kind::Rule => {
+            let rule: ExtendedRule = serde_json::from_str(text)?;
+            rule.check()?;
+            let warnings = lint::check(&rule.rule.if_all, &rule.rule.then);
+            (rule, warnings)
         }
-        _ => {
-            eprintln!("Invalid argument, try --help.");
-            exit(2);
+    };
+    let graphs = vec![None; rule.rule.if_all.len()];
+    let (positive, _, diagnostics) = finish((rule.rule, graphs, warnings), &rule.if_none, args)?;
+    rule.rule = positive;
+    // the negative conditions are rewritten alike, the diagnostics only count the rest
+    let mut negative = rule.negative();
+    args.canonicalize.rule(&mut negative);
+    args.datatypes.rule(&mut negative);
+    args.namespaces.check(&negative)?;
+    if let Some(limit) = args.max_literal_length {
+        literals::check(&negative, limit)?;
+    }
+    rule.set_negative(negative);
+
+    let mut out = open_output(args)?;
+    let annotations = annotations(args, &rule.rule, &diagnostics, &declared);
+    serde_json::to_writer_pretty(&mut out, &output::annotated_extended(&rule, annotations))?;
+    writeln!(out)?;
+    write_audit(
+        args,
+        &[output::Audit::new(vec![], &rule.rule, &diagnostics)],
+    )
+}
+
+/// `convert_sparql` for `--extended`
+fn extended_sparql(
+    text: &str,
+    args: &cli::Args,
+) -> Result<(ExtendedRule, Vec<Warning>), Box<dyn Error>> {
+    let (q, _) = parse_query(text, args)?;
+    let options = ConvertOptions {
+        negation: true,
+        ..ConvertOptions::default()
+    };
+    let mut rules = convert_with(q, &options)?;
+    assert_eq!(rules.len(), 1, "only a UNION has several branches");
+    let ConvertedRule {
+        rule,
+        if_none,
+        warnings,
+        ..
+    } = rules.pop().expect("just checked");
+    Ok((ExtendedRule { rule, if_none }, warnings))
+}
+
+/// `convert_stdin` for a query whose UNION or VALUES expand into any other number of rules than
+/// one, written as a ruleset
+fn write_expanded(
+    args: &cli::Args,
+    converted: Vec<Converted<Diagnostic>>,
+    declared: &Declared,
+) -> Result<(), Box<dyn Error>> {
+    if args.rename_vars.is_some() {
+        return Err("--rename-vars needs a single rule, the query expands into several".into());
+    }
+    let rules = converted
+        .iter()
+        .map(|(rule, _, diagnostics)| (rule, annotations(args, rule, diagnostics, declared)))
+        .collect();
+    let audit: Vec<output::Audit> = converted
+        .iter()
+        .map(|(rule, _, diagnostics)| output::Audit::new(vec![], rule, diagnostics))
+        .collect();
+    write_annotated_ruleset(args, rules)?;
+    write_audit(args, &audit)
+}
+
+/// convert each of `inputs` into one ruleset, noting which file each rule came from
+fn convert_files(args: &cli::Args, inputs: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    let mut converted = Vec::new();
+    let mut timed_files = Vec::new();
+    for path in inputs {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let in_file = |e: &dyn Error| format!("{}: {}", path.display(), e);
+        let mut timings = Timings::default();
+        let rules = convert_text_timed(&text, args, &mut timings).map_err(|e| in_file(&*e))?;
+        let declared = Declared::parse(&text).map_err(|e| in_file(&*e))?;
+        if args.timings {
+            time_serialize(args, &mut timings, || {
+                let rules = rules.iter();
+                rules
+                    .map(|(rule, _, diagnostics)| {
+                        (rule, annotations(args, rule, diagnostics, &declared))
+                    })
+                    .collect()
+            });
+            timed_files.push((path.display().to_string(), timings));
         }
+        converted.extend(
+            rules
+                .into_iter()
+                .map(|(rule, _, diagnostics)| (path, rule, diagnostics, declared.clone())),
+        );
+    }
+    for (i, (source, timings)) in timed_files.iter().enumerate() {
+        timings::report(i + 1, Some(source), timings);
     }
+    if args.merge_rules {
+        return write_merged(args, &converted);
+    }
+    let rules = converted
+        .iter()
+        .map(|(path, rule, diagnostics, declared)| {
+            let mut annotations = annotations(args, rule, diagnostics, declared);
+            let source = path.display().to_string();
+            annotations.insert("source".to_string(), source.into());
+            (rule, annotations)
+        })
+        .collect();
+    let audit: Vec<output::Audit> = converted
+        .iter()
+        .map(|(path, rule, diagnostics, _)| {
+            output::Audit::new(vec![path.display().to_string()], rule, diagnostics)
+        })
+        .collect();
+    let order = stable_order(args, converted.iter().map(|(_, rule, _, _)| rule))?;
+    let (rules, audit) = (permute(rules, &order), permute(audit, &order));
+    report_changes(args, &rules)?;
+    write_annotated_ruleset(args, rules)?;
+    write_audit(args, &audit)
 }
 
-fn sparql2rify(sparql: Query) -> Result<Rule<String, RdfNode>, InvalidRule> {
-    let (construct, dataset, algebra, base_iri) = match sparql.0 {
-        QueryVariants::Construct {
-            construct,
-            dataset,
-            algebra,
-            base_iri,
-        } => (construct, dataset, algebra, base_iri),
-        _ => return Err(InvalidRule::MustBeConstruct),
+/// convert each of the queries read from stdin into one ruleset
+fn batch_stdin(args: &cli::Args, jobs: usize, verify_serial: bool) -> Result<(), Box<dyn Error>> {
+    let mut stin = String::new();
+    stdin().read_to_string(&mut stin)?;
+    let queries = batch::split(&stin);
+    let converted = convert_batch(args, &queries, jobs)?;
+    if args.timings {
+        for (i, (rules, timings)) in converted.iter().enumerate() {
+            let mut timings = *timings;
+            time_serialize(args, &mut timings, || annotated(args, rules));
+            timings::report(i + 1, None, &timings);
+        }
+    }
+    let converted: Vec<BatchRule> = converted.into_iter().flat_map(|(rules, _)| rules).collect();
+    if verify_serial {
+        let serial: Vec<BatchRule> = convert_batch(args, &queries, 1)?
+            .into_iter()
+            .flat_map(|(rules, _)| rules)
+            .collect();
+        let (rules, serial) = (annotated(args, &converted), annotated(args, &serial));
+        let bytes = |rules: &[(&RuleParts, output::Annotations)]| {
+            serde_json::to_vec_pretty(&output::annotated_ruleset(rules.to_vec(), args.format))
+        };
+        if bytes(&rules)? != bytes(&serial)? {
+            let at = match rules.iter().zip(&serial).position(|(a, b)| a != b) {
+                Some(index) => format!("rule {}", index),
+                None => format!("{} rule(s) rather than {}", rules.len(), serial.len()),
+            };
+            return Err(format!(
+                "converted on {} thread(s), the ruleset differs from a serial run's at {}",
+                jobs, at
+            )
+            .into());
+        }
+        eprintln!(
+            "converted on {} thread(s), the ruleset is identical to a serial run's",
+            jobs
+        );
+    }
+    write_annotated_ruleset(args, annotated(args, &converted))
+}
+
+/// A rule of a batch, with what was reported while converting it and what its query declares.
+type BatchRule = (RuleParts, Vec<Diagnostic>, Declared);
+
+/// The rules of a query of a batch, and how long converting it took.
+type BatchQuery = (Vec<BatchRule>, Timings);
+
+fn annotated<'a>(
+    args: &cli::Args,
+    converted: &'a [BatchRule],
+) -> Vec<(&'a RuleParts, output::Annotations)> {
+    converted
+        .iter()
+        .map(|(rule, diagnostics, declared)| (rule, annotations(args, rule, diagnostics, declared)))
+        .collect()
+}
+
+/// Convert the queries of a batch on `jobs` threads, query `i` on thread `i % jobs`. The queries
+/// are put in the order they were written whichever thread converts them first, and a thread
+/// stops at the first of its queries that fails, so the ruleset, or the error of the first query
+/// failing, is always that of a serial run. Only the lints reported on stderr may come in another
+/// order.
+fn convert_batch(
+    args: &cli::Args,
+    queries: &[&str],
+    jobs: usize,
+) -> Result<Vec<BatchQuery>, String> {
+    let convert = |i: usize, text: &str| -> Result<BatchQuery, String> {
+        let in_query = |e: &dyn Error| format!("query {}: {}", i + 1, e);
+        let mut timings = Timings::default();
+        let rules = convert_text_timed(text, args, &mut timings).map_err(|e| in_query(&*e))?;
+        let declared = Declared::parse(text).map_err(|e| in_query(&*e))?;
+        let rules = rules
+            .into_iter()
+            .map(|(rule, _, diagnostics)| (rule, diagnostics, declared.clone()))
+            .collect();
+        Ok((rules, timings))
     };
+    if jobs == 1 {
+        let mut ret = Vec::new();
+        for (i, text) in queries.iter().enumerate() {
+            ret.push(convert(i, text)?);
+        }
+        return Ok(ret);
+    }
+    let mut converted: Vec<Option<Result<BatchQuery, String>>> =
+        queries.iter().map(|_| None).collect();
+    std::thread::scope(|scope| {
+        let convert = &convert;
+        let threads: Vec<_> = (0..jobs)
+            .map(|job| {
+                scope.spawn(move || {
+                    let mut done = Vec::new();
+                    for (i, text) in queries.iter().enumerate().skip(job).step_by(jobs) {
+                        let result = convert(i, text);
+                        let failed = result.is_err();
+                        done.push((i, result));
+                        if failed {
+                            break;
+                        }
+                    }
+                    done
+                })
+            })
+            .collect();
+        for thread in threads {
+            let done = thread.join().expect("conversion threads don't panic");
+            for (i, result) in done {
+                converted[i] = Some(result);
+            }
+        }
+    });
+    let mut ret = Vec::new();
+    for result in converted {
+        // a query is only skipped after an earlier one of its thread failed
+        ret.push(result.expect("a failure comes first")?);
+    }
+    Ok(ret)
+}
 
-    if (QueryDataset {
-        default: Some(vec![GraphName::DefaultGraph]),
-        named: None,
-    } != dataset)
-    {
-        return Err(InvalidRule::IllegalFrom);
+/// `convert_files` for `--merge-rules`, each rule naming all the files it came from
+fn write_merged(
+    args: &cli::Args,
+    converted: &[(&PathBuf, RuleParts, Vec<Diagnostic>, Declared)],
+) -> Result<(), Box<dyn Error>> {
+    let rules: Vec<RuleParts> = converted
+        .iter()
+        .map(|(_, rule, _, _)| rule.clone())
+        .collect();
+    let merged = merge::merge(&rules);
+    for merged in &merged {
+        let first = merged.sources[0];
+        if let Some(&i) = merged
+            .sources
+            .iter()
+            .find(|&&i| !converted[i].3.merges_with(&converted[first].3))
+        {
+            return Err(format!(
+                "--merge-rules can't merge rules from {} and {}, they declare different tags or \
+                 dates they are valid for",
+                converted[first].0.display(),
+                converted[i].0.display()
+            )
+            .into());
+        }
     }
+    let mut audit = Vec::new();
+    let rules = merged
+        .iter()
+        .map(|merged| {
+            let mut diagnostics: Vec<Diagnostic> = merged
+                .sources
+                .iter()
+                .flat_map(|&i| converted[i].2.iter().cloned())
+                .collect();
+            if merged.sources.len() > 1 {
+                diagnostics.push(Diagnostic {
+                    kind: DiagnosticKind::Rewrite,
+                    code: "merge-rules".to_string(),
+                    message: format!(
+                        "Merged {} rules with the same conditions.",
+                        merged.sources.len()
+                    ),
+                });
+            }
+            let declared = &converted[merged.sources[0]].3;
+            let mut annotations = annotations(args, &merged.rule, &diagnostics, declared);
+            if merged.sources.len() > 1 {
+                let provenance = Provenance {
+                    operation: Operation::Merge,
+                    parents: merged
+                        .sources
+                        .iter()
+                        .map(|&i| converted[i].3.parent(&converted[i].1))
+                        .collect(),
+                };
+                annotations.extend(provenance::annotations(&provenance));
+            }
+            if args.emit_linked_data {
+                let docs: Vec<_> = merged
+                    .sources
+                    .iter()
+                    .map(|&i| &converted[i].3.docs)
+                    .collect();
+                annotations.extend(jsonld::annotations(&merged.rule, &docs));
+            }
+            let sources: Vec<String> = merged
+                .sources
+                .iter()
+                .map(|&i| converted[i].0.display().to_string())
+                .collect();
+            audit.push(output::Audit::new(
+                sources.clone(),
+                &merged.rule,
+                &diagnostics,
+            ));
+            annotations.insert("sources".to_string(), sources.into());
+            (&merged.rule, annotations)
+        })
+        .collect();
+    let order = stable_order(args, merged.iter().map(|merged| &merged.rule))?;
+    let (rules, audit) = (permute(rules, &order), permute(audit, &order));
+    report_changes(args, &rules)?;
+    write_annotated_ruleset(args, rules)?;
+    write_audit(args, &audit)
+}
 
-    if base_iri.is_some() {
-        return Err(InvalidRule::IllegalBaseIri);
+/// The order to write converted rules in, keeping the index each had in `--previous` if it was
+/// given, otherwise the order they were converted in.
+fn stable_order<'a>(
+    args: &cli::Args,
+    rules: impl ExactSizeIterator<Item = &'a RuleParts>,
+) -> Result<Vec<usize>, String> {
+    match &args.previous {
+        Some(path) => {
+            let previous: Vec<RuleParts> = read_json(path)?;
+            Ok(order::stable_order(&previous, rules))
+        }
+        None => Ok((0..rules.len()).collect()),
     }
+}
 
-    let (project, _vars) = match algebra.borrow() {
-        GraphPattern::Project(patt, vars) => (patt, vars),
-        _ => return Err(InvalidRule::MustBeBasicGraphPattern),
+/// With `--against`, report on stderr how the rules about to be written differ from that ruleset.
+/// Merged rules name no single source, so a merged rule that changed is reported as removed and
+/// added.
+fn report_changes(
+    args: &cli::Args,
+    rules: &[(&RuleParts, output::Annotations)],
+) -> Result<(), String> {
+    let path = match &args.against {
+        Some(path) => path,
+        None => return Ok(()),
     };
-    let bgp = match &**project {
-        GraphPattern::BGP(bgp) => bgp,
-        _ => return Err(InvalidRule::MustBeBasicGraphPattern),
+    let previous: Vec<diff::Sourced> = read_json(path)?;
+    let previous: Vec<_> = previous
+        .iter()
+        .map(|sourced| (&sourced.rule, sourced.source.as_deref()))
+        .collect();
+    let current: Vec<_> = rules
+        .iter()
+        .map(|(rule, annotations)| (*rule, annotations.get("source").and_then(|s| s.as_str())))
+        .collect();
+    let changes = diff::diff(&previous, &current);
+    let describe = |index: usize, source: Option<&str>| match source {
+        Some(source) => format!("rule {} ({})", index, source),
+        None => format!("rule {}", index),
     };
+    let (mut changed, mut added, mut removed) = (0, 0, 0);
+    for change in &changes {
+        match *change {
+            diff::Change::Changed {
+                previous: was,
+                index,
+            } => {
+                changed += 1;
+                let rule = describe(index, current[index].1);
+                eprintln!("changed: {}, was rule {}", rule, was);
+            }
+            diff::Change::Added { index } => {
+                added += 1;
+                eprintln!("added: {}", describe(index, current[index].1));
+            }
+            diff::Change::Removed { previous: was } => {
+                removed += 1;
+                eprintln!("removed: {}", describe(was, previous[was].1));
+            }
+        }
+    }
+    eprintln!(
+        "Against {}: {} rule(s) changed, {} added, {} removed, {} unchanged.",
+        path.display(),
+        changed,
+        added,
+        removed,
+        current.len() - changed - added
+    );
+    Ok(())
+}
 
-    // graph pattern must not contain path patterns
-    let bgp = as_triples(&bgp)?;
+/// `items` in the order of the indices in `order`
+fn permute<T>(items: Vec<T>, order: &[usize]) -> Vec<T> {
+    let mut items: Vec<Option<T>> = items.into_iter().map(Some).collect();
+    order
+        .iter()
+        .map(|&i| items[i].take().expect("order is a permutation"))
+        .collect()
+}
 
-    let mut if_all = to_rify_pattern(&bgp);
-    let mut then = to_rify_pattern(&construct);
+/// Write `rules` to `--output`. With `--split-types` the conclusions of each rule that are
+/// rdf:type claims are written to its file instead, as rules of their own.
+fn write_annotated_ruleset(
+    args: &cli::Args,
+    rules: Vec<(&RuleParts, output::Annotations)>,
+) -> Result<(), Box<dyn Error>> {
+    let path = match &args.split_types {
+        Some(path) => path,
+        None => {
+            let mut out = open_output(args)?;
+            serde_json::to_writer_pretty(&mut out, &output::annotated_ruleset(rules, args.format))?;
+            writeln!(out)?;
+            return Ok(());
+        }
+    };
+    let split: Vec<_> = rules
+        .into_iter()
+        .map(|(rule, annotations)| (vocab::split_types(rule), annotations))
+        .collect();
+    let partition = |types: bool| {
+        split
+            .iter()
+            .filter_map(|((type_rule, rest), annotations)| {
+                let rule = if types { type_rule } else { rest };
+                let mut annotations = annotations.clone();
+                // what the rule derives changed
+                if let (Some(rule), Some(usage)) = (rule, annotations.get_mut("rdf_type")) {
+                    *usage = serde_json::to_value(vocab::type_usage(rule)).expect("serializes");
+                }
+                rule.as_ref().map(|rule| (rule, annotations))
+            })
+            .collect()
+    };
+    let mut file = File::create(path)?;
+    let types = output::annotated_ruleset(partition(true), args.format);
+    serde_json::to_writer_pretty(&mut file, &types)?;
+    writeln!(file)?;
+    let mut out = open_output(args)?;
+    serde_json::to_writer_pretty(
+        &mut out,
+        &output::annotated_ruleset(partition(false), args.format),
+    )?;
+    writeln!(out)?;
+    Ok(())
+}
 
-    // blank nodes in `then` are a footgun so they are not allowed
-    for ent in then.iter().flatten() {
-        if let Some(name) = util::as_blank(ent) {
-            return Err(InvalidRule::BlankNodeImplied {
-                name: name.to_string(),
-            });
+/// write `--audit`, if it was given
+fn write_audit(args: &cli::Args, audit: &[output::Audit]) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &args.audit {
+        let mut file = File::create(path)?;
+        serde_json::to_writer_pretty(&mut file, audit)?;
+        writeln!(file)?;
+    }
+    Ok(())
+}
+
+/// What a query declares in its comments about the rules converted from it, or a rule that was
+/// already converted in its fields.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Declared {
+    validity: Validity,
+    tags: Vec<String>,
+    docs: Documentation,
+    provenance: Option<Provenance>,
+}
+
+impl Declared {
+    fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let validity = Validity::parse(text)?;
+        let tags = tags::parse(text);
+        let docs = Documentation::parse(text);
+        let provenance = provenance::read(text)?;
+        Ok(Self {
+            validity,
+            tags,
+            docs,
+            provenance,
+        })
+    }
+
+    /// `rule`, converted from the text these were declared in, as the parent of a derived rule
+    fn parent(&self, rule: &RuleParts) -> Parent {
+        Parent {
+            fingerprint: fingerprint::fingerprint(rule),
+            source: self.docs.source.clone(),
+            provenance: self.provenance.clone().map(Box::new),
         }
     }
 
-    util::unbind_blanks(&mut if_all, &mut then)?;
+    /// whether these add fields to a rule whatever the options
+    fn declares(&self) -> bool {
+        self.validity != Validity::default() || !self.tags.is_empty()
+    }
+
+    /// whether rules declared with these and `other` may be merged, as they apply to the same
+    /// deployments on the same days
+    fn merges_with(&self, other: &Self) -> bool {
+        self.validity == other.validity && self.tags == other.tags
+    }
 
-    Rule::create(if_all, then).map_err(Into::into)
+    /// the fields these add to a rule, along with the tags of `--tag`
+    fn annotations(&self, args: &cli::Args) -> output::Annotations {
+        let mut ret = self.validity.annotations();
+        let mut tags = self.tags.clone();
+        tags::add(&mut tags, args.tags.iter().map(String::as_str));
+        if !tags.is_empty() {
+            ret.insert("tags".to_string(), tags.into());
+        }
+        ret
+    }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::RdfNode::Iri;
-    use rify::Entity::{Bound, Unbound};
+/// the fields a query declares, and the extra fields `--embed-diagnostics`, `--emit-var-map`,
+/// `--emit-type-usage` and `--emit-linked-data` add, to a rule
+fn annotations(
+    args: &cli::Args,
+    rule: &RuleParts,
+    diagnostics: &[Diagnostic],
+    declared: &Declared,
+) -> output::Annotations {
+    let mut annotations = declared.annotations(args);
+    if args.embed_diagnostics {
+        annotations.extend(output::diagnostics(diagnostics));
+    }
+    if args.emit_var_map {
+        let var_map = serde_json::to_value(vars::var_map(rule)).expect("always serializes");
+        annotations.insert("var_map".to_string(), var_map);
+    }
+    if args.emit_type_usage {
+        let usage = serde_json::to_value(vocab::type_usage(rule)).expect("always serializes");
+        annotations.insert("rdf_type".to_string(), usage);
+    }
+    if args.emit_linked_data {
+        annotations.extend(jsonld::annotations(rule, &[&declared.docs]));
+    }
+    annotations
+}
 
-    #[test]
-    fn simple_rule() {
-        let sparql = "CONSTRUCT { ?s ?p ?o . }  WHERE { ?s ?p ?o . }"
-            .parse()
-            .unwrap();
-        let r = sparql2rify(dbg!(sparql)).unwrap();
-        assert_eq!(
-            r,
-            rify::Rule::create(
-                vec![[unbd("s"), unbd("p"), unbd("o")]],
-                vec![[unbd("s"), unbd("p"), unbd("o")]]
-            )
-            .unwrap()
-        );
+/// translate rules in Dock's claim deduction format to `--format`
+fn from_dock_stdin(args: &cli::Args) -> Result<(), Box<dyn Error>> {
+    let rules: DockRules = match serde_json::from_reader(stdin())? {
+        rules @ serde_json::Value::Array(_) => DockRules::Many(select(args, from_value(rules)?)?),
+        rule => from_value(rule)?,
+    };
+    let from_dock = |rule| -> Result<RuleParts, Box<dyn Error>> {
+        let rule = RuleParts::try_from(rule)?;
+        rule.check()?;
+        rule.check_iris()?;
+        Ok(rule)
+    };
+    let mut out = open_output(args)?;
+    match rules {
+        DockRules::One(rule) => output::write(&mut out, &from_dock(rule)?, args.format)?,
+        DockRules::Many(rules) => {
+            let rules = rules
+                .into_iter()
+                .map(from_dock)
+                .collect::<Result<Vec<_>, _>>()?;
+            let rules: Vec<&RuleParts> = rules.iter().collect();
+            output::write_ruleset(&mut out, &rules, args.format)?
+        }
     }
+    writeln!(out)?;
+    Ok(())
+}
 
-    #[test]
-    fn reified_claim() {
-        let sparql = "
-            PREFIX rdf: <http://www.w3.org/1999/02/22-rdf-syntax-ns#>
-            
-            CONSTRUCT {
-                ?s ?p ?o .
-            } WHERE {
-                ?a rdf:subject ?s ;
-                   rdf:predicate ?p ;
-                   rdf:object ?o .
-            }
-        "
-        .parse();
-        let res = sparql2rify(sparql.unwrap()).unwrap();
-        assert_eq!(
-            res,
-            rify::Rule::create(
-                vec![
-                    [unbd("a"), rdf("subject"), unbd("s")],
-                    [unbd("a"), rdf("predicate"), unbd("p")],
-                    [unbd("a"), rdf("object"), unbd("o")]
-                ],
-                vec![[unbd("s"), unbd("p"), unbd("o")]]
-            )
-            .unwrap()
+/// generate a ruleset from a credential schema
+fn from_schema_stdin(
+    args: &cli::Args,
+    context: &PathBuf,
+    lift: &schema::Lift,
+) -> Result<(), Box<dyn Error>> {
+    let credential_schema: serde_json::Value = serde_json::from_reader(stdin())?;
+    let context: serde_json::Value = serde_json::from_reader(File::open(context)?)?;
+    let rules = schema::rules(&credential_schema, &context, lift)?;
+    for rule in &rules {
+        rule.check()?;
+        rule.check_iris()?;
+    }
+    let rules: Vec<&RuleParts> = rules.iter().collect();
+    let mut out = open_output(args)?;
+    output::write_ruleset(&mut out, &rules, args.format)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// generate a ruleset from a CSV table of predicate mappings
+fn from_csv_stdin(
+    args: &cli::Args,
+    prefixes: &BTreeMap<String, String>,
+) -> Result<(), Box<dyn Error>> {
+    let mut csv = String::new();
+    stdin().read_to_string(&mut csv)?;
+    let rules = mapping::rules(&csv, prefixes)?;
+    for rule in &rules {
+        rule.check()?;
+        rule.check_iris()?;
+        args.namespaces.check(rule)?;
+    }
+    let rules: Vec<&RuleParts> = rules.iter().collect();
+    let mut out = open_output(args)?;
+    output::write_ruleset(&mut out, &rules, args.format)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Report how close each rule comes to deriving the --expect triple from --data, with what the
+/// rules derive from it, when they don't.
+fn why_not_stdin(args: &cli::Args, data: &PathBuf, expect: &str) -> Result<(), Box<dyn Error>> {
+    let mut rules: Vec<RuleParts> = read_ruleset(args)?;
+    reason::explicit_datatypes(&mut rules);
+    let rify_rules = rules
+        .iter()
+        .map(RuleParts::to_rule)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut triples = read_data(data)?;
+    triples.extend(rify::infer(&triples, &rify_rules));
+    let expect = expect.trim();
+    let statement = if expect.ends_with('.') {
+        expect.to_string()
+    } else {
+        format!("{} .", expect)
+    };
+    let parsed = GraphParser::from_format(GraphFormat::NTriples)
+        .read_triples(statement.as_bytes())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("--expect: {}", e))?;
+    let expected = match parsed.as_slice() {
+        [triple] => to_claim(triple.clone()),
+        _ => return Err("--expect must be a single N-Triples triple".into()),
+    };
+    if triples.contains(&expected) {
+        eprintln!("{} holds, with what the rules derive", expect);
+    }
+    let misses = reason::why_not(&rules, &triples, &expected);
+    if misses.is_empty() {
+        eprintln!("no rule has a conclusion that could be {}", expect);
+    }
+    write_report(args, &misses)
+}
+
+/// Report how many claims the rule in a file, a query or a rule in any of the input formats,
+/// derives from --data and with which predicates, for reviewing it before it is deployed.
+fn impact_file(args: &cli::Args, rule: &PathBuf, data: &PathBuf) -> Result<(), Box<dyn Error>> {
+    let text = std::fs::read_to_string(rule).map_err(|e| format!("{}: {}", rule.display(), e))?;
+    let mut rules: Vec<RuleParts> = convert_text_all(&text, args)
+        .map_err(|e| format!("{}: {}", rule.display(), e))?
+        .into_iter()
+        .map(|(rule, _, _)| rule)
+        .collect();
+    reason::explicit_datatypes(&mut rules);
+    let rules = rules
+        .iter()
+        .map(RuleParts::to_rule)
+        .collect::<Result<Vec<_>, _>>()?;
+    let triples = read_data(data)?;
+    let impact = reason::impact(&rules, &triples);
+    eprintln!(
+        "{} derives {} claim(s) {} doesn't hold, with {} predicate(s)",
+        rule.display(),
+        impact.derived,
+        data.display(),
+        impact.predicates.len()
+    );
+    let mut out = open_output(args)?;
+    serde_json::to_writer_pretty(&mut out, &impact)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// write a report on rules and data, with `--truncate-literals` abbreviating long literals
+fn write_report(args: &cli::Args, report: &impl serde::Serialize) -> Result<(), Box<dyn Error>> {
+    let mut out = open_output(args)?;
+    match args.truncate_literals {
+        Some(keep) => {
+            let mut report = serde_json::to_value(report)?;
+            literals::abbreviate_json(&mut report, keep);
+            serde_json::to_writer_pretty(&mut out, &report)?;
+        }
+        None => serde_json::to_writer_pretty(&mut out, report)?,
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// the triples in an RDF file, parsed according to its extension
+fn read_data(data: &PathBuf) -> Result<Vec<Claim<RdfNode>>, Box<dyn Error>> {
+    let format = match data.extension().and_then(|ext| ext.to_str()) {
+        Some("nt") => GraphFormat::NTriples,
+        Some("rdf") | Some("xml") => GraphFormat::RdfXml,
+        _ => GraphFormat::Turtle,
+    };
+    let file = File::open(data).map_err(|e| format!("{}: {}", data.display(), e))?;
+    let mut triples = Vec::new();
+    for triple in GraphParser::from_format(format).read_triples(BufReader::new(file))? {
+        triples.push(to_claim(
+            triple.map_err(|e| format!("{}: {}", data.display(), e))?,
+        ));
+    }
+    Ok(triples)
+}
+
+fn to_claim(triple: Triple) -> Claim<RdfNode> {
+    [
+        RdfNode::from(&Term::from(triple.subject)),
+        RdfNode::Iri(triple.predicate.as_str().to_string()),
+        RdfNode::from(&triple.object),
+    ]
+}
+
+/// write sample data the rule fires on, or near misses it must not fire on, for testing it
+fn sample_stdin(args: &cli::Args, negative: bool) -> Result<(), Box<dyn Error>> {
+    let mut stin = String::new();
+    stdin().read_to_string(&mut stin)?;
+    let (rule, _) = convert_text(&stin, args)?;
+    let mut out = open_output(args)?;
+    if negative {
+        serde_json::to_writer_pretty(&mut out, &sample::negative(&rule))?;
+        writeln!(out)?;
+    } else {
+        write!(out, "{}", sample::positive(&rule))?;
+    }
+    Ok(())
+}
+
+/// bind variables of a rule to terms, e.g. to instantiate a template for one tenant
+fn specialize_stdin(args: &cli::Args, bindings: &PathBuf) -> Result<(), Box<dyn Error>> {
+    let mut stin = String::new();
+    stdin().read_to_string(&mut stin)?;
+    let (rule, _) = convert_text(&stin, args)?;
+    let bindings: BTreeMap<String, RdfNode> = read_json(bindings)?;
+    let provenance = Provenance {
+        operation: Operation::Specialize,
+        parents: vec![Parent::new(&rule, &stin)?],
+    };
+    write_rewritten(args, specialize::specialize(&rule, &bindings)?, &provenance)
+}
+
+/// replace IRIs in a rule with variables, e.g. to factor near identical rules into a template
+fn generalize_stdin(args: &cli::Args, variables: &PathBuf) -> Result<(), Box<dyn Error>> {
+    let mut stin = String::new();
+    stdin().read_to_string(&mut stin)?;
+    let (rule, _) = convert_text(&stin, args)?;
+    let variables: BTreeMap<String, String> = read_json(variables)?;
+    let provenance = Provenance {
+        operation: Operation::Generalize,
+        parents: vec![Parent::new(&rule, &stin)?],
+    };
+    write_rewritten(
+        args,
+        specialize::generalize(&rule, &variables)?,
+        &provenance,
+    )
+}
+
+/// write the rules that go straight from one rule's conditions to what a second rule derives
+/// from the first one's conclusions
+fn compose_files(
+    args: &cli::Args,
+    first: &PathBuf,
+    second: &PathBuf,
+) -> Result<(), Box<dyn Error>> {
+    let convert = |path: &PathBuf| -> Result<(RuleParts, Parent), Box<dyn Error>> {
+        let in_file = |e: &dyn Error| format!("{}: {}", path.display(), e);
+        let text = std::fs::read_to_string(path).map_err(|e| in_file(&e))?;
+        let (rule, _) = convert_text(&text, args).map_err(|e| in_file(&*e))?;
+        let parent = Parent::new(&rule, &text).map_err(|e| in_file(&e))?;
+        Ok((rule, parent))
+    };
+    let ((first, first_parent), (second, second_parent)) = (convert(first)?, convert(second)?);
+    let provenance = Provenance {
+        operation: Operation::Compose,
+        parents: vec![first_parent, second_parent],
+    };
+    let mut rules = compose::compose(&first, &second);
+    for rule in &mut rules {
+        if !args.keep_redundant {
+            simplify::simplify(rule, &mut vec![None; rule.if_all.len()], &[]);
+        }
+        if !args.preserve_order {
+            order::canonical_order(&mut rule.if_all);
+        }
+        rule.check()?;
+        rule.check_iris()?;
+    }
+    let rules = rules
+        .iter()
+        .map(|rule| (rule, provenance::annotations(&provenance)))
+        .collect();
+    let mut out = open_output(args)?;
+    serde_json::to_writer_pretty(&mut out, &output::annotated_ruleset(rules, args.format))?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// The ruleset read from stdin, a JSON array of rules, with only the rules `--include-tags` and
+/// `--exclude-tags` select. Rules with negative conditions are rejected rather than read as the
+/// rify rules they are not.
+fn read_ruleset<T: DeserializeOwned>(args: &cli::Args) -> Result<Vec<T>, Box<dyn Error>> {
+    let rules = read_rules(args)?;
+    if let Some(index) = rules.iter().position(extended::has_negation) {
+        return Err(format!(
+            "rule {} has negative conditions (if_none), only a reasoner for the extended format \
+             can apply it",
+            index
+        )
+        .into());
+    }
+    Ok(from_value(rules.into())?)
+}
+
+/// `read_ruleset` for commands that apply to rules in the extended format too
+fn read_rules(args: &cli::Args) -> Result<Vec<output::Annotations>, Box<dyn Error>> {
+    let rules = serde_json::from_reader(stdin())?;
+    if args.select.is_empty() {
+        return Ok(rules);
+    }
+    select(args, rules)
+}
+
+/// the rules of a ruleset `--include-tags` and `--exclude-tags` select
+fn select<T: DeserializeOwned>(
+    args: &cli::Args,
+    rules: Vec<output::Annotations>,
+) -> Result<Vec<T>, Box<dyn Error>> {
+    let count = rules.len();
+    let rules = args.select.filter(rules);
+    if rules.len() != count {
+        eprintln!("loaded {} of {} rule(s) by their tags", rules.len(), count);
+    }
+    Ok(from_value(rules.into())?)
+}
+
+fn read_json<T: DeserializeOwned>(path: &PathBuf) -> Result<T, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_reader(file).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// check, lint and write a rule that was rewritten after conversion
+fn write_rewritten(
+    args: &cli::Args,
+    rule: RuleParts,
+    provenance: &Provenance,
+) -> Result<(), Box<dyn Error>> {
+    rule.check()?;
+    rule.check_iris()?;
+    args.namespaces.check(&rule)?;
+    lint::report(&lint::check(&rule.if_all, &rule.then), &args.levels)?;
+    let mut out = open_output(args)?;
+    let annotations = provenance::annotations(provenance);
+    serde_json::to_writer_pretty(
+        &mut out,
+        &output::annotated(&rule, args.format, annotations),
+    )?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// write a manifest for a ruleset in rify format
+fn manifest_stdin(args: &cli::Args, signer: &str, version: &str) -> Result<(), Box<dyn Error>> {
+    let rules: Vec<RuleParts> = read_ruleset(args)?;
+    let manifest = Manifest::new(&rules, signer.to_string(), version.to_string());
+    let mut out = open_output(args)?;
+    serde_json::to_writer_pretty(&mut out, &manifest)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// materialize a ruleset in rify format into an oxigraph store
+#[cfg(feature = "store")]
+fn apply_stdin(
+    args: &cli::Args,
+    store: &PathBuf,
+    graph: Option<&str>,
+    jobs: usize,
+    memory_budget: Option<usize>,
+    round_graphs: &Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    use oxigraph::model::{GraphName, NamedNode};
+    let rules: Vec<RuleParts> = read_ruleset(args)?;
+    for rule in &rules {
+        rule.check_iris()?;
+        args.namespaces.check(rule)?;
+    }
+    let graph = match graph {
+        Some(iri) => GraphName::from(NamedNode::new(iri)?),
+        None => GraphName::DefaultGraph,
+    };
+    if let Some(prefix) = round_graphs {
+        NamedNode::new(format!("{}1", prefix))?;
+    }
+    let store =
+        oxigraph::SledStore::open(store).map_err(|e| format!("{}: {}", store.display(), e))?;
+    let options = sparql2rify::store::Options {
+        jobs,
+        memory_budget,
+        round_graphs: round_graphs.clone(),
+    };
+    let materialized = sparql2rify::store::materialize(&store, &rules, &graph, &options)?;
+    if materialized.in_store {
+        eprintln!(
+            "More than {} claim(s) to read, inferred on the store, inserted {} claim(s).",
+            materialized.read, materialized.inferred
+        );
+    } else {
+        eprintln!(
+            "Read {} claim(s), inserted {} inferred claim(s).",
+            materialized.read, materialized.inferred
         );
     }
+    if let Some(prefix) = round_graphs {
+        for (i, inserted) in materialized.rounds.iter().enumerate() {
+            eprintln!(
+                "Round {}: inserted {} claim(s) into <{}{}>.",
+                i + 1,
+                inserted,
+                prefix,
+                i + 1
+            );
+        }
+    }
+    if materialized.skipped > 0 {
+        eprintln!(
+            "warning: skipped {} inferred claim(s) that aren't valid RDF.",
+            materialized.skipped
+        );
+    }
+    Ok(())
+}
 
-    #[test]
-    fn anonymous_blanknode() {
-        let sparql = "
-            PREFIX rdf: <http://www.w3.org/1999/02/22-rdf-syntax-ns#>
-            
-            CONSTRUCT { } WHERE {
-                [] rdf:subject [] .
+#[cfg(not(feature = "store"))]
+fn apply_stdin(
+    _: &cli::Args,
+    _: &PathBuf,
+    _: Option<&str>,
+    _: usize,
+    _: Option<usize>,
+    _: &Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    Err(
+        "sparql2rify was built without oxigraph store support, rebuild it with --features store."
+            .into(),
+    )
+}
+
+/// report the claims a ruleset in rify format implies for some data that the data lacks,
+/// failing if there are any
+fn check_data_stdin(args: &cli::Args, data: &PathBuf) -> Result<(), Box<dyn Error>> {
+    let mut rules: Vec<RuleParts> = read_ruleset(args)?;
+    for rule in &rules {
+        rule.check()?;
+        rule.check_iris()?;
+    }
+    reason::explicit_datatypes(&mut rules);
+    let triples = read_data(data)?;
+    let missing = reason::missing(&rules, &triples);
+    write_report(args, &missing)?;
+    if !missing.is_empty() {
+        return Err(format!(
+            "{} implied claim(s) missing from {}",
+            missing.len(),
+            data.display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// list the IRIs used by a ruleset in rify format
+fn vocab_stdin(args: &cli::Args) -> Result<(), Box<dyn Error>> {
+    let (rules, count) = intern::read(stdin(), &args.select, &mut intern::Interner::default())?;
+    if rules.len() != count {
+        eprintln!("loaded {} of {} rule(s) by their tags", rules.len(), count);
+    }
+    let mut out = open_output(args)?;
+    serde_json::to_writer_pretty(&mut out, &vocab::vocabulary(&rules))?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// rename IRIs across a ruleset in rify format
+fn refactor_stdin(args: &cli::Args, map: &BTreeMap<String, String>) -> Result<(), Box<dyn Error>> {
+    let mut rules: Vec<RuleParts> = read_ruleset(args)?;
+    for (old, count) in refactor::rename_iris(&mut rules, map) {
+        if count == 0 {
+            eprintln!("warning: <{}> does not appear in the ruleset", old);
+        }
+    }
+    for rule in &rules {
+        rule.check()?;
+        rule.check_iris()?;
+        args.namespaces.check(rule)?;
+    }
+    let rules: Vec<&RuleParts> = rules.iter().collect();
+    let mut out = open_output(args)?;
+    output::write_ruleset(&mut out, &rules, args.format)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// encode a ruleset in rify format as a rulepack
+fn pack_stdin(args: &cli::Args) -> Result<(), Box<dyn Error>> {
+    let rules: Vec<RuleParts> = read_ruleset(args)?;
+    for rule in &rules {
+        rule.check()?;
+        rule.check_iris()?;
+    }
+    let rules: Vec<&RuleParts> = rules.iter().collect();
+    open_output(args)?.write_all(&rulepack::write(&rules))?;
+    Ok(())
+}
+
+/// decode a rulepack, writing its rules in --format
+fn unpack_stdin(args: &cli::Args) -> Result<(), Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    stdin().read_to_end(&mut bytes)?;
+    let rules = rulepack::read(&bytes)?;
+    for rule in &rules {
+        rule.check()?;
+        rule.check_iris()?;
+    }
+    let rules: Vec<&RuleParts> = rules.iter().collect();
+    let mut out = open_output(args)?;
+    output::write_ruleset(&mut out, &rules, args.format)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// check a ruleset in rify format against a manifest, failing if it doesn't match
+fn verify_manifest_stdin(args: &cli::Args, manifest: &PathBuf) -> Result<(), Box<dyn Error>> {
+    let manifest: Manifest = serde_json::from_reader(File::open(manifest)?)?;
+    let rules: Vec<RuleParts> = read_ruleset(args)?;
+    manifest.verify(&rules)?;
+    eprintln!(
+        "ruleset matches version {} of the manifest, signer {}",
+        manifest.version, manifest.signer
+    );
+    Ok(())
+}
+
+/// Split a ruleset into a file for each predicate, or graph, its rules write to in the directory
+/// `--output`, with an index.json from file name to what the rules in it write to.
+fn partition_stdin(args: &cli::Args, by: partition::By) -> Result<(), Box<dyn Error>> {
+    let dir = args.output.as_ref().expect("checked by cli::parse");
+    let partitions = match by {
+        partition::By::Predicate => {
+            let rules: Vec<RuleParts> = read_ruleset(args)?;
+            for rule in &rules {
+                rule.check()?;
+                rule.check_iris()?;
             }
-        "
-        .parse();
-        sparql2rify(sparql.unwrap()).unwrap();
+            partition::by_predicate(&rules)
+                .into_iter()
+                .map(|(target, rules)| {
+                    let rules: Vec<&RuleParts> = rules.iter().collect();
+                    let mut json = Vec::new();
+                    output::write_ruleset(&mut json, &rules, args.format)?;
+                    Ok((target, rules.len(), json))
+                })
+                .collect::<serde_json::Result<Vec<_>>>()?
+        }
+        partition::By::Graph => {
+            let rules: Vec<DockRule> = read_ruleset(args)?;
+            partition::by_graph(&rules)
+                .into_iter()
+                .map(|(target, rules)| {
+                    Ok((target, rules.len(), serde_json::to_vec_pretty(&rules)?))
+                })
+                .collect::<serde_json::Result<Vec<_>>>()?
+        }
+    };
+    std::fs::create_dir_all(dir)?;
+    let names = partition::file_names(partitions.iter().map(|(target, _, _)| target));
+    let mut index = BTreeMap::new();
+    for ((target, count, mut json), name) in partitions.into_iter().zip(names) {
+        json.push(b'\n');
+        std::fs::write(dir.join(&name), json)?;
+        eprintln!("wrote {} rule(s) for {} to {}", count, target.label(), name);
+        index.insert(name, target.label().to_string());
     }
+    let mut file = File::create(dir.join("index.json"))?;
+    serde_json::to_writer_pretty(&mut file, &index)?;
+    writeln!(file)?;
+    Ok(())
+}
 
-    #[test]
-    fn errs() {
-        use InvalidRule::*;
-        let cases: &[(_, &[_])] = &[
-            (MustBeConstruct, &["SELECT ?a ?b ?c WHERE { ?s ?p ?o . }"]),
-            (IllegalFrom, &[]),
-            (IllegalBaseIri, &[]),
-            (
-                MustBeBasicGraphPattern,
-                &[
-                    "CONSTRUCT {} WHERE { {} UNION  {} . }",
-                    "CONSTRUCT {} WHERE { GRAPH <http://example.com> {} . }",
-                ],
-            ),
-            (IllegalPathPattern, &[]),
-            (
-                UnboundImplied {
-                    name: "a".to_string(),
+/// drop the rules of a ruleset read from stdin whose validity window ended before `at`
+fn prune_stdin(args: &cli::Args, at: &str) -> Result<(), Box<dyn Error>> {
+    let rules = read_rules(args)?;
+    let count = rules.len();
+    let rules: Vec<output::Annotations> = rules
+        .into_iter()
+        .filter(|rule| !Validity::of(rule).expired(at))
+        .collect();
+    let mut out = open_output(args)?;
+    serde_json::to_writer_pretty(&mut out, &rules)?;
+    writeln!(out)?;
+    eprintln!(
+        "pruned {} of {} rule(s) that expired before {}",
+        count - rules.len(),
+        count,
+        at
+    );
+    Ok(())
+}
+
+/// `--output` if given, otherwise stdout
+fn open_output(args: &cli::Args) -> std::io::Result<Box<dyn Write>> {
+    Ok(match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(stdout()),
+    })
+}
+
+/// validate a query without writing any output
+fn check_stdin(args: &cli::Args) -> Result<(), Box<dyn Error>> {
+    let mut stin = String::new();
+    stdin().read_to_string(&mut stin)?;
+    convert_text(&stin, args)?;
+    Ok(())
+}
+
+/// A line of `ndjson` input. `id` is only echoed back so it can be anything.
+#[derive(serde::Deserialize)]
+struct Request {
+    id: serde_json::Value,
+    sparql: String,
+}
+
+/// A line of `ndjson` output, the rule or the reason there isn't one.
+#[derive(serde::Serialize)]
+struct Response {
+    id: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// convert one query per line of stdin, writing one result per line as soon as it's ready
+fn ndjson_stdin(args: &cli::Args) -> Result<(), Box<dyn Error>> {
+    let mut out = open_output(args)?;
+    for line in stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(Request { id, sparql }) => match convert_dated(&sparql, args) {
+                Ok((rule, diagnostics, declared)) => Response {
+                    id,
+                    rule: Some(output::annotated(
+                        &rule,
+                        args.format,
+                        annotations(args, &rule, &diagnostics, &declared),
+                    )),
+                    error: None,
                 },
-                &["CONSTRUCT { ?a ?b ?c . } WHERE {}"],
-            ),
-            (
-                NameCollision {
-                    name: "a".to_string(),
+                Err(e) => Response {
+                    id,
+                    rule: None,
+                    error: Some(e.to_string()),
                 },
-                &["CONSTRUCT {  } WHERE { _:a ?a <http://example.com> . }"],
-            ),
-        ];
-        for (err, queries) in cases {
-            for query in *queries {
-                assert_eq!(err, &sparql2rify(query.parse().unwrap()).unwrap_err());
-            }
+            },
+            // a line that can't be read has no id to report against
+            Err(e) => Response {
+                id: serde_json::Value::Null,
+                rule: None,
+                error: Some(e.to_string()),
+            },
+        };
+        serde_json::to_writer(&mut out, &response)?;
+        writeln!(out)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// print the figures the on-chain cost of the converted rule depends on
+fn weigh_stdin(args: &cli::Args) -> Result<(), Box<dyn Error>> {
+    let mut stin = String::new();
+    stdin().read_to_string(&mut stin)?;
+    let (rule, _) = convert_text(&stin, args)?;
+    let mut out = open_output(args)?;
+    serde_json::to_writer_pretty(&mut out, &weight::weigh(&rule, args.format))?;
+    writeln!(out)?;
+    Ok(())
+}
+
+fn fingerprint_stdin(args: &cli::Args) -> Result<(), Box<dyn Error>> {
+    let mut stin = String::new();
+    stdin().read_to_string(&mut stin)?;
+    let (rule, _) = convert_text(&stin, args)?;
+    let mut out = open_output(args)?;
+    writeln!(out, "{}", fingerprint::fingerprint(&rule))?;
+    Ok(())
+}
+
+/// convert `inputs` and keep the output up to date with them until interrupted
+fn watch_files(
+    args: &cli::Args,
+    inputs: &[PathBuf],
+    interval: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let output = args.output.as_ref().expect("checked by cli::parse");
+    let mut watch = watch::Watch::new(inputs, |text| convert_dated(text, args));
+    let mut first = true;
+    loop {
+        if watch.refresh()? || first {
+            let rules = watch.rules();
+            watch::write_atomically(output, |file| {
+                let declares = !args.tags.is_empty()
+                    || args.emit_linked_data
+                    || rules.iter().any(|(_, _, declared)| declared.declares());
+                if args.embed_diagnostics || args.emit_var_map || args.emit_type_usage || declares {
+                    let rules = rules
+                        .iter()
+                        .map(|(rule, diagnostics, declared)| {
+                            (rule, annotations(args, rule, diagnostics, declared))
+                        })
+                        .collect();
+                    let ruleset = output::annotated_ruleset(rules, args.format);
+                    serde_json::to_writer_pretty(&mut *file, &ruleset)?;
+                } else {
+                    let rules: Vec<&RuleParts> = rules.iter().map(|(rule, _, _)| rule).collect();
+                    output::write_ruleset(&mut *file, &rules, args.format)?;
+                }
+                writeln!(file)?;
+                Ok(())
+            })?;
+            eprintln!("wrote {} rule(s) to {}", rules.len(), output.display());
+            first = false;
         }
+        sleep(interval);
     }
+}
 
-    #[test]
-    fn more_errs() {
-        let query = "CONSTRUCT { ?a ?b [] . } WHERE {}";
-        let err = sparql2rify(query.parse().unwrap()).unwrap_err();
-        match err {
-            InvalidRule::BlankNodeImplied { .. } => {}
-            _ => {
-                dbg!(err);
-                panic!();
-            }
+/// Parse and convert a single query, N3 rule or rify rule, reporting lints. Also returns the
+/// warnings and rewrites for `--embed-diagnostics`.
+fn convert_text(
+    text: &str,
+    args: &cli::Args,
+) -> Result<(RuleParts, Vec<Diagnostic>), Box<dyn Error>> {
+    let (rule, _, diagnostics) = convert_text_with_graphs(text, args)?;
+    Ok((rule, diagnostics))
+}
+
+/// `convert_text`, also returning what the comments of `text` declare about its rules
+fn convert_dated(
+    text: &str,
+    args: &cli::Args,
+) -> Result<(RuleParts, Vec<Diagnostic>, Declared), Box<dyn Error>> {
+    let (rule, diagnostics) = convert_text(text, args)?;
+    Ok((rule, diagnostics, Declared::parse(text)?))
+}
+
+/// A rule, the graph of each claim in its `if_all`, and what was reported about it.
+type Converted<T> = (RuleParts, Vec<Graph>, Vec<T>);
+
+/// `convert_text`, also returning the graph of each claim in `if_all` for `--quads`
+fn convert_text_with_graphs(
+    text: &str,
+    args: &cli::Args,
+) -> Result<Converted<Diagnostic>, Box<dyn Error>> {
+    let mut converted = convert_text_all(text, args)?;
+    if converted.len() != 1 {
+        let message = format!(
+            "the query expands into {} rules, convert it with `convert` or `batch` to get them as a \
+             ruleset",
+            converted.len()
+        );
+        return Err(message.into());
+    }
+    Ok(converted.pop().expect("just checked"))
+}
+
+/// `convert_text_with_graphs` for input that may be a query with UNION or VALUES, which expands
+/// into a rule for each branch and row
+fn convert_text_all(
+    text: &str,
+    args: &cli::Args,
+) -> Result<Vec<Converted<Diagnostic>>, Box<dyn Error>> {
+    convert_text_timed(text, args, &mut Timings::default())
+}
+
+/// `convert_text_all`, adding how long each stage took to `timings`
+fn convert_text_timed(
+    text: &str,
+    args: &cli::Args,
+    timings: &mut Timings,
+) -> Result<Vec<Converted<Diagnostic>>, Box<dyn Error>> {
+    let converted = match input::detect(text) {
+        Kind::Sparql => convert_sparql(text, args, timings)?,
+        Kind::N3 => {
+            let sparql = timed(&mut timings.parse, || n3::to_sparql(text))?;
+            convert_sparql(&sparql, args, timings)?
+        }
+        Kind::Rule => {
+            let rule: RuleParts = timed(&mut timings.parse, || serde_json::from_str(text))?;
+            timed(&mut timings.validate, || -> Result<_, Box<dyn Error>> {
+                rule.check()?;
+                rule.check_iris()?;
+                let warnings = lint::check(&rule.if_all, &rule.then);
+                let graphs = vec![None; rule.if_all.len()];
+                Ok(vec![(rule, graphs, warnings)])
+            })?
+        }
+    };
+    timed(&mut timings.rewrite, || {
+        converted
+            .into_iter()
+            .map(|converted| finish(converted, &[], args))
+            .collect()
+    })
+}
+
+/// how long writing `rules` as a ruleset of their own takes, for `--timings`
+fn time_serialize<'a>(
+    args: &cli::Args,
+    timings: &mut Timings,
+    rules: impl FnOnce() -> Vec<(&'a RuleParts, output::Annotations)>,
+) {
+    timed(&mut timings.serialize, || {
+        let ruleset = output::annotated_ruleset(rules(), args.format);
+        serde_json::to_vec_pretty(&ruleset).expect("rules always serialize")
+    });
+}
+
+/// report the lints of a converted rule, whose negative conditions are `if_none`, and apply the
+/// rewrites `args` ask for
+fn finish(
+    (mut rule, mut graphs, mut warnings): Converted<Warning>,
+    if_none: &[extended::Pattern],
+    args: &cli::Args,
+) -> Result<Converted<Diagnostic>, Box<dyn Error>> {
+    if let Some(limit) = args.max_literal_length {
+        literals::check(&rule, limit)?;
+    }
+    if let Some(allowed) = &args.allowed_datatypes {
+        warnings.extend(lint::check_datatypes(&rule, allowed));
+    }
+    if let Some(seen) = &args.endpoint {
+        warnings.extend(lint::check_seen(&rule, seen));
+    }
+    lint::report(&warnings, &args.levels)?;
+    let mut diagnostics = lint::diagnostics(&warnings, &args.levels);
+    let canonicalized = args.canonicalize.rule(&mut rule);
+    rewritten(&mut diagnostics, "canonicalize", canonicalized);
+    let datatypes = args.datatypes.rule(&mut rule);
+    rewritten(&mut diagnostics, "datatypes", datatypes);
+    if !args.keep_redundant {
+        let removed = simplify::simplify(&mut rule, &mut graphs, if_none);
+        if removed > 0 {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::Rewrite,
+                code: "simplify".to_string(),
+                message: format!("Removed {} redundant condition(s).", removed),
+            });
+        }
+    }
+    args.namespaces.check(&rule)?;
+    if !args.preserve_order && order::canonical_order_with_graphs(&mut rule.if_all, &mut graphs) {
+        diagnostics.push(Diagnostic {
+            kind: DiagnosticKind::Rewrite,
+            code: "order".to_string(),
+            message: "Sorted conditions, most selective first.".to_string(),
+        });
+    }
+    Ok((rule, graphs, diagnostics))
+}
+
+/// note that the rewrite `code` changed `changed` terms, if any
+fn rewritten(diagnostics: &mut Vec<Diagnostic>, code: &str, changed: usize) {
+    if changed > 0 {
+        diagnostics.push(Diagnostic {
+            kind: DiagnosticKind::Rewrite,
+            code: code.to_string(),
+            message: format!("Rewrote {} term(s).", changed),
+        });
+    }
+}
+
+/// the query in `text` and, with `--allow-from`, the graphs its FROM clauses named
+fn parse_query(text: &str, args: &cli::Args) -> Result<(Query, Dataset), Box<dyn Error>> {
+    let mut q = match sparql2rify::parse(text, args.base.as_deref()) {
+        Ok(q) => q,
+        // reported with its code like any other
+        Err(sparql2rify::Error::InvalidRule { source }) => return Err(source.into()),
+        Err(e) => return Err(e.into()),
+    };
+    let dataset = if args.allow_from {
+        take_dataset(&mut q)
+    } else {
+        Dataset::default()
+    };
+    Ok((q, dataset))
+}
+
+/// parse and convert a query, adding how long each took to `timings`
+fn convert_sparql(
+    text: &str,
+    args: &cli::Args,
+    timings: &mut Timings,
+) -> Result<Vec<Converted<Warning>>, Box<dyn Error>> {
+    let (q, dataset) = timed(&mut timings.parse, || parse_query(text, args))?;
+    timed(&mut timings.validate, || convert_parsed(q, dataset, args))
+}
+
+fn convert_parsed(
+    q: Query,
+    dataset: Dataset,
+    args: &cli::Args,
+) -> Result<Vec<Converted<Warning>>, Box<dyn Error>> {
+    let options = ConvertOptions {
+        quads: args.quads,
+        expand: !args.quads,
+        optional: args.split_optional,
+        ..ConvertOptions::default()
+    };
+    let mut rules = Vec::new();
+    for converted in convert_with(q, &options)? {
+        let ConvertedRule {
+            mut rule,
+            mut graphs,
+            warnings,
+            ..
+        } = converted;
+        if args.quads {
+            dataset.apply(&mut rule, &mut graphs)?;
         }
+        rules.push((rule, graphs, warnings));
     }
+    Ok(rules)
+}
+
+const USAGE: &str = "\
+sparql2rify - Convert a SPARQL CONSTRUCT clause to a rify rule.
+USE: cat input.sparql | sparql2rify [OPTIONS] > output.json
+     sparql2rify convert [OPTIONS] <FILE>... -o ruleset.json
+     sparql2rify batch [--jobs <N> [--verify-serial]] [OPTIONS] < queries.sparql
+     sparql2rify check [OPTIONS] < input.sparql
+     sparql2rify weigh [OPTIONS] < input.sparql
+     sparql2rify fingerprint [OPTIONS] < input.sparql
+     sparql2rify ndjson [OPTIONS] < requests.ndjson
+     sparql2rify from-dock [OPTIONS] < rules.json
+     sparql2rify from-schema --context <FILE> --vocab <IRI> [OPTIONS] < schema.json
+     sparql2rify from-csv [--prefix <NAME>=<IRI>]... [OPTIONS] < mappings.csv
+     sparql2rify manifest --signer <ID> --ruleset-version <VERSION> < ruleset.json
+     sparql2rify verify-manifest <MANIFEST> < ruleset.json
+     sparql2rify sample [--negative] [OPTIONS] < input.sparql
+     sparql2rify specialize --bindings <FILE> [OPTIONS] < input.sparql
+     sparql2rify apply --store <PATH> [--graph <IRI> | --round-graphs <IRI>]
+                       [--jobs <N>] [--memory-budget <CLAIMS>] < ruleset.json
+     sparql2rify check-data --data <FILE> [OPTIONS] < ruleset.json
+     sparql2rify why-not --data <FILE> --expect <TRIPLE> [OPTIONS] < ruleset.json
+     sparql2rify impact --rule <FILE> --data <FILE> [OPTIONS]
+     sparql2rify vocab [OPTIONS] < ruleset.json
+     sparql2rify refactor --map <OLD>=<NEW>... [OPTIONS] < ruleset.json
+     sparql2rify pack [OPTIONS] < ruleset.json > ruleset.rpk
+     sparql2rify unpack [OPTIONS] < ruleset.rpk
+     sparql2rify partition [--by <predicate|graph>] --output <DIR> [OPTIONS] < ruleset.json
+     sparql2rify prune --at <DATE> [OPTIONS] < ruleset.json
+     sparql2rify compose [OPTIONS] <FIRST> <SECOND>
+     sparql2rify generalize --variables <FILE> [OPTIONS] < input.sparql
+     sparql2rify watch [OPTIONS] <PATH>... --output <FILE>
+     sparql2rify --explain <CODE>
+
+Input may also be an N3 rule, { .. } => { .. } ., or a rule that is already in rify's
+JSON format, which is checked and written in --format. The kind of input is detected
+from how it starts.
+
+A query may declare the days it applies on in comments of their own lines,
+# @valid-from 2024-01-01 and # @valid-until 2025-06-30, both days included. Each rule
+converted from it gets a \"valid_from\" and \"valid_until\" field, see prune. Likewise
+# @tags kyc, eu tags the rules, listed in a \"tags\" field, see --include-tags.
+
+SUBCOMMANDS:
+  convert                 Convert each FILE and write them as one ruleset, each rule
+                          with a \"source\" field naming its file. Without files, the
+                          same as no subcommand.
+  batch                   Convert several queries read from stdin and write them as one
+                          ruleset, in order. Queries are separated by a ; outside any
+                          braces, as in SPARQL Update, or by a blank line between the
+                          end of one query and the PREFIX, BASE or CONSTRUCT starting
+                          the next. With --jobs the queries are converted in parallel,
+                          the ruleset being the same as a serial run's.
+  check                   Only check that a query converts, without writing any output.
+                          Exits with a non-zero status if it doesn't.
+  ndjson                  Convert a stream of newline delimited JSON records of the
+                          form {\"id\": .., \"sparql\": \"..\"}, writing a record
+                          {\"id\": .., \"rule\": ..} or {\"id\": .., \"error\": \"..\"}
+                          for each as soon as it's converted. Lints are still
+                          reported on stderr.
+  weigh                   Print the size of the converted rule: its length in bytes in
+                          --format without whitespace, the number of claims in if_all
+                          and then, and the number of distinct variables. These are
+                          what the cost of registering a rule on chain depends on.
+  fingerprint             Print a SHA-256 hash of the converted rule that doesn't
+                          depend on the names of its variables or the order of its
+                          claims, for deduplicating and content addressing rules.
+  from-dock               Read a rule, or an array of rules, in Dock's claim deduction
+                          format and write them in --format.
+  from-schema             Read a verifiable credential's JSON schema and write a
+                          ruleset that lifts each credentialSubject property to a
+                          predicate of your own vocabulary: if a credential says its
+                          subject has \"name\", the subject has <VOCAB>name.
+  from-csv                Read a CSV table of predicate mappings, each row the IRI of a
+                          predicate and the IRI it maps to, and write a ruleset with a
+                          rule { ?s FROM ?o } => { ?s TO ?o } for each row. A first row
+                          without IRIs is a header, rows starting with # are comments.
+  manifest                Write a manifest of SHA-256 hashes for a ruleset, a JSON
+                          array of rify rules, to anchor on chain or in a transparency
+                          log. The manifest records the signer and version but is not
+                          signed itself.
+  verify-manifest         Check that a ruleset matches MANIFEST. Exits with a non-zero
+                          status if it doesn't.
+  sample                  Write a Turtle document the converted rule fires on: its
+                          conditions with each variable ?name replaced by the IRI
+                          <urn:sample:name>. With --negative, write a JSON array of
+                          near misses the rule must not fire on instead, each with
+                          one condition removed or one constant changed, as objects
+                          with a \"description\" and the \"turtle\" document.
+  specialize              Replace variables of the converted rule with the terms they
+                          are bound to in the --bindings file, e.g. to instantiate a
+                          rule template for each tenant. The rule's \"provenance\" names
+                          the rule it was specialized from.
+  apply                   Apply a ruleset, a JSON array of rify rules, to the oxigraph
+                          store at PATH, inserting the claims it derives from the
+                          claims in all of the store's graphs into the default graph,
+                          or the graph named by --graph. Needs the store feature.
+  check-data              Use a ruleset, a JSON array of rify rules, as a data quality
+                          check: report, as a JSON array, each claim a rule implies
+                          for the data in --data that the data doesn't hold. Exits
+                          with a non-zero status if there are any.
+  why-not                 Explain why a ruleset, a JSON array of rify rules, doesn't
+                          derive the --expect triple from --data: for each conclusion
+                          that could be the triple, the most conditions of its rule
+                          the data, with what the rules do derive, matches together
+                          and the conditions left unmatched with the variables the
+                          rest bind filled in, as a JSON array, closest first.
+  impact                  Report what the --rule file, a query or rule, would add to the
+                          data in --data, without changing it: the number of claims it
+                          derives that the data doesn't hold, applying it until nothing
+                          new follows, and how many of them have each predicate. For
+                          judging how far a new rule reaches before deploying it.
+  vocab                   List every IRI a ruleset, a JSON array of rify rules, uses as a
+                          JSON object from namespace to IRI to how many times it is
+                          used as a predicate, class (object of rdf:type), datatype
+                          or other subject or object.
+  refactor                Replace IRIs across a ruleset, a JSON array of rify rules,
+                          e.g. to migrate to a new version of an ontology, and write
+                          it in --format. An IRI to be replaced that appears nowhere
+                          is reported as a warning.
+  pack                    Encode a ruleset, a JSON array of rify rules, as a rulepack:
+                          a compact binary format with each distinct string stored
+                          once, for wallets and on-chain storage. See the rulepack
+                          module for the layout.
+  unpack                  Decode a rulepack and write its rules in --format.
+  partition               Split a ruleset, a JSON array of rify rules, into a file in
+                          --output for each predicate its rules derive claims with, so
+                          a pipeline stage can load only the rules it needs. A rule
+                          deriving several is split, each part keeping all of its
+                          conditions. Conclusions with a variable predicate go to
+                          any.json, which every stage needs too. index.json lists the
+                          files and the predicate each is for.
+  prune                   Drop the rules of a ruleset, a JSON array of rules in any
+                          format, whose \"valid_until\" is before --at. Rules that
+                          aren't valid yet are kept, so a ruleset can be published
+                          ahead of the date it takes effect.
+  compose                 Chain two rules, each a query or rule file: write a ruleset of
+                          the rules going straight from FIRST's conditions to what
+                          SECOND derives from FIRST's conclusions, one for each way
+                          they can match. Empty if SECOND never matches them. Each
+                          rule's \"provenance\" names the two rules it was composed from.
+  generalize              Replace IRIs in the converted rule with the variables they
+                          map to in the --variables file, the inverse of specialize.
+                          The rule's \"provenance\" names the rule it was generalized
+                          from.
+  watch                   Keep a combined ruleset (a JSON array of rules) up to date
+                          with a set of query files. Directories are searched for
+                          .sparql, .rq and .n3 files. Only files that changed are
+                          converted again, a file that fails to convert keeps its last
+                          good rule.
 
-    fn rdf(suffix: &str) -> rify::Entity<String, RdfNode> {
-        Bound(Iri(format!(
-            "http://www.w3.org/1999/02/22-rdf-syntax-ns#{}",
-            suffix
-        )))
+OPTIONS:
+  -A, --allow <LINT>      Silence a lint.
+  -W, --warn <LINT>       Report a lint as a warning (the default).
+  -D, --deny <LINT>       Report a lint as an error and fail the conversion.
+  --lint-config <FILE>    Read lint levels from a JSON file of the form
+                          {\"allow\": [..], \"warn\": [..], \"deny\": [..]}.
+  --datatypes <FILE>      Read a JSON array of permitted datatype IRIs. Literals with
+                          any other datatype trigger the unlisted_datatype lint, use
+                          -D unlisted_datatype to make them an error. Plain and
+                          language tagged literals are always permitted.
+  --max-literal-length <BYTES>
+                          Fail on rules with a literal longer than BYTES, e.g. a whole
+                          base64 encoded credential (conversions and check only).
+  --endpoint <URL>        Ask the SPARQL endpoint at URL which predicates and classes
+                          its data uses, conditions using others trigger the
+                          unseen_term lint. Needs --features endpoint.
+  --namespaces <FILE>     Read the namespaces rules may use from a JSON file of the form
+                          {\"allow\": [..], \"deny\": [..], \"deny_implied\": [..]}.
+                          If \"allow\" is given every IRI must start with one of
+                          its namespaces, none may start with one in \"deny\", and
+                          the claims a rule derives may not use one in
+                          \"deny_implied\", e.g. to keep rules from writing rdf: and
+                          owl: terms. Checked on conversion and by apply.
+  --base <IRI>            Resolve relative IRIs in queries that don't declare a BASE
+                          against IRI. Without either they fail with E003.
+  --allow-from            Accept queries with FROM and FROM NAMED clauses instead of
+                          failing with E002. They are ignored, except with --quads,
+                          where claims outside GRAPH blocks are in the FROM graph and
+                          GRAPH variables are bound to the FROM NAMED graph. A query
+                          naming more than one of either still fails.
+  --canonicalize <KIND>   Rewrite literals in the rule to their canonical forms so they
+                          match data from canonicalizing stores. KIND is one of:
+                            numbers     xsd:integer and its subtypes, xsd:decimal,
+                                        xsd:float and xsd:double.
+                            language-tags
+                                        Use BCP 47 case conventions for language
+                                        tags, e.g. en-GB rather than en-gb.
+                            nfc         Unicode NFC for IRIs and literal values.
+                            iris        Lowercase IRI schemes and hosts, uppercase
+                                        percent-encodings and remove dot segments.
+                            date-times  xsd:dateTime, e.g. a +00:00 timezone
+                                        becomes Z.
+                            date-times-utc
+                                        Like date-times, but also shift times
+                                        with a timezone to UTC.
+                          May be given more than once.
+  --omit-datatype <KIND>  Leave out datatypes that follow from the form of a literal,
+                          to match data that does the same. KIND is one of:
+                            string      xsd:string on plain literals.
+                            lang-string rdf:langString on language tagged literals.
+                          May be given more than once.
+  --rename-vars <FILE>    Rename variables to v0, v1, ... in order of appearance and
+                          write a table of their original names to FILE.
+  --format <FORMAT>       Output format, one of:
+                            rify        A rify rule (the default).
+                            shared-iris IRIs are listed once in an \"iris\" table and
+                                        referenced from claims by index.
+                            dock        The format used by Dock's javascript claim
+                                        deduction library, claims are quads.
+                            typed       Literals of common xsd datatypes are written
+                                        as their values, e.g. {\"Integer\": 5}.
+                            indexed     Variables are numbered from 0, their names
+                                        are listed in \"variables\".
+  --preserve-order        Keep the conditions of a rule in the order they appear in the
+                          WHERE clause. By default they are sorted, most selective
+                          first, so queries that only differ in the order of their
+                          triples convert to the same rule.
+  --merge-rules           Merge rules with the same conditions, up to the names of their
+                          variables, into one rule deriving all of their conclusions
+                          (convert <FILE>... only). Each rule lists the files it came
+                          from in \"sources\" rather than \"source\", and a merged rule
+                          the rules it was merged from in \"provenance\".
+  --previous <RULESET>    The ruleset, in rify's format, that the same files converted to
+                          before (convert <FILE>... only). Rules that didn't change, up
+                          to the names of their variables and the order of their
+                          claims, keep their index in it. New and changed rules take
+                          the places of rules that are gone, then go at the end.
+  --against <RULESET>     Report on stderr which rules changed from a ruleset the same
+                          files converted to before (convert <FILE>... only). As with
+                          --previous, renaming variables or reordering claims is no
+                          change. A rule that is gone and one that is new from the
+                          same file count as one changed rule.
+  --keep-redundant        Keep conditions that are implied by the others. By default a
+                          condition is removed if it only differs from another in
+                          variables used nowhere else, negative conditions included,
+                          e.g. those added for property paths and blank nodes, or if
+                          it is a duplicate.
+  --split-optional        Convert a query whose WHERE clause has an OPTIONAL, without a
+                          FILTER in it, to two rules: one with the optional triples,
+                          and one without them and the conclusions using variables
+                          only they bind. Each OPTIONAL doubles the number of rules.
+  --quads                 Write a rule over quads, in the dock format, rather than
+                          triples. The WHERE clause may then use GRAPH blocks, claims
+                          outside them are in the default graph. Only for a single
+                          conversion, and not with --rename-vars, --emit-var-map or
+                          --split-optional.
+  --extended              Convert FILTER NOT EXISTS { .. } and MINUS { .. } to an
+                          \"if_none\" section of patterns that must not match, for
+                          reasoners with stratified negation. Only for a single
+                          conversion in the rify format, and not with --quads,
+                          --rename-vars, --emit-var-map or --split-optional. Commands
+                          that read rify rules reject rules with negative conditions.
+  --default-graph <GRAPH> How the default graph is named with --quads, one of:
+                            default     {\"DefaultGraph\": true} (the default).
+                            <IRI>       A named graph the store uses as its default.
+                            ?<NAME>     A variable, so claims outside GRAPH blocks
+                                        match any one graph.
+  --conclusion-graph <GRAPH>
+                          The graph the conclusions of a rule are in with --quads, as
+                          SPARQL can't put GRAPH in a CONSTRUCT template: default (the
+                          default), an IRI, or a ?<NAME> variable bound in the WHERE
+                          clause, e.g. by GRAPH ?<NAME> { .. }.
+  --embed-diagnostics     Add a \"diagnostics\" array to each rule written, listing the
+                          warnings reported for it and the rewrites applied to it, for
+                          systems that keep the output but not stderr.
+  --emit-var-map          Add a \"var_map\" object to each rule written, listing where
+                          each variable appears: in if_all or then, at which claim and
+                          as subject, predicate or object.
+  --emit-type-usage       Add an \"rdf_type\" object to each rule written, saying whether
+                          it \"matches\" and \"derives\" rdf:type claims. A variable
+                          predicate counts, since it can be rdf:type.
+  --emit-linked-data      Add JSON-LD fields to each rule written, so the ruleset can be
+                          published as linked data: \"@id\", the rule's fingerprint,
+                          \"dct:description\", from # @description comments, and
+                          \"prov:wasDerivedFrom\", a hash of its query. Not with --quads
+                          or --extended.
+  --split-types <FILE>    Write the conclusions of each rule that are rdf:type claims to
+                          FILE, as rules with the same conditions, and the rest to the
+                          output, so type inference can be scheduled on its own
+                          (convert <FILE>... and batch only, not with --emit-var-map).
+  --tag <TAG>             Tag every rule written with TAG, as well as the tags its query
+                          declares. May be given more than once (conversions only).
+  --include-tags <TAGS>   Only load the rules of a ruleset read from stdin that have one
+                          of TAGS, separated by commas.
+  --exclude-tags <TAGS>   Don't load the rules of a ruleset read from stdin that have any
+                          of TAGS, separated by commas, even if included. Both may be
+                          given more than once, for the subcommands reading a ruleset.
+  -o, --output <FILE>     Write to FILE instead of stdout.
+  --audit <FILE>          Write a JSON array to FILE with an entry for each rule written,
+                          listing the files it came from, its fingerprint and every
+                          rewrite applied to it: canonicalization, simplification,
+                          sorting, renaming and merging (convert only).
+  --signer <ID>           Who will sign the manifest, e.g. a DID (manifest only).
+  --ruleset-version <VERSION>
+                          The version of the ruleset (manifest only).
+  --bindings <FILE>       A JSON object from variable name to term, in rify's format,
+                          e.g. {\"issuer\": {\"Iri\": \"did:example:a\"}}
+                          (specialize only).
+  --data <FILE>           Turtle, N-Triples (.nt) or RDF/XML (.rdf) data to check
+                          (check-data, why-not and impact only).
+  --rule <FILE>           The query or rule impact applies (impact only).
+  --expect <TRIPLE>       The triple why-not explains, in N-Triples, e.g.
+                          \"<http://example.com/b> <http://example.com/child>
+                          <http://example.com/a>\" (why-not only).
+  --truncate-literals <BYTES>
+                          Write literals longer than BYTES as their first BYTES, their
+                          length and their SHA-256 hash (check-data and why-not only).
+  --store <PATH>          The directory of an oxigraph store (apply only).
+  --graph <IRI>           The graph inferred claims are inserted into (apply only).
+  -j, --jobs <N>          Share inference, or the queries of a batch, out between N
+                          threads (apply and batch only, default 1).
+  --verify-serial         Convert the batch serially too, failing unless the ruleset
+                          is byte for byte the same as the one --jobs converts
+                          (batch only).
+  --timings               Once converted, write a line of JSON to stderr for each
+                          query: the milliseconds spent parsing, validating,
+                          rewriting and serializing it (convert and batch only).
+  --memory-budget <CLAIMS>
+                          The most claims apply reads into memory. Past it inference
+                          runs on the store itself, slower but without holding the
+                          claims, and on a single thread.
+  --round-graphs <IRI>    Insert the claims each round of inference derives into a
+                          graph of its own, named by IRI followed by the round
+                          number, e.g. <urn:round:1>, <urn:round:2>, to see how
+                          conclusions build up (apply only).
+  --by <predicate|graph>  What partition splits rules by. graph reads rules over quads in
+                          Dock's format, as convert --quads writes them, and splits
+                          them by the graph their conclusions are in, the default
+                          graph going to default.json (default predicate).
+  --at <DATE>             The day prune keeps rules valid on or after, YYYY-MM-DD
+                          (prune only).
+  --map <OLD>=<NEW>       Replace the IRI OLD with NEW, including as a datatype. OLD
+                          ends at the first =. May be given more than once (refactor
+                          only).
+  --variables <FILE>      A JSON object from IRI to variable name, e.g.
+                          {\"did:example:a\": \"issuer\"} (generalize only).
+  --context <FILE>        The credential's JSON-LD context, which says what IRIs the
+                          schema's property names stand for (from-schema only).
+                          Remote contexts are not fetched, so terms they define must
+                          be repeated in FILE.
+  --vocab <IRI>           Prefix of the generated predicates (from-schema only).
+  --type <IRI>            Also derive that the subject has this rdf:type
+                          (from-schema only).
+  --issuer <IRI>          Only lift properties from credentials issued by this issuer
+                          (from-schema only).
+  --prefix <NAME>=<IRI>   Read NAME:local in a cell as IRI followed by local. May be
+                          given more than once (from-csv only).
+  --interval <MS>         How often watch checks for changes (default 500).
+  --explain <CODE>        Explain an error code, e.g. E006, or a lint, with examples
+                          of queries that trigger it and how to rewrite them.
+
+Lint flags are applied in order so later flags override earlier ones. The lint
+name \"warnings\" refers to every lint.
+";
+
+fn handle_args() -> cli::Args {
+    match cli::parse(std::env::args().skip(1)) {
+        Ok(cli::Parsed::Run(args)) => *args,
+        Ok(cli::Parsed::Help) => {
+            eprint!("{}", USAGE);
+            eprintln!("Lints: {}", lint::LINTS.join(", "));
+            exit(0);
+        }
+        Ok(cli::Parsed::Explain(code)) => match explain::explain(&code) {
+            Some(text) => {
+                print!("{}", text);
+                exit(0);
+            }
+            None => {
+                let codes: Vec<_> = explain::codes().collect();
+                eprintln!(
+                    "No explanation for \"{}\", try one of {}.",
+                    code,
+                    codes.join(", ")
+                );
+                exit(2);
+            }
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2);
+        }
     }
+}
 
-    fn unbd(name: &str) -> rify::Entity<String, RdfNode> {
-        Unbound(name.to_string())
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parallel_batch_is_serial() {
+        let queries: Vec<String> = (0..25)
+            .map(|i| {
+                format!(
+                    "CONSTRUCT {{ ?a <urn:x:p{}> ?b }} WHERE {{ ?a <urn:x:q> [ <urn:x:r> ?b ] }}",
+                    i
+                )
+            })
+            .collect();
+        let mut queries: Vec<&str> = queries.iter().map(String::as_str).collect();
+        let args = cli::Args::default();
+        let rules = |jobs: usize| -> Vec<Vec<BatchRule>> {
+            let converted = convert_batch(&args, &queries, jobs).unwrap();
+            converted.into_iter().map(|(rules, _)| rules).collect()
+        };
+        let serial = rules(1);
+        assert_eq!(serial.len(), 25);
+        for jobs in [2, 3, 8, 40] {
+            assert_eq!(rules(jobs), serial);
+        }
+
+        // the error is the first query's to fail, whichever thread gets to it
+        queries[7] = "CONSTRUCT { ?a <urn:x:p> ?z } WHERE { ?a ?b ?c }";
+        queries[12] = "not a query";
+        for jobs in [1, 2, 5] {
+            let err = convert_batch(&args, &queries, jobs).unwrap_err();
+            assert!(err.starts_with("query 8: "), "{}", err);
+        }
     }
 }
@@ -0,0 +1,131 @@
+//! Manifests for publishing rulesets. A manifest records the hash of a ruleset and of each of
+//! its rules so the manifest, rather than the whole ruleset, can be anchored on a blockchain or
+//! in a transparency log. Anyone who downloads the ruleset can then check it against the
+//! anchored manifest.
+//!
+//! Hashes are SHA-256 of a rule's, or the ruleset's, rify JSON without whitespace, so they
+//! don't depend on how the ruleset file happens to be formatted.
+
+use crate::types::RuleParts;
+use displaydoc::Display;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+
+pub const ALGORITHM: &str = "sha256";
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct Manifest {
+    pub version: String,
+    /// Who is expected to sign the manifest, e.g. a DID. The manifest itself is not signed
+    /// here, signing is part of anchoring it.
+    pub signer: String,
+    /// the hash function used for `ruleset` and `rules`
+    pub algorithm: String,
+    /// hex encoded hash of the whole ruleset
+    pub ruleset: String,
+    /// hex encoded hash of each rule, in ruleset order
+    pub rules: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Display)]
+pub enum ManifestError {
+    /// The manifest uses the hash function "{algorithm}", only sha256 is supported.
+    UnknownAlgorithm { algorithm: String },
+    /// The manifest lists {expected} rule(s) but the ruleset has {found}.
+    RuleCount { expected: usize, found: usize },
+    /// Rule {index} does not match the manifest.
+    RuleHash { index: usize },
+    /// The ruleset does not match the manifest.
+    RulesetHash,
+}
+
+impl Error for ManifestError {}
+
+impl Manifest {
+    pub fn new(rules: &[RuleParts], signer: String, version: String) -> Self {
+        Manifest {
+            version,
+            signer,
+            algorithm: ALGORITHM.to_string(),
+            ruleset: hash(rules),
+            rules: rules.iter().map(hash).collect(),
+        }
+    }
+
+    /// Check `rules` against the manifest. Individual rules are checked first so a mismatch
+    /// is reported for the first rule that differs.
+    pub fn verify(&self, rules: &[RuleParts]) -> Result<(), ManifestError> {
+        if self.algorithm != ALGORITHM {
+            let algorithm = self.algorithm.clone();
+            return Err(ManifestError::UnknownAlgorithm { algorithm });
+        }
+        if self.rules.len() != rules.len() {
+            return Err(ManifestError::RuleCount {
+                expected: self.rules.len(),
+                found: rules.len(),
+            });
+        }
+        for (index, (expected, rule)) in self.rules.iter().zip(rules).enumerate() {
+            if !expected.eq_ignore_ascii_case(&hash(rule)) {
+                return Err(ManifestError::RuleHash { index });
+            }
+        }
+        if !self.ruleset.eq_ignore_ascii_case(&hash(rules)) {
+            return Err(ManifestError::RulesetHash);
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn hash(value: &(impl serde::Serialize + ?Sized)) -> String {
+    let json = serde_json::to_vec(value).expect("rules always serialize");
+    digest(&json)
+}
+
+/// hex encoded SHA-256 of `bytes`
+pub(crate) fn digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::RdfNode;
+    use rify::Entity;
+
+    #[test]
+    fn verify() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| Entity::Bound(RdfNode::Iri(iri.to_string()));
+        let rule = |p: &str| RuleParts {
+            if_all: vec![[unbd("a"), iri(p), unbd("b")]],
+            then: vec![[unbd("b"), iri(p), unbd("a")]],
+        };
+        let rules = vec![rule("http://example.com/p"), rule("http://example.com/q")];
+        let manifest = Manifest::new(&rules, "did:example:signer".to_string(), "1".to_string());
+        assert_eq!(manifest.rules.len(), 2);
+        assert_eq!(manifest.ruleset.len(), 64);
+        assert_eq!(manifest.verify(&rules), Ok(()));
+
+        let tampered = vec![rule("http://example.com/p"), rule("http://example.com/r")];
+        assert_eq!(
+            manifest.verify(&tampered),
+            Err(ManifestError::RuleHash { index: 1 })
+        );
+        assert_eq!(
+            manifest.verify(&rules[..1]),
+            Err(ManifestError::RuleCount {
+                expected: 2,
+                found: 1
+            })
+        );
+        let reordered = vec![rules[1].clone(), rules[0].clone()];
+        assert_eq!(
+            manifest.verify(&reordered),
+            Err(ManifestError::RuleHash { index: 0 })
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Rules from a table of predicate mappings, the kind kept in a spreadsheet while migrating data
+//! from one vocabulary to another. Each row of a CSV file maps one predicate to another and
+//! becomes the rule
+//!
+//! ```text
+//! IF ?s <from> ?o . THEN ?s <to> ?o .
+//! ```
+//!
+//! Cells are IRIs, written out, in angle brackets, or as compact IRIs with a declared prefix. A
+//! first row with no IRIs in it is a header. Blank lines, and rows whose first cell starts with
+//! `#`, are skipped.
+
+use crate::types::{RdfNode, RuleParts};
+use displaydoc::Display;
+use rify::Entity;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+#[derive(Debug, PartialEq, Display)]
+pub enum MappingError {
+    /// Line {line}: a quoted cell is never closed.
+    UnclosedQuote { line: usize },
+    /// Line {line}: expected 2 cells, the predicates to map from and to, found {count}.
+    Columns { line: usize, count: usize },
+    /// Line {line}: <{iri}> is mapped to itself.
+    Identity { line: usize, iri: String },
+}
+
+impl Error for MappingError {}
+
+/// One rule for each mapping in `csv`, in order. `prefixes` maps the prefix of a compact IRI,
+/// without its colon, to the namespace it stands for. A cell with any other prefix is read as an
+/// IRI.
+pub fn rules(
+    csv: &str,
+    prefixes: &BTreeMap<String, String>,
+) -> Result<Vec<RuleParts>, MappingError> {
+    let expand = |cell: &str| {
+        let cell = cell.trim();
+        if let Some(iri) = cell.strip_prefix('<').and_then(|c| c.strip_suffix('>')) {
+            return iri.to_string();
+        }
+        match cell.split_once(':') {
+            Some((prefix, local)) if prefixes.contains_key(prefix) => {
+                format!("{}{}", prefixes[prefix], local)
+            }
+            _ => cell.to_string(),
+        }
+    };
+    let mut ret = Vec::new();
+    let mut first = true;
+    for (line, mut record) in records(csv)? {
+        // spreadsheets pad rows to the width of the widest
+        while record.last().is_some_and(|cell| cell.trim().is_empty()) {
+            record.pop();
+        }
+        if record.is_empty() || record[0].trim_start().starts_with('#') {
+            continue;
+        }
+        let cells: Vec<String> = record.iter().map(|cell| expand(cell)).collect();
+        if std::mem::take(&mut first) && !cells.iter().any(|cell| cell.contains(':')) {
+            continue;
+        }
+        match cells.as_slice() {
+            [from, to] if from == to => {
+                let iri = from.clone();
+                return Err(MappingError::Identity { line, iri });
+            }
+            [from, to] => ret.push(rule(from, to)),
+            _ => {
+                let count = cells.len();
+                return Err(MappingError::Columns { line, count });
+            }
+        }
+    }
+    Ok(ret)
+}
+
+fn rule(from: &str, to: &str) -> RuleParts {
+    let unbd = |name: &str| Entity::Unbound(name.to_string());
+    let iri = |iri: &str| Entity::Bound(RdfNode::Iri(iri.to_string()));
+    RuleParts {
+        if_all: vec![[unbd("s"), iri(from), unbd("o")]],
+        then: vec![[unbd("s"), iri(to), unbd("o")]],
+    }
+}
+
+/// The records of a CSV document, each with the line it starts on. As in RFC 4180, cells are
+/// separated by commas and a cell in double quotes may hold commas, line breaks and `""` for a
+/// quote. Line breaks may be CRLF.
+fn records(csv: &str) -> Result<Vec<(usize, Vec<String>)>, MappingError> {
+    let mut ret = Vec::new();
+    let mut chars = csv.chars().peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        let start = line;
+        let mut record = Vec::new();
+        let mut cell = String::new();
+        loop {
+            match chars.next() {
+                None => break,
+                Some('\n') => {
+                    line += 1;
+                    break;
+                }
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some(',') => record.push(std::mem::take(&mut cell)),
+                Some('"') if cell.trim().is_empty() => loop {
+                    match chars.next() {
+                        None => return Err(MappingError::UnclosedQuote { line: start }),
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            cell.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            cell.push(c);
+                        }
+                    }
+                },
+                Some(c) => cell.push(c),
+            }
+        }
+        record.push(cell);
+        ret.push((start, record));
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csv_mappings() {
+        let prefixes: BTreeMap<String, String> = [("ex", "http://example.com/")]
+            .iter()
+            .map(|(prefix, ns)| (prefix.to_string(), ns.to_string()))
+            .collect();
+        let csv = "from,to,,\r\n\
+                   # renamed in v2\n\
+                   ex:oldName, ex:name\n\
+                   \n\
+                   \"<http://example.com/a,b>\",urn:x:ab\n";
+        assert_eq!(
+            rules(csv, &prefixes).unwrap(),
+            [
+                rule("http://example.com/oldName", "http://example.com/name"),
+                rule("http://example.com/a,b", "urn:x:ab"),
+            ]
+        );
+        assert_eq!(
+            rules("ex:a,ex:b\n\"ex:c\nex:d,ex:e", &prefixes),
+            Err(MappingError::UnclosedQuote { line: 2 })
+        );
+        assert_eq!(
+            rules("ex:a,ex:b\n\"ex:c\nex:d\",ex:e,ex:f", &prefixes),
+            Err(MappingError::Columns { line: 2, count: 3 })
+        );
+        assert_eq!(
+            rules("ex:a,<http://example.com/a>", &prefixes),
+            Err(MappingError::Identity {
+                line: 1,
+                iri: "http://example.com/a".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Merging the rules of a ruleset that have the same conditions. Rules written separately often
+//! match the same claims under different variable names, and a reasoner does the join for each
+//! of them. One rule deriving all of their conclusions does the join once.
+
+use crate::types::{RdfNode, RuleParts};
+use crate::util::as_unbound;
+use rify::{Claim, Entity};
+use std::collections::BTreeMap;
+
+/// A rule standing for one or more rules of the original ruleset.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Merged {
+    pub rule: RuleParts,
+    /// indices of the rules merged into this one, in order
+    pub sources: Vec<usize>,
+}
+
+/// Merge rules whose `if_all` clauses are the same up to the names of their variables and the
+/// order of their claims. The merged rule keeps the variable names of the first of them and
+/// derives the union of their `then` clauses. Rules are kept in order of first appearance.
+pub fn merge(rules: &[RuleParts]) -> Vec<Merged> {
+    let mut ret: Vec<Merged> = Vec::new();
+    for (i, rule) in rules.iter().enumerate() {
+        let found = ret.iter().enumerate().find_map(|(j, merged)| {
+            let renaming = equivalent(&rule.if_all, &merged.rule.if_all)?;
+            let then: Vec<_> = rule
+                .then
+                .iter()
+                .map(|claim| renaming.apply(claim))
+                .collect();
+            Some((j, then))
+        });
+        match found {
+            Some((j, then)) => {
+                let merged = &mut ret[j];
+                for claim in then {
+                    if !merged.rule.then.contains(&claim) {
+                        merged.rule.then.push(claim);
+                    }
+                }
+                merged.sources.push(i);
+            }
+            None => ret.push(Merged {
+                rule: rule.clone(),
+                sources: vec![i],
+            }),
+        }
+    }
+    ret
+}
+
+/// A one to one renaming of variables.
+#[derive(Clone, Default)]
+struct Renaming<'a> {
+    forward: BTreeMap<&'a str, &'a str>,
+    backward: BTreeMap<&'a str, &'a str>,
+}
+
+impl<'a> Renaming<'a> {
+    /// this renaming extended so that it turns `from` into `to`, if that's possible
+    fn extend(
+        &self,
+        from: &'a Claim<Entity<String, RdfNode>>,
+        to: &'a Claim<Entity<String, RdfNode>>,
+    ) -> Option<Self> {
+        let mut ret = self.clone();
+        for (from, to) in from.iter().zip(to) {
+            match (as_unbound(from), as_unbound(to)) {
+                (Some(from), Some(to)) => {
+                    let forward = *ret.forward.entry(from).or_insert(to);
+                    let backward = *ret.backward.entry(to).or_insert(from);
+                    if forward != to || backward != from {
+                        return None;
+                    }
+                }
+                (None, None) if from == to => {}
+                _ => return None,
+            }
+        }
+        Some(ret)
+    }
+
+    fn apply(&self, claim: &Claim<Entity<String, RdfNode>>) -> Claim<Entity<String, RdfNode>> {
+        let rename = |ent: &Entity<String, RdfNode>| match as_unbound(ent) {
+            Some(name) => Entity::Unbound(self.forward.get(name).unwrap_or(&name).to_string()),
+            None => ent.clone(),
+        };
+        let [s, p, o] = claim;
+        [rename(s), rename(p), rename(o)]
+    }
+}
+
+/// a renaming of the variables of `from` that makes it the same set of claims as `to`
+fn equivalent<'a>(
+    from: &'a [Claim<Entity<String, RdfNode>>],
+    to: &'a [Claim<Entity<String, RdfNode>>],
+) -> Option<Renaming<'a>> {
+    let distinct = |claims: &'a [Claim<Entity<String, RdfNode>>]| {
+        let mut ret: Vec<&Claim<Entity<String, RdfNode>>> = Vec::new();
+        for claim in claims {
+            if !ret.contains(&claim) {
+                ret.push(claim);
+            }
+        }
+        ret
+    };
+    let from = distinct(from);
+    let to = distinct(to);
+    if from.len() != to.len() {
+        return None;
+    }
+    search(&from, &to, &mut vec![false; to.len()], &Renaming::default())
+}
+
+/// match each of `from` to a distinct unused claim of `to`, backtracking on conflicts
+fn search<'a>(
+    from: &[&'a Claim<Entity<String, RdfNode>>],
+    to: &[&'a Claim<Entity<String, RdfNode>>],
+    used: &mut Vec<bool>,
+    renaming: &Renaming<'a>,
+) -> Option<Renaming<'a>> {
+    let (first, rest) = match from.split_first() {
+        Some(split) => split,
+        None => return Some(renaming.clone()),
+    };
+    for (j, claim) in to.iter().enumerate() {
+        if used[j] {
+            continue;
+        }
+        if let Some(extended) = renaming.extend(first, claim) {
+            used[j] = true;
+            if let Some(found) = search(rest, to, used, &extended) {
+                return Some(found);
+            }
+            used[j] = false;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_equivalent_conditions() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri =
+            |suffix: &str| Entity::Bound(RdfNode::Iri(format!("http://example.com/{}", suffix)));
+        let rules = [
+            RuleParts {
+                if_all: vec![
+                    [unbd("a"), iri("parent"), unbd("b")],
+                    [unbd("b"), iri("parent"), unbd("c")],
+                ],
+                then: vec![[unbd("a"), iri("grandparent"), unbd("c")]],
+            },
+            RuleParts {
+                if_all: vec![[unbd("x"), iri("parent"), unbd("y")]],
+                then: vec![[unbd("y"), iri("child"), unbd("x")]],
+            },
+            RuleParts {
+                if_all: vec![
+                    [unbd("y"), iri("parent"), unbd("z")],
+                    [unbd("x"), iri("parent"), unbd("y")],
+                ],
+                then: vec![
+                    [unbd("z"), iri("grandchild"), unbd("x")],
+                    [unbd("x"), iri("grandparent"), unbd("z")],
+                ],
+            },
+            // also two parent claims, but siblings rather than a chain
+            RuleParts {
+                if_all: vec![
+                    [unbd("a"), iri("parent"), unbd("b")],
+                    [unbd("c"), iri("parent"), unbd("b")],
+                ],
+                then: vec![[unbd("a"), iri("sibling"), unbd("c")]],
+            },
+        ];
+        let merged = merge(&rules);
+        assert_eq!(
+            merged.iter().map(|m| &m.sources[..]).collect::<Vec<_>>(),
+            [&[0, 2][..], &[1], &[3]]
+        );
+        assert_eq!(
+            merged[0].rule.then,
+            vec![
+                [unbd("a"), iri("grandparent"), unbd("c")],
+                [unbd("c"), iri("grandchild"), unbd("a")],
+            ]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Simple N3 rules, `{ if_all } => { then } .`, converted by rewriting them as SPARQL
+//! CONSTRUCT queries. N3 formulae use the same triple syntax as SPARQL so the bodies are copied
+//! as they are, only `@prefix` directives and the rule itself need translating. A document
+//! must hold exactly one rule.
+
+use displaydoc::Display;
+use std::error::Error;
+
+#[derive(Debug, PartialEq, Display)]
+pub enum N3Error {
+    /// Expected a rule of the form {{ .. }} => {{ .. }} .
+    ExpectedRule,
+    /// Unterminated {what}.
+    Unterminated { what: &'static str },
+    /// Only a single rule can be converted, found more text after the first: "{text}"
+    TrailingText { text: String },
+}
+
+impl Error for N3Error {}
+
+/// rewrite an N3 rule as an equivalent SPARQL CONSTRUCT query
+pub fn to_sparql(n3: &str) -> Result<String, N3Error> {
+    let mut sparql = String::new();
+    let mut rest = skip_space(n3);
+    while let Some(directive) = directive(rest) {
+        let end = statement_end(rest)?;
+        let declaration = rest[directive.len()..end].trim();
+        let keyword = if directive == "@prefix" {
+            "PREFIX"
+        } else {
+            "BASE"
+        };
+        sparql.push_str(&format!("{} {}\n", keyword, declaration));
+        rest = skip_space(&rest[end + 1..]);
+    }
+
+    let (if_all, after) = formula(rest)?;
+    rest = skip_space(after);
+    rest = rest.strip_prefix("=>").ok_or(N3Error::ExpectedRule)?;
+    let (then, after) = formula(skip_space(rest))?;
+    rest = skip_space(after);
+    rest = skip_space(rest.strip_prefix('.').unwrap_or(rest));
+    if !rest.is_empty() {
+        let text = rest.chars().take(20).collect();
+        return Err(N3Error::TrailingText { text });
+    }
+
+    sparql.push_str(&format!("CONSTRUCT {{{}}} WHERE {{{}}}\n", then, if_all));
+    Ok(sparql)
+}
+
+/// the directive at the start of `text`, if there is one
+fn directive(text: &str) -> Option<&'static str> {
+    ["@prefix", "@base"]
+        .iter()
+        .copied()
+        .find(|directive| text.starts_with(directive))
+}
+
+/// Skip whitespace and comments.
+pub(crate) fn skip_space(mut text: &str) -> &str {
+    loop {
+        text = text.trim_start();
+        match text.strip_prefix('#') {
+            Some(comment) => text = comment.find('\n').map_or("", |end| &comment[end..]),
+            None => return text,
+        }
+    }
+}
+
+/// the body of the formula at the start of `text`, and the text after it
+fn formula(text: &str) -> Result<(&str, &str), N3Error> {
+    if !text.starts_with('{') {
+        return Err(N3Error::ExpectedRule);
+    }
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        match text.as_bytes()[i] {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((&text[1..i], &text[i + 1..]));
+                }
+            }
+            _ => {}
+        }
+        i = skip_token(text, i)?;
+    }
+    Err(N3Error::Unterminated { what: "formula" })
+}
+
+/// the index of the `.` ending the statement at the start of `text`
+fn statement_end(text: &str) -> Result<usize, N3Error> {
+    let mut i = 0;
+    while i < text.len() {
+        if text.as_bytes()[i] == b'.' {
+            return Ok(i);
+        }
+        i = skip_token(text, i)?;
+    }
+    Err(N3Error::Unterminated { what: "directive" })
+}
+
+/// The index just past the string, IRI or comment starting at `i`, or just past `i` if
+/// nothing starts there. Braces and dots inside these don't count. A `<` followed by something
+/// other than an IRI and `>` is an operator rather than the start of an IRI.
+pub(crate) fn skip_token(text: &str, i: usize) -> Result<usize, N3Error> {
+    let rest = &text[i..];
+    let end = |what, found: Option<usize>| found.ok_or(N3Error::Unterminated { what });
+    Ok(i + match rest.as_bytes()[0] {
+        b'#' => rest.find('\n').unwrap_or(rest.len()),
+        b'<' => match rest[1..].find(|c: char| c.is_whitespace() || "<>\"{}|^`".contains(c)) {
+            Some(close) if rest[1 + close..].starts_with('>') => close + 2,
+            Some(_) => 1,
+            None => return Err(N3Error::Unterminated { what: "IRI" }),
+        },
+        quote @ (b'"' | b'\'') => {
+            let quote = quote as char;
+            let long: String = [quote; 3].iter().collect();
+            if let Some(body) = rest.strip_prefix(&long) {
+                end("string", find_unescaped(body, &long))? + 6
+            } else {
+                end("string", find_unescaped(&rest[1..], &quote.to_string()))? + 2
+            }
+        }
+        _ => rest.chars().next().map_or(1, char::len_utf8),
+    })
+}
+
+/// the first occurrence of `close` in `text` that isn't escaped with a backslash
+fn find_unescaped(text: &str, close: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if text[i..].starts_with(close) {
+            return Some(i);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rule() {
+        let n3 = r#"
+            @prefix ex: <http://example.com/> .
+            # a comment with a { brace
+            { ?a ex:knows ?b . ?a ex:name "}" } => { ?b ex:knows ?a } .
+        "#;
+        assert_eq!(
+            to_sparql(n3).unwrap(),
+            "PREFIX ex: <http://example.com/>\n\
+             CONSTRUCT { ?b ex:knows ?a } WHERE { ?a ex:knows ?b . ?a ex:name \"}\" }\n"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(to_sparql("{ ?a ?b ?c }"), Err(N3Error::ExpectedRule));
+        assert_eq!(
+            to_sparql("{ ?a ?b \"c }"),
+            Err(N3Error::Unterminated { what: "string" })
+        );
+        assert_eq!(
+            to_sparql("{} => {} . {} => {} ."),
+            Err(N3Error::TrailingText {
+                text: "{} => {} .".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Node.js bindings, built as a native addon with
+//! `cargo rustc --lib --release --crate-type cdylib --features node`.
+//! Besides conversion they expose the checks `reason` makes, over rulesets in rify's format and
+//! data as an array of `[subject, predicate, object]` triples of rify nodes.
+
+use crate::canon::DatatypePolicy;
+use crate::reason;
+use crate::types::{RdfNode, RuleParts};
+use crate::{parse, sparql2rify};
+use napi::{Error, Result, Status};
+use napi_derive::napi;
+use rify::{Claim, Rule};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Convert a SPARQL CONSTRUCT query to a rify rule of the form `{ if_all, then }`. Lints are not
+/// reported.
+#[napi]
+pub fn convert(sparql: String) -> Result<Value> {
+    let query = parse(&sparql, None).map_err(convert_error)?;
+    let invalid = |source| convert_error(crate::Error::InvalidRule { source });
+    let (rule, _warnings) = sparql2rify(query).map_err(invalid)?;
+    to_json(&rule)
+}
+
+/// The triples `rules` derive from `triples`, applying them until nothing new follows, that
+/// `triples` don't already hold.
+#[napi]
+pub fn infer(rules: Value, triples: Value) -> Result<Value> {
+    let rules = read_rules(rules)?;
+    let rules = rify_rules(&rules)?;
+    let triples = read_triples(triples)?;
+    let present: BTreeSet<&Claim<RdfNode>> = triples.iter().collect();
+    let derived: Vec<Claim<RdfNode>> = rify::infer(&triples, &rules)
+        .into_iter()
+        .filter(|claim| !present.contains(claim))
+        .collect();
+    to_json(&derived)
+}
+
+/// The claims `rules` imply for `triples` in a single step that `triples` don't hold, as the
+/// `check-data` subcommand reports them, see `reason::missing`.
+#[napi]
+pub fn missing(rules: Value, triples: Value) -> Result<Value> {
+    let rules = read_rules(rules)?;
+    for rule in &rules {
+        rule.check().map_err(|e| error("invalid-rule", e))?;
+    }
+    let triples = read_triples(triples)?;
+    to_json(&reason::missing(&rules, &triples))
+}
+
+/// Why `rules` don't derive `expected` from `triples`, with what they do derive, as the `why-not`
+/// subcommand reports it, see `reason::why_not`.
+#[napi]
+pub fn why_not(rules: Value, triples: Value, expected: Value) -> Result<Value> {
+    let rules = read_rules(rules)?;
+    let mut triples = read_triples(triples)?;
+    let mut expected: Claim<RdfNode> = from_json(expected)?;
+    spell_out_datatypes(&mut expected);
+    triples.extend(rify::infer(&triples, &rify_rules(&rules)?));
+    to_json(&reason::why_not(&rules, &triples, &expected))
+}
+
+/// How many claims `rules` would add to `triples`, and with which predicates, see
+/// `reason::impact`.
+#[napi]
+pub fn impact(rules: Value, triples: Value) -> Result<Value> {
+    let rules = read_rules(rules)?;
+    let rules = rify_rules(&rules)?;
+    let triples = read_triples(triples)?;
+    to_json(&reason::impact(&rules, &triples))
+}
+
+/// a ruleset in rify's format, with the datatypes it leaves implicit spelled out as in data, see
+/// `reason::explicit_datatypes`
+fn read_rules(value: Value) -> Result<Vec<RuleParts>> {
+    let mut rules: Vec<RuleParts> = from_json(value)?;
+    reason::explicit_datatypes(&mut rules);
+    Ok(rules)
+}
+
+/// triples of rify nodes, with the datatypes they leave implicit spelled out
+fn read_triples(value: Value) -> Result<Vec<Claim<RdfNode>>> {
+    let mut triples: Vec<Claim<RdfNode>> = from_json(value)?;
+    for triple in &mut triples {
+        spell_out_datatypes(triple);
+    }
+    Ok(triples)
+}
+
+fn spell_out_datatypes(triple: &mut Claim<RdfNode>) {
+    for node in triple.iter_mut() {
+        DatatypePolicy::default().node(node);
+    }
+}
+
+fn rify_rules(rules: &[RuleParts]) -> Result<Vec<Rule<String, RdfNode>>> {
+    rules
+        .iter()
+        .map(|rule| rule.to_rule().map_err(|e| error("invalid-rule", e)))
+        .collect()
+}
+
+fn from_json<T: DeserializeOwned>(value: Value) -> Result<T> {
+    serde_json::from_value(value).map_err(|e| error("invalid-input", e))
+}
+
+fn to_json(value: &impl Serialize) -> Result<Value> {
+    serde_json::to_value(value).map_err(|e| Error::new(Status::GenericFailure, e.to_string()))
+}
+
+/// The message is prefixed with the same `kind` the wasm bindings report, so callers can tell
+/// invalid SPARQL apart from queries with no rify equivalent. Rulesets and data that aren't in
+/// rify's format are `invalid-input`.
+fn convert_error(err: crate::Error) -> Error {
+    let kind = match err {
+        crate::Error::Parse { .. } => "parse",
+        crate::Error::InvalidRule { .. } => "invalid-rule",
+    };
+    error(kind, err)
+}
+
+fn error(kind: &str, message: impl std::fmt::Display) -> Error {
+    Error::new(Status::InvalidArg, format!("{}: {}", kind, message))
+}
@@ -0,0 +1,159 @@
+//! Canonical ordering of a rule's conditions. The order of the triples in a WHERE clause has no
+//! meaning, so sorting them makes queries that differ only in that order convert to identical
+//! rules.
+
+use crate::fingerprint::fingerprint;
+use crate::types::{RdfNode, RuleParts};
+use crate::Graph;
+use rify::{Claim, Entity};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Sort `if_all` so the most selective claims come first. A claim with more bound terms matches
+/// fewer facts, and a bound subject or object narrows a match more than a bound predicate.
+/// Claims that are equally selective are ordered by their bound terms, and only then by
+/// variable names, so renaming variables rarely changes the order.
+pub fn canonical_order(if_all: &mut [Claim<Entity<String, RdfNode>>]) {
+    if_all.sort_by(|a, b| key(a).cmp(&key(b)));
+}
+
+/// `canonical_order` for a rule over quads, `graphs` being the graph of each claim. Claims that
+/// are otherwise equal are ordered by graph, the default graph first. Returns whether any claim
+/// moved.
+pub fn canonical_order_with_graphs(
+    if_all: &mut Vec<Claim<Entity<String, RdfNode>>>,
+    graphs: &mut Vec<Graph>,
+) -> bool {
+    let mut quads: Vec<_> = if_all.drain(..).zip(graphs.drain(..)).enumerate().collect();
+    quads.sort_by(|(_, a), (_, b)| quad_key(a).cmp(&quad_key(b)));
+    let moved = quads.iter().enumerate().any(|(i, (was, _))| i != *was);
+    let (claims, graph_names) = quads.into_iter().map(|(_, quad)| quad).unzip();
+    *if_all = claims;
+    *graphs = graph_names;
+    moved
+}
+
+/// The order to write `rules` in, as indices into `rules`, so a rebuilt ruleset keeps the
+/// indices of `previous`. A rule with the same fingerprint as a previous one takes its place,
+/// new and changed rules fill the places of rules that are gone, in the order given, and what is
+/// left of them goes at the end. Places nothing fills are closed up, moving the rules after them.
+pub fn stable_order<'a>(
+    previous: &[RuleParts],
+    rules: impl IntoIterator<Item = &'a RuleParts>,
+) -> Vec<usize> {
+    let mut places: BTreeMap<String, VecDeque<usize>> = BTreeMap::new();
+    for (i, rule) in previous.iter().enumerate() {
+        places.entry(fingerprint(rule)).or_default().push_back(i);
+    }
+    let mut slots = vec![None; previous.len()];
+    let mut new = VecDeque::new();
+    for (i, rule) in rules.into_iter().enumerate() {
+        match places
+            .get_mut(&fingerprint(rule))
+            .and_then(VecDeque::pop_front)
+        {
+            Some(place) => slots[place] = Some(i),
+            None => new.push_back(i),
+        }
+    }
+    for slot in slots.iter_mut().filter(|slot| slot.is_none()) {
+        *slot = new.pop_front();
+    }
+    slots.into_iter().flatten().chain(new).collect()
+}
+
+/// borrows the terms of the claim, which may be long literals, rather than copying them
+type Key<'a> = (
+    Reverse<usize>,
+    [bool; 3],
+    [Option<&'a RdfNode>; 3],
+    [Option<&'a str>; 3],
+);
+
+fn key(claim: &Claim<Entity<String, RdfNode>>) -> Key<'_> {
+    let [s, p, o] = claim;
+    // subject and object before predicate
+    let terms = [s, o, p];
+    let nodes = terms.map(bound);
+    let names = terms.map(unbound);
+    let shape = nodes.map(|node| node.is_none());
+    let count = shape.iter().filter(|unbound| !**unbound).count();
+    (Reverse(count), shape, nodes, names)
+}
+
+fn quad_key((claim, graph): &(Claim<Entity<String, RdfNode>>, Graph)) -> (Key<'_>, GraphKey<'_>) {
+    let graph = graph.as_ref().map(|graph| (bound(graph), unbound(graph)));
+    (key(claim), graph)
+}
+
+type GraphKey<'a> = Option<(Option<&'a RdfNode>, Option<&'a str>)>;
+
+fn bound(ent: &Entity<String, RdfNode>) -> Option<&RdfNode> {
+    match ent {
+        Entity::Bound(node) => Some(node),
+        Entity::Unbound(_) => None,
+    }
+}
+
+fn unbound(ent: &Entity<String, RdfNode>) -> Option<&str> {
+    match ent {
+        Entity::Bound(_) => None,
+        Entity::Unbound(name) => Some(name),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn most_selective_first() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| Entity::Bound(RdfNode::Iri(format!("http://example.com/{}", iri)));
+        let mut if_all = vec![
+            [unbd("a"), unbd("p"), unbd("b")],
+            [unbd("a"), iri("q"), unbd("b")],
+            [unbd("a"), iri("p"), unbd("b")],
+            [unbd("a"), iri("p"), iri("o")],
+            [iri("s"), unbd("p"), unbd("b")],
+        ];
+        canonical_order(&mut if_all);
+        assert_eq!(
+            if_all,
+            vec![
+                [unbd("a"), iri("p"), iri("o")],
+                [iri("s"), unbd("p"), unbd("b")],
+                [unbd("a"), iri("p"), unbd("b")],
+                [unbd("a"), iri("q"), unbd("b")],
+                [unbd("a"), unbd("p"), unbd("b")],
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_previous_places() {
+        let rule = |p: &str, q: &str| RuleParts {
+            if_all: vec![[
+                Entity::Unbound("a".to_string()),
+                Entity::Bound(RdfNode::Iri(format!("http://example.com/{}", p))),
+                Entity::Unbound("b".to_string()),
+            ]],
+            then: vec![[
+                Entity::Unbound("b".to_string()),
+                Entity::Bound(RdfNode::Iri(format!("http://example.com/{}", q))),
+                Entity::Unbound("a".to_string()),
+            ]],
+        };
+        let previous = [rule("p", "q"), rule("q", "r"), rule("r", "s")];
+        // q r was edited, and two rules were added in front
+        let rules = [
+            rule("x", "y"),
+            rule("r", "s"),
+            rule("q", "z"),
+            rule("p", "q"),
+        ];
+        assert_eq!(stable_order(&previous, &rules), vec![3, 0, 1, 2]);
+        // with nothing new, the place of a removed rule is closed up
+        assert_eq!(stable_order(&previous, &previous[1..]), vec![0, 1]);
+    }
+}
@@ -0,0 +1,396 @@
+use crate::dock::DockRule;
+use crate::extended::ExtendedRule;
+use crate::fingerprint;
+use crate::lint::{Diagnostic, DiagnosticKind};
+use crate::typed::TypedRule;
+use crate::types::{RdfNode, RuleParts};
+use crate::vars::IndexedRule;
+use rify::{Claim, Entity};
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::str::FromStr;
+
+/// The representations a converted rule can be written in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// plain rify rules
+    #[default]
+    Rify,
+    /// see `SharedIris`
+    SharedIris,
+    /// see `DockRule`
+    Dock,
+    /// see `TypedRule`
+    Typed,
+    /// see `IndexedRule`
+    Indexed,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "rify" => Ok(Self::Rify),
+            "shared-iris" => Ok(Self::SharedIris),
+            "dock" => Ok(Self::Dock),
+            "typed" => Ok(Self::Typed),
+            "indexed" => Ok(Self::Indexed),
+            _ => Err(format!("Unknown output format \"{}\", try --help.", s)),
+        }
+    }
+}
+
+pub fn write(out: impl Write, rule: &RuleParts, format: Format) -> serde_json::Result<()> {
+    encode(&mut serde_json::Serializer::pretty(out), rule, format)
+}
+
+/// `rule` in `format` as a JSON value, for embedding in other documents
+pub fn to_value(rule: &RuleParts, format: Format) -> serde_json::Value {
+    encode(serde_json::value::Serializer, rule, format).expect("rules always serialize")
+}
+
+/// Extra fields added to a rule in the output, e.g. its diagnostics or the file it came from.
+pub type Annotations = serde_json::Map<String, serde_json::Value>;
+
+/// `diagnostics` as an annotation
+pub fn diagnostics(diagnostics: &[Diagnostic]) -> Annotations {
+    let diagnostics = serde_json::to_value(diagnostics).expect("diagnostics always serialize");
+    let mut annotations = Annotations::new();
+    annotations.insert("diagnostics".to_string(), diagnostics);
+    annotations
+}
+
+/// What `--audit` records about a rule that was written: the inputs it came from, its
+/// fingerprint to find it by in the output, and every rewrite applied to it on the way, whether
+/// or not diagnostics were embedded.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Audit {
+    /// paths of the files the rule was converted from, empty if it was read from stdin
+    pub sources: Vec<String>,
+    pub fingerprint: String,
+    pub rewrites: Vec<Diagnostic>,
+}
+
+impl Audit {
+    /// the rewrites among `diagnostics`, for `rule` as it was written
+    pub fn new(sources: Vec<String>, rule: &RuleParts, diagnostics: &[Diagnostic]) -> Self {
+        Self {
+            sources,
+            fingerprint: fingerprint::fingerprint(rule),
+            rewrites: diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.kind == DiagnosticKind::Rewrite)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// `rule` as `to_value` gives it, with `annotations` added
+pub fn annotated(rule: &RuleParts, format: Format, annotations: Annotations) -> serde_json::Value {
+    let mut value = to_value(rule, format);
+    annotate(&mut value, annotations);
+    value
+}
+
+/// A rule over quads with `annotations` added. Quads are always written in Dock's format, the
+/// other formats only have room for triples.
+pub fn annotated_quads(rule: &DockRule, annotations: Annotations) -> serde_json::Value {
+    let mut value = serde_json::to_value(rule).expect("rules always serialize");
+    annotate(&mut value, annotations);
+    value
+}
+
+/// A rule in the extended format with `annotations` added, see `extended`. Only rify's format has
+/// room for negative conditions.
+pub fn annotated_extended(rule: &ExtendedRule, annotations: Annotations) -> serde_json::Value {
+    let mut value = serde_json::to_value(rule).expect("rules always serialize");
+    annotate(&mut value, annotations);
+    value
+}
+
+/// a ruleset as `write_ruleset` writes it, with each rule's annotations added to the rule
+pub fn annotated_ruleset(
+    rules: Vec<(&RuleParts, Annotations)>,
+    format: Format,
+) -> serde_json::Value {
+    let parts: Vec<&RuleParts> = rules.iter().map(|(rule, _)| *rule).collect();
+    let mut value = encode_ruleset(serde_json::value::Serializer, &parts, format)
+        .expect("rules always serialize");
+    let array = match format {
+        Format::SharedIris => &mut value["rules"],
+        _ => &mut value,
+    };
+    if let serde_json::Value::Array(values) = array {
+        for (value, (_, annotations)) in values.iter_mut().zip(rules) {
+            annotate(value, annotations);
+        }
+    }
+    value
+}
+
+fn annotate(rule: &mut serde_json::Value, annotations: Annotations) {
+    if let serde_json::Value::Object(rule) = rule {
+        rule.extend(annotations);
+    }
+}
+
+/// the length of `rule` in `format` without any whitespace
+pub fn encoded_len(rule: &RuleParts, format: Format) -> usize {
+    let mut out = Vec::new();
+    encode(&mut serde_json::Serializer::new(&mut out), rule, format)
+        .expect("writing to memory can't fail");
+    out.len()
+}
+
+fn encode<S: Serializer>(ser: S, rule: &RuleParts, format: Format) -> Result<S::Ok, S::Error> {
+    match format {
+        Format::Rify => rule.serialize(ser),
+        Format::SharedIris => {
+            let mut table = IriTable::default();
+            let rule = table.rule(rule);
+            let shared = SharedIris {
+                iris: table.into_iris(),
+                rule,
+            };
+            shared.serialize(ser)
+        }
+        Format::Dock => DockRule::from(rule).serialize(ser),
+        Format::Typed => TypedRule::from(rule).serialize(ser),
+        Format::Indexed => IndexedRule::from(rule).serialize(ser),
+    }
+}
+
+/// write several rules as one document, a JSON array of rules or a single IRI table shared by
+/// every rule
+pub fn write_ruleset(
+    out: impl Write,
+    rules: &[&RuleParts],
+    format: Format,
+) -> serde_json::Result<()> {
+    encode_ruleset(&mut serde_json::Serializer::pretty(out), rules, format)
+}
+
+fn encode_ruleset<S: Serializer>(
+    ser: S,
+    rules: &[&RuleParts],
+    format: Format,
+) -> Result<S::Ok, S::Error> {
+    match format {
+        Format::Rify => rules.serialize(ser),
+        Format::SharedIris => {
+            let mut table = IriTable::default();
+            let rules = rules.iter().map(|rule| table.rule(rule)).collect();
+            let shared = SharedIrisRuleset {
+                iris: table.into_iris(),
+                rules,
+            };
+            shared.serialize(ser)
+        }
+        Format::Dock => {
+            let rules: Vec<DockRule> = rules.iter().map(|rule| DockRule::from(*rule)).collect();
+            rules.serialize(ser)
+        }
+        Format::Typed => {
+            let rules: Vec<TypedRule> = rules.iter().map(|rule| TypedRule::from(*rule)).collect();
+            rules.serialize(ser)
+        }
+        Format::Indexed => {
+            let rules: Vec<IndexedRule> =
+                rules.iter().map(|rule| IndexedRule::from(*rule)).collect();
+            rules.serialize(ser)
+        }
+    }
+}
+
+/// A rule where each distinct IRI, including literal datatypes, is written once in `iris` and
+/// referred to by its index everywhere else. Ontology-derived rules repeat the same few long
+/// IRIs many times so this is much smaller than the plain representation.
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct SharedIris {
+    pub iris: Vec<String>,
+    #[serde(flatten)]
+    pub rule: SharedRule,
+}
+
+/// Like `SharedIris`, but for a list of rules.
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct SharedIrisRuleset {
+    pub iris: Vec<String>,
+    pub rules: Vec<SharedRule>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct SharedRule {
+    pub if_all: Vec<Claim<Entity<String, SharedNode>>>,
+    pub then: Vec<Claim<Entity<String, SharedNode>>>,
+}
+
+/// An `RdfNode` with IRIs replaced by indices into an IRI table.
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub enum SharedNode {
+    Blank(String),
+    Iri(usize),
+    Literal {
+        value: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        datatype: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+    },
+}
+
+/// Assigns indices to IRIs in order of first appearance.
+#[derive(Debug, Default)]
+pub struct IriTable {
+    indices: BTreeMap<String, usize>,
+    iris: Vec<String>,
+}
+
+impl IriTable {
+    pub fn index(&mut self, iri: &str) -> usize {
+        if let Some(index) = self.indices.get(iri) {
+            return *index;
+        }
+        let index = self.iris.len();
+        self.indices.insert(iri.to_string(), index);
+        self.iris.push(iri.to_string());
+        index
+    }
+
+    pub fn node(&mut self, node: &RdfNode) -> SharedNode {
+        match node {
+            RdfNode::Blank(name) => SharedNode::Blank(name.clone()),
+            RdfNode::Iri(iri) => SharedNode::Iri(self.index(iri)),
+            RdfNode::Literal {
+                value,
+                datatype,
+                language,
+            } => SharedNode::Literal {
+                value: value.clone(),
+                datatype: datatype.as_deref().map(|d| self.index(d)),
+                language: language.clone(),
+            },
+        }
+    }
+
+    pub fn rule(&mut self, rule: &RuleParts) -> SharedRule {
+        let mut claims = |claims: &[Claim<Entity<String, RdfNode>>]| -> Vec<_> {
+            claims
+                .iter()
+                .map(|claim| {
+                    let [s, p, o] = claim;
+                    [self.entity(s), self.entity(p), self.entity(o)]
+                })
+                .collect()
+        };
+        let if_all = claims(&rule.if_all);
+        let then = claims(&rule.then);
+        SharedRule { if_all, then }
+    }
+
+    pub fn into_iris(self) -> Vec<String> {
+        self.iris
+    }
+
+    fn entity(&mut self, ent: &Entity<String, RdfNode>) -> Entity<String, SharedNode> {
+        match ent {
+            Entity::Unbound(name) => Entity::Unbound(name.clone()),
+            Entity::Bound(node) => Entity::Bound(self.node(node)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shared_iris() {
+        let iri = |s: &str| Entity::Bound(RdfNode::Iri(s.to_string()));
+        let rule = RuleParts {
+            if_all: vec![[
+                Entity::Unbound("a".to_string()),
+                iri("http://example.com/p"),
+                Entity::Bound(RdfNode::Literal {
+                    value: "1".to_string(),
+                    datatype: Some("http://www.w3.org/2001/XMLSchema#integer".to_string()),
+                    language: None,
+                }),
+            ]],
+            then: vec![[
+                Entity::Unbound("a".to_string()),
+                iri("http://example.com/p"),
+                iri("http://example.com/o"),
+            ]],
+        };
+
+        let mut out = Vec::new();
+        write(&mut out, &rule, Format::SharedIris).unwrap();
+        let expected = serde_json::json!({
+            "iris": [
+                "http://example.com/p",
+                "http://www.w3.org/2001/XMLSchema#integer",
+                "http://example.com/o",
+            ],
+            "if_all": [[
+                {"Unbound": "a"},
+                {"Bound": {"Iri": 0}},
+                {"Bound": {"Literal": {"value": "1", "datatype": 1}}},
+            ]],
+            "then": [[
+                {"Unbound": "a"},
+                {"Bound": {"Iri": 0}},
+                {"Bound": {"Iri": 2}},
+            ]],
+        });
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&out).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        let rule = RuleParts {
+            if_all: vec![],
+            then: vec![],
+        };
+        let diagnostics = [Diagnostic {
+            kind: crate::lint::DiagnosticKind::Rewrite,
+            code: "canonicalize".to_string(),
+            message: "Rewrote 1 term(s).".to_string(),
+        }];
+        let expected = serde_json::json!([{
+            "code": "canonicalize",
+            "kind": "rewrite",
+            "message": "Rewrote 1 term(s).",
+        }]);
+        let rules = || {
+            vec![
+                (&rule, super::diagnostics(&diagnostics)),
+                (&rule, Annotations::new()),
+            ]
+        };
+        let ruleset = annotated_ruleset(rules(), Format::SharedIris);
+        assert_eq!(ruleset["rules"][0]["diagnostics"], expected);
+        assert!(ruleset["rules"][1].get("diagnostics").is_none());
+        let ruleset = annotated_ruleset(rules(), Format::Dock);
+        assert_eq!(ruleset[0]["diagnostics"], expected);
+
+        let warning = Diagnostic {
+            kind: DiagnosticKind::Warning,
+            code: "identity_rule".to_string(),
+            message: "Derives nothing new.".to_string(),
+        };
+        let audit = Audit::new(
+            vec!["a.sparql".to_string()],
+            &rule,
+            &[warning, diagnostics[0].clone()],
+        );
+        assert_eq!(audit.rewrites, diagnostics);
+        assert_eq!(audit.fingerprint, fingerprint::fingerprint(&rule));
+    }
+}
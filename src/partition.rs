@@ -0,0 +1,187 @@
+//! Splitting a ruleset by what its rules write to, so a deployment can load only the rules one
+//! stage of a pipeline needs. Like `vocab::split_types`, a rule whose conclusions write to
+//! several predicates, or graphs, becomes one rule for each with all of its conditions. A
+//! conclusion with a variable in that place can write anywhere, so those are kept apart in a
+//! partition every stage has to load alongside its own.
+
+use crate::dock::{DockNode, DockRule};
+use crate::types::{RdfNode, RuleParts};
+use crate::vocab::namespace;
+use rify::Entity;
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+/// What to partition a ruleset by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum By {
+    #[default]
+    Predicate,
+    /// the fourth element of claims in Dock's format, see `DockRule`
+    Graph,
+}
+
+impl FromStr for By {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "predicate" => Ok(Self::Predicate),
+            "graph" => Ok(Self::Graph),
+            _ => Err(format!("Invalid partition \"{}\", try --help.", s)),
+        }
+    }
+}
+
+/// Where a conclusion writes.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Target {
+    Iri(String),
+    DefaultGraph,
+    /// a variable, or a term that doesn't name a predicate or graph
+    Any,
+}
+
+impl Target {
+    /// the IRI, or `default` or `any`, which can't be mistaken for one as IRIs are absolute
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Iri(iri) => iri,
+            Self::DefaultGraph => "default",
+            Self::Any => "any",
+        }
+    }
+}
+
+/// `rules` grouped by the predicates their conclusions write to, in their original order
+pub fn by_predicate(rules: &[RuleParts]) -> BTreeMap<Target, Vec<RuleParts>> {
+    let mut ret: BTreeMap<Target, Vec<RuleParts>> = BTreeMap::new();
+    for rule in rules {
+        for (target, then) in group(&rule.then, |[_, p, _]| match p {
+            Entity::Bound(RdfNode::Iri(iri)) => Target::Iri(iri.clone()),
+            _ => Target::Any,
+        }) {
+            let if_all = rule.if_all.clone();
+            ret.entry(target)
+                .or_default()
+                .push(RuleParts { if_all, then });
+        }
+    }
+    ret
+}
+
+/// `rules` grouped by the graphs their conclusions write to, in their original order
+pub fn by_graph(rules: &[DockRule]) -> BTreeMap<Target, Vec<DockRule>> {
+    let mut ret: BTreeMap<Target, Vec<DockRule>> = BTreeMap::new();
+    for rule in rules {
+        for (target, then) in group(&rule.then, |[_, _, _, g]| match g {
+            Entity::Bound(DockNode::Iri(iri)) => Target::Iri(iri.clone()),
+            Entity::Bound(DockNode::DefaultGraph(_)) => Target::DefaultGraph,
+            _ => Target::Any,
+        }) {
+            let if_all = rule.if_all.clone();
+            ret.entry(target)
+                .or_default()
+                .push(DockRule { if_all, then });
+        }
+    }
+    ret
+}
+
+fn group<C: Clone>(then: &[C], target: impl Fn(&C) -> Target) -> BTreeMap<Target, Vec<C>> {
+    let mut ret: BTreeMap<Target, Vec<C>> = BTreeMap::new();
+    for claim in then {
+        ret.entry(target(claim)).or_default().push(claim.clone());
+    }
+    ret
+}
+
+/// A JSON file name for each of `targets`, in order. IRIs are named by their local name, made
+/// safe for any file system, with a number appended to any that would clash with an earlier one
+/// when case is ignored. The default graph and variables get `default.json` and `any.json`, and
+/// none is named `index.json`, where the command line tool lists them.
+pub fn file_names<'a>(targets: impl IntoIterator<Item = &'a Target>) -> Vec<String> {
+    let mut taken: BTreeSet<String> = ["index", "default", "any"]
+        .iter()
+        .map(|stem| stem.to_string())
+        .collect();
+    targets
+        .into_iter()
+        .map(|target| {
+            let stem: String = match target {
+                Target::Iri(iri) => iri[namespace(iri).len()..]
+                    .chars()
+                    .map(|c| match c {
+                        'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                        _ => '_',
+                    })
+                    .collect(),
+                other => return format!("{}.json", other.label()),
+            };
+            let stem = if stem.is_empty() { "partition" } else { &stem };
+            let stem = std::iter::once(stem.to_string())
+                .chain((2..).map(|n| format!("{}-{}", stem, n)))
+                .find(|stem| taken.insert(stem.to_ascii_lowercase()))
+                .expect("names are finite");
+            format!("{}.json", stem)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn partition_by_predicate() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| Entity::Bound(RdfNode::Iri(iri.to_string()));
+        let rule = RuleParts {
+            if_all: vec![[unbd("a"), iri("http://example.com/parent"), unbd("b")]],
+            then: vec![
+                [unbd("b"), iri("http://example.com/child"), unbd("a")],
+                [unbd("a"), iri("http://example.com/Child"), unbd("b")],
+                [unbd("a"), unbd("b"), unbd("b")],
+                [unbd("b"), iri("http://example.com/child"), unbd("b")],
+            ],
+        };
+        let partitions = by_predicate(std::slice::from_ref(&rule));
+        let targets: Vec<&Target> = partitions.keys().collect();
+        assert_eq!(
+            targets,
+            [
+                &Target::Iri("http://example.com/Child".to_string()),
+                &Target::Iri("http://example.com/child".to_string()),
+                &Target::Any,
+            ]
+        );
+        let child = &partitions[&Target::Iri("http://example.com/child".to_string())];
+        assert_eq!(
+            child,
+            &[RuleParts {
+                if_all: rule.if_all.clone(),
+                then: vec![rule.then[0].clone(), rule.then[3].clone()],
+            }]
+        );
+        assert_eq!(
+            file_names(partitions.keys()),
+            ["Child.json", "child-2.json", "any.json"]
+        );
+        let odd = [
+            Target::Iri("http://example.com/".to_string()),
+            Target::Iri("urn:x:index".to_string()),
+            Target::Iri("http://example.com/a%20b".to_string()),
+            Target::Iri("http://example.com/Any".to_string()),
+            Target::DefaultGraph,
+        ];
+        assert_eq!(
+            file_names(&odd),
+            [
+                "partition.json",
+                "index-2.json",
+                "a_20b.json",
+                "Any-2.json",
+                "default.json"
+            ]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Where derived rules came from. `compose`, `merge` and `specialize` make rules out of others,
+//! and the rules they write say how in a `provenance` field: the operation, and for each rule it
+//! started from, that rule's fingerprint, see `fingerprint`, and a hash of the text it was read
+//! from. A parent that was itself derived has a `provenance` of its own, so following `parents`
+//! from any derived rule leads back to the queries it was originally converted from:
+//!
+//! ```text
+//! "provenance": {"operation": "compose", "parents": [
+//!     {"fingerprint": "<hex>", "source": "<sha256 of query>"},
+//!     {"fingerprint": "<hex>", "source": "<sha256 of rule>", "provenance": {..}}]}
+//! ```
+
+use crate::fingerprint::fingerprint;
+use crate::input::{self, Kind};
+use crate::manifest;
+use crate::output::Annotations;
+use crate::types::RuleParts;
+use serde::{Deserialize, Serialize};
+
+/// How a rule was derived, and from which rules.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub operation: Operation,
+    /// the rules it was derived from, in order
+    pub parents: Vec<Parent>,
+}
+
+/// The operations that derive rules from others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    /// see `compose`
+    Compose,
+    /// see `merge`
+    Merge,
+    /// see `specialize::specialize`
+    Specialize,
+    /// see `specialize::generalize`
+    Generalize,
+}
+
+/// A rule another was derived from.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Parent {
+    pub fingerprint: String,
+    /// hex encoded SHA-256 of the text the rule was read from, the query if it was converted
+    /// from one
+    pub source: String,
+    /// how the rule was derived, if it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Box<Provenance>>,
+}
+
+impl Parent {
+    /// `rule`, as converted from `text`, as the parent of a derived rule
+    pub fn new(rule: &RuleParts, text: &str) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            fingerprint: fingerprint(rule),
+            source: manifest::digest(text.as_bytes()),
+            provenance: read(text)?.map(Box::new),
+        })
+    }
+}
+
+/// The provenance of the rule in `text`, if it is a rule in JSON that was derived from others.
+pub fn read(text: &str) -> Result<Option<Provenance>, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct Derived {
+        provenance: Option<Provenance>,
+    }
+    if input::detect(text) != Kind::Rule {
+        return Ok(None);
+    }
+    Ok(serde_json::from_str::<Derived>(text)?.provenance)
+}
+
+/// `provenance` as an annotation
+pub fn annotations(provenance: &Provenance) -> Annotations {
+    let provenance = serde_json::to_value(provenance).expect("provenance always serializes");
+    let mut annotations = Annotations::new();
+    annotations.insert("provenance".to_string(), provenance);
+    annotations
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{compose, output, sparql2rify};
+    use serde_json::json;
+
+    #[test]
+    fn chains_provenance() {
+        let member = "CONSTRUCT { ?a <http://example.com/member> ?b }\n\
+                      WHERE { ?a <http://example.com/employee> ?b }";
+        let trusts = "CONSTRUCT { ?a <http://example.com/trusts> ?b }\n\
+                      WHERE { ?a <http://example.com/member> ?b }";
+        let (first, _) = sparql2rify(member.parse().unwrap()).unwrap();
+        let (second, _) = sparql2rify(trusts.parse().unwrap()).unwrap();
+        assert_eq!(read(member).unwrap(), None);
+        let composed = Provenance {
+            operation: Operation::Compose,
+            parents: vec![
+                Parent::new(&first, member).unwrap(),
+                Parent::new(&second, trusts).unwrap(),
+            ],
+        };
+        let rule = compose::compose(&first, &second).pop().unwrap();
+        let derived = output::annotated(&rule, output::Format::Rify, annotations(&composed));
+        assert_eq!(
+            derived["provenance"],
+            json!({"operation": "compose", "parents": [
+                {"fingerprint": fingerprint(&first), "source": manifest::digest(member.as_bytes())},
+                {"fingerprint": fingerprint(&second), "source": manifest::digest(trusts.as_bytes())}
+            ]})
+        );
+
+        // a rule read back in leads to the queries through the rule it was derived from
+        let text = derived.to_string();
+        assert_eq!(read(&text).unwrap().as_ref(), Some(&composed));
+        let parent = Parent::new(&rule, &text).unwrap();
+        assert_eq!(parent.provenance.as_deref(), Some(&composed));
+        assert_eq!(parent.fingerprint, fingerprint(&rule));
+        assert!(read(r#"{"if_all": [], "then": [], "provenance": "compose"}"#).is_err());
+    }
+}
@@ -0,0 +1,417 @@
+//! Matching rules against data directly, for checks that need to know where a rule matches
+//! rather than everything a reasoner would derive. Data is a list of triples, `Claim<RdfNode>`,
+//! and rules match it the way rify matches them: blank nodes in a rule are compared by label.
+
+use crate::canon::DatatypePolicy;
+use crate::types::{RdfNode, RuleParts};
+use rify::{Claim, Entity, Rule};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The terms a match assigns to each variable.
+pub type Bindings<'a> = BTreeMap<&'a str, &'a RdfNode>;
+
+/// Every assignment of terms to the variables of `conditions` that makes each condition one of
+/// `triples`.
+pub fn matches<'a>(
+    conditions: &'a [Claim<Entity<String, RdfNode>>],
+    triples: &'a [Claim<RdfNode>],
+) -> Vec<Bindings<'a>> {
+    let index = Index::new(triples);
+    let mut ret = Vec::new();
+    search(conditions, &index, Bindings::new(), &mut ret);
+    ret
+}
+
+fn search<'a>(
+    conditions: &'a [Claim<Entity<String, RdfNode>>],
+    index: &Index<'a>,
+    bindings: Bindings<'a>,
+    found: &mut Vec<Bindings<'a>>,
+) {
+    let (first, rest) = match conditions.split_first() {
+        Some(split) => split,
+        None => return found.push(bindings),
+    };
+    for triple in index.candidates(first, &bindings) {
+        if let Some(extended) = extend(first, triple, &bindings) {
+            search(rest, index, extended, found);
+        }
+    }
+}
+
+/// Triples by predicate, so a condition is only compared with triples that can match it.
+struct Index<'a> {
+    by_predicate: BTreeMap<&'a RdfNode, Vec<&'a Claim<RdfNode>>>,
+    all: Vec<&'a Claim<RdfNode>>,
+}
+
+impl<'a> Index<'a> {
+    fn new(triples: &'a [Claim<RdfNode>]) -> Self {
+        let mut by_predicate: BTreeMap<&RdfNode, Vec<&Claim<RdfNode>>> = BTreeMap::new();
+        for triple in triples {
+            by_predicate.entry(&triple[1]).or_default().push(triple);
+        }
+        let all = triples.iter().collect();
+        Self { by_predicate, all }
+    }
+
+    fn candidates(
+        &self,
+        condition: &Claim<Entity<String, RdfNode>>,
+        bindings: &Bindings,
+    ) -> &[&'a Claim<RdfNode>] {
+        let predicate = match &condition[1] {
+            Entity::Bound(predicate) => Some(predicate),
+            Entity::Unbound(name) => bindings.get(name.as_str()).copied(),
+        };
+        match predicate {
+            Some(predicate) => self.by_predicate.get(predicate).map_or(&[][..], |c| &c[..]),
+            None => &self.all,
+        }
+    }
+}
+
+/// `bindings` extended so that `claim` is `triple`, None if they can't be made equal
+fn extend<'a>(
+    claim: &'a Claim<Entity<String, RdfNode>>,
+    triple: &'a Claim<RdfNode>,
+    bindings: &Bindings<'a>,
+) -> Option<Bindings<'a>> {
+    let mut extended = bindings.clone();
+    let matched = claim
+        .iter()
+        .zip(triple.iter())
+        .all(|(ent, node)| match ent {
+            Entity::Unbound(name) => *extended.entry(name).or_insert(node) == node,
+            Entity::Bound(bound) => bound.same_term(node),
+        });
+    Some(extended).filter(|_| matched)
+}
+
+/// `claim` with its variables replaced by the terms they are bound to, None if one isn't bound
+pub fn instantiate(
+    claim: &Claim<Entity<String, RdfNode>>,
+    bindings: &Bindings,
+) -> Option<Claim<RdfNode>> {
+    let node = |ent: &Entity<String, RdfNode>| match ent {
+        Entity::Unbound(name) => bindings.get(name.as_str()).map(|node| (*node).clone()),
+        Entity::Bound(node) => Some(node.clone()),
+    };
+    let [s, p, o] = claim;
+    Some([node(s)?, node(p)?, node(o)?])
+}
+
+/// Spell out the datatypes `rules` leave implicit, see `canon::DatatypePolicy`. Data read
+/// through oxigraph always has them, and rify, like `missing` looking up what the data holds,
+/// compares terms with `==`, so rules are put in this form before they are applied to data.
+pub fn explicit_datatypes(rules: &mut [RuleParts]) {
+    for rule in rules {
+        DatatypePolicy::default().rule(rule);
+    }
+}
+
+/// A claim a rule implies for some data that the data doesn't hold.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Missing {
+    /// index of the rule in the ruleset
+    pub rule: usize,
+    pub claim: Claim<RdfNode>,
+    /// the match that implies the claim, the first one if there are several
+    pub bindings: BTreeMap<String, RdfNode>,
+}
+
+/// The claims `rules` derive from `triples` in a single step that `triples` don't already hold,
+/// each listed once per rule. Data the rules were already applied to has none.
+pub fn missing(rules: &[RuleParts], triples: &[Claim<RdfNode>]) -> Vec<Missing> {
+    let present: BTreeSet<&Claim<RdfNode>> = triples.iter().collect();
+    let mut ret = Vec::new();
+    for (i, rule) in rules.iter().enumerate() {
+        let mut reported = BTreeSet::new();
+        for bindings in matches(&rule.if_all, triples) {
+            for claim in rule.then.iter().filter_map(|c| instantiate(c, &bindings)) {
+                if present.contains(&claim) || !reported.insert(claim.clone()) {
+                    continue;
+                }
+                ret.push(Missing {
+                    rule: i,
+                    claim,
+                    bindings: bindings
+                        .iter()
+                        .map(|(name, node)| (name.to_string(), (*node).clone()))
+                        .collect(),
+                });
+            }
+        }
+    }
+    ret
+}
+
+/// What a rule would add to some data, to judge how far its effects reach before deploying it.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Impact {
+    /// the claims it derives that the data doesn't hold, applying it until nothing new follows
+    pub derived: usize,
+    /// How many of them have each predicate. A claim with a literal or blank node as its
+    /// predicate, which no RDF store could hold, only counts towards `derived`.
+    pub predicates: BTreeMap<String, usize>,
+}
+
+/// The impact of applying `rules` to `triples`, nothing in `triples` being changed.
+pub fn impact(rules: &[Rule<String, RdfNode>], triples: &[Claim<RdfNode>]) -> Impact {
+    let present: BTreeSet<&Claim<RdfNode>> = triples.iter().collect();
+    let derived: BTreeSet<Claim<RdfNode>> = rify::infer(triples, rules)
+        .into_iter()
+        .filter(|claim| !present.contains(claim))
+        .collect();
+    let mut predicates: BTreeMap<String, usize> = BTreeMap::new();
+    for [_, predicate, _] in &derived {
+        if let RdfNode::Iri(iri) = predicate {
+            *predicates.entry(iri.clone()).or_default() += 1;
+        }
+    }
+    Impact {
+        derived: derived.len(),
+        predicates,
+    }
+}
+
+/// How close a rule came to deriving a claim.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct NearMiss {
+    /// index of the rule in the ruleset
+    pub rule: usize,
+    /// index of the conclusion that would be the claim
+    pub conclusion: usize,
+    /// indices of the conditions nothing matched alongside the others
+    pub unmatched: Vec<usize>,
+    /// the unmatched conditions with the variables the rest of the match binds replaced, what
+    /// the data would have to hold for the rule to fire
+    pub wanted: Vec<Claim<Entity<String, RdfNode>>>,
+    /// what the claim and the matched conditions bind each variable to
+    pub bindings: BTreeMap<String, RdfNode>,
+}
+
+/// Why `rules` don't derive `expected` from `triples`. For each conclusion that could be
+/// `expected`, the match of as many of its rule's conditions as `triples` hold together, closest
+/// first. Conditions only a chain of rules would satisfy are reported as unmatched unless
+/// `triples` already includes what the rules derive.
+pub fn why_not<'a>(
+    rules: &'a [RuleParts],
+    triples: &'a [Claim<RdfNode>],
+    expected: &'a Claim<RdfNode>,
+) -> Vec<NearMiss> {
+    let index = Index::new(triples);
+    let mut ret = Vec::new();
+    for (i, rule) in rules.iter().enumerate() {
+        for (j, conclusion) in rule.then.iter().enumerate() {
+            let bindings = match extend(conclusion, expected, &Bindings::new()) {
+                Some(bindings) => bindings,
+                None => continue,
+            };
+            let mut best = None;
+            closest(
+                &rule.if_all,
+                0,
+                &index,
+                bindings,
+                &mut Vec::new(),
+                &mut best,
+            );
+            let (unmatched, bindings) = best.expect("leaving out every condition always matches");
+            ret.push(NearMiss {
+                rule: i,
+                conclusion: j,
+                wanted: unmatched
+                    .iter()
+                    .map(|&k| substitute(&rule.if_all[k], &bindings))
+                    .collect(),
+                unmatched,
+                bindings: bindings
+                    .iter()
+                    .map(|(name, node)| (name.to_string(), (*node).clone()))
+                    .collect(),
+            });
+        }
+    }
+    ret.sort_by_key(|miss| miss.unmatched.len());
+    ret
+}
+
+/// Like `search` from condition `next` on, but a condition may also be left out. Sets `best` to
+/// the first match leaving out the fewest conditions, and their indices.
+fn closest<'a>(
+    conditions: &'a [Claim<Entity<String, RdfNode>>],
+    next: usize,
+    index: &Index<'a>,
+    bindings: Bindings<'a>,
+    left_out: &mut Vec<usize>,
+    best: &mut Option<(Vec<usize>, Bindings<'a>)>,
+) {
+    if matches!(best, Some((fewest, _)) if left_out.len() >= fewest.len()) {
+        return;
+    }
+    let condition = match conditions.get(next) {
+        Some(condition) => condition,
+        None => {
+            *best = Some((left_out.clone(), bindings));
+            return;
+        }
+    };
+    for triple in index.candidates(condition, &bindings) {
+        if let Some(extended) = extend(condition, triple, &bindings) {
+            closest(conditions, next + 1, index, extended, left_out, best);
+        }
+    }
+    left_out.push(next);
+    closest(conditions, next + 1, index, bindings, left_out, best);
+    left_out.pop();
+}
+
+/// `claim` with the variables `bindings` binds replaced
+fn substitute(
+    claim: &Claim<Entity<String, RdfNode>>,
+    bindings: &Bindings,
+) -> Claim<Entity<String, RdfNode>> {
+    let ent = |ent: &Entity<String, RdfNode>| match ent {
+        Entity::Unbound(name) => bindings
+            .get(name.as_str())
+            .map_or_else(|| ent.clone(), |node| Entity::Bound((*node).clone())),
+        Entity::Bound(_) => ent.clone(),
+    };
+    let [s, p, o] = claim;
+    [ent(s), ent(p), ent(o)]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{RDF_LANG_STRING, XSD_STRING};
+
+    #[test]
+    fn missing_claims() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |suffix: &str| RdfNode::Iri(format!("http://example.com/{}", suffix));
+        let rules = [RuleParts {
+            if_all: vec![[unbd("a"), Entity::Bound(iri("parent")), unbd("b")]],
+            then: vec![[unbd("b"), Entity::Bound(iri("child")), unbd("a")]],
+        }];
+        let triples = [
+            [iri("alice"), iri("parent"), iri("bob")],
+            [iri("bob"), iri("child"), iri("alice")],
+            [iri("alice"), iri("parent"), iri("carol")],
+            [iri("dave"), iri("knows"), iri("alice")],
+        ];
+        assert_eq!(matches(&rules[0].if_all, &triples).len(), 2);
+        assert_eq!(
+            missing(&rules, &triples),
+            vec![Missing {
+                rule: 0,
+                claim: [iri("carol"), iri("child"), iri("alice")],
+                bindings: [
+                    ("a".to_string(), iri("alice")),
+                    ("b".to_string(), iri("carol")),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+            }]
+        );
+        assert_eq!(missing(&rules, &triples[..2]), vec![]);
+
+        let rify_rules = [rules[0].to_rule().unwrap()];
+        let child = "http://example.com/child".to_string();
+        assert_eq!(
+            impact(&rify_rules, &triples),
+            Impact {
+                derived: 1,
+                predicates: [(child, 1)].iter().cloned().collect(),
+            }
+        );
+        assert_eq!(impact(&rify_rules, &triples[..2]), Impact::default());
+    }
+
+    #[test]
+    fn implicit_datatypes() {
+        let literal = |datatype: Option<&str>| RdfNode::Literal {
+            value: "a".to_string(),
+            datatype: datatype.map(str::to_string),
+            language: None,
+        };
+        let iri = RdfNode::Iri("http://example.com/p".to_string());
+        let conditions = [[
+            Entity::Unbound("s".to_string()),
+            Entity::Bound(iri.clone()),
+            Entity::Bound(literal(None)),
+        ]];
+        let triples = [[iri.clone(), iri.clone(), literal(Some(XSD_STRING))]];
+        assert!(literal(None).same_term(&literal(Some(XSD_STRING))));
+        assert!(!literal(None).same_term(&literal(Some(RDF_LANG_STRING))));
+        assert_eq!(matches(&conditions, &triples).len(), 1);
+    }
+
+    #[test]
+    fn omitted_datatypes() {
+        let query = "CONSTRUCT { ?a <http://example.com/q> \"b\" . }
+                     WHERE { ?a <http://example.com/p> \"a\" . }";
+        let (mut rule, _) = crate::sparql2rify(query.parse().unwrap()).unwrap();
+        let omit = DatatypePolicy {
+            omit_string: true,
+            omit_lang_string: true,
+        };
+        assert_eq!(omit.rule(&mut rule), 2);
+        let iri = |suffix: &str| RdfNode::Iri(format!("http://example.com/{}", suffix));
+        let literal = |value: &str| RdfNode::Literal {
+            value: value.to_string(),
+            datatype: Some(XSD_STRING.to_string()),
+            language: None,
+        };
+        let triples = [[iri("s"), iri("p"), literal("a")]];
+        let mut rules = vec![rule];
+        explicit_datatypes(&mut rules);
+        let missing = missing(&rules, &triples);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].claim, [iri("s"), iri("q"), literal("b")]);
+
+        // what the data already holds isn't missing
+        let triples = [triples[0].clone(), missing[0].claim.clone()];
+        assert_eq!(super::missing(&rules, &triples), vec![]);
+        let rify_rules = [rules[0].to_rule().unwrap()];
+        assert_eq!(impact(&rify_rules, &triples), Impact::default());
+    }
+
+    #[test]
+    fn near_misses() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |suffix: &str| RdfNode::Iri(format!("http://example.com/{}", suffix));
+        let bound = |suffix: &str| Entity::Bound(iri(suffix));
+        let rules = [
+            RuleParts {
+                if_all: vec![
+                    [unbd("a"), bound("parent"), unbd("b")],
+                    [unbd("b"), bound("type"), bound("Person")],
+                    [unbd("a"), bound("type"), bound("Person")],
+                ],
+                then: vec![[unbd("b"), bound("child"), unbd("a")]],
+            },
+            RuleParts {
+                if_all: vec![[unbd("a"), bound("knows"), unbd("b")]],
+                then: vec![[unbd("a"), bound("friend"), unbd("b")]],
+            },
+        ];
+        let triples = [
+            [iri("alice"), iri("parent"), iri("bob")],
+            [iri("alice"), iri("type"), iri("Person")],
+        ];
+        let expected = [iri("bob"), iri("child"), iri("alice")];
+        let misses = why_not(&rules, &triples, &expected);
+        assert_eq!(misses.len(), 1);
+        assert_eq!((misses[0].rule, misses[0].conclusion), (0, 0));
+        assert_eq!(misses[0].unmatched, [1]);
+        assert_eq!(
+            misses[0].wanted,
+            [[bound("bob"), bound("type"), bound("Person")]]
+        );
+        assert!(why_not(&rules, &triples, &triples[0]).is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+//! Renaming IRIs across a ruleset, for migrating rules from one version of an ontology to the
+//! next without editing each of them by hand.
+
+use crate::types::{RdfNode, RuleParts};
+use rify::Entity;
+use std::collections::BTreeMap;
+
+/// Replace each IRI that is a key of `map` with the IRI it maps to, wherever it appears in
+/// `rules`, including as the datatype of a literal. Returns how many terms each key replaced,
+/// zero for those that appear nowhere.
+pub fn rename_iris(
+    rules: &mut [RuleParts],
+    map: &BTreeMap<String, String>,
+) -> BTreeMap<String, usize> {
+    let mut replaced: BTreeMap<String, usize> = map.keys().map(|old| (old.clone(), 0)).collect();
+    let mut rename = |iri: &mut String| {
+        if let Some(new) = map.get(iri) {
+            *replaced.get_mut(iri).expect("every key is counted") += 1;
+            *iri = new.clone();
+        }
+    };
+    for rule in rules {
+        for ent in rule.if_all.iter_mut().chain(&mut rule.then).flatten() {
+            match ent {
+                Entity::Bound(RdfNode::Iri(iri))
+                | Entity::Bound(RdfNode::Literal {
+                    datatype: Some(iri),
+                    ..
+                }) => rename(iri),
+                _ => {}
+            }
+        }
+    }
+    replaced
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renames_everywhere() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| Entity::Bound(RdfNode::Iri(iri.to_string()));
+        let literal = |datatype: &str| {
+            Entity::Bound(RdfNode::Literal {
+                value: "1".to_string(),
+                datatype: Some(datatype.to_string()),
+                language: None,
+            })
+        };
+        let mut rules = vec![
+            RuleParts {
+                if_all: vec![[unbd("a"), iri("http://old.example/p"), unbd("b")]],
+                then: vec![[unbd("b"), iri("http://old.example/p"), unbd("a")]],
+            },
+            RuleParts {
+                if_all: vec![[
+                    unbd("a"),
+                    iri("http://example.com/q"),
+                    literal("http://old.example/n"),
+                ]],
+                then: vec![[
+                    unbd("a"),
+                    iri("http://example.com/r"),
+                    iri("http://old.example/o"),
+                ]],
+            },
+        ];
+        let map: BTreeMap<String, String> = [
+            ("http://old.example/p", "http://new.example/p"),
+            ("http://old.example/n", "http://new.example/n"),
+            ("http://old.example/gone", "http://new.example/gone"),
+        ]
+        .iter()
+        .map(|(old, new)| (old.to_string(), new.to_string()))
+        .collect();
+        let replaced = rename_iris(&mut rules, &map);
+        assert_eq!(replaced["http://old.example/p"], 2);
+        assert_eq!(replaced["http://old.example/n"], 1);
+        assert_eq!(replaced["http://old.example/gone"], 0);
+        assert_eq!(rules[0].if_all[0][1], iri("http://new.example/p"));
+        assert_eq!(rules[1].if_all[0][2], literal("http://new.example/n"));
+        assert_eq!(rules[1].then[0][2], iri("http://old.example/o"));
+    }
+}
@@ -0,0 +1,278 @@
+//! Rulepacks, a compact binary encoding of a ruleset for wallets and on-chain storage where JSON
+//! costs too much. A rulepack holds each distinct string once in a table and refers to strings by
+//! their index, using LEB128 varints for every number:
+//!
+//! ```text
+//! "RPK" version:u8
+//! string count, then for each string its length in bytes and its UTF-8 bytes
+//! rule count, then for each rule
+//!     if_all length, then length, then 3 terms for each claim of if_all followed by then
+//! ```
+//!
+//! A term is one varint, the index of its string shifted left by two, with its kind in the low
+//! bits: a variable, an IRI, a blank node or a literal. A literal is followed by two more, its
+//! datatype and its language, each 0 for none or the index of the string plus one. Strings are
+//! numbered in the order they first appear so the same ruleset always packs to the same bytes.
+
+use crate::types::{RdfNode, RuleParts};
+use displaydoc::Display;
+use rify::{Claim, Entity};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+const MAGIC: &[u8] = b"RPK";
+const VERSION: u8 = 1;
+
+const VARIABLE: u64 = 0;
+const IRI: u64 = 1;
+const BLANK: u64 = 2;
+const LITERAL: u64 = 3;
+
+/// Why bytes could not be read as a rulepack.
+#[derive(Debug, PartialEq, Display)]
+pub enum Error {
+    /// Not a rulepack.
+    Magic,
+    /// Rulepack version {version} is not supported, this build reads version 1.
+    Version { version: u8 },
+    /// The rulepack ends early.
+    Truncated,
+    /// A number in the rulepack is too large.
+    Overflow,
+    /// String {index} of the rulepack is not valid UTF-8.
+    Utf8 { index: usize },
+    /// The rulepack refers to string {index} but only has {count}.
+    Index { index: u64, count: usize },
+    /// The rulepack has {count} byte(s) after its last rule.
+    Trailing { count: usize },
+}
+
+impl std::error::Error for Error {}
+
+/// `rules` as a rulepack
+pub fn write(rules: &[&RuleParts]) -> Vec<u8> {
+    let mut strings = Strings::default();
+    let mut body = Vec::new();
+    varint(&mut body, rules.len() as u64);
+    for rule in rules {
+        varint(&mut body, rule.if_all.len() as u64);
+        varint(&mut body, rule.then.len() as u64);
+        for ent in rule.if_all.iter().chain(&rule.then).flatten() {
+            term(&mut body, &mut strings, ent);
+        }
+    }
+    let mut ret = MAGIC.to_vec();
+    ret.push(VERSION);
+    varint(&mut ret, strings.order.len() as u64);
+    for s in &strings.order {
+        varint(&mut ret, s.len() as u64);
+        ret.extend_from_slice(s.as_bytes());
+    }
+    ret.extend(body);
+    ret
+}
+
+/// The rules in a rulepack. They are not checked, a rulepack is as trustworthy as its source.
+pub fn read(bytes: &[u8]) -> Result<Vec<RuleParts>, Error> {
+    let rest = bytes.strip_prefix(MAGIC).ok_or(Error::Magic)?;
+    let (&version, rest) = rest.split_first().ok_or(Error::Magic)?;
+    if version != VERSION {
+        return Err(Error::Version { version });
+    }
+    let mut reader = Reader { rest };
+    let count = reader.len()?;
+    let mut strings = Vec::new();
+    for index in 0..count {
+        let len = reader.len()?;
+        let bytes = reader.take(len)?;
+        let s = std::str::from_utf8(bytes).map_err(|_| Error::Utf8 { index })?;
+        strings.push(s);
+    }
+    let mut rules = Vec::new();
+    for _ in 0..reader.len()? {
+        let (if_all, then) = (reader.len()?, reader.len()?);
+        let mut claims: Vec<Claim<_>> = Vec::new();
+        for _ in 0..if_all + then {
+            let claim = [
+                reader.term(&strings)?,
+                reader.term(&strings)?,
+                reader.term(&strings)?,
+            ];
+            claims.push(claim);
+        }
+        let then = claims.split_off(if_all);
+        rules.push(RuleParts {
+            if_all: claims,
+            then,
+        });
+    }
+    if !reader.rest.is_empty() {
+        let count = reader.rest.len();
+        return Err(Error::Trailing { count });
+    }
+    Ok(rules)
+}
+
+/// the string table, built as terms are written
+#[derive(Default)]
+struct Strings<'a> {
+    order: Vec<&'a str>,
+    index: HashMap<&'a str, u64>,
+}
+
+impl<'a> Strings<'a> {
+    fn index(&mut self, s: &'a str) -> u64 {
+        let next = self.order.len() as u64;
+        let order = &mut self.order;
+        *self.index.entry(s).or_insert_with(|| {
+            order.push(s);
+            next
+        })
+    }
+}
+
+fn term<'a>(out: &mut Vec<u8>, strings: &mut Strings<'a>, ent: &'a Entity<String, RdfNode>) {
+    let (kind, s) = match ent {
+        Entity::Unbound(name) => (VARIABLE, name),
+        Entity::Bound(RdfNode::Iri(iri)) => (IRI, iri),
+        Entity::Bound(RdfNode::Blank(name)) => (BLANK, name),
+        Entity::Bound(RdfNode::Literal { value, .. }) => (LITERAL, value),
+    };
+    varint(out, strings.index(s) << 2 | kind);
+    if let Entity::Bound(RdfNode::Literal {
+        datatype, language, ..
+    }) = ent
+    {
+        for optional in [datatype, language] {
+            let index = optional.as_deref().map_or(0, |s| strings.index(s) + 1);
+            varint(out, index);
+        }
+    }
+}
+
+fn varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn string(strings: &[&str], index: u64) -> Result<String, Error> {
+    let count = strings.len();
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| strings.get(i))
+        .map(|s| s.to_string())
+        .ok_or(Error::Index { index, count })
+}
+
+struct Reader<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut ret = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.rest.split_first().ok_or(Error::Truncated)?;
+            self.rest = rest;
+            let bits = u64::from(byte & 0x7f);
+            if bits << shift >> shift != bits {
+                return Err(Error::Overflow);
+            }
+            ret |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(ret);
+            }
+        }
+        Err(Error::Overflow)
+    }
+
+    fn term(&mut self, strings: &[&str]) -> Result<Entity<String, RdfNode>, Error> {
+        let packed = self.varint()?;
+        let s = string(strings, packed >> 2)?;
+        Ok(match packed & 3 {
+            VARIABLE => Entity::Unbound(s),
+            IRI => Entity::Bound(RdfNode::Iri(s)),
+            BLANK => Entity::Bound(RdfNode::Blank(s)),
+            _ => Entity::Bound(RdfNode::Literal {
+                value: s,
+                datatype: self.optional(strings)?,
+                language: self.optional(strings)?,
+            }),
+        })
+    }
+
+    /// a string that may be missing, numbered from 1
+    fn optional(&mut self, strings: &[&str]) -> Result<Option<String>, Error> {
+        match self.varint()? {
+            0 => Ok(None),
+            index => string(strings, index - 1).map(Some),
+        }
+    }
+
+    /// a count or length, which can't be more than the bytes left since each item takes one
+    fn len(&mut self) -> Result<usize, Error> {
+        let n = self.varint()?;
+        match usize::try_from(n) {
+            Ok(n) if n <= self.rest.len() => Ok(n),
+            _ => Err(Error::Truncated),
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.rest.len() {
+            return Err(Error::Truncated);
+        }
+        let (taken, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Ok(taken)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| Entity::Bound(RdfNode::Iri(iri.to_string()));
+        let rules = [
+            RuleParts {
+                if_all: vec![[unbd("a"), iri("http://example.com/parent"), unbd("b")]],
+                then: vec![[unbd("b"), iri("http://example.com/child"), unbd("a")]],
+            },
+            RuleParts {
+                if_all: vec![[
+                    unbd("a"),
+                    iri("http://example.com/name"),
+                    Entity::Bound(RdfNode::Literal {
+                        value: "Alice".to_string(),
+                        datatype: Some(
+                            "http://www.w3.org/1999/02/22-rdf-syntax-ns#langString".to_string(),
+                        ),
+                        language: Some("en".to_string()),
+                    }),
+                ]],
+                then: vec![[
+                    Entity::Bound(RdfNode::Blank("b".to_string())),
+                    iri("http://example.com/parent"),
+                    unbd("a"),
+                ]],
+            },
+        ];
+        let refs: Vec<&RuleParts> = rules.iter().collect();
+        let packed = write(&refs);
+        assert_eq!(read(&packed).unwrap(), rules);
+        assert!(packed.len() < serde_json::to_vec(&rules).unwrap().len() / 2);
+
+        assert_eq!(read(&packed[..packed.len() - 1]), Err(Error::Truncated));
+        assert_eq!(read(b"RPK\x02"), Err(Error::Version { version: 2 }));
+        assert_eq!(read(b"{}"), Err(Error::Magic));
+        let mut trailing = packed;
+        trailing.push(0);
+        assert_eq!(read(&trailing), Err(Error::Trailing { count: 1 }));
+    }
+}
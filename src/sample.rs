@@ -0,0 +1,194 @@
+//! Sample data for testing rules. A rule's conditions with each variable replaced by an IRI of
+//! its own is the smallest document the rule fires on, written as Turtle so it can be loaded
+//! straight into a reasoner or endpoint. Near misses, the same document with one claim
+//! removed or one constant changed, are what it must not fire on.
+
+use crate::reason;
+use crate::types::{RdfNode, RuleParts};
+use rify::{Claim, Entity};
+use serde::Serialize;
+
+/// where the IRIs standing in for variables are minted, `?a` becomes `<urn:sample:a>`
+pub const SAMPLE_PREFIX: &str = "urn:sample:";
+
+/// A Turtle document holding `rule`'s conditions, each variable replaced by a fresh IRI. The
+/// rule fires on it, deriving its conclusions for the same replacement.
+pub fn positive(rule: &RuleParts) -> String {
+    turtle(&instantiate(&rule.if_all))
+}
+
+/// A document a rule must not fire on.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct NearMiss {
+    /// how it differs from the positive sample
+    pub description: String,
+    pub turtle: String,
+}
+
+/// Documents that differ from `positive(rule)` by one condition being left out or one constant
+/// in a condition being replaced by the IRI `<urn:sample:perturbed>`. A near miss the rule would
+/// still fire on, because its conditions repeat each other, is not listed.
+pub fn negative(rule: &RuleParts) -> Vec<NearMiss> {
+    let mut ret = Vec::new();
+    let mut near_miss = |description: String, conditions: &[Claim<Entity<String, RdfNode>>]| {
+        let triples = instantiate(conditions);
+        if reason::matches(&rule.if_all, &triples).is_empty() {
+            let turtle = turtle(&triples);
+            ret.push(NearMiss {
+                description,
+                turtle,
+            });
+        }
+    };
+    for i in 0..rule.if_all.len() {
+        let mut conditions = rule.if_all.clone();
+        conditions.remove(i);
+        near_miss(format!("Removed condition {}.", i + 1), &conditions);
+    }
+    let perturbed = Entity::Bound(RdfNode::Iri(format!("{}perturbed", SAMPLE_PREFIX)));
+    for (i, claim) in rule.if_all.iter().enumerate() {
+        for (j, position) in ["subject", "predicate", "object"].iter().enumerate() {
+            if let Entity::Bound(RdfNode::Iri(_)) | Entity::Bound(RdfNode::Literal { .. }) =
+                claim[j]
+            {
+                let mut conditions = rule.if_all.clone();
+                conditions[i][j] = perturbed.clone();
+                let description = format!("Changed the {} of condition {}.", position, i + 1);
+                near_miss(description, &conditions);
+            }
+        }
+    }
+    ret
+}
+
+/// the claims with variables replaced by sample IRIs, duplicates removed
+fn instantiate(claims: &[Claim<Entity<String, RdfNode>>]) -> Vec<Claim<RdfNode>> {
+    let node = |ent: &Entity<String, RdfNode>| match ent {
+        Entity::Unbound(name) => RdfNode::Iri(format!("{}{}", SAMPLE_PREFIX, name)),
+        Entity::Bound(node) => node.clone(),
+    };
+    let mut ret: Vec<Claim<RdfNode>> = Vec::new();
+    for [s, p, o] in claims {
+        let triple = [node(s), node(p), node(o)];
+        if !ret.contains(&triple) {
+            ret.push(triple);
+        }
+    }
+    ret
+}
+
+/// triples written one per line, which is Turtle as well as N-Triples
+fn turtle(triples: &[Claim<RdfNode>]) -> String {
+    let mut ret = String::new();
+    for [s, p, o] in triples {
+        ret.push_str(&format!("{} {} {} .\n", term(s), term(p), term(o)));
+    }
+    ret
+}
+
+fn term(node: &RdfNode) -> String {
+    match node {
+        RdfNode::Blank(name) => format!("_:{}", name),
+        RdfNode::Iri(iri) => format!("<{}>", iri),
+        RdfNode::Literal {
+            value,
+            datatype,
+            language,
+        } => {
+            let mut escaped = String::with_capacity(value.len() + 2);
+            escaped.push('"');
+            for c in value.chars() {
+                match c {
+                    '"' => escaped.push_str("\\\""),
+                    '\\' => escaped.push_str("\\\\"),
+                    '\n' => escaped.push_str("\\n"),
+                    '\r' => escaped.push_str("\\r"),
+                    c => escaped.push(c),
+                }
+            }
+            escaped.push('"');
+            match (datatype, language) {
+                (_, Some(language)) => format!("{}@{}", escaped, language),
+                (Some(datatype), None) => format!("{}^^<{}>", escaped, datatype),
+                (None, None) => escaped,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fires_rule() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri =
+            |suffix: &str| Entity::Bound(RdfNode::Iri(format!("http://example.com/{}", suffix)));
+        let literal = |value: &str, language: Option<&str>| {
+            Entity::Bound(RdfNode::Literal {
+                value: value.to_string(),
+                datatype: None,
+                language: language.map(str::to_string),
+            })
+        };
+        let rule = RuleParts {
+            if_all: vec![
+                [unbd("a"), iri("parent"), unbd("b")],
+                [unbd("b"), iri("name"), literal("say \"hi\"", None)],
+                [unbd("b"), iri("name"), literal("salut", Some("fr"))],
+                [unbd("a"), iri("parent"), unbd("b")],
+            ],
+            then: vec![[unbd("b"), iri("child"), unbd("a")]],
+        };
+        assert_eq!(
+            positive(&rule),
+            "<urn:sample:a> <http://example.com/parent> <urn:sample:b> .\n\
+             <urn:sample:b> <http://example.com/name> \"say \\\"hi\\\"\" .\n\
+             <urn:sample:b> <http://example.com/name> \"salut\"@fr .\n"
+        );
+    }
+
+    #[test]
+    fn near_misses() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri =
+            |suffix: &str| Entity::Bound(RdfNode::Iri(format!("http://example.com/{}", suffix)));
+        let rule = RuleParts {
+            if_all: vec![
+                [unbd("a"), iri("parent"), unbd("b")],
+                [unbd("b"), iri("age"), iri("Adult")],
+            ],
+            then: vec![[unbd("b"), iri("adultChild"), unbd("a")]],
+        };
+        let near_misses = negative(&rule);
+        assert_eq!(
+            near_misses
+                .iter()
+                .map(|near_miss| &near_miss.description[..])
+                .collect::<Vec<_>>(),
+            [
+                "Removed condition 1.",
+                "Removed condition 2.",
+                "Changed the predicate of condition 1.",
+                "Changed the predicate of condition 2.",
+                "Changed the object of condition 2.",
+            ]
+        );
+        assert_eq!(
+            near_misses[4].turtle,
+            "<urn:sample:a> <http://example.com/parent> <urn:sample:b> .\n\
+             <urn:sample:b> <http://example.com/age> <urn:sample:perturbed> .\n"
+        );
+
+        // without either condition, or with either changed, the other still matches
+        let repeated = RuleParts {
+            if_all: vec![
+                [unbd("a"), iri("parent"), unbd("b")],
+                [unbd("a"), iri("parent"), unbd("c")],
+            ],
+            then: vec![],
+        };
+        assert_eq!(negative(&repeated), vec![]);
+    }
+}
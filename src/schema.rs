@@ -0,0 +1,232 @@
+//! Rules generated from verifiable credential schemas. Every application built on credentials
+//! needs the same rule for each property of a credential's subject: if a credential says its
+//! subject has the property, the subject has the application's own version of it. These rules
+//! are written out from the credential's JSON schema and JSON-LD context instead of by hand.
+
+use crate::types::{RdfNode, RuleParts};
+use displaydoc::Display;
+use rify::Entity;
+use serde_json::{Map, Value};
+use std::error::Error;
+
+pub const CREDENTIAL_SUBJECT: &str = "https://www.w3.org/2018/credentials#credentialSubject";
+pub const ISSUER: &str = "https://www.w3.org/2018/credentials#issuer";
+pub const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+
+/// What the generated rules derive.
+#[derive(Debug, Default, PartialEq)]
+pub struct Lift {
+    /// the domain predicate for a property is this followed by the property's name
+    pub vocab: String,
+    /// also derive that the subject has this type
+    pub subject_type: Option<String>,
+    /// only lift properties from credentials issued by this issuer
+    pub issuer: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Display)]
+pub enum SchemaError {
+    /// The schema has no credentialSubject properties.
+    NoSubjectProperties,
+    /// The JSON-LD context must be an object, or an array of objects and remote context URLs.
+    UnsupportedContext,
+    /// The property "{term}" is not defined in the JSON-LD context.
+    UndefinedTerm { term: String },
+}
+
+impl Error for SchemaError {}
+
+/// One rule for each property of the credential subject in `schema`, ordered by name. A
+/// property "name" that the context expands to `p` becomes
+///
+/// ```text
+/// IF ?credential cred:credentialSubject ?subject . ?subject p ?value .
+/// THEN ?subject <vocab + "name"> ?value .
+/// ```
+pub fn rules(schema: &Value, context: &Value, lift: &Lift) -> Result<Vec<RuleParts>, SchemaError> {
+    let properties = schema
+        .pointer("/properties/credentialSubject/properties")
+        .and_then(Value::as_object)
+        .ok_or(SchemaError::NoSubjectProperties)?;
+    let context = Context::new(context)?;
+    properties
+        .keys()
+        .filter(|term| *term != "id")
+        .map(|term| {
+            let predicate = context.expand(term)?;
+            Ok(rule(&predicate, &format!("{}{}", lift.vocab, term), lift))
+        })
+        .collect()
+}
+
+fn rule(predicate: &str, domain: &str, lift: &Lift) -> RuleParts {
+    let unbd = |name: &str| Entity::Unbound(name.to_string());
+    let iri = |iri: &str| Entity::Bound(RdfNode::Iri(iri.to_string()));
+    let mut if_all = vec![
+        [unbd("credential"), iri(CREDENTIAL_SUBJECT), unbd("subject")],
+        [unbd("subject"), iri(predicate), unbd("value")],
+    ];
+    if let Some(issuer) = &lift.issuer {
+        if_all.push([unbd("credential"), iri(ISSUER), iri(issuer)]);
+    }
+    let mut then = vec![[unbd("subject"), iri(domain), unbd("value")]];
+    if let Some(subject_type) = &lift.subject_type {
+        then.push([unbd("subject"), iri(RDF_TYPE), iri(subject_type)]);
+    }
+    RuleParts { if_all, then }
+}
+
+/// The term definitions of a local JSON-LD context. Remote contexts aren't fetched, terms they
+/// define have to be repeated locally.
+struct Context<'a> {
+    /// later definitions override earlier ones
+    definitions: Vec<&'a Map<String, Value>>,
+}
+
+impl<'a> Context<'a> {
+    /// `context` may be a context document, i.e. `{"@context": ..}`, or the context itself
+    fn new(context: &'a Value) -> Result<Self, SchemaError> {
+        let context = context.get("@context").unwrap_or(context);
+        let definitions = match context {
+            Value::Object(map) => vec![map],
+            Value::Array(contexts) => contexts
+                .iter()
+                .filter(|context| !context.is_string())
+                .map(Value::as_object)
+                .collect::<Option<_>>()
+                .ok_or(SchemaError::UnsupportedContext)?,
+            _ => return Err(SchemaError::UnsupportedContext),
+        };
+        Ok(Self { definitions })
+    }
+
+    fn lookup(&self, key: &str) -> Option<&'a Value> {
+        self.definitions.iter().rev().find_map(|map| map.get(key))
+    }
+
+    /// the IRI `term` stands for, by its own definition, as a compact IRI, or under `@vocab`
+    fn expand(&self, term: &str) -> Result<String, SchemaError> {
+        let undefined = || SchemaError::UndefinedTerm {
+            term: term.to_string(),
+        };
+        let id = match self.lookup(term) {
+            Some(Value::String(id)) => Some(id.as_str()),
+            Some(Value::Object(definition)) => definition.get("@id").and_then(Value::as_str),
+            Some(_) => return Err(undefined()),
+            None => None,
+        };
+        match id {
+            Some(id) => Ok(self.expand_iri(id)),
+            None if term.contains(':') => Ok(self.expand_iri(term)),
+            None => match self.lookup("@vocab") {
+                Some(Value::String(vocab)) => Ok(format!("{}{}", vocab, term)),
+                _ => Err(undefined()),
+            },
+        }
+    }
+
+    /// expand a compact IRI such as `schema:name` if its prefix is defined
+    fn expand_iri(&self, iri: &str) -> String {
+        if let Some((prefix, suffix)) = iri.split_once(':') {
+            if !suffix.starts_with("//") {
+                if let Some(Value::String(namespace)) = self.lookup(prefix) {
+                    return format!("{}{}", namespace, suffix);
+                }
+            }
+        }
+        iri.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn expand() {
+        let context = json!({"@context": [
+            "https://www.w3.org/2018/credentials/v1",
+            {"@vocab": "https://example.com/vocab#", "schema": "https://schema.org/"},
+            {
+                "name": "schema:name",
+                "email": {"@id": "https://schema.org/email", "@type": "@id"},
+            },
+        ]});
+        let context = Context::new(&context).unwrap();
+        assert_eq!(context.expand("name").unwrap(), "https://schema.org/name");
+        assert_eq!(context.expand("email").unwrap(), "https://schema.org/email");
+        assert_eq!(
+            context.expand("schema:age").unwrap(),
+            "https://schema.org/age"
+        );
+        assert_eq!(
+            context.expand("degree").unwrap(),
+            "https://example.com/vocab#degree"
+        );
+        assert_eq!(
+            Context::new(&json!([1])).err(),
+            Some(SchemaError::UnsupportedContext)
+        );
+    }
+
+    #[test]
+    fn rules_from_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "credentialSubject": {
+                    "type": "object",
+                    "properties": {
+                        "id": {"type": "string"},
+                        "name": {"type": "string"},
+                    },
+                },
+            },
+        });
+        let context = json!({"name": "https://schema.org/name"});
+        let lift = Lift {
+            vocab: "https://example.com/app#".to_string(),
+            subject_type: Some("https://example.com/app#Person".to_string()),
+            issuer: Some("did:example:issuer".to_string()),
+        };
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| Entity::Bound(RdfNode::Iri(iri.to_string()));
+        assert_eq!(
+            rules(&schema, &context, &lift).unwrap(),
+            [RuleParts {
+                if_all: vec![
+                    [unbd("credential"), iri(CREDENTIAL_SUBJECT), unbd("subject")],
+                    [
+                        unbd("subject"),
+                        iri("https://schema.org/name"),
+                        unbd("value")
+                    ],
+                    [unbd("credential"), iri(ISSUER), iri("did:example:issuer")],
+                ],
+                then: vec![
+                    [
+                        unbd("subject"),
+                        iri("https://example.com/app#name"),
+                        unbd("value")
+                    ],
+                    [
+                        unbd("subject"),
+                        iri(RDF_TYPE),
+                        iri("https://example.com/app#Person")
+                    ],
+                ],
+            }]
+        );
+        assert_eq!(
+            rules(&json!({}), &context, &lift),
+            Err(SchemaError::NoSubjectProperties)
+        );
+        assert_eq!(
+            rules(&schema, &json!({}), &lift),
+            Err(SchemaError::UndefinedTerm {
+                term: "name".to_string()
+            })
+        );
+    }
+}
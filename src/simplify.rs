@@ -0,0 +1,172 @@
+//! Removing conditions a rule doesn't need. Property paths and blank nodes bring variables of
+//! their own into a rule, and a condition that only differs from another in such variables
+//! matches whenever the other does, so it costs the reasoner work without changing what the
+//! rule derives.
+
+use crate::extended::Pattern;
+use crate::types::{RdfNode, RuleParts};
+use crate::util::as_unbound;
+use crate::Graph;
+use rify::{Claim, Entity};
+use std::collections::BTreeMap;
+
+/// Remove the conditions in `rule.if_all` that are implied by the rest, `graphs` being the
+/// graph of each condition and `if_none` the negative conditions of a rule in the extended
+/// format, see `extended`. Returns how many were removed. A condition is implied by another if
+/// replacing the variables that appear nowhere else in the rule, its negative conditions
+/// included, turns it into the other, e.g. `?a ex:p ?c` is implied by `?a ex:p ?b` when `?c` is
+/// used only once. Duplicate conditions are the simplest case. The rule matches exactly the
+/// same data afterwards.
+pub fn simplify(rule: &mut RuleParts, graphs: &mut Vec<Graph>, if_none: &[Pattern]) -> usize {
+    let mut removed = 0;
+    while let Some(i) = redundant(rule, graphs, if_none) {
+        rule.if_all.remove(i);
+        graphs.remove(i);
+        removed += 1;
+    }
+    removed
+}
+
+/// the last condition implied by another, if any
+fn redundant(rule: &RuleParts, graphs: &[Graph], if_none: &[Pattern]) -> Option<usize> {
+    let quads: Vec<_> = rule
+        .if_all
+        .iter()
+        .zip(graphs)
+        .map(|(claim, graph)| quad(claim, graph))
+        .collect();
+    let mut uses: BTreeMap<&str, usize> = BTreeMap::new();
+    let all = quads
+        .iter()
+        .flatten()
+        .flatten()
+        .copied()
+        .chain(rule.then.iter().flatten())
+        .chain(if_none.iter().flatten().flatten());
+    for name in all.filter_map(as_unbound) {
+        *uses.entry(name).or_default() += 1;
+    }
+
+    (0..quads.len()).rev().find(|&i| {
+        let mut local: BTreeMap<&str, usize> = BTreeMap::new();
+        for name in quads[i].iter().flatten().filter_map(|ent| as_unbound(ent)) {
+            *local.entry(name).or_default() += 1;
+        }
+        local.retain(|name, count| uses[name] == *count);
+        (0..quads.len()).any(|j| j != i && maps_onto(&quads[i], &quads[j], &local))
+    })
+}
+
+type Quad<'a> = [Option<&'a Entity<String, RdfNode>>; 4];
+
+/// a claim and its graph, `None` standing for the default graph
+fn quad<'a>(claim: &'a Claim<Entity<String, RdfNode>>, graph: &'a Graph) -> Quad<'a> {
+    let [s, p, o] = claim;
+    [Some(s), Some(p), Some(o), graph.as_ref()]
+}
+
+/// Whether some assignment to the `local` variables of `from` makes it equal to `to`. Only
+/// named graphs can be assigned to a graph variable, as in SPARQL.
+fn maps_onto<T>(from: &Quad, to: &Quad, local: &BTreeMap<&str, T>) -> bool {
+    let mut assigned = BTreeMap::new();
+    from.iter().zip(to).all(|(from, to)| {
+        match (*from)
+            .and_then(as_unbound)
+            .filter(|name| local.contains_key(name))
+        {
+            Some(name) if to.is_some() => *assigned.entry(name).or_insert(to) == to,
+            _ => from == to,
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{convert_with, ConvertOptions, ConvertedRule};
+
+    #[test]
+    fn redundant_conditions() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri =
+            |suffix: &str| Entity::Bound(RdfNode::Iri(format!("http://example.com/{}", suffix)));
+        let mut rule = RuleParts {
+            if_all: vec![
+                [unbd("a"), iri("p"), unbd("b")],
+                [unbd("a"), iri("p"), unbd("c")],
+                [unbd("a"), iri("p"), unbd("b")],
+                [unbd("a"), iri("q"), unbd("d")],
+                [unbd("d"), iri("q"), unbd("d")],
+            ],
+            then: vec![[unbd("a"), iri("r"), unbd("b")]],
+        };
+        let mut graphs = vec![None; 5];
+        assert_eq!(simplify(&mut rule, &mut graphs, &[]), 2);
+        assert_eq!(
+            rule.if_all,
+            vec![
+                [unbd("a"), iri("p"), unbd("b")],
+                [unbd("a"), iri("q"), unbd("d")],
+                [unbd("d"), iri("q"), unbd("d")],
+            ]
+        );
+        assert_eq!(graphs.len(), 3);
+
+        // ?d is used twice, so neither condition implies the other
+        let mut rule = RuleParts {
+            if_all: vec![
+                [unbd("a"), iri("q"), unbd("d")],
+                [unbd("d"), iri("q"), unbd("a")],
+            ],
+            then: vec![],
+        };
+        assert_eq!(simplify(&mut rule, &mut vec![None; 2], &[]), 0);
+    }
+
+    #[test]
+    fn graphs() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri =
+            |suffix: &str| Entity::Bound(RdfNode::Iri(format!("http://example.com/{}", suffix)));
+        let claim = [unbd("a"), iri("p"), unbd("b")];
+        let mut rule = RuleParts {
+            if_all: vec![claim.clone(), claim.clone()],
+            then: vec![],
+        };
+
+        // the same claim in different graphs
+        let mut graphs = vec![None, Some(iri("g"))];
+        assert_eq!(simplify(&mut rule, &mut graphs, &[]), 0);
+
+        // a graph variable used once matches any named graph, but not the default graph
+        let mut graphs = vec![None, Some(unbd("g"))];
+        assert_eq!(simplify(&mut rule, &mut graphs, &[]), 0);
+        let mut graphs = vec![Some(iri("g")), Some(unbd("g"))];
+        assert_eq!(simplify(&mut rule, &mut graphs, &[]), 1);
+        assert_eq!(graphs, vec![Some(iri("g"))]);
+    }
+
+    #[test]
+    fn negative_conditions() {
+        let query = "PREFIX ex: <http://example.com/>
+                     CONSTRUCT { ?a ex:ok ex:yes . }
+                     WHERE { ?a ex:p ?b . ?a ex:p ?c . FILTER NOT EXISTS { ?c ex:banned true } }";
+        let options = ConvertOptions {
+            negation: true,
+            ..ConvertOptions::default()
+        };
+        let ConvertedRule {
+            mut rule,
+            mut graphs,
+            if_none,
+            ..
+        } = convert_with(query.parse().unwrap(), &options)
+            .unwrap()
+            .remove(0);
+        // ?c is also used by the negative condition, so only `?a ex:p ?b` is implied
+        let kept = rule.if_all[1].clone();
+        assert_eq!(simplify(&mut rule, &mut graphs, &if_none), 1);
+        assert_eq!(rule.if_all, [kept]);
+        assert_eq!(graphs.len(), 1);
+    }
+}
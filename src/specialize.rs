@@ -0,0 +1,251 @@
+//! Instantiating generic rules. A rule template written once with variables for, say, the
+//! issuer or vocabulary it applies to is specialized for each tenant by binding those variables
+//! to terms. Generalizing goes the other way, turning a family of near identical rules into
+//! one template.
+
+use crate::types::{RdfNode, RuleParts};
+use crate::util::as_unbound;
+use displaydoc::Display;
+use rify::Entity;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+#[derive(Debug, PartialEq, Display)]
+pub enum SpecializeError {
+    /// The variable "{name}" is bound but does not appear in the rule.
+    UnknownVariable { name: String },
+    /// The variable "{name}" is bound to a blank node, bind it to an IRI or literal instead.
+    BlankNode { name: String },
+    /// The variable "{name}" is used as a {position} so it can't be bound to a literal.
+    Literal {
+        name: String,
+        position: &'static str,
+    },
+    /// The IRI <{iri}> is to be replaced but does not appear in the rule.
+    UnknownIri { iri: String },
+    /// The variable "{name}" is already used, each IRI needs a variable of its own.
+    VariableInUse { name: String },
+}
+
+impl Error for SpecializeError {}
+
+/// Replace the variables named in `bindings` with the terms they are bound to. Variable names
+/// may be written with or without a leading `?`. The result is not checked as a rule, see
+/// `RuleParts::to_rule` and `RuleParts::check_iris`.
+pub fn specialize(
+    rule: &RuleParts,
+    bindings: &BTreeMap<String, RdfNode>,
+) -> Result<RuleParts, SpecializeError> {
+    let mut bound = BTreeMap::new();
+    for (name, node) in bindings {
+        let name = name.strip_prefix('?').unwrap_or(name);
+        if let RdfNode::Blank(_) = node {
+            let name = name.to_string();
+            return Err(SpecializeError::BlankNode { name });
+        }
+        bound.insert(name, node);
+    }
+    let used = |name: &str| {
+        let var = Entity::Unbound(name.to_string());
+        rule.if_all
+            .iter()
+            .chain(&rule.then)
+            .flatten()
+            .any(|ent| *ent == var)
+    };
+    if let Some(name) = bound.keys().find(|name| !used(name)) {
+        let name = name.to_string();
+        return Err(SpecializeError::UnknownVariable { name });
+    }
+
+    let mut ret = rule.clone();
+    for claim in ret.if_all.iter_mut().chain(&mut ret.then) {
+        for (position, ent) in ["subject", "predicate", "object"].iter().zip(claim) {
+            let name = match ent {
+                Entity::Unbound(name) => name.as_str(),
+                Entity::Bound(_) => continue,
+            };
+            let node = match bound.get(name) {
+                Some(node) => *node,
+                None => continue,
+            };
+            if matches!(node, RdfNode::Literal { .. }) && *position != "object" {
+                let name = name.to_string();
+                return Err(SpecializeError::Literal { name, position });
+            }
+            *ent = Entity::Bound(node.clone());
+        }
+    }
+    Ok(ret)
+}
+
+/// Replace the IRIs named in `variables` with the variables they map to, the inverse of
+/// `specialize`. Variable names may be written with or without a leading `?`. The result is not
+/// checked as a rule, an IRI that only appears in `then` leaves its variable unbound.
+pub fn generalize(
+    rule: &RuleParts,
+    variables: &BTreeMap<String, String>,
+) -> Result<RuleParts, SpecializeError> {
+    let mut names: BTreeSet<&str> = rule
+        .if_all
+        .iter()
+        .chain(&rule.then)
+        .flatten()
+        .filter_map(as_unbound)
+        .collect();
+    let mut replace = BTreeMap::new();
+    for (iri, name) in variables {
+        let name = name.strip_prefix('?').unwrap_or(name);
+        if !names.insert(name) {
+            let name = name.to_string();
+            return Err(SpecializeError::VariableInUse { name });
+        }
+        replace.insert(iri.as_str(), name);
+    }
+    let used = |iri: &str| {
+        let iri = Entity::Bound(RdfNode::Iri(iri.to_string()));
+        rule.if_all
+            .iter()
+            .chain(&rule.then)
+            .flatten()
+            .any(|ent| *ent == iri)
+    };
+    if let Some(iri) = replace.keys().find(|iri| !used(iri)) {
+        let iri = iri.to_string();
+        return Err(SpecializeError::UnknownIri { iri });
+    }
+
+    let mut ret = rule.clone();
+    for ent in ret.if_all.iter_mut().chain(&mut ret.then).flatten() {
+        let name = match ent {
+            Entity::Bound(RdfNode::Iri(iri)) => replace.get(iri.as_str()),
+            _ => None,
+        };
+        if let Some(name) = name {
+            *ent = Entity::Unbound(name.to_string());
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bind_variables() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| RdfNode::Iri(iri.to_string());
+        let rule = RuleParts {
+            if_all: vec![
+                [
+                    unbd("cred"),
+                    Entity::Bound(iri("http://example.com/issuer")),
+                    unbd("issuer"),
+                ],
+                [
+                    unbd("cred"),
+                    Entity::Bound(iri("http://example.com/claims")),
+                    unbd("s"),
+                ],
+            ],
+            then: vec![[unbd("s"), unbd("p"), unbd("cred")]],
+        };
+        let bindings: BTreeMap<String, RdfNode> = [
+            ("?issuer".to_string(), iri("did:example:tenant")),
+            ("p".to_string(), iri("http://example.com/vouched")),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let specialized = specialize(&rule, &bindings).unwrap();
+        assert_eq!(
+            specialized.if_all[0][2],
+            Entity::Bound(iri("did:example:tenant"))
+        );
+        assert_eq!(
+            specialized.then[0][1],
+            Entity::Bound(iri("http://example.com/vouched"))
+        );
+        assert_eq!(specialized.if_all[1], rule.if_all[1]);
+
+        let bind = |name: &str, node: RdfNode| {
+            let bindings = [(name.to_string(), node)].iter().cloned().collect();
+            specialize(&rule, &bindings).unwrap_err()
+        };
+        assert_eq!(
+            bind("q", iri("http://example.com")),
+            SpecializeError::UnknownVariable {
+                name: "q".to_string()
+            }
+        );
+        assert_eq!(
+            bind("s", RdfNode::Blank("b".to_string())),
+            SpecializeError::BlankNode {
+                name: "s".to_string()
+            }
+        );
+        let literal = RdfNode::Literal {
+            value: "1".to_string(),
+            datatype: None,
+            language: None,
+        };
+        assert_eq!(
+            bind("p", literal),
+            SpecializeError::Literal {
+                name: "p".to_string(),
+                position: "predicate"
+            }
+        );
+    }
+
+    #[test]
+    fn generalize_iris() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| Entity::Bound(RdfNode::Iri(iri.to_string()));
+        let rule = RuleParts {
+            if_all: vec![[
+                unbd("cred"),
+                iri("http://example.com/issuer"),
+                iri("did:example:a"),
+            ]],
+            then: vec![[
+                unbd("cred"),
+                iri("http://example.com/trusted"),
+                iri("did:example:a"),
+            ]],
+        };
+        let variables = |iri: &str, name: &str| -> BTreeMap<String, String> {
+            [(iri.to_string(), name.to_string())]
+                .iter()
+                .cloned()
+                .collect()
+        };
+        let template = generalize(&rule, &variables("did:example:a", "?issuer")).unwrap();
+        assert_eq!(template.if_all[0][2], unbd("issuer"));
+        assert_eq!(template.then[0][2], unbd("issuer"));
+
+        // and back again
+        let bindings = [(
+            "issuer".to_string(),
+            RdfNode::Iri("did:example:a".to_string()),
+        )]
+        .iter()
+        .cloned()
+        .collect();
+        assert_eq!(specialize(&template, &bindings).unwrap(), rule);
+
+        assert_eq!(
+            generalize(&rule, &variables("did:example:b", "issuer")).unwrap_err(),
+            SpecializeError::UnknownIri {
+                iri: "did:example:b".to_string()
+            }
+        );
+        assert_eq!(
+            generalize(&rule, &variables("did:example:a", "cred")).unwrap_err(),
+            SpecializeError::VariableInUse {
+                name: "cred".to_string()
+            }
+        );
+    }
+}
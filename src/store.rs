@@ -0,0 +1,511 @@
+//! Materializing inferences in an oxigraph store on disk. The claims a ruleset could match are
+//! read straight out of the store, rify derives what follows from them, and the result is
+//! written back into a graph of the caller's choosing, so there is no export and import of the
+//! data in between. With more than one job the rules are applied in rounds instead, each round
+//! sharing the rules out between threads, until a round derives nothing new. Stores holding
+//! more claims than fit in memory can be materialized in rounds on the store itself, its
+//! indexes standing in for the in memory ones. Rounds can also be kept apart, each in a graph of
+//! its own, to see how conclusions build up.
+
+use crate::reason::{self, Bindings};
+use crate::types::{InvalidRule, RdfNode, RuleParts};
+use displaydoc::Display;
+use oxigraph::model::{
+    BlankNode, GraphName, Literal, NamedNode, NamedNodeRef, NamedOrBlankNode, Quad, Term,
+};
+use oxigraph::SledStore;
+use rify::{Claim, Entity};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::io;
+
+#[derive(Debug, Display)]
+pub enum StoreError {
+    /// Could not read or write the store: {source}
+    Io { source: io::Error },
+    /// {source}
+    InvalidRule { source: InvalidRule },
+}
+
+impl Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(source: io::Error) -> Self {
+        Self::Io { source }
+    }
+}
+
+impl From<InvalidRule> for StoreError {
+    fn from(source: InvalidRule) -> Self {
+        Self::InvalidRule { source }
+    }
+}
+
+/// How `materialize` runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Options {
+    /// how many threads to share inference out between
+    pub jobs: usize,
+    /// The most claims to read into memory. Past it inference runs on the store, slower but
+    /// without holding the claims.
+    pub memory_budget: Option<usize>,
+    /// Run inference in rounds and insert what round `n` derives into the graph named by this
+    /// IRI followed by `n`, counting from 1, rather than into `graph`.
+    pub round_graphs: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            jobs: 1,
+            memory_budget: None,
+            round_graphs: None,
+        }
+    }
+}
+
+/// What `materialize` did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Materialized {
+    /// claims read into memory
+    pub read: usize,
+    /// whether the claims exceeded the memory budget, so inference ran on the store
+    pub in_store: bool,
+    /// claims derived and written to the store
+    pub inferred: usize,
+    /// claims derived that aren't valid RDF, e.g. with a literal as subject, so were not written
+    pub skipped: usize,
+    /// claims written in each round, if inference ran in rounds
+    pub rounds: Vec<usize>,
+}
+
+/// Apply `rules` to the claims in `store` and insert what they derive into `graph`. Claims are
+/// read from every graph. When every condition of every rule names its predicate only claims
+/// with those predicates are read, otherwise the whole store is.
+pub fn materialize(
+    store: &SledStore,
+    rules: &[RuleParts],
+    graph: &GraphName,
+    options: &Options,
+) -> Result<Materialized, StoreError> {
+    let rules = &applied(rules);
+    let rify_rules = rules
+        .iter()
+        .map(RuleParts::to_rule)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let quads: Box<dyn Iterator<Item = Result<Quad, io::Error>>> = match predicates(rules) {
+        Some(predicates) => Box::new(predicates.into_iter().flat_map(move |predicate| {
+            let predicate = NamedNodeRef::new_unchecked(predicate);
+            store.quads_for_pattern(None, Some(predicate), None, None)
+        })),
+        None => Box::new(store.quads_for_pattern(None, None, None, None)),
+    };
+    let mut premises = Vec::new();
+    for quad in quads {
+        if Some(premises.len()) == options.memory_budget {
+            let mut ret = infer_in_store(store, rules, graph, options)?;
+            ret.read = premises.len();
+            return Ok(ret);
+        }
+        premises.push(to_claim(quad?));
+    }
+
+    let mut ret = Materialized {
+        read: premises.len(),
+        ..Materialized::default()
+    };
+    let in_rounds = options.jobs > 1 || options.round_graphs.is_some();
+    let rounds = if in_rounds {
+        infer_rounds(premises, rules, options.jobs)
+    } else {
+        vec![rify::infer(&premises, &rify_rules)]
+    };
+    for (round, inferred) in rounds.into_iter().enumerate() {
+        let graph = round_graph(graph, options, round);
+        let mut written = 0;
+        for claim in inferred {
+            match to_quad(claim, &graph) {
+                Some(quad) => {
+                    store.insert(&quad)?;
+                    written += 1;
+                }
+                None => ret.skipped += 1,
+            }
+        }
+        ret.inferred += written;
+        if in_rounds {
+            ret.rounds.push(written);
+        }
+    }
+    Ok(ret)
+}
+
+/// the graph what `round`, counting from 0, derives is inserted into
+fn round_graph(graph: &GraphName, options: &Options, round: usize) -> GraphName {
+    match &options.round_graphs {
+        Some(prefix) => NamedNode::new_unchecked(format!("{}{}", prefix, round + 1)).into(),
+        None => graph.clone(),
+    }
+}
+
+/// The claims `rules` derive from `premises` in each round, not including the premises. Each
+/// round every rule is matched against everything known so far, rule `i` on thread `i % jobs`,
+/// and the new claims are merged in before the next round.
+/// `rules` as they are applied, with the datatypes they leave implicit spelled out as they are in
+/// the claims read from the store, see `reason::explicit_datatypes`
+fn applied(rules: &[RuleParts]) -> Vec<RuleParts> {
+    let mut rules = rules.to_vec();
+    reason::explicit_datatypes(&mut rules);
+    rules
+}
+
+fn infer_rounds(
+    premises: Vec<Claim<RdfNode>>,
+    rules: &[RuleParts],
+    jobs: usize,
+) -> Vec<Vec<Claim<RdfNode>>> {
+    let mut known: BTreeSet<Claim<RdfNode>> = premises.iter().cloned().collect();
+    let mut claims = premises;
+    let mut rounds = Vec::new();
+    loop {
+        let derived: Vec<Vec<Claim<RdfNode>>> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..jobs)
+                .map(|job| {
+                    let (claims, known) = (&claims, &known);
+                    scope.spawn(move || {
+                        let mut derived = Vec::new();
+                        for rule in rules.iter().skip(job).step_by(jobs) {
+                            for bindings in reason::matches(&rule.if_all, claims) {
+                                derived.extend(
+                                    rule.then
+                                        .iter()
+                                        .filter_map(|claim| reason::instantiate(claim, &bindings))
+                                        .filter(|claim| !known.contains(claim)),
+                                );
+                            }
+                        }
+                        derived
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().expect("inference threads don't panic"))
+                .collect()
+        });
+        let before = claims.len();
+        for claim in derived.into_iter().flatten() {
+            if known.insert(claim.clone()) {
+                claims.push(claim);
+            }
+        }
+        if claims.len() == before {
+            return rounds;
+        }
+        rounds.push(claims[before..].to_vec());
+    }
+}
+
+/// `materialize` without holding the store's claims in memory. Each round every rule is
+/// matched by looking its conditions up in the store one at a time, and what it derives is
+/// inserted straight away, until a round derives nothing new. Rules later in a round see what
+/// earlier ones derived, so rounds are counted differently than in memory.
+fn infer_in_store(
+    store: &SledStore,
+    rules: &[RuleParts],
+    graph: &GraphName,
+    options: &Options,
+) -> Result<Materialized, StoreError> {
+    let mut ret = Materialized {
+        in_store: true,
+        ..Materialized::default()
+    };
+    let mut skipped = BTreeSet::new();
+    loop {
+        let before = ret.inferred;
+        let graph = &round_graph(graph, options, ret.rounds.len());
+        for rule in rules {
+            let mut found = Vec::new();
+            store_matches(store, &rule.if_all, &BTreeMap::new(), &mut found)?;
+            for bindings in found {
+                let bindings: Bindings = bindings.iter().map(|(k, v)| (k.as_str(), v)).collect();
+                for claim in rule.then.iter() {
+                    let claim = reason::instantiate(claim, &bindings).expect("rules are valid");
+                    let quad = match to_quad(claim.clone(), graph) {
+                        Some(quad) => quad,
+                        None => {
+                            skipped.insert(claim);
+                            continue;
+                        }
+                    };
+                    let known = store
+                        .quads_for_pattern(
+                            Some(quad.subject.as_ref()),
+                            Some(quad.predicate.as_ref()),
+                            Some(quad.object.as_ref()),
+                            None,
+                        )
+                        .next()
+                        .transpose()?
+                        .is_some();
+                    if !known {
+                        store.insert(&quad)?;
+                        ret.inferred += 1;
+                    }
+                }
+            }
+        }
+        if ret.inferred == before {
+            ret.skipped = skipped.len();
+            return Ok(ret);
+        }
+        ret.rounds.push(ret.inferred - before);
+    }
+}
+
+/// every match of `conditions` in `store` that extends `bindings`
+fn store_matches(
+    store: &SledStore,
+    conditions: &[Claim<Entity<String, RdfNode>>],
+    bindings: &BTreeMap<String, RdfNode>,
+    found: &mut Vec<BTreeMap<String, RdfNode>>,
+) -> Result<(), io::Error> {
+    let (first, rest) = match conditions.split_first() {
+        Some(split) => split,
+        None => {
+            found.push(bindings.clone());
+            return Ok(());
+        }
+    };
+    let bound: Vec<Option<&RdfNode>> = first
+        .iter()
+        .map(|ent| match ent {
+            Entity::Unbound(name) => bindings.get(name),
+            Entity::Bound(node) => Some(node),
+        })
+        .collect();
+    // a term that can't appear in its position matches nothing
+    let subject = match bound[0].map(to_subject) {
+        Some(None) => return Ok(()),
+        subject => subject.flatten(),
+    };
+    let predicate = match bound[1] {
+        Some(RdfNode::Iri(iri)) => Some(NamedNode::new_unchecked(iri.as_str())),
+        Some(_) => return Ok(()),
+        None => None,
+    };
+    let object = bound[2].map(|node| to_object(node.clone()));
+    let quads = store.quads_for_pattern(
+        subject.as_ref().map(NamedOrBlankNode::as_ref),
+        predicate.as_ref().map(NamedNode::as_ref),
+        object.as_ref().map(Term::as_ref),
+        None,
+    );
+    for quad in quads {
+        let claim = to_claim(quad?);
+        let mut extended = bindings.clone();
+        let matched = first.iter().zip(claim.iter()).all(|(ent, node)| match ent {
+            Entity::Unbound(name) => {
+                extended.entry(name.clone()).or_insert_with(|| node.clone()) == node
+            }
+            Entity::Bound(bound) => bound.same_term(node),
+        });
+        if matched {
+            store_matches(store, rest, &extended, found)?;
+        }
+    }
+    Ok(())
+}
+
+/// the predicates rules can match, None if some condition matches any predicate
+fn predicates(rules: &[RuleParts]) -> Option<BTreeSet<&str>> {
+    rules
+        .iter()
+        .flat_map(|rule| &rule.if_all)
+        .map(|[_, p, _]| match p {
+            Entity::Bound(RdfNode::Iri(iri)) => Some(iri.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn to_claim(quad: Quad) -> Claim<RdfNode> {
+    let Quad {
+        subject,
+        predicate,
+        object,
+        ..
+    } = quad;
+    [
+        RdfNode::from(&Term::from(subject)),
+        RdfNode::Iri(predicate.as_str().to_string()),
+        RdfNode::from(&object),
+    ]
+}
+
+/// a derived claim as a quad in `graph`, None if it isn't valid RDF
+fn to_quad(claim: Claim<RdfNode>, graph: &GraphName) -> Option<Quad> {
+    let [s, p, o] = claim;
+    let subject = to_subject(&s)?;
+    let predicate = match p {
+        RdfNode::Iri(iri) => NamedNode::new_unchecked(iri),
+        _ => return None,
+    };
+    Some(Quad::new(subject, predicate, to_object(o), graph.clone()))
+}
+
+fn to_subject(node: &RdfNode) -> Option<NamedOrBlankNode> {
+    match node {
+        RdfNode::Iri(iri) => Some(NamedNode::new_unchecked(iri.as_str()).into()),
+        RdfNode::Blank(id) => Some(BlankNode::new_unchecked(id.as_str()).into()),
+        RdfNode::Literal { .. } => None,
+    }
+}
+
+fn to_object(node: RdfNode) -> Term {
+    match node {
+        RdfNode::Iri(iri) => Term::from(NamedNode::new_unchecked(iri)),
+        RdfNode::Blank(id) => Term::from(BlankNode::new_unchecked(id)),
+        RdfNode::Literal {
+            value,
+            language: Some(language),
+            ..
+        } => Term::from(Literal::new_language_tagged_literal_unchecked(
+            value, language,
+        )),
+        RdfNode::Literal {
+            value,
+            datatype: Some(datatype),
+            language: None,
+        } => Term::from(Literal::new_typed_literal(
+            value,
+            NamedNode::new_unchecked(datatype),
+        )),
+        RdfNode::Literal {
+            value,
+            datatype: None,
+            language: None,
+        } => Term::from(Literal::new_simple_literal(value)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn claims_and_quads() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| RdfNode::Iri(iri.to_string());
+        let mut rules = vec![RuleParts {
+            if_all: vec![[
+                unbd("a"),
+                Entity::Bound(iri("http://example.com/p")),
+                unbd("b"),
+            ]],
+            then: vec![[
+                unbd("b"),
+                Entity::Bound(iri("http://example.com/q")),
+                unbd("a"),
+            ]],
+        }];
+        assert_eq!(
+            predicates(&rules),
+            Some(vec!["http://example.com/p"].into_iter().collect())
+        );
+        rules[0].if_all.push([unbd("b"), unbd("p"), unbd("c")]);
+        assert_eq!(predicates(&rules), None);
+
+        let graph = GraphName::from(NamedNode::new_unchecked("http://example.com/inferred"));
+        let literal = RdfNode::Literal {
+            value: "1".to_string(),
+            datatype: Some("http://www.w3.org/2001/XMLSchema#integer".to_string()),
+            language: None,
+        };
+        let claim = [
+            iri("http://example.com/s"),
+            iri("http://example.com/p"),
+            literal,
+        ];
+        let quad = to_quad(claim.clone(), &graph).unwrap();
+        assert_eq!(quad.graph_name, graph);
+        assert_eq!(to_claim(quad), claim);
+
+        let backwards = [claim[2].clone(), claim[1].clone(), claim[0].clone()];
+        assert_eq!(to_quad(backwards, &graph), None);
+
+        // a literal stored without its datatype is read back with it, and rules leaving it
+        // implicit still match it
+        let plain = RdfNode::Literal {
+            value: "a".to_string(),
+            datatype: None,
+            language: None,
+        };
+        let claim = [claim[0].clone(), claim[1].clone(), plain.clone()];
+        let stored = to_claim(to_quad(claim, &graph).unwrap());
+        assert_ne!(stored[2], plain);
+        assert!(stored[2].same_term(&plain));
+        let rules = [RuleParts {
+            if_all: vec![[
+                unbd("a"),
+                Entity::Bound(iri("http://example.com/p")),
+                Entity::Bound(plain.clone()),
+            ]],
+            then: vec![[
+                unbd("a"),
+                Entity::Bound(iri("http://example.com/q")),
+                Entity::Bound(plain),
+            ]],
+        }];
+        let derived = [
+            stored[0].clone(),
+            iri("http://example.com/q"),
+            stored[2].clone(),
+        ];
+        let premises = vec![stored];
+        let rify_rules = [applied(&rules)[0].to_rule().unwrap()];
+        assert_eq!(rify::infer(&premises, &rify_rules), vec![derived.clone()]);
+        assert_eq!(infer_rounds(premises, &applied(&rules), 2), [vec![derived]]);
+    }
+
+    #[test]
+    fn parallel_inference_chains_rules() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |suffix: &str| RdfNode::Iri(format!("http://example.com/{}", suffix));
+        let rules = [
+            RuleParts {
+                if_all: vec![[unbd("a"), Entity::Bound(iri("parent")), unbd("b")]],
+                then: vec![[unbd("a"), Entity::Bound(iri("ancestor")), unbd("b")]],
+            },
+            RuleParts {
+                if_all: vec![
+                    [unbd("a"), Entity::Bound(iri("ancestor")), unbd("b")],
+                    [unbd("b"), Entity::Bound(iri("ancestor")), unbd("c")],
+                ],
+                then: vec![[unbd("a"), Entity::Bound(iri("ancestor")), unbd("c")]],
+            },
+        ];
+        let premises = vec![
+            [iri("a"), iri("parent"), iri("b")],
+            [iri("b"), iri("parent"), iri("c")],
+            [iri("c"), iri("parent"), iri("d")],
+        ];
+        let rounds = infer_rounds(premises, &rules, 2);
+        let sizes: Vec<usize> = rounds.iter().map(Vec::len).collect();
+        assert_eq!(sizes, [3, 2, 1]);
+        let inferred: BTreeSet<_> = rounds.into_iter().flatten().collect();
+        let ancestor = |s: &str, o: &str| [iri(s), iri("ancestor"), iri(o)];
+        let expected: BTreeSet<_> = vec![
+            ancestor("a", "b"),
+            ancestor("b", "c"),
+            ancestor("c", "d"),
+            ancestor("a", "c"),
+            ancestor("b", "d"),
+            ancestor("a", "d"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(inferred, expected);
+    }
+}
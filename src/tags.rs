@@ -0,0 +1,110 @@
+//! Tags, so one library of rules can serve several deployments. A query tags the rules converted
+//! from it in a comment,
+//!
+//! ```text
+//! # @tags kyc, eu
+//! ```
+//!
+//! separated by commas or spaces, and rules carry their tags in a `tags` array. Deployments load
+//! the rules of the library they need with a `Selection`.
+
+use crate::output::Annotations;
+use crate::util::comment_annotations;
+use std::collections::BTreeSet;
+
+/// The tags declared in the comments of `text`, in the order they first appear.
+pub fn parse(text: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    for (_, name, value) in comment_annotations(text) {
+        if name == "tags" {
+            add(&mut ret, split(value));
+        }
+    }
+    ret
+}
+
+/// the tags in a list of them, separated by commas or spaces
+pub fn split(list: &str) -> impl Iterator<Item = &str> {
+    list.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|tag| !tag.is_empty())
+}
+
+/// Add the tags of `more` that `tags` doesn't have yet.
+pub fn add<'a>(tags: &mut Vec<String>, more: impl IntoIterator<Item = &'a str>) {
+    for tag in more {
+        if !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+}
+
+/// The tags of a rule written into a ruleset.
+pub fn of(rule: &Annotations) -> BTreeSet<&str> {
+    rule.get("tags")
+        .and_then(|tags| tags.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|tag| tag.as_str())
+        .collect()
+}
+
+/// Which rules of a ruleset to load, by their tags.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    /// if any, only rules with at least one of these
+    pub include: BTreeSet<String>,
+    /// no rules with any of these, even if included
+    pub exclude: BTreeSet<String>,
+}
+
+impl Selection {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// whether a rule with `tags` is selected, untagged rules only being selected when no tags
+    /// are included
+    pub fn selects(&self, tags: &BTreeSet<&str>) -> bool {
+        (self.include.is_empty() || tags.iter().any(|&tag| self.include.contains(tag)))
+            && !tags.iter().any(|&tag| self.exclude.contains(tag))
+    }
+
+    /// The rules of a ruleset that are selected, in order.
+    pub fn filter(&self, rules: Vec<Annotations>) -> Vec<Annotations> {
+        rules
+            .into_iter()
+            .filter(|rule| self.selects(&of(rule)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn select_by_tags() {
+        let query = "# @tags kyc, eu\n#@tags  eu,,us\n# @tagsx no\nCONSTRUCT {} WHERE {}";
+        assert_eq!(parse(query), ["kyc", "eu", "us"]);
+
+        let rule = |tags: &[&str]| {
+            let mut rule = Annotations::new();
+            rule.insert("tags".to_string(), tags.into());
+            rule
+        };
+        let rules = vec![
+            rule(&["kyc", "eu"]),
+            rule(&["kyc", "us"]),
+            Annotations::new(),
+        ];
+        let selection = |include: &[&str], exclude: &[&str]| Selection {
+            include: include.iter().map(|tag| tag.to_string()).collect(),
+            exclude: exclude.iter().map(|tag| tag.to_string()).collect(),
+        };
+        let kept = |selection: Selection| selection.filter(rules.clone()).len();
+        assert_eq!(kept(selection(&[], &[])), 3);
+        assert_eq!(kept(selection(&["eu", "us"], &[])), 2);
+        assert_eq!(kept(selection(&["kyc"], &["us"])), 1);
+        assert_eq!(kept(selection(&[], &["eu"])), 2);
+    }
+}
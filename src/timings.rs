@@ -0,0 +1,59 @@
+//! `--timings`, how long each stage of converting a query took, to tell whether the parser or the
+//! converter is what makes a large query file slow. Once every query is converted, a record is
+//! printed to stderr for each, in order, as a line of JSON:
+//!
+//! ```text
+//! {"query":1,"parse_ms":0.41,"validate_ms":0.12,"rewrite_ms":0.08,"serialize_ms":0.02}
+//! ```
+
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// The time spent on each stage of converting one query.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timings {
+    /// parsing the query, or the N3 or JSON rule
+    pub parse: Duration,
+    /// checking the query and converting it to rules
+    pub validate: Duration,
+    /// reporting lints and applying the rewrites options ask for
+    pub rewrite: Duration,
+    /// writing the rules of the query, on their own
+    pub serialize: Duration,
+}
+
+/// Run `f`, adding how long it took to `total`.
+pub fn timed<T>(total: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let ret = f();
+    *total += start.elapsed();
+    ret
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    query: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<&'a str>,
+    parse_ms: f64,
+    validate_ms: f64,
+    rewrite_ms: f64,
+    serialize_ms: f64,
+}
+
+/// Print the timings of the `query`th query, counted from 1, read from the file `source` if any.
+pub fn report(query: usize, source: Option<&str>, timings: &Timings) {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let record = Record {
+        query,
+        source,
+        parse_ms: ms(timings.parse),
+        validate_ms: ms(timings.validate),
+        rewrite_ms: ms(timings.rewrite),
+        serialize_ms: ms(timings.serialize),
+    };
+    eprintln!(
+        "{}",
+        serde_json::to_string(&record).expect("always serializes")
+    );
+}
@@ -0,0 +1,157 @@
+//! Rules with literals of common datatypes already parsed, for consumers that compare values
+//! rather than lexical forms.
+
+use crate::canon::{self, INTEGER_TYPES, XSD};
+use crate::types::{RdfNode, RuleParts};
+use rify::{Claim, Entity};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct TypedRule {
+    pub if_all: Vec<Claim<Entity<String, TypedNode>>>,
+    pub then: Vec<Claim<Entity<String, TypedNode>>>,
+}
+
+/// An `RdfNode` where literals with a well known datatype are replaced by their value. Literals
+/// that are out of range or not valid for their datatype are kept as `Literal`.
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+pub enum TypedNode {
+    Blank(String),
+    Iri(String),
+    /// xsd:integer or one of its subtypes, if it fits in an i64
+    Integer(i64),
+    /// xsd:boolean
+    Boolean(bool),
+    /// xsd:decimal in canonical form, e.g. `5.1`, arbitrary precision so kept as a string
+    Decimal(String),
+    /// xsd:double or xsd:float, other than NaN and the infinities
+    Double(f64),
+    /// xsd:dateTime in canonical form, shifted to UTC if it has a timezone. Two date times with
+    /// timezones compare in time order as strings, as long as their years have four digits.
+    DateTime(String),
+    Literal {
+        value: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        datatype: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+    },
+}
+
+impl From<&RuleParts> for TypedRule {
+    fn from(rule: &RuleParts) -> Self {
+        let claims = |claims: &[Claim<Entity<String, RdfNode>>]| -> Vec<_> {
+            claims
+                .iter()
+                .map(|[s, p, o]| [typed(s), typed(p), typed(o)])
+                .collect()
+        };
+        TypedRule {
+            if_all: claims(&rule.if_all),
+            then: claims(&rule.then),
+        }
+    }
+}
+
+fn typed(ent: &Entity<String, RdfNode>) -> Entity<String, TypedNode> {
+    match ent {
+        Entity::Unbound(name) => Entity::Unbound(name.clone()),
+        Entity::Bound(node) => Entity::Bound(node.into()),
+    }
+}
+
+impl From<&RdfNode> for TypedNode {
+    fn from(node: &RdfNode) -> Self {
+        let (value, datatype, language) = match node {
+            RdfNode::Blank(name) => return Self::Blank(name.clone()),
+            RdfNode::Iri(iri) => return Self::Iri(iri.clone()),
+            RdfNode::Literal {
+                value,
+                datatype,
+                language,
+            } => (value, datatype, language),
+        };
+        datatype
+            .as_deref()
+            .and_then(|d| d.strip_prefix(XSD))
+            .and_then(|local| parse(value, local))
+            .unwrap_or_else(|| Self::Literal {
+                value: value.clone(),
+                datatype: datatype.clone(),
+                language: language.clone(),
+            })
+    }
+}
+
+/// the value of a literal whose datatype is `xsd:{local}`
+fn parse(value: &str, local: &str) -> Option<TypedNode> {
+    match local {
+        "boolean" => match value.trim() {
+            "true" | "1" => Some(TypedNode::Boolean(true)),
+            "false" | "0" => Some(TypedNode::Boolean(false)),
+            _ => None,
+        },
+        "decimal" => canon::decimal(value).map(TypedNode::Decimal),
+        "double" | "float" => {
+            let canonical = canon::Canonicalize {
+                numbers: true,
+                ..Default::default()
+            };
+            // going through the canonical form rejects spellings rust accepts but xsd doesn't
+            let double: f64 = canonical
+                .literal(value, &format!("{}double", XSD))?
+                .parse()
+                .ok()?;
+            Some(TypedNode::Double(double)).filter(|_| double.is_finite())
+        }
+        "dateTime" | "dateTimeStamp" => canon::date_time(value, true).map(TypedNode::DateTime),
+        _ if INTEGER_TYPES.contains(&local) => {
+            canon::integer(value)?.parse().ok().map(TypedNode::Integer)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn typed_literals() {
+        let literal = |value: &str, local: &str| {
+            TypedNode::from(&RdfNode::Literal {
+                value: value.to_string(),
+                datatype: Some(format!("{}{}", XSD, local)),
+                language: None,
+            })
+        };
+        assert_eq!(literal("+05", "integer"), TypedNode::Integer(5));
+        assert_eq!(literal("-3", "short"), TypedNode::Integer(-3));
+        assert_eq!(literal("1", "boolean"), TypedNode::Boolean(true));
+        assert_eq!(
+            literal("5.10", "decimal"),
+            TypedNode::Decimal("5.1".to_string())
+        );
+        assert_eq!(literal("1.5e2", "double"), TypedNode::Double(150.0));
+        assert_eq!(
+            literal("2020-01-01T01:00:00+01:00", "dateTime"),
+            TypedNode::DateTime("2020-01-01T00:00:00Z".to_string())
+        );
+
+        for (value, local) in &[
+            ("99999999999999999999", "integer"),
+            ("yes", "boolean"),
+            ("INF", "double"),
+            ("inf", "double"),
+            ("5", "string"),
+        ] {
+            assert_eq!(
+                literal(value, local),
+                TypedNode::Literal {
+                    value: value.to_string(),
+                    datatype: Some(format!("{}{}", XSD, local)),
+                    language: None,
+                }
+            );
+        }
+    }
+}
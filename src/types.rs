@@ -1,4 +1,7 @@
+use crate::util::as_unbound;
 use displaydoc::Display;
+use rify::{Claim, Entity, Rule};
+use std::collections::BTreeSet;
 use std::error::Error;
 
 #[derive(Debug, PartialEq, Display)]
@@ -7,10 +10,8 @@ pub enum InvalidRule {
     MustBeConstruct,
     /// FROM statements are not allowed.
     IllegalFrom,
-    /// Base iri is not allowed.
+    /// The query has relative IRIs but no BASE to resolve them against, declare one or pass --base.
     IllegalBaseIri,
-    /// Only Basic Graph Patterns are allowed.
-    MustBeBasicGraphPattern,
     /// Path patterns are not allowed.
     IllegalPathPattern,
     #[doc = "A variable exists in the construct clause that does not exist in the WHERE clause. \
@@ -23,20 +24,188 @@ pub enum InvalidRule {
     #[doc = "A blank node called \"{name}\" was found in the output portion of the CONSTRUCT \
              clause. Blank nodes in the output of a rule are a footgun so they are not allowed."]
     BlankNodeImplied { name: String },
+    /// "{iri}" is not a valid absolute IRI.
+    InvalidIri { iri: String },
+    #[doc = "The function {function}() gives a different result each time it is called, but a \
+             rule must derive the same claims every time it matches."]
+    NondeterministicFunction { function: String },
+    #[doc = "The CONSTRUCT clause contains a collection, ( .. ). Each node of a collection is a \
+             blank node, which rify rules can't derive."]
+    CollectionImplied,
+    /// <{iri}> is not in any of the namespaces rules are allowed to use.
+    NamespaceNotAllowed { iri: String },
+    /// <{iri}> is in the namespace <{namespace}>, which rules may not {usage}.
+    NamespaceDenied {
+        iri: String,
+        namespace: String,
+        /// "use" or "derive claims with"
+        usage: &'static str,
+    },
+    /// The WHERE clause never matches, a FILTER in it is always false.
+    NeverMatches,
+    #[doc = "The value ?{name} is bound to depends on the claims the rule matches. Only a BIND \
+             whose value is known while converting, e.g. a constant, can be folded into the \
+             rule."]
+    UnfoldableBind { name: String },
+    #[doc = "FILTER NOT EXISTS and MINUS can't be converted to a rify rule, which only has \
+             conditions that must hold. The extended rule format, --extended, has conditions \
+             that must not."]
+    NegationNeedsExtended,
+    #[doc = "A claim the rule derives could match one of its own negative conditions, so \
+             whether it fires would depend on what it derives."]
+    NotStratified,
+    /// MINUS shares no variable with the pattern it is applied to, so it never removes a match.
+    MinusSharesNothing,
+    #[doc = "?{name} is local to a FILTER NOT EXISTS or MINUS, where it stands for any term, but \
+             the WHERE clause binds it outside of it too. Rename one of them."]
+    NegationScope { name: String },
+    #[doc = "A literal of {length} bytes is longer than the {limit} bytes --max-literal-length \
+             allows: \"{literal}\"."]
+    LiteralTooLong {
+        length: usize,
+        limit: usize,
+        /// the start of the literal, see `literals::abbreviate`
+        literal: String,
+    },
+    #[doc = "UNION can't be converted to a single rule, only expanded into a rule for each \
+             branch, which conversions with --quads or --extended don't do."]
+    UnsupportedUnion,
+    #[doc = "VALUES can't be converted to a single rule, only expanded into a rule for each \
+             row, which conversions with --quads or --extended don't do."]
+    UnsupportedValues,
+    #[doc = "OPTIONAL can't be converted to a single rule. Without a FILTER in it, \
+             --split-optional converts it to a rule with its triples and one without."]
+    UnsupportedOptional,
+    /// GRAPH can only be converted to a rule over quads, with --quads.
+    UnsupportedGraph,
+    #[doc = "The FILTER condition {expr} depends on the claims the rule matches, so it can't \
+             be converted. Only a condition whose value is known while converting, or an \
+             EXISTS, can be folded into the rule."]
+    UnsupportedFilter { expr: String },
+    #[doc = "EXISTS, NOT EXISTS and MINUS can only be converted when they hold nothing but \
+             triples, optionally grouped by braces."]
+    UnsupportedExistsPattern,
+    /// Subqueries can't be converted, a rule has a single WHERE clause.
+    UnsupportedSubquery,
+    /// SERVICE can't be converted, a rule only matches the claims of the reasoner applying it.
+    UnsupportedService,
+    #[doc = "{name} can't be converted, a rule fires for each match on its own and can't \
+             combine them."]
+    UnsupportedAggregate {
+        /// the aggregate function, or GROUP BY without one
+        name: String,
+    },
+    /// {modifier} can't be converted, a rule derives every claim it can, in no order.
+    UnsupportedModifier { modifier: &'static str },
 }
 
 impl Error for InvalidRule {}
 
 pub type Iri = String;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub const XSD_STRING: &str = "http://www.w3.org/2001/XMLSchema#string";
+pub const RDF_LANG_STRING: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#langString";
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum RdfNode {
     Blank(String),
     Iri(Iri),
     Literal {
         value: String,
-        datatype: Iri,
+        /// None if the datatype is left implicit, see `canon::DatatypePolicy`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        datatype: Option<Iri>,
         #[serde(skip_serializing_if = "Option::is_none")]
         language: Option<String>,
     },
 }
+
+impl RdfNode {
+    /// the datatype of a literal, including an implicit one
+    pub fn datatype(&self) -> Option<&str> {
+        match self {
+            RdfNode::Literal {
+                datatype: Some(datatype),
+                ..
+            } => Some(datatype),
+            RdfNode::Literal { language: None, .. } => Some(XSD_STRING),
+            RdfNode::Literal { .. } => Some(RDF_LANG_STRING),
+            _ => None,
+        }
+    }
+
+    /// Whether both are the same RDF term, a literal whose datatype is left implicit being the
+    /// same as one that spells it out. `==` tells them apart, so a rule written with
+    /// `--omit-datatype` is compared to data this way.
+    pub fn same_term(&self, other: &RdfNode) -> bool {
+        match (self, other) {
+            (
+                RdfNode::Literal {
+                    value, language, ..
+                },
+                RdfNode::Literal {
+                    value: other_value,
+                    language: other_language,
+                    ..
+                },
+            ) => {
+                value == other_value
+                    && language == other_language
+                    && self.datatype() == other.datatype()
+            }
+            _ => self == other,
+        }
+    }
+}
+
+/// The claims of a rule. This serializes exactly like a `rify::Rule` but, unlike a `rify::Rule`,
+/// lets the claims be inspected and rewritten after conversion.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct RuleParts {
+    pub if_all: Vec<Claim<Entity<String, RdfNode>>>,
+    pub then: Vec<Claim<Entity<String, RdfNode>>>,
+}
+
+impl RuleParts {
+    /// check that these claims make a valid rify rule
+    pub fn to_rule(&self) -> Result<Rule<String, RdfNode>, InvalidRule> {
+        Rule::create(self.if_all.clone(), self.then.clone()).map_err(Into::into)
+    }
+
+    /// `to_rule` without building the rule, so checking a rule with long literals doesn't copy
+    /// them: every variable the rule derives claims with must be bound by its conditions.
+    pub fn check(&self) -> Result<(), InvalidRule> {
+        let bound: BTreeSet<&str> = self
+            .if_all
+            .iter()
+            .flatten()
+            .filter_map(as_unbound)
+            .collect();
+        let mut then = self.then.iter().flatten().filter_map(as_unbound);
+        match then.find(|name| !bound.contains(name)) {
+            Some(name) => Err(InvalidRule::UnboundImplied {
+                name: name.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that every IRI, including literal datatypes, is absolute and valid according to
+    /// RFC 3987. Downstream RDF tooling rejects rules that fail this.
+    pub fn check_iris(&self) -> Result<(), InvalidRule> {
+        for ent in self.if_all.iter().chain(&self.then).flatten() {
+            let iri = match ent {
+                Entity::Bound(RdfNode::Iri(iri)) => iri,
+                Entity::Bound(RdfNode::Literal {
+                    datatype: Some(datatype),
+                    ..
+                }) => datatype,
+                _ => continue,
+            };
+            if oxiri::Iri::parse(iri.as_str()).is_err() {
+                return Err(InvalidRule::InvalidIri { iri: iri.clone() });
+            }
+        }
+        Ok(())
+    }
+}
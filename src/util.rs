@@ -1,6 +1,7 @@
 use crate::types::{InvalidRule, RdfNode};
+use crate::vars::Fresh;
 use rify::{Claim, Entity};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
 pub fn as_blank(ent: &Entity<String, RdfNode>) -> Option<&str> {
     match ent {
@@ -16,6 +17,59 @@ pub fn as_unbound(ent: &Entity<String, RdfNode>) -> Option<&str> {
     }
 }
 
+/// The `# @name value` annotations in the comments of a query, each with its line number. Only
+/// lines that are comments from their start are read.
+pub fn comment_annotations(text: &str) -> impl Iterator<Item = (usize, &str, &str)> {
+    text.lines().enumerate().filter_map(|(i, line)| {
+        let comment = line.trim_start().strip_prefix('#')?.trim_start();
+        let annotation = comment.strip_prefix('@')?;
+        let (name, value) = annotation
+            .split_once(char::is_whitespace)
+            .unwrap_or((annotation, ""));
+        Some((i + 1, name, value.trim()))
+    })
+}
+
+/// Blank nodes written as `[]`, and the nodes the parser adds for collections and property
+/// paths, get random names. Rename them `b0`, `b1`, ... in order of first appearance so
+/// converting the same query twice gives the same rule. Names the query already uses are skipped.
+pub fn relabel_anonymous(
+    if_all: &mut [Claim<Entity<String, RdfNode>>],
+    then: &mut [Claim<Entity<String, RdfNode>>],
+) {
+    let ents = if_all.iter().chain(&*then).flatten();
+    let in_use = ents.filter_map(|ent| match as_blank(ent) {
+        Some(name) if is_anonymous(name) => None,
+        Some(name) => Some(name),
+        None => as_unbound(ent),
+    });
+    let mut fresh = Fresh::default();
+    for name in in_use {
+        fresh.reserve(name);
+    }
+    let mut renamed = BTreeMap::new();
+    for ent in if_all.iter_mut().chain(then).flatten() {
+        if let Entity::Bound(RdfNode::Blank(name)) = ent {
+            if is_anonymous(name) {
+                let new = renamed
+                    .entry(std::mem::take(name))
+                    .or_insert_with(|| fresh.name("b"));
+                *name = new.clone();
+            }
+        }
+    }
+}
+
+/// Whether a blank node name looks like one the parser generated, the hex form of a random
+/// u128. One of those is shorter than 17 digits with probability 2^-64. A label written in the
+/// query that happens to look like this is renamed too, which is harmless as labels mean nothing
+/// outside the query.
+fn is_anonymous(name: &str) -> bool {
+    (17..=32).contains(&name.len())
+        && !name.starts_with('0')
+        && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
 /// convert blank nodes to unbound variables, in order to prevent naming collisions
 /// we first ensure no blank nodes have the same name as an unbound variable
 pub fn unbind_blanks(
@@ -33,8 +87,8 @@ pub fn unbind_blanks(
 
     // execute
     for ent in if_all.iter_mut().chain(then).flatten() {
-        if let Some(name) = as_blank(&*ent) {
-            *ent = Entity::Unbound(name.to_string());
+        if let Entity::Bound(RdfNode::Blank(name)) = ent {
+            *ent = Entity::Unbound(std::mem::take(name));
         }
     }
 
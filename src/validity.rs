@@ -0,0 +1,176 @@
+//! Validity windows, for rules that only apply for a time, as regulations do. A query declares
+//! its window in comments,
+//!
+//! ```text
+//! # @valid-from 2024-01-01
+//! # @valid-until 2025-06-30
+//! ```
+//!
+//! both days included, and each rule converted from it carries the window as `valid_from` and
+//! `valid_until`. Dates are compared as written, so they are full `YYYY-MM-DD` dates and nothing
+//! else.
+
+use crate::output::Annotations;
+use crate::util::comment_annotations;
+use displaydoc::Display;
+use std::error::Error;
+
+#[derive(Debug, PartialEq, Display)]
+pub enum ValidityError {
+    /// Line {line}: "{date}" is not a date, expected YYYY-MM-DD.
+    Date { line: usize, date: String },
+    /// Line {line}: @{name} is declared twice.
+    Repeated { line: usize, name: &'static str },
+    /// The rule would be valid until {until}, before it is valid from {from}.
+    Empty { from: String, until: String },
+}
+
+impl Error for ValidityError {}
+
+/// The days a rule applies, unbounded on either side when not given.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Validity {
+    pub from: Option<String>,
+    pub until: Option<String>,
+}
+
+impl Validity {
+    /// The window declared in the comments of `text`. Only lines that are comments from their
+    /// start are read, and any other `@` annotation is left alone.
+    pub fn parse(text: &str) -> Result<Self, ValidityError> {
+        let mut ret = Self::default();
+        for (line, name, date) in comment_annotations(text) {
+            let (name, field) = match name {
+                "valid-from" => ("valid-from", &mut ret.from),
+                "valid-until" => ("valid-until", &mut ret.until),
+                _ => continue,
+            };
+            if !is_date(date) {
+                let date = date.to_string();
+                return Err(ValidityError::Date { line, date });
+            }
+            if field.replace(date.to_string()).is_some() {
+                return Err(ValidityError::Repeated { line, name });
+            }
+        }
+        if let (Some(from), Some(until)) = (&ret.from, &ret.until) {
+            if until < from {
+                let (from, until) = (from.clone(), until.clone());
+                return Err(ValidityError::Empty { from, until });
+            }
+        }
+        Ok(ret)
+    }
+
+    /// The window as written into a ruleset, nothing when unbounded.
+    pub fn annotations(&self) -> Annotations {
+        let mut ret = Annotations::new();
+        for (key, date) in [("valid_from", &self.from), ("valid_until", &self.until)] {
+            if let Some(date) = date {
+                ret.insert(key.to_string(), date.clone().into());
+            }
+        }
+        ret
+    }
+
+    /// The window of a rule written into a ruleset.
+    pub fn of(rule: &Annotations) -> Self {
+        let date = |key: &str| rule.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        Self {
+            from: date("valid_from"),
+            until: date("valid_until"),
+        }
+    }
+
+    /// whether the window ended before `date`
+    pub fn expired(&self, date: &str) -> bool {
+        self.until.as_deref().is_some_and(|until| until < date)
+    }
+}
+
+/// whether `s` is a day of the Gregorian calendar written `YYYY-MM-DD`
+pub fn is_date(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    if bytes.len() != 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+        return false;
+    }
+    let number = |range: std::ops::Range<usize>| -> Option<u32> {
+        let digits = &s[range];
+        if digits.bytes().all(|b| b.is_ascii_digit()) {
+            digits.parse().ok()
+        } else {
+            None
+        }
+    };
+    let (year, month, day) = match (number(0..4), number(5..7), number(8..10)) {
+        (Some(year), Some(month), Some(day)) => (year, month, day),
+        _ => return false,
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validity_windows() {
+        let query = "# @valid-from 2024-02-29\n\
+                     #@valid-until   2025-06-30 \n\
+                     # @author someone\n\
+                     SELECT * WHERE { ?a ?b ?c } # @valid-from 2000-01-01";
+        let validity = Validity::parse(query).unwrap();
+        assert_eq!(
+            validity,
+            Validity {
+                from: Some("2024-02-29".to_string()),
+                until: Some("2025-06-30".to_string()),
+            }
+        );
+        assert_eq!(Validity::of(&validity.annotations()), validity);
+        assert!(!validity.expired("2025-06-30"));
+        assert!(validity.expired("2025-07-01"));
+        assert!(!Validity::default().expired("9999-12-31"));
+        assert!(Validity::default().annotations().is_empty());
+
+        for date in [
+            "2023-02-29",
+            "1900-02-29",
+            "2024-04-31",
+            "2024-13-01",
+            "2024-1-01",
+        ] {
+            assert!(!is_date(date), "{}", date);
+        }
+        assert!(is_date("2000-02-29"));
+        assert_eq!(
+            Validity::parse("\n# @valid-until 2024-06-31"),
+            Err(ValidityError::Date {
+                line: 2,
+                date: "2024-06-31".to_string()
+            })
+        );
+        assert_eq!(
+            Validity::parse("# @valid-from 2024-01-01\n# @valid-from 2024-01-02"),
+            Err(ValidityError::Repeated {
+                line: 2,
+                name: "valid-from"
+            })
+        );
+        assert_eq!(
+            Validity::parse("# @valid-from 2024-01-02\n# @valid-until 2024-01-01"),
+            Err(ValidityError::Empty {
+                from: "2024-01-02".to_string(),
+                until: "2024-01-01".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Renaming, numbering and locating the unbound variables of a rule.
+
+use crate::types::{RdfNode, RuleParts};
+use rify::{Claim, Entity};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+
+/// A rule with variables numbered rather than named, for engines that want dense integer
+/// identifiers. `variables[i]` is the original name of variable `i`.
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct IndexedRule {
+    pub variables: Vec<String>,
+    pub if_all: Vec<Claim<Entity<u32, RdfNode>>>,
+    pub then: Vec<Claim<Entity<u32, RdfNode>>>,
+}
+
+impl From<&RuleParts> for IndexedRule {
+    fn from(rule: &RuleParts) -> Self {
+        let mut variables = Vec::new();
+        let mut index = BTreeMap::new();
+        let mut claims = |claims: &[Claim<Entity<String, RdfNode>>]| -> Vec<_> {
+            let mut entity = |ent: &Entity<String, RdfNode>| match ent {
+                Entity::Unbound(name) => Entity::Unbound(number(&mut index, &mut variables, name)),
+                Entity::Bound(node) => Entity::Bound(node.clone()),
+            };
+            claims
+                .iter()
+                .map(|[s, p, o]| [entity(s), entity(p), entity(o)])
+                .collect()
+        };
+        let if_all = claims(&rule.if_all);
+        let then = claims(&rule.then);
+        IndexedRule {
+            variables,
+            if_all,
+            then,
+        }
+    }
+}
+
+/// the number of variable `name`, numbering it if it is new
+fn number(index: &mut BTreeMap<String, u32>, names: &mut Vec<String>, name: &str) -> u32 {
+    if let Some(i) = index.get(name) {
+        return *i;
+    }
+    let i = u32::try_from(names.len()).expect("fewer than 2^32 variables");
+    index.insert(name.to_string(), i);
+    names.push(name.to_string());
+    i
+}
+
+/// Rename every variable in `rule` to `v0`, `v1`, ... in order of first appearance, which keeps
+/// published rulesets small and free of internal naming conventions. Returns the original names,
+/// the name of `v{i}` at index `i`.
+pub fn rename(rule: &mut RuleParts) -> Vec<String> {
+    let mut original = Vec::new();
+    let mut index = BTreeMap::new();
+    for ent in rule.if_all.iter_mut().chain(&mut rule.then).flatten() {
+        if let Entity::Unbound(name) = ent {
+            *name = format!("v{}", number(&mut index, &mut original, name));
+        }
+    }
+    original
+}
+
+/// Where a variable appears in a rule.
+#[derive(Debug, serde::Serialize, PartialEq, Eq)]
+pub struct Occurrence {
+    pub clause: Clause,
+    /// index of the claim within the clause
+    pub claim: usize,
+    pub position: Position,
+}
+
+#[derive(Clone, Copy, Debug, serde::Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Clause {
+    IfAll,
+    Then,
+}
+
+#[derive(Clone, Copy, Debug, serde::Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Position {
+    Subject,
+    Predicate,
+    Object,
+}
+
+/// Every occurrence of every variable in `rule`, for code that binds inputs to a rule's
+/// variables or reads the bindings out of a proof.
+pub fn var_map(rule: &RuleParts) -> BTreeMap<&str, Vec<Occurrence>> {
+    let mut ret: BTreeMap<&str, Vec<Occurrence>> = BTreeMap::new();
+    let clauses = [(Clause::IfAll, &rule.if_all), (Clause::Then, &rule.then)];
+    let positions = [Position::Subject, Position::Predicate, Position::Object];
+    for (clause, claims) in clauses.iter() {
+        for (claim, entities) in claims.iter().enumerate() {
+            for (position, ent) in positions.iter().zip(entities) {
+                if let Entity::Unbound(name) = ent {
+                    ret.entry(name).or_default().push(Occurrence {
+                        clause: *clause,
+                        claim,
+                        position: *position,
+                    });
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// A source of names for the variables and blank nodes a rewrite adds, which collide with none
+/// the rules already use nor with each other. Every rewrite that invents names goes through one,
+/// so rewrites applied one after another can't capture the query's variables. A name is a prefix
+/// followed by the lowest number that gives a free name, so the same input is always given the
+/// same names.
+#[derive(Clone, Debug, Default)]
+pub struct Fresh {
+    taken: BTreeSet<String>,
+    /// for each prefix, a number below which every name with that prefix is taken
+    next: BTreeMap<String, usize>,
+}
+
+impl Fresh {
+    /// a generator avoiding every variable and blank node label in `claims`
+    pub fn avoiding<'a>(
+        claims: impl IntoIterator<Item = &'a Claim<Entity<String, RdfNode>>>,
+    ) -> Self {
+        let mut ret = Self::default();
+        for ent in claims.into_iter().flatten() {
+            if let Entity::Unbound(name) | Entity::Bound(RdfNode::Blank(name)) = ent {
+                ret.reserve(name);
+            }
+        }
+        ret
+    }
+
+    /// Keep `name` from being handed out. Returns whether it was free.
+    pub fn reserve(&mut self, name: &str) -> bool {
+        self.taken.insert(name.to_string())
+    }
+
+    pub fn is_taken(&self, name: &str) -> bool {
+        self.taken.contains(name)
+    }
+
+    /// a free name starting with `prefix`, which is taken from then on
+    pub fn name(&mut self, prefix: &str) -> String {
+        let next = self.next.entry(prefix.to_string()).or_insert(0);
+        loop {
+            let name = format!("{}{}", prefix, next);
+            *next += 1;
+            if self.taken.insert(name.clone()) {
+                return name;
+            }
+        }
+    }
+}
+
+/// `rename`'s result as a JSON object from new name to original name
+pub fn mapping_table(original: &[String]) -> BTreeMap<String, &str> {
+    original
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (format!("v{}", i), name.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rename_in_order_of_appearance() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = Entity::Bound(RdfNode::Iri("http://example.com".to_string()));
+        let mut rule = RuleParts {
+            if_all: vec![[unbd("subject"), iri.clone(), unbd("object")]],
+            then: vec![[unbd("object"), iri.clone(), unbd("subject")]],
+        };
+        let original = rename(&mut rule);
+        assert_eq!(original, ["subject", "object"]);
+        assert_eq!(
+            rule,
+            RuleParts {
+                if_all: vec![[unbd("v0"), iri.clone(), unbd("v1")]],
+                then: vec![[unbd("v1"), iri, unbd("v0")]],
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(mapping_table(&original)).unwrap(),
+            serde_json::json!({"v0": "subject", "v1": "object"})
+        );
+    }
+
+    #[test]
+    fn var_map_positions() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = Entity::Bound(RdfNode::Iri("http://example.com".to_string()));
+        let rule = RuleParts {
+            if_all: vec![[unbd("a"), iri.clone(), unbd("b")]],
+            then: vec![[unbd("b"), unbd("a"), iri]],
+        };
+        assert_eq!(
+            serde_json::to_value(var_map(&rule)).unwrap(),
+            serde_json::json!({
+                "a": [
+                    {"clause": "if_all", "claim": 0, "position": "subject"},
+                    {"clause": "then", "claim": 0, "position": "predicate"},
+                ],
+                "b": [
+                    {"clause": "if_all", "claim": 0, "position": "object"},
+                    {"clause": "then", "claim": 0, "position": "subject"},
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn indexed() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = || RdfNode::Iri("http://example.com".to_string());
+        let rule = RuleParts {
+            if_all: vec![[unbd("subject"), Entity::Bound(iri()), unbd("object")]],
+            then: vec![[unbd("object"), Entity::Bound(iri()), unbd("subject")]],
+        };
+        assert_eq!(
+            IndexedRule::from(&rule),
+            IndexedRule {
+                variables: vec!["subject".to_string(), "object".to_string()],
+                if_all: vec![[Entity::Unbound(0), Entity::Bound(iri()), Entity::Unbound(1)]],
+                then: vec![[Entity::Unbound(1), Entity::Bound(iri()), Entity::Unbound(0)]],
+            }
+        );
+    }
+
+    #[test]
+    fn fresh_names() {
+        let iri = Entity::Bound(RdfNode::Iri("http://example.com".to_string()));
+        let claims = [[
+            Entity::Unbound("b0".to_string()),
+            iri,
+            Entity::Bound(RdfNode::Blank("b2".to_string())),
+        ]];
+        let mut fresh = Fresh::avoiding(&claims);
+        assert!(fresh.is_taken("b2"));
+        assert!(fresh.reserve("x"));
+        assert!(!fresh.reserve("x"));
+        let names: Vec<String> = (0..3).map(|_| fresh.name("b")).collect();
+        assert_eq!(names, ["b1", "b3", "b4"]);
+        assert_eq!(fresh.name("x"), "x0");
+        assert!(fresh.clone().is_taken("b4"));
+    }
+}
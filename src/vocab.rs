@@ -0,0 +1,279 @@
+//! The vocabulary a ruleset uses. Listing every IRI its rules mention, grouped by namespace,
+//! shows at a glance which ontologies a ruleset touches, e.g. to check they are all approved
+//! before it is deployed, and a `Policy` checks it automatically. rdf:type gets functions of its
+//! own since type inference is often scheduled apart from other rules.
+
+use crate::intern::{Node, Rule};
+use crate::schema::RDF_TYPE;
+use crate::types::{InvalidRule, RdfNode, RuleParts};
+use rify::Entity;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What an IRI is used as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Predicate,
+    /// the object of an rdf:type claim
+    Class,
+    /// the datatype of a literal, including an implicit xsd:string or rdf:langString
+    Datatype,
+    /// any other subject or object
+    Other,
+}
+
+/// Namespace to IRI to how many times it is used in each role.
+pub type Vocabulary = BTreeMap<String, BTreeMap<String, BTreeMap<Role, usize>>>;
+
+/// every IRI in `rules`, in both conditions and conclusions
+pub fn vocabulary(rules: &[Rule]) -> Vocabulary {
+    // counted under the strings the rules hold, which they share as they were read
+    let mut counts: BTreeMap<&str, BTreeMap<Role, usize>> = BTreeMap::new();
+    let mut count = |iri, role| {
+        *counts.entry(iri).or_default().entry(role).or_default() += 1;
+    };
+    for [s, p, o] in rules
+        .iter()
+        .flat_map(|rule| rule.if_all.iter().chain(&rule.then))
+    {
+        let is_type = matches!(p, Entity::Bound(Node::Iri(iri)) if &**iri == RDF_TYPE);
+        for (ent, role) in [
+            (s, Role::Other),
+            (p, Role::Predicate),
+            (o, if is_type { Role::Class } else { Role::Other }),
+        ] {
+            match ent {
+                Entity::Bound(Node::Iri(iri)) => count(iri, role),
+                Entity::Bound(literal @ Node::Literal { .. }) => count(
+                    literal.datatype().expect("literals have one"),
+                    Role::Datatype,
+                ),
+                _ => {}
+            }
+        }
+    }
+    let mut ret = Vocabulary::new();
+    for (iri, roles) in counts {
+        ret.entry(namespace(iri).to_string())
+            .or_default()
+            .insert(iri.to_string(), roles);
+    }
+    ret
+}
+
+/// Namespaces rules may and may not use, as read from a `--namespaces` file. An IRI is in a
+/// namespace if it starts with it. Only IRIs used as terms are checked, not the datatypes of
+/// literals.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// if given, every IRI must be in one of these
+    #[serde(default)]
+    pub allow: Option<Vec<String>>,
+    /// IRIs in these may not be used at all
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// IRIs in these may be matched but not derived, i.e. not used in `then`
+    #[serde(default)]
+    pub deny_implied: Vec<String>,
+}
+
+impl Policy {
+    /// the first IRI in `rule` that breaks the policy, if any
+    pub fn check(&self, rule: &RuleParts) -> Result<(), InvalidRule> {
+        let iris = |claims: &'_ [rify::Claim<Entity<String, RdfNode>>]| {
+            claims
+                .iter()
+                .flatten()
+                .filter_map(|ent| match ent {
+                    Entity::Bound(RdfNode::Iri(iri)) => Some(iri.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let within = |iri: &str, namespaces: &[String]| {
+            namespaces
+                .iter()
+                .find(|namespace| iri.starts_with(namespace.as_str()))
+                .cloned()
+        };
+        for (iri, implied) in iris(&rule.if_all)
+            .into_iter()
+            .map(|iri| (iri, false))
+            .chain(iris(&rule.then).into_iter().map(|iri| (iri, true)))
+        {
+            if let Some(namespace) = within(&iri, &self.deny) {
+                let usage = "use";
+                return Err(InvalidRule::NamespaceDenied {
+                    iri,
+                    namespace,
+                    usage,
+                });
+            }
+            if let Some(namespace) = within(&iri, &self.deny_implied).filter(|_| implied) {
+                let usage = "derive claims with";
+                return Err(InvalidRule::NamespaceDenied {
+                    iri,
+                    namespace,
+                    usage,
+                });
+            }
+            if let Some(allow) = &self.allow {
+                if within(&iri, allow).is_none() {
+                    return Err(InvalidRule::NamespaceNotAllowed { iri });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a rule uses rdf:type claims. A variable predicate counts as rdf:type since it can be.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TypeUsage {
+    /// some condition can match an rdf:type claim
+    pub matches: bool,
+    /// some conclusion can be an rdf:type claim
+    pub derives: bool,
+}
+
+pub fn type_usage(rule: &RuleParts) -> TypeUsage {
+    let typed = |claims: &[rify::Claim<Entity<String, RdfNode>>]| {
+        claims.iter().any(|[_, p, _]| match p {
+            Entity::Bound(RdfNode::Iri(iri)) => iri == RDF_TYPE,
+            Entity::Bound(_) => false,
+            Entity::Unbound(_) => true,
+        })
+    };
+    TypeUsage {
+        matches: typed(&rule.if_all),
+        derives: typed(&rule.then),
+    }
+}
+
+/// `rule` as a rule deriving its rdf:type conclusions and one deriving the rest, both with all
+/// of its conditions, None in place of either that would derive nothing. Only conclusions with
+/// rdf:type written out as their predicate are moved.
+pub fn split_types(rule: &RuleParts) -> (Option<RuleParts>, Option<RuleParts>) {
+    let (types, rest): (Vec<_>, Vec<_>) =
+        rule.then.iter().cloned().partition(
+            |[_, p, _]| matches!(p, Entity::Bound(RdfNode::Iri(iri)) if iri == RDF_TYPE),
+        );
+    let with = |then: Vec<_>| {
+        Some(RuleParts {
+            if_all: rule.if_all.clone(),
+            then,
+        })
+        .filter(|rule| !rule.then.is_empty())
+    };
+    (with(types), with(rest))
+}
+
+/// The namespace of `iri`, up to and including its last `#` or `/`, or its last `:` if it has
+/// neither, as in `urn:` and `did:` IRIs.
+pub fn namespace(iri: &str) -> &str {
+    let end = iri
+        .rfind(['#', '/'])
+        .or_else(|| iri.rfind(':'))
+        .map_or(0, |i| i + 1);
+    &iri[..end]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intern::Interner;
+    use crate::types::XSD_STRING;
+
+    #[test]
+    fn grouped_by_namespace() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| Entity::Bound(RdfNode::Iri(iri.to_string()));
+        let rule = RuleParts {
+            if_all: vec![
+                [unbd("a"), iri("http://schema.org/name"), unbd("name")],
+                [unbd("a"), iri(RDF_TYPE), iri("http://schema.org/Person")],
+                [
+                    unbd("a"),
+                    iri("http://schema.org/name"),
+                    Entity::Bound(RdfNode::Literal {
+                        value: "Alice".to_string(),
+                        datatype: None,
+                        language: None,
+                    }),
+                ],
+            ],
+            then: vec![[unbd("a"), iri("did:example:knows"), iri("did:example:bob")]],
+        };
+        let vocabulary = vocabulary(&[Interner::default().rule(&rule)]);
+        assert_eq!(
+            vocabulary.keys().collect::<Vec<_>>(),
+            [
+                "did:example:",
+                "http://schema.org/",
+                "http://www.w3.org/1999/02/22-rdf-syntax-ns#",
+                "http://www.w3.org/2001/XMLSchema#",
+            ]
+        );
+        let schema = &vocabulary["http://schema.org/"];
+        assert_eq!(schema["http://schema.org/name"][&Role::Predicate], 2);
+        assert_eq!(schema["http://schema.org/Person"][&Role::Class], 1);
+        let xsd = &vocabulary["http://www.w3.org/2001/XMLSchema#"];
+        assert_eq!(xsd[XSD_STRING][&Role::Datatype], 1);
+        assert_eq!(
+            vocabulary["did:example:"]["did:example:bob"][&Role::Other],
+            1
+        );
+
+        let policy = |json| serde_json::from_value::<Policy>(json).unwrap();
+        assert_eq!(
+            policy(serde_json::json!({"allow": ["http://schema.org/"]})).check(&rule),
+            Err(InvalidRule::NamespaceNotAllowed {
+                iri: RDF_TYPE.to_string()
+            })
+        );
+        let implied = policy(serde_json::json!({"deny_implied": ["did:example:"]}));
+        assert_eq!(
+            implied.check(&rule),
+            Err(InvalidRule::NamespaceDenied {
+                iri: "did:example:knows".to_string(),
+                namespace: "did:example:".to_string(),
+                usage: "derive claims with",
+            })
+        );
+        let swapped = RuleParts {
+            if_all: rule.then.clone(),
+            then: rule.if_all.clone(),
+        };
+        assert_eq!(implied.check(&swapped), Ok(()));
+    }
+
+    #[test]
+    fn types_apart() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = |iri: &str| Entity::Bound(RdfNode::Iri(iri.to_string()));
+        let rule = RuleParts {
+            if_all: vec![[unbd("a"), iri("http://schema.org/spouse"), unbd("b")]],
+            then: vec![
+                [unbd("a"), iri(RDF_TYPE), iri("http://schema.org/Person")],
+                [unbd("b"), iri("http://schema.org/spouse"), unbd("a")],
+            ],
+        };
+        assert_eq!(
+            type_usage(&rule),
+            TypeUsage {
+                matches: false,
+                derives: true
+            }
+        );
+        let (types, rest) = split_types(&rule);
+        assert_eq!(types.unwrap().then, rule.then[..1]);
+        assert_eq!(rest.unwrap().then, rule.then[1..]);
+        let only_types = RuleParts {
+            if_all: rule.if_all.clone(),
+            then: rule.then[..1].to_vec(),
+        };
+        assert!(matches!(split_types(&only_types), (Some(_), None)));
+    }
+}
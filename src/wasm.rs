@@ -0,0 +1,63 @@
+//! Javascript bindings. The npm package in `js/` wraps these in an ES module, see the README.
+
+use crate::{parse, sparql2rify, Error};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+// wasm-bindgen only knows `convert` returns some JsValue, these are the real types
+#[wasm_bindgen(typescript_custom_section)]
+const TYPES: &str = r#"
+export type RdfNode =
+  | { Blank: string }
+  | { Iri: string }
+  | { Literal: { value: string; datatype?: string; language?: string } };
+
+export type Entity = { Unbound: string } | { Bound: RdfNode };
+
+export interface Rule {
+  if_all: [Entity, Entity, Entity][];
+  then: [Entity, Entity, Entity][];
+}
+
+export interface ConvertError {
+  kind: "parse" | "invalid-rule";
+  message: string;
+}
+
+/**
+ * Convert a SPARQL CONSTRUCT query to a rify rule. Throws a `ConvertError` if that is not
+ * possible.
+ */
+export function convert(sparql: string): Rule;
+"#;
+
+/// Thrown by `convert` when a query can't be converted.
+#[derive(Serialize)]
+struct ConvertError {
+    /// "parse" if the query is not valid SPARQL, "invalid-rule" if it is valid but has no rify
+    /// equivalent
+    kind: &'static str,
+    message: String,
+}
+
+/// Convert a SPARQL CONSTRUCT query to a rify rule of the form `{ if_all, then }`. Lints are not
+/// reported.
+#[wasm_bindgen(skip_typescript)]
+pub fn convert(sparql: &str) -> Result<JsValue, JsValue> {
+    let query = parse(sparql, None).map_err(error)?;
+    let (rule, _warnings) =
+        sparql2rify(query).map_err(|source| error(Error::InvalidRule { source }))?;
+    serde_wasm_bindgen::to_value(&rule).map_err(Into::into)
+}
+
+fn error(err: Error) -> JsValue {
+    let kind = match err {
+        Error::Parse { .. } => "parse",
+        Error::InvalidRule { .. } => "invalid-rule",
+    };
+    let err = ConvertError {
+        kind,
+        message: err.to_string(),
+    };
+    serde_wasm_bindgen::to_value(&err).expect("a struct of strings always converts")
+}
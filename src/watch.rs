@@ -0,0 +1,206 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Extensions of the files picked up when watching a directory.
+const EXTENSIONS: &[&str] = &["sparql", "rq", "n3"];
+
+/// What was last seen of a single query file.
+struct Source<T> {
+    /// modification time and length, if these are unchanged the file is not read again
+    stamp: (Option<SystemTime>, u64),
+    /// hash of the file content, if this is unchanged the file is not converted again
+    hash: u64,
+    /// result of the last successful conversion
+    rule: Option<T>,
+}
+
+/// A set of query files and their most recent conversions, `T` being whatever a conversion
+/// produces. Only files whose content changed are converted again on `refresh`.
+pub struct Watch<'a, F, T> {
+    inputs: &'a [PathBuf],
+    convert: F,
+    sources: BTreeMap<PathBuf, Source<T>>,
+}
+
+impl<'a, F, T> Watch<'a, F, T>
+where
+    F: FnMut(&str) -> Result<T, Box<dyn Error>>,
+    T: Clone + PartialEq,
+{
+    /// `inputs` are query files or directories to search for query files
+    pub fn new(inputs: &'a [PathBuf], convert: F) -> Self {
+        Self {
+            inputs,
+            convert,
+            sources: BTreeMap::new(),
+        }
+    }
+
+    /// Bring the conversions up to date with the files on disk. Returns whether the combined
+    /// ruleset changed. A file that fails to convert keeps its last good rule so that a half
+    /// written query doesn't wipe out the output.
+    pub fn refresh(&mut self) -> io::Result<bool> {
+        let paths = self.source_paths()?;
+        let before = self.sources.len();
+        self.sources.retain(|path, _| paths.contains(path));
+        let mut changed = self.sources.len() != before;
+
+        for path in paths {
+            // files can disappear between listing and reading while an editor is saving
+            let meta = match fs::metadata(&path) {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+            let stamp = (meta.modified().ok(), meta.len());
+            if matches!(self.sources.get(&path), Some(source) if source.stamp == stamp) {
+                continue;
+            }
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(_) => continue,
+            };
+            let hash = hash(&text);
+            if let Some(source) = self.sources.get_mut(&path) {
+                source.stamp = stamp;
+                if source.hash == hash {
+                    continue;
+                }
+            }
+
+            let previous = self.sources.remove(&path).and_then(|source| source.rule);
+            let rule = match (self.convert)(&text) {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    previous.clone()
+                }
+            };
+            changed |= rule != previous;
+            self.sources.insert(path, Source { stamp, hash, rule });
+        }
+
+        Ok(changed)
+    }
+
+    /// the current rules, ordered by source path
+    pub fn rules(&self) -> Vec<&T> {
+        self.sources
+            .values()
+            .filter_map(|s| s.rule.as_ref())
+            .collect()
+    }
+
+    fn source_paths(&self) -> io::Result<BTreeSet<PathBuf>> {
+        let mut ret = BTreeSet::new();
+        for input in self.inputs {
+            if input.is_dir() {
+                search(input, &mut ret)?;
+            } else if input.exists() {
+                ret.insert(input.clone());
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// recursively collect query files from a directory
+fn search(dir: &Path, found: &mut BTreeSet<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            search(&path, found)?;
+        } else if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+            if EXTENSIONS.contains(&ext) {
+                found.insert(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Replace the contents of `path` without readers ever seeing a partially written file.
+pub fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut fs::File) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = fs::File::create(&tmp)?;
+    write(&mut file)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sparql2rify::types::RuleParts;
+    use std::cell::Cell;
+
+    #[test]
+    fn only_changed_files_are_converted() {
+        let dir = std::env::temp_dir().join(format!("sparql2rify-watch-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.sparql"), "a").unwrap();
+        fs::write(dir.join("nested").join("b.rq"), "b").unwrap();
+        fs::write(dir.join("notes.txt"), "not a query").unwrap();
+
+        let calls = Cell::new(0);
+        let inputs = [dir.clone()];
+        let mut watch = Watch::new(&inputs, |text| {
+            calls.set(calls.get() + 1);
+            if text.contains("bad") {
+                return Err("bad query".into());
+            }
+            Ok(RuleParts {
+                if_all: vec![],
+                then: vec![[
+                    rify::Entity::Unbound(text.to_string()),
+                    rify::Entity::Unbound(text.to_string()),
+                    rify::Entity::Unbound(text.to_string()),
+                ]],
+            })
+        });
+
+        assert!(watch.refresh().unwrap());
+        assert_eq!(calls.get(), 2);
+        assert_eq!(watch.rules().len(), 2);
+
+        assert!(!watch.refresh().unwrap());
+        assert_eq!(calls.get(), 2);
+
+        // same content rewritten
+        fs::write(dir.join("a.sparql"), "a").unwrap();
+        assert!(!watch.refresh().unwrap());
+
+        // a broken edit keeps the previous rule
+        fs::write(dir.join("a.sparql"), "a bad edit").unwrap();
+        assert!(!watch.refresh().unwrap());
+        assert_eq!(calls.get(), 3);
+        assert_eq!(watch.rules().len(), 2);
+
+        fs::write(dir.join("a.sparql"), "a2").unwrap();
+        assert!(watch.refresh().unwrap());
+        assert_eq!(calls.get(), 4);
+
+        fs::remove_file(dir.join("nested").join("b.rq")).unwrap();
+        assert!(watch.refresh().unwrap());
+        assert_eq!(watch.rules().len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
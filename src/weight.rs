@@ -0,0 +1,62 @@
+//! Size metrics for a rule, for estimating what it costs to store and evaluate on chain.
+
+use crate::output::{encoded_len, Format};
+use crate::types::RuleParts;
+use crate::util::as_unbound;
+use std::collections::BTreeSet;
+
+/// The figures the cost of registering a rule depends on.
+#[derive(Debug, serde::Serialize, PartialEq, Eq)]
+pub struct Weight {
+    /// length of the rule in the chosen format, without whitespace
+    pub bytes: usize,
+    pub if_all_claims: usize,
+    pub then_claims: usize,
+    /// number of distinct variables
+    pub variables: usize,
+}
+
+pub fn weigh(rule: &RuleParts, format: Format) -> Weight {
+    let variables: BTreeSet<&str> = rule
+        .if_all
+        .iter()
+        .chain(&rule.then)
+        .flatten()
+        .filter_map(as_unbound)
+        .collect();
+    Weight {
+        bytes: encoded_len(rule, format),
+        if_all_claims: rule.if_all.len(),
+        then_claims: rule.then.len(),
+        variables: variables.len(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::RdfNode;
+    use rify::Entity;
+
+    #[test]
+    fn weight() {
+        let unbd = |name: &str| Entity::Unbound(name.to_string());
+        let iri = Entity::Bound(RdfNode::Iri("http://example.com".to_string()));
+        let rule = RuleParts {
+            if_all: vec![
+                [unbd("a"), iri.clone(), unbd("b")],
+                [unbd("b"), iri.clone(), unbd("c")],
+            ],
+            then: vec![[unbd("a"), iri, unbd("c")]],
+        };
+        assert_eq!(
+            weigh(&rule, Format::Rify),
+            Weight {
+                bytes: serde_json::to_vec(&rule).unwrap().len(),
+                if_all_claims: 2,
+                then_claims: 1,
+                variables: 3,
+            }
+        );
+    }
+}